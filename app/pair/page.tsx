@@ -16,6 +16,8 @@ export default function PairPage() {
   useEffect(() => {
     async function pair() {
       const desktopInfo = parseQRData(window.location.href);
+      // Keep the pairing secret out of browser history
+      window.history.replaceState(null, '', window.location.pathname + window.location.search);
 
       if (!desktopInfo) {
         setErrorMessage('Invalid QR code — please try scanning again.');
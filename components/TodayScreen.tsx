@@ -1,6 +1,6 @@
 'use client';
 
-import { useState, useEffect, useMemo } from 'react';
+import { useState, useEffect, useMemo, useRef, type ChangeEvent } from 'react';
 import { Dumbbell, Play, CheckCircle, Smartphone, Flame, ChartBar, Volume2, VolumeX } from 'lucide-react';
 import { Button } from '@traindaily/ui';
 import {
@@ -20,7 +20,7 @@ import { useMobility } from '@/hooks/useMobility';
 import { formatDisplayDate, getWeekNumber } from '@/lib/workout-utils';
 import { getFirstSessionDate } from '@/lib/storage';
 import { getWorkoutType, getTrainingStreak } from '@/lib/schedule';
-import { syncWithDesktop, getStoredDesktopInfo, clearDesktopInfo } from '@/lib/sync-client';
+import { syncWithDesktop, getStoredDesktopInfo, clearDesktopInfo, exportBundle, importBundle } from '@/lib/sync-client';
 import { playWentOffline, playBackOnline, isMuted, setMuted } from '@/lib/audio';
 import { WorkoutErrorBoundary } from './WorkoutErrorBoundary';
 
@@ -162,6 +162,39 @@ function TodayContent({ date }: { date: Date }) {
     setSyncStatus('idle');
   };
 
+  // Offline sync: hand the desktop a bundle file (AirDrop, Files), or read one it wrote
+  const bundleInput = useRef<HTMLInputElement>(null);
+  const handleSendBundle = async () => {
+    try {
+      const file = await exportBundle();
+      if (navigator.canShare?.({ files: [file] })) {
+        await navigator.share({ files: [file] });
+      } else {
+        const url = URL.createObjectURL(file);
+        const link = document.createElement('a');
+        link.href = url;
+        link.download = file.name;
+        link.click();
+        URL.revokeObjectURL(url);
+      }
+    } catch (err) {
+      if (err instanceof Error && err.name === 'AbortError') return; // Share sheet dismissed
+      console.error('Failed to export bundle:', err);
+      setSyncStatus('error');
+      setTimeout(() => setSyncStatus('idle'), 2000);
+    }
+  };
+
+  const handleReceiveBundle = async (e: ChangeEvent<HTMLInputElement>) => {
+    const file = e.target.files?.[0];
+    e.target.value = '';
+    if (!file) return;
+    setSyncStatus('syncing');
+    const result = await importBundle(file);
+    setSyncStatus(result.success ? 'success' : 'error');
+    setTimeout(() => setSyncStatus('idle'), 2000);
+  };
+
   // History screen
   if (showHistory) {
     return (
@@ -344,15 +377,36 @@ function TodayContent({ date }: { date: Date }) {
           </p>
         )}
 
-        {/* Unpair option when paired */}
+        {/* Unpair and file sync options when paired */}
         {isPaired && syncStatus === 'idle' && (
-          <button
-            onClick={handleUnpair}
-            className="text-xs text-muted-foreground/40 hover:text-muted-foreground transition-colors"
-          >
-            Disconnect desktop
-          </button>
+          <div className="flex items-center gap-4">
+            <button
+              onClick={handleSendBundle}
+              className="text-xs text-muted-foreground/40 hover:text-muted-foreground transition-colors"
+            >
+              Send as file
+            </button>
+            <button
+              onClick={() => bundleInput.current?.click()}
+              className="text-xs text-muted-foreground/40 hover:text-muted-foreground transition-colors"
+            >
+              Import file
+            </button>
+            <button
+              onClick={handleUnpair}
+              className="text-xs text-muted-foreground/40 hover:text-muted-foreground transition-colors"
+            >
+              Disconnect desktop
+            </button>
+          </div>
         )}
+        <input
+          ref={bundleInput}
+          type="file"
+          accept=".tdbundle,application/json"
+          className="hidden"
+          onChange={handleReceiveBundle}
+        />
       </div>
     </div>
   );
@@ -13,6 +13,21 @@ interface DesktopInfo {
   lastKnownIp: string;
   port: number;
   authToken: string;
+  // Every address the desktop offered when pairing, best first
+  candidateIps?: string[];
+  // Pinned server key fingerprint from the QR (`fp`)
+  fingerprint?: string;
+  // End-to-end payload key from the QR fragment (`key`, base64url)
+  e2eKey?: string;
+}
+
+// Signed part of a /api/v1/ping reply
+interface PingResponse {
+  deviceId: string;
+  fingerprint: string;
+  nonce: string;
+  timestamp: number;
+  signature: string;
 }
 
 const STORAGE_KEY = 'traindaily_desktop_info';
@@ -56,34 +71,150 @@ export function clearDesktopInfo(): void {
   }
 }
 
-// Ping desktop at IP to check if it's the right device
-async function tryPing(ip: string, port: number, expectedDeviceId?: string): Promise<string | null> {
+// Base URL for a desktop address (IPv6 literals need brackets)
+function desktopBaseUrl(ip: string, port: number): string {
+  const host = ip.includes(':') ? `[${ip}]` : ip;
+  return `http://${host}:${port}`;
+}
+
+// Random challenge the desktop must echo back in its signed ping
+function randomNonce(): string {
+  const bytes = crypto.getRandomValues(new Uint8Array(16));
+  return Array.from(bytes, (b) => b.toString(16).padStart(2, '0')).join('');
+}
+
+// HMAC-SHA256 as hex, like auth::sign on the desktop
+async function hmacHex(secret: string, message: string): Promise<string> {
+  const encoder = new TextEncoder();
+  const key = await crypto.subtle.importKey(
+    'raw',
+    encoder.encode(secret),
+    { name: 'HMAC', hash: 'SHA-256' },
+    false,
+    ['sign'],
+  );
+  const mac = await crypto.subtle.sign('HMAC', key, encoder.encode(message));
+  return Array.from(new Uint8Array(mac), (b) => b.toString(16).padStart(2, '0')).join('');
+}
+
+const PING_MAX_SKEW_SECONDS = 300;
+
+// End-to-end envelope in place of a session (src-tauri/src/sync/e2e.rs):
+// AES-256-GCM, AAD "traindaily-e2e-v1\n<dateKey>", tag appended
+interface Envelope {
+  e2e: 1;
+  alg: 'A256GCM';
+  kid: string;
+  nonce: string;
+  ciphertext: string;
+}
+
+interface E2eKey {
+  key: CryptoKey;
+  // First 8 hex digits of SHA-256(key)
+  kid: string;
+}
+
+const E2E_AAD_PREFIX = 'traindaily-e2e-v1';
+
+function base64ToBytes(value: string): Uint8Array {
+  const normalized = value.replace(/-/g, '+').replace(/_/g, '/');
+  const padded = normalized + '='.repeat((4 - (normalized.length % 4)) % 4);
+  return Uint8Array.from(atob(padded), (c) => c.charCodeAt(0));
+}
+
+function bytesToBase64(bytes: Uint8Array): string {
+  // In chunks: spreading a whole bundle payload overflows the call stack
+  let binary = '';
+  for (let i = 0; i < bytes.length; i += 0x8000) {
+    binary += String.fromCharCode(...bytes.subarray(i, i + 0x8000));
+  }
+  return btoa(binary);
+}
+
+function isEnvelope(value: unknown): value is Envelope {
+  return typeof value === 'object' && value !== null && 'e2e' in value && 'ciphertext' in value;
+}
+
+async function importE2eKey(encoded: string): Promise<E2eKey> {
+  const raw = base64ToBytes(encoded);
+  const digest = new Uint8Array(await crypto.subtle.digest('SHA-256', raw));
+  const kid = Array.from(digest.slice(0, 4), (b) => b.toString(16).padStart(2, '0')).join('');
+  const key = await crypto.subtle.importKey('raw', raw, { name: 'AES-GCM' }, false, ['encrypt', 'decrypt']);
+  return { key, kid };
+}
+
+function e2eAad(dateKey: string): Uint8Array {
+  return new TextEncoder().encode(`${E2E_AAD_PREFIX}\n${dateKey}`);
+}
+
+async function sealSession(e2e: E2eKey, dateKey: string, session: WorkoutSession): Promise<Envelope> {
+  const nonce = crypto.getRandomValues(new Uint8Array(12));
+  const plaintext = new TextEncoder().encode(JSON.stringify(session));
+  const ciphertext = await crypto.subtle.encrypt(
+    { name: 'AES-GCM', iv: nonce, additionalData: e2eAad(dateKey) },
+    e2e.key,
+    plaintext,
+  );
+  return {
+    e2e: 1,
+    alg: 'A256GCM',
+    kid: e2e.kid,
+    nonce: bytesToBase64(nonce),
+    ciphertext: bytesToBase64(new Uint8Array(ciphertext)),
+  };
+}
+
+async function openSession(e2e: E2eKey, dateKey: string, envelope: Envelope): Promise<WorkoutSession> {
+  if (envelope.kid !== e2e.kid) {
+    throw new Error('Sealed with another key');
+  }
+  const plaintext = await crypto.subtle.decrypt(
+    { name: 'AES-GCM', iv: base64ToBytes(envelope.nonce), additionalData: e2eAad(dateKey) },
+    e2e.key,
+    base64ToBytes(envelope.ciphertext),
+  );
+  return JSON.parse(new TextDecoder().decode(plaintext));
+}
+
+// Only the paired desktop knows the secret: check the signature over our
+// nonce, and the pinned key fingerprint when the QR carried one
+// (payload: ping_signing_payload in src-tauri/src/sync/mod.rs)
+async function isAuthenticPing(ping: PingResponse, nonce: string, desktop: DesktopInfo): Promise<boolean> {
+  if (ping.deviceId !== desktop.deviceId || ping.nonce !== nonce) return false;
+  if (desktop.fingerprint && ping.fingerprint !== desktop.fingerprint) return false;
+  if (Math.abs(Date.now() / 1000 - ping.timestamp) > PING_MAX_SKEW_SECONDS) return false;
+
+  const payload = ['traindaily-ping-v1', ping.deviceId, ping.fingerprint, nonce, ping.timestamp].join('\n');
+  return (await hmacHex(desktop.authToken, payload)) === ping.signature;
+}
+
+// Ping desktop at IP to check if it's the paired device
+async function tryPing(ip: string, port: number, desktop: DesktopInfo): Promise<boolean> {
   try {
     const controller = new AbortController();
     const timeout = setTimeout(() => controller.abort(), 1000); // 1 second timeout
 
-    const response = await fetch(`http://${ip}:${port}/api/ping`, {
+    const nonce = randomNonce();
+    const response = await fetch(`${desktopBaseUrl(ip, port)}/api/v1/ping?nonce=${nonce}`, {
       signal: controller.signal,
       mode: 'cors',
     });
 
     clearTimeout(timeout);
 
-    if (!response.ok) return null;
-
-    const { deviceId } = await response.json();
-    if (expectedDeviceId && deviceId !== expectedDeviceId) return null;
+    if (!response.ok) return false;
 
-    return deviceId;
+    return await isAuthenticPing(await response.json(), nonce, desktop);
   } catch {
-    return null;
+    return false;
   }
 }
 
 // Discover desktop by device ID (scan nearby IPs)
-async function discoverByDeviceId(targetId: string, port: number, lastKnownIp: string): Promise<string | null> {
+async function discoverByDeviceId(desktop: DesktopInfo): Promise<string | null> {
   // Get subnet from last known IP
-  const parts = lastKnownIp.split('.');
+  const parts = desktop.lastKnownIp.split('.');
   if (parts.length !== 4) return null;
 
   const subnet = parts.slice(0, 3).join('.');
@@ -95,16 +226,14 @@ async function discoverByDeviceId(targetId: string, port: number, lastKnownIp: s
     if (octet < 1 || octet > 254) continue;
 
     const ip = `${subnet}.${octet}`;
-    const deviceId = await tryPing(ip, port, targetId);
-    if (deviceId === targetId) return ip;
+    if (await tryPing(ip, desktop.port, desktop)) return ip;
   }
 
   // Full scan if not found nearby (slower)
   for (let i = 1; i <= 254; i++) {
     if (Math.abs(i - lastOctet) <= 5) continue; // Already checked
     const ip = `${subnet}.${i}`;
-    const deviceId = await tryPing(ip, port, targetId);
-    if (deviceId === targetId) return ip;
+    if (await tryPing(ip, desktop.port, desktop)) return ip;
   }
 
   return null;
@@ -112,6 +241,33 @@ async function discoverByDeviceId(targetId: string, port: number, lastKnownIp: s
 
 const SYNC_TIMEOUT_MS = 15000;
 
+// Deep merge: combine local and desktop data
+// For the same date, merge at the exercise key level (both sides keep their exercises)
+// logged_at and week_number: prefer whichever was logged later
+function mergeSessions(localData: WorkoutData, desktopSessions: WorkoutData): WorkoutData {
+  const merged: WorkoutData = { ...localData };
+
+  for (const [dateKey, desktopSession] of Object.entries(desktopSessions)) {
+    const localSession = localData[dateKey];
+    if (!localSession) {
+      // Desktop has a date we don't — take it as-is
+      merged[dateKey] = desktopSession;
+    } else {
+      // Both have this date — merge exercise keys from both sides.
+      // For shared keys, prefer whichever session was logged later.
+      const localTime = new Date(localSession.logged_at || 0).getTime();
+      const desktopTime = new Date(desktopSession.logged_at || 0).getTime();
+      const preferDesktop = desktopTime >= localTime;
+      // Spread: all keys from both sides, newer session wins on conflicts
+      merged[dateKey] = preferDesktop
+        ? { ...localSession, ...desktopSession }
+        : { ...desktopSession, ...localSession };
+    }
+  }
+
+  return merged;
+}
+
 // Sync with desktop
 export async function syncWithDesktop(): Promise<{ success: boolean; message: string }> {
   const desktop = getStoredDesktopInfo();
@@ -124,25 +280,37 @@ export async function syncWithDesktop(): Promise<{ success: boolean; message: st
 
   try {
     // Try cached IP first (fast)
-    let desktopUrl = `http://${desktop.lastKnownIp}:${desktop.port}`;
-    const deviceId = await tryPing(desktop.lastKnownIp, desktop.port, desktop.deviceId);
-
-    // If cached IP failed, re-discover
-    if (!deviceId) {
-      const newIp = await discoverByDeviceId(desktop.deviceId, desktop.port, desktop.lastKnownIp);
+    let desktopUrl = desktopBaseUrl(desktop.lastKnownIp, desktop.port);
+    const reachable = await tryPing(desktop.lastKnownIp, desktop.port, desktop);
+
+    // If cached IP failed, try the other paired addresses, then re-discover
+    if (!reachable) {
+      let newIp: string | null = null;
+      for (const ip of desktop.candidateIps ?? []) {
+        if (ip === desktop.lastKnownIp) continue;
+        if (await tryPing(ip, desktop.port, desktop)) {
+          newIp = ip;
+          break;
+        }
+      }
+      newIp ??= await discoverByDeviceId(desktop);
       if (newIp) {
         updateCachedIp(newIp);
-        desktopUrl = `http://${newIp}:${desktop.port}`;
+        desktopUrl = desktopBaseUrl(newIp, desktop.port);
       } else {
         clearTimeout(timeoutId);
         return { success: false, message: 'Desktop not reachable' };
       }
     }
 
+    // Sessions travel sealed when pairing handed us the end-to-end key
+    const e2e = desktop.e2eKey ? await importE2eKey(desktop.e2eKey) : null;
+
     // Fetch sessions from desktop
-    const response = await fetch(`${desktopUrl}/api/sync/sessions`, {
+    const response = await fetch(`${desktopUrl}/api/v1/sessions`, {
       headers: {
         'Authorization': `Bearer ${desktop.authToken}`,
+        ...(e2e ? { 'X-E2E': '1' } : {}),
       },
       signal: controller.signal,
     });
@@ -155,58 +323,66 @@ export async function syncWithDesktop(): Promise<{ success: boolean; message: st
       return { success: false, message: `Sync failed: ${response.statusText}` };
     }
 
-    const desktopSessions: WorkoutData = await response.json();
-
-    // Deep merge: combine local and desktop data
-    // For the same date, merge at the exercise key level (both sides keep their exercises)
-    // logged_at and week_number: prefer whichever was logged later
-    const localData = loadWorkoutData();
-    const merged: WorkoutData = { ...localData };
-
-    for (const [dateKey, desktopSession] of Object.entries(desktopSessions)) {
-      const localSession = localData[dateKey];
-      if (!localSession) {
-        // Desktop has a date we don't — take it as-is
-        merged[dateKey] = desktopSession;
+    const received: Record<string, unknown> = await response.json();
+    const desktopSessions: WorkoutData = {};
+    for (const [dateKey, value] of Object.entries(received)) {
+      if (e2e && !isEnvelope(value)) {
+        // With a key, everything arrives sealed: plaintext was injected on the way
+        clearTimeout(timeoutId);
+        return { success: false, message: 'Desktop sent unencrypted data, sync refused' };
+      }
+      if (!isEnvelope(value)) {
+        desktopSessions[dateKey] = value as WorkoutSession;
+      } else if (e2e) {
+        desktopSessions[dateKey] = await openSession(e2e, dateKey, value);
       } else {
-        // Both have this date — merge exercise keys from both sides.
-        // For shared keys, prefer whichever session was logged later.
-        const localTime = new Date(localSession.logged_at || 0).getTime();
-        const desktopTime = new Date(desktopSession.logged_at || 0).getTime();
-        const preferDesktop = desktopTime >= localTime;
-        // Spread: all keys from both sides, newer session wins on conflicts
-        merged[dateKey] = preferDesktop
-          ? { ...localSession, ...desktopSession }
-          : { ...desktopSession, ...localSession };
+        clearTimeout(timeoutId);
+        return { success: false, message: 'Desktop requires encryption (unpair and re-pair)' };
       }
     }
 
+    const localData = loadWorkoutData();
+    const merged = mergeSessions(localData, desktopSessions);
     saveWorkoutData(merged);
 
     // Push sessions that desktop doesn't have (or that we have newer data for)
+    // in one batch request, applied by the desktop in a single transaction
+    const uploads = Object.entries(localData)
+      .filter(([dateKey, session]) => {
+        const desktopSession = desktopSessions[dateKey];
+        return !desktopSession ||
+          new Date(session.logged_at || 0) > new Date(desktopSession.logged_at || 0);
+      })
+      .map(([dateKey, session]) => ({ dateKey, session }));
+    const payload = await Promise.all(
+      uploads.map(async ({ dateKey, session }) => ({
+        dateKey,
+        session: e2e ? await sealSession(e2e, dateKey, session) : session,
+      })),
+    );
+
     const uploadErrors: string[] = [];
-    for (const [dateKey, session] of Object.entries(localData)) {
-      const desktopSession = desktopSessions[dateKey];
-      const shouldUpload = !desktopSession ||
-        new Date(session.logged_at || 0) > new Date(desktopSession.logged_at || 0);
-
-      if (shouldUpload) {
-        try {
-          const uploadRes = await fetch(`${desktopUrl}/api/sync/session`, {
-            method: 'POST',
-            headers: {
-              'Authorization': `Bearer ${desktop.authToken}`,
-              'Content-Type': 'application/json',
-            },
-            body: JSON.stringify({ dateKey, session }),
-            signal: controller.signal,
-          });
-          if (!uploadRes.ok) {
-            uploadErrors.push(dateKey);
+    if (uploads.length > 0) {
+      try {
+        const uploadRes = await fetch(`${desktopUrl}/api/v1/sessions`, {
+          method: 'POST',
+          headers: {
+            'Authorization': `Bearer ${desktop.authToken}`,
+            'Content-Type': 'application/json',
+          },
+          body: JSON.stringify({ sessions: payload }),
+          signal: controller.signal,
+        });
+        if (uploadRes.ok) {
+          const { results } = await uploadRes.json() as { results: { dateKey: string; status: string }[] };
+          for (const result of results) {
+            if (result.status !== 'applied') uploadErrors.push(result.dateKey);
           }
-        } catch {
-          uploadErrors.push(dateKey);
+        } else {
+          uploadErrors.push(...uploads.map((upload) => upload.dateKey));
         }
+      } catch {
+        uploadErrors.push(...uploads.map((upload) => upload.dateKey));
       }
     }
 
@@ -233,7 +409,8 @@ export async function verifyPairing(info: DesktopInfo): Promise<{ ok: boolean; e
     const controller = new AbortController();
     const timeout = setTimeout(() => controller.abort(), 5000);
 
-    const response = await fetch(`http://${info.lastKnownIp}:${info.port}/api/ping`, {
+    const nonce = randomNonce();
+    const response = await fetch(`${desktopBaseUrl(info.lastKnownIp, info.port)}/api/v1/ping?nonce=${nonce}`, {
       signal: controller.signal,
       mode: 'cors',
     });
@@ -244,10 +421,13 @@ export async function verifyPairing(info: DesktopInfo): Promise<{ ok: boolean; e
       return { ok: false, error: 'Desktop responded with an error' };
     }
 
-    const { deviceId } = await response.json();
-    if (deviceId !== info.deviceId) {
+    const ping: PingResponse = await response.json();
+    if (ping.deviceId !== info.deviceId) {
       return { ok: false, error: 'Device ID mismatch — wrong device?' };
     }
+    if (!(await isAuthenticPing(ping, nonce, info))) {
+      return { ok: false, error: 'Desktop could not prove it holds the pairing secret — scan the QR code again' };
+    }
 
     return { ok: true };
   } catch (err) {
@@ -258,22 +438,158 @@ export async function verifyPairing(info: DesktopInfo): Promise<{ ok: boolean; e
   }
 }
 
-// Parse QR code data (format: https://traindaily.vercel.app/pair?deviceId=...&ip=...&port=...&secret=...)
+// Offline sync bundle, a .tdbundle file (src-tauri/src/bundle/mod.rs), for
+// when the phone never shares a network with the desktop
+interface Bundle {
+  format: string;
+  version: number;
+  deviceId: string;
+  createdAt: string;
+  after: number;
+  cursor: number;
+  nonce: string | null;
+  payload: string;
+  signature: string;
+}
+
+interface FeedChange {
+  dateKey: string;
+  session: WorkoutSession;
+  changedAt: string;
+  origin: string;
+}
+
+const BUNDLE_FORMAT = 'traindaily-bundle';
+const BUNDLE_VERSION = 1;
+const BUNDLE_KEY_PURPOSE = 'traindaily-bundle-v1';
+// Who wrote a phone bundle; the desktop checks it against its own secret
+const BUNDLE_DEVICE_ID = 'phone';
+
+function bundleHeader(bundle: Bundle): string {
+  return [`${bundle.format}-v${bundle.version}`, bundle.deviceId, bundle.createdAt, bundle.after, bundle.cursor].join('\n');
+}
+
+function bundleSigningPayload(bundle: Bundle): string {
+  return [bundleHeader(bundle), bundle.nonce ?? '', bundle.payload].join('\n');
+}
+
+// AES-256-GCM key from HKDF-SHA256 over the pairing secret, like crypto::derive_key
+async function bundleKey(secret: string): Promise<CryptoKey> {
+  const encoder = new TextEncoder();
+  const material = await crypto.subtle.importKey('raw', encoder.encode(secret), 'HKDF', false, ['deriveKey']);
+  return crypto.subtle.deriveKey(
+    { name: 'HKDF', hash: 'SHA-256', salt: new Uint8Array(), info: encoder.encode(BUNDLE_KEY_PURPOSE) },
+    material,
+    { name: 'AES-GCM', length: 256 },
+    false,
+    ['encrypt', 'decrypt'],
+  );
+}
+
+// Every local session as an encrypted bundle signed with the pairing secret
+export async function exportBundle(): Promise<File> {
+  const desktop = getStoredDesktopInfo();
+  if (!desktop) throw new Error('Desktop not paired');
+
+  const createdAt = new Date().toISOString();
+  const changes: FeedChange[] = Object.entries(loadWorkoutData()).map(([dateKey, session]) => ({
+    dateKey,
+    session,
+    changedAt: session.logged_at || createdAt,
+    origin: BUNDLE_DEVICE_ID,
+  }));
+
+  const bundle: Bundle = {
+    format: BUNDLE_FORMAT,
+    version: BUNDLE_VERSION,
+    deviceId: BUNDLE_DEVICE_ID,
+    createdAt,
+    after: 0,
+    cursor: 0,
+    nonce: null,
+    payload: '',
+    signature: '',
+  };
+  const nonce = crypto.getRandomValues(new Uint8Array(12));
+  const ciphertext = await crypto.subtle.encrypt(
+    { name: 'AES-GCM', iv: nonce, additionalData: new TextEncoder().encode(bundleHeader(bundle)) },
+    await bundleKey(desktop.authToken),
+    new TextEncoder().encode(JSON.stringify(changes)),
+  );
+  bundle.nonce = bytesToBase64(nonce);
+  bundle.payload = bytesToBase64(new Uint8Array(ciphertext));
+  bundle.signature = await hmacHex(desktop.authToken, bundleSigningPayload(bundle));
+
+  const name = `traindaily-${BUNDLE_DEVICE_ID}-${createdAt.replace(/[-:]/g, '').slice(0, 15)}.tdbundle`;
+  return new File([JSON.stringify(bundle)], name, { type: 'application/json' });
+}
+
+// Verify a bundle written by the paired desktop and merge its sessions
+export async function importBundle(file: File): Promise<{ success: boolean; message: string }> {
+  const desktop = getStoredDesktopInfo();
+  if (!desktop) return { success: false, message: 'Desktop not paired' };
+
+  let bundle: Bundle;
+  try {
+    bundle = JSON.parse(await file.text());
+  } catch {
+    return { success: false, message: 'Not a TrainDaily bundle' };
+  }
+  if (bundle.format !== BUNDLE_FORMAT || bundle.version !== BUNDLE_VERSION) {
+    return { success: false, message: 'Not a TrainDaily bundle (or from a newer app)' };
+  }
+  if (bundle.deviceId !== desktop.deviceId ||
+      (await hmacHex(desktop.authToken, bundleSigningPayload(bundle))) !== bundle.signature) {
+    return { success: false, message: 'Bundle was not written by the paired desktop' };
+  }
+
+  try {
+    let plaintext = base64ToBytes(bundle.payload);
+    if (bundle.nonce) {
+      plaintext = new Uint8Array(await crypto.subtle.decrypt(
+        { name: 'AES-GCM', iv: base64ToBytes(bundle.nonce), additionalData: new TextEncoder().encode(bundleHeader(bundle)) },
+        await bundleKey(desktop.authToken),
+        plaintext,
+      ));
+    }
+    const changes: FeedChange[] = JSON.parse(new TextDecoder().decode(plaintext));
+    const received: WorkoutData = {};
+    for (const change of changes) received[change.dateKey] = change.session;
+
+    saveWorkoutData(mergeSessions(loadWorkoutData(), received));
+    return { success: true, message: `Imported ${changes.length} session(s)` };
+  } catch {
+    return { success: false, message: 'Corrupt bundle' };
+  }
+}
+
+// Parse QR code data (format: https://traindaily.vercel.app/pair?deviceId=...&ip=...&ips=...&port=...&fp=...#secret=...&key=...)
 export function parseQRData(url: string): DesktopInfo | null {
   try {
     const parsed = new URL(url);
     const deviceId = parsed.searchParams.get('deviceId');
     const ip = parsed.searchParams.get('ip');
     const port = parsed.searchParams.get('port');
-    const token = parsed.searchParams.get('secret') || parsed.searchParams.get('token'); // Support both
+    // Secrets are in the fragment; older desktops put them in the query
+    const secrets = new URLSearchParams(parsed.hash.slice(1));
+    const secret = (name: string) => secrets.get(name) || parsed.searchParams.get(name);
+    const token = secret('secret') || secret('token'); // Support both
 
     if (!deviceId || !ip || !port || !token) return null;
 
+    // ips: all candidate addresses (newer desktops); ip stays the best one
+    const candidateIps = (parsed.searchParams.get('ips') || ip).split(',').filter(Boolean);
+    const fingerprint = parsed.searchParams.get('fp') || undefined;
+    const e2eKey = secret('key') || undefined;
+
     return {
       deviceId,
       lastKnownIp: ip,
       port: parseInt(port),
       authToken: token,
+      candidateIps,
+      fingerprint,
+      e2eKey,
     };
   } catch {
     return null;
@@ -1,6 +1,6 @@
 import { useState, useCallback, useRef, useEffect, useMemo } from 'react';
-import { WorkoutState, WorkoutData, ExerciseKey, Exercise } from '../lib/types';
-import { PUSH_EXERCISES, PULL_EXERCISES, LEGS_EXERCISES, REST_DURATION } from '../lib/constants';
+import { WorkoutState, WorkoutData, WorkoutType, ExerciseKey, Exercise } from '../lib/types';
+import { PUSH_EXERCISES, REST_DURATION, getExercisesForWorkout } from '../lib/constants';
 import { formatDateKey, getWeekNumber, getSetsForWeek } from '../lib/workout-utils';
 import { getTargets } from '../lib/progression';
 import { getWorkoutType } from '../lib/schedule';
@@ -8,6 +8,8 @@ import type { StorageAdapter } from '../lib/storage-interface';
 
 export interface UseWorkoutOptions {
   date: Date;
+  /** Today's workout from the platform's schedule (default: getWorkoutType) */
+  workoutType?: WorkoutType;
   storageAdapter: StorageAdapter;
   audioCallbacks?: {
     unlockAudio?: () => void;
@@ -60,7 +62,7 @@ export interface UseWorkoutReturn {
 }
 
 export function useWorkout(options: UseWorkoutOptions): UseWorkoutReturn {
-  const { date, storageAdapter, audioCallbacks = {}, onWakeLockRequest, onWakeLockRelease, onHistoryPush, devTools } = options;
+  const { date, workoutType: scheduledType, storageAdapter, audioCallbacks = {}, onWakeLockRequest, onWakeLockRelease, onHistoryPush, devTools } = options;
 
   const [state, setState] = useState<WorkoutState>('idle');
   const [exerciseIndex, setExerciseIndex] = useState(0);
@@ -113,10 +115,10 @@ export function useWorkout(options: UseWorkoutOptions): UseWorkoutReturn {
   const setsPerExercise = getSetsForWeek(weekNumber);
 
   const { workoutType, exercises } = useMemo(() => {
-    const wt = getWorkoutType(date);
-    const exs = wt === 'push' ? PUSH_EXERCISES : wt === 'pull' ? PULL_EXERCISES : wt === 'legs' ? LEGS_EXERCISES : PUSH_EXERCISES;
+    const wt = scheduledType ?? getWorkoutType(date);
+    const exs = wt === 'rest' ? PUSH_EXERCISES : getExercisesForWorkout(wt);
     return { workoutType: wt, exercises: exs };
-  }, [date]);
+  }, [date, scheduledType]);
 
   const currentExercise = exercises[exerciseIndex];
   const targets = currentExercise ? getTargets(currentExercise.key, weekNumber, date, data) : [];
@@ -1,4 +1,4 @@
-import { Exercise, MobilityExercise, MicroBreakExercise } from './types';
+import { Exercise, MobilityExercise, MicroBreakExercise, WorkoutType } from './types';
 
 // PUSH DAY EXERCISES
 export const PUSH_EXERCISES: Exercise[] = [
@@ -111,6 +111,25 @@ export const LEGS_EXERCISES: Exercise[] = [
 // All exercises combined
 export const EXERCISES: Exercise[] = [...PUSH_EXERCISES, ...PULL_EXERCISES, ...LEGS_EXERCISES];
 
+// Exercises for a workout; combined days take the first exercises of each group
+export function getExercisesForWorkout(workoutType: WorkoutType): Exercise[] {
+  switch (workoutType) {
+    case 'push':
+      return PUSH_EXERCISES;
+    case 'pull':
+      return PULL_EXERCISES;
+    case 'legs':
+    case 'lower':
+      return LEGS_EXERCISES;
+    case 'upper':
+      return [...PUSH_EXERCISES.slice(0, 2), ...PULL_EXERCISES.slice(0, 2)];
+    case 'full_body':
+      return [PUSH_EXERCISES[0], PULL_EXERCISES[0], ...LEGS_EXERCISES.slice(0, 2)];
+    case 'rest':
+      return [];
+  }
+}
+
 export const STORAGE_KEY = 'traindaily_sessions';
 export const FIRST_SESSION_KEY = 'traindaily_first_session';
 export const MOBILITY_DONE_KEY = 'traindaily_mobility_done';
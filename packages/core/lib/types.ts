@@ -21,7 +21,9 @@ export type LegsExerciseKey =
 
 export type ExerciseKey = PushExerciseKey | PullExerciseKey | LegsExerciseKey;
 
-export type WorkoutType = 'push' | 'pull' | 'legs' | 'rest';
+// push / pull / legs: the default PPL split; upper / lower / full_body come
+// from other schedule templates (desktop) and reuse the same exercises
+export type WorkoutType = 'push' | 'pull' | 'legs' | 'upper' | 'lower' | 'full_body' | 'rest';
 
 export interface Exercise {
   key: ExerciseKey;
@@ -0,0 +1,307 @@
+/**
+ * FIT Encoder
+ *
+ * Just enough of the Garmin FIT protocol for a strength-training activity
+ * - file_id, timer start event, one set message per set, lap, session,
+ *   timer stop event and activity, in that order
+ * - Little-endian definitions, written the first time a message layout
+ *   appears; local message types are handed out per layout
+ * - 14-byte header and trailing file CRC (FIT CRC-16)
+ */
+
+use super::{Activity, Exercise};
+use chrono::{DateTime, Utc};
+
+const PROTOCOL_VERSION: u8 = 0x10;
+const PROFILE_VERSION: u16 = 2132;
+const HEADER_SIZE: u8 = 14;
+
+/// FIT timestamps count seconds from 1989-12-31T00:00:00Z
+const FIT_EPOCH_OFFSET: i64 = 631_065_600;
+
+// Global message numbers
+const MESG_FILE_ID: u16 = 0;
+const MESG_SESSION: u16 = 18;
+const MESG_LAP: u16 = 19;
+const MESG_EVENT: u16 = 21;
+const MESG_ACTIVITY: u16 = 34;
+const MESG_SET: u16 = 225;
+
+// Field numbers shared by most messages
+const FIELD_TIMESTAMP: u8 = 253;
+const FIELD_SET_TIMESTAMP: u8 = 254;
+
+// Profile enums
+const FILE_ACTIVITY: u8 = 4;
+const MANUFACTURER_DEVELOPMENT: u16 = 255;
+const EVENT_TIMER: u8 = 0;
+const EVENT_SESSION: u8 = 8;
+const EVENT_LAP: u8 = 9;
+const EVENT_ACTIVITY: u8 = 26;
+const EVENT_TYPE_START: u8 = 0;
+const EVENT_TYPE_STOP: u8 = 1;
+const EVENT_TYPE_STOP_ALL: u8 = 4;
+const SPORT_TRAINING: u8 = 10;
+const SUB_SPORT_STRENGTH_TRAINING: u8 = 20;
+const SET_TYPE_ACTIVE: u8 = 1;
+const ACTIVITY_MANUAL: u8 = 0;
+
+// exercise_category values
+const CATEGORY_CALF_RAISE: u16 = 1;
+const CATEGORY_CURL: u16 = 7;
+const CATEGORY_LEG_CURL: u16 = 15;
+const CATEGORY_LUNGE: u16 = 17;
+const CATEGORY_PUSH_UP: u16 = 22;
+const CATEGORY_ROW: u16 = 23;
+const CATEGORY_SHOULDER_PRESS: u16 = 24;
+const CATEGORY_SQUAT: u16 = 28;
+const CATEGORY_TRICEPS_EXTENSION: u16 = 30;
+const CATEGORY_UNKNOWN: u16 = 65534;
+
+const CRC_TABLE: [u16; 16] = [
+    0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401,
+    0xA001, 0x6C00, 0x7800, 0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
+];
+
+/// Field value with its FIT base type
+#[derive(Clone, Copy)]
+enum Value {
+    Enum(u8),
+    Uint8(u8),
+    Uint16(u16),
+    Uint32(u32),
+    Uint32z(u32),
+}
+
+impl Value {
+    fn base_type(self) -> u8 {
+        match self {
+            Value::Enum(_) => 0x00,
+            Value::Uint8(_) => 0x02,
+            Value::Uint16(_) => 0x84,
+            Value::Uint32(_) => 0x86,
+            Value::Uint32z(_) => 0x8C,
+        }
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        match self {
+            Value::Enum(value) | Value::Uint8(value) => out.push(value),
+            Value::Uint16(value) => out.extend_from_slice(&value.to_le_bytes()),
+            Value::Uint32(value) | Value::Uint32z(value) => out.extend_from_slice(&value.to_le_bytes()),
+        }
+    }
+
+    fn size(self) -> u8 {
+        match self {
+            Value::Enum(_) | Value::Uint8(_) => 1,
+            Value::Uint16(_) => 2,
+            Value::Uint32(_) | Value::Uint32z(_) => 4,
+        }
+    }
+}
+
+/// (global message, [(field number, size, base type)])
+type Layout = (u16, Vec<(u8, u8, u8)>);
+
+#[derive(Default)]
+struct Encoder {
+    records: Vec<u8>,
+    /// Index = local message type
+    layouts: Vec<Layout>,
+}
+
+impl Encoder {
+    fn message(&mut self, global: u16, fields: &[(u8, Value)]) {
+        let layout: Layout = (
+            global,
+            fields.iter().map(|(num, value)| (*num, value.size(), value.base_type())).collect(),
+        );
+        let local = match self.layouts.iter().position(|known| *known == layout) {
+            Some(local) => local as u8,
+            None => {
+                let local = self.layouts.len() as u8;
+                // Definition: header, reserved, little-endian, global number, fields
+                self.records.push(0x40 | local);
+                self.records.extend_from_slice(&[0, 0]);
+                self.records.extend_from_slice(&global.to_le_bytes());
+                self.records.push(layout.1.len() as u8);
+                for (num, size, base_type) in &layout.1 {
+                    self.records.extend_from_slice(&[*num, *size, *base_type]);
+                }
+                self.layouts.push(layout);
+                local
+            }
+        };
+
+        self.records.push(local);
+        for (_, value) in fields {
+            value.write(&mut self.records);
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut file = Vec::with_capacity(HEADER_SIZE as usize + self.records.len() + 2);
+        file.push(HEADER_SIZE);
+        file.push(PROTOCOL_VERSION);
+        file.extend_from_slice(&PROFILE_VERSION.to_le_bytes());
+        file.extend_from_slice(&(self.records.len() as u32).to_le_bytes());
+        file.extend_from_slice(b".FIT");
+        let header_crc = crc(&file);
+        file.extend_from_slice(&header_crc.to_le_bytes());
+
+        file.extend_from_slice(&self.records);
+        let file_crc = crc(&file);
+        file.extend_from_slice(&file_crc.to_le_bytes());
+        file
+    }
+}
+
+/// FIT CRC-16 (reflected 0xA001, nibble table)
+fn crc(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |mut crc, &byte| {
+        for nibble in [byte & 0x0F, byte >> 4] {
+            let tmp = CRC_TABLE[(crc & 0x0F) as usize];
+            crc = (crc >> 4) & 0x0FFF;
+            crc = crc ^ tmp ^ CRC_TABLE[nibble as usize];
+        }
+        crc
+    })
+}
+
+fn timestamp(value: DateTime<Utc>) -> Value {
+    Value::Uint32((value.timestamp() - FIT_EPOCH_OFFSET).max(0) as u32)
+}
+
+/// Seconds in the profile's 1/1000 s scale
+fn millis(secs: u32) -> Value {
+    Value::Uint32(secs.saturating_mul(1000))
+}
+
+fn category(exercise: &Exercise) -> u16 {
+    match exercise.key.as_str() {
+        "trx_pushup" | "regular_pushup" => CATEGORY_PUSH_UP,
+        "pike_pushup" => CATEGORY_SHOULDER_PRESS,
+        "tricep_extension" => CATEGORY_TRICEPS_EXTENSION,
+        "trx_row" | "face_pull" | "inverted_row" => CATEGORY_ROW,
+        "bicep_curl" => CATEGORY_CURL,
+        "bulgarian_split_squat" => CATEGORY_LUNGE,
+        "pistol_squat_progression" => CATEGORY_SQUAT,
+        "trx_hamstring_curl" => CATEGORY_LEG_CURL,
+        "calf_raise" => CATEGORY_CALF_RAISE,
+        _ => CATEGORY_UNKNOWN,
+    }
+}
+
+/// Device serial: the activity start, so re-exports of the same session
+/// are recognised as the same file
+fn serial_number(activity: &Activity) -> u32 {
+    (activity.start.timestamp() as u32).max(1)
+}
+
+pub(super) fn encode(activity: &Activity) -> Vec<u8> {
+    let start = timestamp(activity.start);
+    let end = timestamp(activity.end());
+    let total = millis(activity.total_secs());
+    let mut fit = Encoder::default();
+
+    fit.message(MESG_FILE_ID, &[
+        (0, Value::Enum(FILE_ACTIVITY)),
+        (1, Value::Uint16(MANUFACTURER_DEVELOPMENT)),
+        (2, Value::Uint16(0)),
+        (3, Value::Uint32z(serial_number(activity))),
+        (4, start),
+    ]);
+    fit.message(MESG_EVENT, &[
+        (FIELD_TIMESTAMP, start),
+        (0, Value::Enum(EVENT_TIMER)),
+        (1, Value::Enum(EVENT_TYPE_START)),
+    ]);
+
+    for (index, set) in activity.sets().enumerate() {
+        let set_end = set.start + chrono::Duration::seconds(i64::from(activity.set_duration_secs));
+        fit.message(MESG_SET, &[
+            (FIELD_SET_TIMESTAMP, timestamp(set_end)),
+            (0, millis(activity.set_duration_secs)),
+            (3, Value::Uint16(set.reps.min(u64::from(u16::MAX - 1)) as u16)),
+            (5, Value::Uint8(SET_TYPE_ACTIVE)),
+            (6, timestamp(set.start)),
+            (7, Value::Uint16(category(set.exercise))),
+            (10, Value::Uint16(index as u16)),
+        ]);
+    }
+
+    fit.message(MESG_LAP, &[
+        (FIELD_TIMESTAMP, end),
+        (0, Value::Enum(EVENT_LAP)),
+        (1, Value::Enum(EVENT_TYPE_STOP)),
+        (2, start),
+        (7, total),
+        (8, total),
+        (25, Value::Enum(SPORT_TRAINING)),
+    ]);
+    fit.message(MESG_SESSION, &[
+        (FIELD_TIMESTAMP, end),
+        (0, Value::Enum(EVENT_SESSION)),
+        (1, Value::Enum(EVENT_TYPE_STOP)),
+        (2, start),
+        (5, Value::Enum(SPORT_TRAINING)),
+        (6, Value::Enum(SUB_SPORT_STRENGTH_TRAINING)),
+        (7, total),
+        (8, total),
+        (25, Value::Uint16(0)),
+        (26, Value::Uint16(1)),
+    ]);
+    fit.message(MESG_EVENT, &[
+        (FIELD_TIMESTAMP, end),
+        (0, Value::Enum(EVENT_TIMER)),
+        (1, Value::Enum(EVENT_TYPE_STOP_ALL)),
+    ]);
+    fit.message(MESG_ACTIVITY, &[
+        (FIELD_TIMESTAMP, end),
+        (0, total),
+        (1, Value::Uint16(1)),
+        (2, Value::Enum(ACTIVITY_MANUAL)),
+        (3, Value::Enum(EVENT_ACTIVITY)),
+        (4, Value::Enum(EVENT_TYPE_STOP)),
+    ]);
+
+    fit.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::WorkoutType;
+    use serde_json::json;
+
+    #[test]
+    fn test_encode_activity_file() {
+        let session = json!({
+            "trx_pushup": [10, 8],
+            "tricep_extension": [12],
+            "logged_at": "2026-03-02T07:15:00Z",
+        });
+        let activity = Activity::from_session(&session, WorkoutType::Push, 45).unwrap();
+        let file = encode(&activity);
+
+        assert_eq!(file[0], HEADER_SIZE);
+        assert_eq!(&file[8..12], b".FIT");
+        let data_size = u32::from_le_bytes(file[4..8].try_into().unwrap()) as usize;
+        assert_eq!(file.len(), HEADER_SIZE as usize + data_size + 2);
+        // A CRC over data followed by its own CRC comes out zero
+        assert_eq!(crc(&file[..HEADER_SIZE as usize]), 0);
+        assert_eq!(crc(&file), 0);
+
+        // Messages with the same layout share one definition
+        let definitions = {
+            let mut fit = Encoder::default();
+            fit.message(MESG_EVENT, &[(0, Value::Enum(0))]);
+            fit.message(MESG_EVENT, &[(0, Value::Enum(1))]);
+            fit.layouts.len()
+        };
+        assert_eq!(definitions, 1);
+        assert_eq!(category(&activity.exercises[0]), CATEGORY_TRICEPS_EXTENSION);
+        assert_eq!(category(&activity.exercises[1]), CATEGORY_PUSH_UP);
+    }
+}
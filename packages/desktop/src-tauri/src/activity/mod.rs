@@ -0,0 +1,274 @@
+/**
+ * Activity Export
+ *
+ * Logged sessions as activity files for watch ecosystems (Garmin Connect,
+ * Strava, TrainingPeaks...), so they count towards training load
+ * - FIT strength-training activity with one set message per set: reps and
+ *   exercise category (see fit.rs)
+ * - TCX fallback for tools without FIT import: one lap per exercise, reps
+ *   in the lap notes (see tcx.rs)
+ * - Sets are laid out back to back, ending at the session's logged_at, each
+ *   lasting the configured per-set duration
+ * - Batch export of a date range into a folder, one file per session and format
+ */
+
+mod fit;
+mod tcx;
+
+use crate::db::{Database, WorkoutSession};
+use crate::schedule::{Schedule, WorkoutType};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::path::{Path, PathBuf};
+
+/// Seconds each set takes in exported activities
+pub const SET_DURATION_SETTING: &str = "activity_set_duration_secs";
+const DEFAULT_SET_DURATION_SECS: u32 = 45;
+const MAX_SET_DURATION_SECS: u32 = 3600;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityFormat {
+    Fit,
+    Tcx,
+}
+
+impl ActivityFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ActivityFormat::Fit => "fit",
+            ActivityFormat::Tcx => "tcx",
+        }
+    }
+
+    fn encode(self, activity: &Activity) -> Vec<u8> {
+        match self {
+            ActivityFormat::Fit => fit::encode(activity),
+            ActivityFormat::Tcx => tcx::encode(activity).into_bytes(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityExport {
+    pub written: Vec<PathBuf>,
+    /// Sessions in the range without a logged_at or any completed set
+    pub skipped: usize,
+}
+
+/// One exercise of a session: reps of each completed set
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Exercise {
+    pub key: String,
+    pub reps: Vec<u64>,
+}
+
+/// Exercises with at least one completed set, by key
+///
+/// Exercise keys map to arrays of reps per set; everything else is metadata.
+pub fn exercises(session: &WorkoutSession) -> Vec<Exercise> {
+    let Some(fields) = session.as_object() else {
+        return Vec::new();
+    };
+
+    let mut exercises: Vec<Exercise> = fields
+        .iter()
+        .filter_map(|(key, value)| {
+            let reps: Vec<u64> = value
+                .as_array()?
+                .iter()
+                .filter_map(JsonValue::as_u64)
+                .filter(|&reps| reps > 0)
+                .collect();
+            (!reps.is_empty()).then(|| Exercise { key: key.clone(), reps })
+        })
+        .collect();
+    exercises.sort_by(|a, b| a.key.cmp(&b.key));
+    exercises
+}
+
+/// `trx_hamstring_curl` -> `TRX hamstring curl`
+pub fn exercise_name(key: &str) -> String {
+    let words: Vec<&str> = key
+        .split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| if word == "trx" { "TRX" } else { word })
+        .collect();
+    capitalize(&words.join(" "))
+}
+
+pub fn capitalize(value: &str) -> String {
+    let mut chars = value.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// A logged session laid out in time
+#[derive(Debug)]
+struct Activity {
+    start: DateTime<Utc>,
+    set_duration_secs: u32,
+    /// "Push", "Pull"... from the session, for names and notes
+    workout: String,
+    exercises: Vec<Exercise>,
+}
+
+/// One set with its place in the activity
+struct TimedSet<'a> {
+    exercise: &'a Exercise,
+    reps: u64,
+    start: DateTime<Utc>,
+}
+
+impl Activity {
+    /// None unless the session was logged and has a completed set;
+    /// `planned` names the workout when the session doesn't
+    fn from_session(session: &WorkoutSession, planned: WorkoutType, set_duration_secs: u32) -> Option<Self> {
+        let end = session
+            .get("logged_at")
+            .and_then(JsonValue::as_str)
+            .and_then(|value| DateTime::parse_from_rfc3339(value).ok())?
+            .with_timezone(&Utc);
+        let exercises = exercises(session);
+        if exercises.is_empty() {
+            return None;
+        }
+
+        let workout = session
+            .get("workout_type")
+            .and_then(JsonValue::as_str)
+            .map(capitalize)
+            .unwrap_or_else(|| planned.label().to_string());
+        let sets: usize = exercises.iter().map(|exercise| exercise.reps.len()).sum();
+
+        Some(Self {
+            start: end - Duration::seconds(i64::from(set_duration_secs) * sets as i64),
+            set_duration_secs,
+            workout,
+            exercises,
+        })
+    }
+
+    fn sets(&self) -> impl Iterator<Item = TimedSet<'_>> {
+        self.exercises
+            .iter()
+            .flat_map(|exercise| exercise.reps.iter().map(move |&reps| (exercise, reps)))
+            .enumerate()
+            .map(|(index, (exercise, reps))| TimedSet {
+                exercise,
+                reps,
+                start: self.start + Duration::seconds(i64::from(self.set_duration_secs) * index as i64),
+            })
+    }
+
+    fn total_secs(&self) -> u32 {
+        self.set_duration_secs * self.sets().count() as u32
+    }
+
+    fn end(&self) -> DateTime<Utc> {
+        self.start + Duration::seconds(i64::from(self.total_secs()))
+    }
+
+    fn name(&self) -> String {
+        format!("TrainDaily {}", self.workout)
+    }
+}
+
+/// Configured per-set duration (`activity_set_duration_secs`, default 45)
+pub fn set_duration(db: &Database) -> Result<u32> {
+    let Some(value) = db.get_setting(SET_DURATION_SETTING)? else {
+        return Ok(DEFAULT_SET_DURATION_SECS);
+    };
+    let secs: u32 = value
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid set duration '{}'", value))?;
+    if secs == 0 || secs > MAX_SET_DURATION_SECS {
+        bail!("Set duration must be between 1 and {} seconds", MAX_SET_DURATION_SECS);
+    }
+    Ok(secs)
+}
+
+/// Write every logged session from `from` to `to` (inclusive) into `folder`,
+/// as `traindaily-<date>.<ext>` per format
+pub fn export_range(
+    db: &Database,
+    from: NaiveDate,
+    to: NaiveDate,
+    folder: &Path,
+    formats: &[ActivityFormat],
+) -> Result<ActivityExport> {
+    if to < from {
+        bail!("Export range ends before it starts");
+    }
+    if formats.is_empty() {
+        bail!("No export format selected");
+    }
+
+    let set_duration_secs = set_duration(db)?;
+    let sessions = db.get_all_sessions()?;
+    let schedule = Schedule::load(db)?;
+    let mut export = ActivityExport { written: Vec::new(), skipped: 0 };
+
+    for date in from.iter_days().take_while(|date| *date <= to) {
+        let date_key = crate::schedule::date_key(date);
+        let Some(session) = sessions.get(&date_key) else {
+            continue;
+        };
+        let Some(activity) = Activity::from_session(session, schedule.workout_type(date), set_duration_secs) else {
+            export.skipped += 1;
+            continue;
+        };
+
+        for format in formats {
+            let path = folder.join(format!("traindaily-{}.{}", date_key, format.extension()));
+            std::fs::write(&path, format.encode(&activity))
+                .with_context(|| format!("Failed to write to {}", folder.display()))?;
+            export.written.push(path);
+        }
+    }
+
+    tracing::info!(
+        "Exported {} activity file(s) to {} ({} session(s) skipped)",
+        export.written.len(),
+        folder.display(),
+        export.skipped
+    );
+    Ok(export)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sets_end_at_logged_at() {
+        let session = json!({
+            "trx_row": [12, 10],
+            "face_pull": [15, 0, null],
+            "calf_raise": [],
+            "logged_at": "2026-03-03T07:30:00Z",
+            "workout_type": "pull",
+        });
+        let activity = Activity::from_session(&session, WorkoutType::Legs, 60).unwrap();
+
+        assert_eq!(activity.workout, "Pull");
+        assert_eq!(activity.exercises.iter().map(|e| e.key.as_str()).collect::<Vec<_>>(), ["face_pull", "trx_row"]);
+        assert_eq!(activity.total_secs(), 180);
+        assert_eq!(activity.start.to_rfc3339(), "2026-03-03T07:27:00+00:00");
+        assert_eq!(activity.end().to_rfc3339(), "2026-03-03T07:30:00+00:00");
+
+        let starts: Vec<_> = activity.sets().map(|set| set.start.format("%H:%M").to_string()).collect();
+        assert_eq!(starts, ["07:27", "07:28", "07:29"]);
+
+        // Not logged yet
+        assert!(Activity::from_session(&json!({ "trx_row": [12] }), WorkoutType::Pull, 60).is_none());
+        assert_eq!(exercise_name("trx_hamstring_curl"), "TRX hamstring curl");
+    }
+}
@@ -0,0 +1,87 @@
+/**
+ * TCX Writer
+ *
+ * Training Center XML has no notion of sets, so each exercise becomes a lap
+ * covering its sets, with the reps in the lap notes
+ */
+
+use super::{exercise_name, Activity};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fmt::Write as _;
+
+const NAMESPACE: &str = "http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2";
+
+fn time(value: DateTime<Utc>) -> String {
+    value.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub(super) fn encode(activity: &Activity) -> String {
+    let mut xml = String::new();
+    let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(xml, r#"<TrainingCenterDatabase xmlns="{}">"#, NAMESPACE);
+    let _ = writeln!(xml, "  <Activities>");
+    let _ = writeln!(xml, r#"    <Activity Sport="Other">"#);
+    let _ = writeln!(xml, "      <Id>{}</Id>", time(activity.start));
+
+    let mut lap_start = activity.start;
+    for exercise in &activity.exercises {
+        let secs = activity.set_duration_secs * exercise.reps.len() as u32;
+        let sets: Vec<String> = exercise.reps.iter().map(u64::to_string).collect();
+        let notes = format!(
+            "{}: {} ({} reps)",
+            exercise_name(&exercise.key),
+            sets.join(" + "),
+            exercise.reps.iter().sum::<u64>()
+        );
+
+        let _ = writeln!(xml, r#"      <Lap StartTime="{}">"#, time(lap_start));
+        let _ = writeln!(xml, "        <TotalTimeSeconds>{}</TotalTimeSeconds>", secs);
+        let _ = writeln!(xml, "        <DistanceMeters>0</DistanceMeters>");
+        let _ = writeln!(xml, "        <Calories>0</Calories>");
+        let _ = writeln!(xml, "        <Intensity>Active</Intensity>");
+        let _ = writeln!(xml, "        <TriggerMethod>Manual</TriggerMethod>");
+        let _ = writeln!(xml, "        <Notes>{}</Notes>", escape(&notes));
+        let _ = writeln!(xml, "      </Lap>");
+
+        lap_start += chrono::Duration::seconds(i64::from(secs));
+    }
+
+    let _ = writeln!(xml, "      <Notes>{}</Notes>", escape(&activity.name()));
+    let _ = writeln!(xml, "    </Activity>");
+    let _ = writeln!(xml, "  </Activities>");
+    let _ = writeln!(xml, "</TrainingCenterDatabase>");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::WorkoutType;
+    use serde_json::json;
+
+    #[test]
+    fn test_one_lap_per_exercise() {
+        let session = json!({
+            "bulgarian_split_squat": [8, 8],
+            "calf_raise": [20],
+            "logged_at": "2026-03-04T18:00:00Z",
+        });
+        let activity = Activity::from_session(&session, WorkoutType::Legs, 30).unwrap();
+        let xml = encode(&activity);
+
+        assert!(xml.contains("<Id>2026-03-04T17:58:30Z</Id>"));
+        assert_eq!(xml.matches("<Lap ").count(), 2);
+        assert!(xml.contains(r#"<Lap StartTime="2026-03-04T17:58:30Z">"#));
+        assert!(xml.contains(r#"<Lap StartTime="2026-03-04T17:59:30Z">"#));
+        assert!(xml.contains("<Notes>Bulgarian split squat: 8 + 8 (16 reps)</Notes>"));
+        assert!(xml.contains("<Notes>TrainDaily Legs</Notes>"));
+    }
+}
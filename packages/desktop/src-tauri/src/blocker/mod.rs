@@ -2,24 +2,46 @@
  * App Blocker Module
  *
  * Full-screen overlay that blocks access to all apps on training days
- * until workout is logged. True "no excuses" enforcement.
+ * (as the schedule module has them) until the workout is logged.
+ * True "no excuses" enforcement.
  *
  * Features:
  * - Full-screen window (always on top, covers everything)
  * - Prevents Cmd+Tab, Cmd+Q (keyboard intercept)
- * - Only dismissible by logging workout
- * - Checks every 10 seconds on training days
+ * - Dismissed by logging the workout, or by an emergency unlock (see
+ *   unlock.rs), which suspends blocking for a while
+ * - When it comes up is the blocking policy (see policy.rs): per-day
+ *   windows, a grace-period warning window first, an optional deadline
+ * - Checks every 10 seconds on training days, and immediately when a
+ *   session is saved (from the desktop or a synced phone), the schedule
+ *   changes or a setting does
+ * - Publishes BlockerWarning / BlockerShown / BlockerCleared on the change bus
  */
 
+pub mod policy;
+pub mod unlock;
+
 use tauri::{Manager, WebviewUrl, WebviewWindowBuilder};
 use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Local, SecondsFormat};
+use tokio::sync::broadcast;
+use crate::changes::{ChangeBus, ChangeEvent, ChangeKind, ChangeOrigin};
 use crate::db::Database;
+use crate::schedule::{self, Schedule};
+use policy::{BlockerPolicy, Phase, ScreenTime};
 
 const CHECK_INTERVAL_SECS: u64 = 10;
 
+const WARNING_WINDOW: &str = "workout-warning";
+
 pub struct BlockerState {
     pub enabled: bool,
     pub window_open: bool,
+    pub warning_open: bool,
+    /// When today's blocking window opened (the grace period runs from here)
+    due_since: Option<DateTime<Local>>,
+    /// The emergency unlock in progress on the blocker screen
+    pub unlock_challenge: Option<unlock::Challenge>,
 }
 
 impl BlockerState {
@@ -27,6 +49,9 @@ impl BlockerState {
         Self {
             enabled: false,
             window_open: false,
+            warning_open: false,
+            due_since: None,
+            unlock_challenge: None,
         }
     }
 }
@@ -36,56 +61,202 @@ pub async fn start_blocker(
     app_handle: tauri::AppHandle,
     db: Arc<Mutex<Database>>,
     state: Arc<Mutex<BlockerState>>,
+    bus: ChangeBus,
 ) {
-    use tokio::time::{sleep, Duration};
+    use tokio::time::{interval, Duration, MissedTickBehavior};
+
+    // None once the bus is gone: the timer alone drives the checks then
+    let mut changes = Some(bus.subscribe());
+    // Carry on with today's screen time and window from before a relaunch
+    let (mut screen, due_since) = {
+        let db = db.lock().unwrap();
+        let today = Local::now().date_naive();
+        let due_since = policy::load_due_since(&db, today).unwrap_or_else(|e| {
+            tracing::warn!("Failed to load blocker state: {}", e);
+            None
+        });
+        (ScreenTime::load(&db, today).unwrap_or_default(), due_since)
+    };
+    state.lock().unwrap().due_since = due_since;
+
+    // Unlike a fresh sleep per loop, bus traffic doesn't push the next check back
+    let mut timer = interval(Duration::from_secs(CHECK_INTERVAL_SECS));
+    timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
     loop {
-        sleep(Duration::from_secs(CHECK_INTERVAL_SECS)).await;
+        let recheck = tokio::select! {
+            _ = timer.tick() => true,
+            change = next_change(&mut changes) => match change {
+                Ok(event) => match event.kind {
+                    ChangeKind::SettingChanged { key } => !policy::is_own_state(&key),
+                    kind => matches!(
+                        kind,
+                        ChangeKind::SessionSaved { .. }
+                            | ChangeKind::ScheduleChanged { .. }
+                            | ChangeKind::EmergencyUnlocked { .. }
+                    ),
+                },
+                // Re-check anyway: a lagged receiver may have missed a save
+                Err(broadcast::error::RecvError::Lagged(_)) => true,
+                Err(broadcast::error::RecvError::Closed) => {
+                    tracing::warn!("Change bus closed, blocker falls back to its timer");
+                    changes = None;
+                    false
+                }
+            },
+        };
+
+        // Count screen time on every wake-up, so a busy bus never looks like sleep
+        let now = Local::now();
+        let screen_secs = screen.tick(now.date_naive());
+        if let Err(e) = screen.save_if_due(&db.lock().unwrap()) {
+            tracing::warn!("Failed to save screen time: {}", e);
+        }
+        if !recheck {
+            continue;
+        }
 
-        // Check if today is a training day and workout not logged
-        let should_block = check_should_block(&db);
+        // Window calls run on the main thread, where commands may be waiting
+        // for the blocker state: never hold it across them. Only this task
+        // changes the open flags, so copies are safe to work on.
+        let (phase, mut warning_open, mut window_open) = {
+            let mut blocker_state = state.lock().unwrap();
+            let phase = check_phase(&db, &mut blocker_state, now, screen_secs);
+            (phase, blocker_state.warning_open, blocker_state.window_open)
+        };
 
-        let mut blocker_state = state.lock().unwrap();
+        match phase {
+            Phase::Warning { blocks_at } if !warning_open => {
+                if let Err(e) = show_warning_window(&app_handle, blocks_at) {
+                    tracing::error!("Failed to show blocker warning: {}", e);
+                } else {
+                    warning_open = true;
+                    let blocks_at = blocks_at.to_rfc3339_opts(SecondsFormat::Secs, false);
+                    publish(&bus, |date_key| ChangeKind::BlockerWarning { date_key, blocks_at });
+                }
+            }
+            Phase::Warning { .. } => {}
+            _ if warning_open => {
+                if let Err(e) = close_window(&app_handle, WARNING_WINDOW) {
+                    tracing::error!("Failed to hide blocker warning: {}", e);
+                } else {
+                    warning_open = false;
+                }
+            }
+            _ => {}
+        }
+
+        let should_block = phase == Phase::Blocked;
 
-        if should_block && !blocker_state.window_open {
+        if should_block && !window_open {
             // Show blocker window
             if let Err(e) = show_blocker_window(&app_handle) {
                 tracing::error!("Failed to show blocker window: {}", e);
             } else {
-                blocker_state.window_open = true;
+                window_open = true;
+                publish(&bus, |date_key| ChangeKind::BlockerShown { date_key });
             }
-        } else if !should_block && blocker_state.window_open {
+        } else if !should_block && window_open {
             // Hide blocker window
-            if let Err(e) = hide_blocker_window(&app_handle) {
+            if let Err(e) = close_window(&app_handle, "blocker") {
                 tracing::error!("Failed to hide blocker window: {}", e);
             } else {
-                blocker_state.window_open = false;
+                window_open = false;
+                publish(&bus, |date_key| ChangeKind::BlockerCleared { date_key });
             }
         }
+
+        let mut blocker_state = state.lock().unwrap();
+        blocker_state.warning_open = warning_open;
+        blocker_state.window_open = window_open;
     }
 }
 
-/// Check if we should block (training day + no workout logged)
-fn check_should_block(db: &Arc<Mutex<Database>>) -> bool {
-    use chrono::Datelike;
-
-    let now = chrono::Local::now();
-    let weekday = now.weekday().num_days_from_monday();
+async fn next_change(
+    changes: &mut Option<broadcast::Receiver<ChangeEvent>>,
+) -> Result<ChangeEvent, broadcast::error::RecvError> {
+    match changes {
+        Some(changes) => changes.recv().await,
+        None => std::future::pending().await,
+    }
+}
 
-    // Training days: Mon (0), Wed (2), Fri (4)
-    let is_training_day = weekday == 0 || weekday == 2 || weekday == 4;
+fn publish(bus: &ChangeBus, kind: impl FnOnce(String) -> ChangeKind) {
+    let date_key = chrono::Local::now().format("%Y-%m-%d").to_string();
+    bus.publish(ChangeEvent { origin: ChangeOrigin::Desktop, kind: kind(date_key) });
+}
 
-    if !is_training_day {
-        return false;
+/// Where today stands: clear on rest days, once the workout is logged and
+/// during an emergency unlock, otherwise as the blocking policy has it
+fn check_phase(
+    db: &Arc<Mutex<Database>>,
+    blocker_state: &mut BlockerState,
+    now: DateTime<Local>,
+    screen_secs: u64,
+) -> Phase {
+    let db_lock = db.lock().unwrap();
+    let saved = blocker_state.due_since;
+    let phase = current_phase(&db_lock, blocker_state, now, screen_secs);
+    if blocker_state.due_since != saved {
+        if let Err(e) = policy::save_due_since(&db_lock, blocker_state.due_since) {
+            tracing::warn!("Failed to save blocker state: {}", e);
+        }
     }
+    phase
+}
 
-    // Check if workout logged today
-    let date_key = now.format("%Y-%m-%d").to_string();
+fn current_phase(
+    db_lock: &Database,
+    blocker_state: &mut BlockerState,
+    now: DateTime<Local>,
+    screen_secs: u64,
+) -> Phase {
+    let today = now.date_naive();
 
-    let db_lock = db.lock().unwrap();
+    let schedule = match Schedule::load(db_lock) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            tracing::error!("Failed to load schedule: {}", e);
+            return Phase::Clear;
+        }
+    };
+
+    // Check if workout logged today
     let sessions = db_lock.get_all_sessions().unwrap_or_default();
+    if !schedule.is_training_day(today) || schedule::is_logged(&sessions, today) {
+        blocker_state.due_since = None;
+        return Phase::Clear;
+    }
+
+    // The policy starts over once the unlock runs out
+    match db_lock.emergency_unlock_until(now.with_timezone(&chrono::Utc)) {
+        Ok(Some(_)) => {
+            blocker_state.due_since = None;
+            return Phase::Clear;
+        }
+        Ok(None) => {}
+        Err(e) => tracing::error!("Failed to check emergency unlocks: {}", e),
+    }
+
+    let policy = match BlockerPolicy::load(db_lock) {
+        Ok(policy) => policy,
+        Err(e) => {
+            tracing::error!("Failed to load blocker policy: {}", e);
+            BlockerPolicy::default()
+        }
+    };
+
+    // A new day, or the policy moved the window
+    if blocker_state.due_since.is_some_and(|since| since.date_naive() != today)
+        || !policy.is_due(now, screen_secs)
+    {
+        blocker_state.due_since = None;
+    }
+    if blocker_state.due_since.is_none() && policy.is_due(now, screen_secs) {
+        blocker_state.due_since = Some(now);
+    }
 
-    !sessions.contains_key(&date_key)
+    policy.phase(now, blocker_state.due_since)
 }
 
 /// Show full-screen blocker window
@@ -119,11 +290,37 @@ fn show_blocker_window(app_handle: &tauri::AppHandle) -> Result<(), Box<dyn std:
     Ok(())
 }
 
-/// Hide blocker window
-fn hide_blocker_window(app_handle: &tauri::AppHandle) -> Result<(), Box<dyn std::error::Error>> {
-    if let Some(window) = app_handle.get_webview_window("blocker") {
+/// Small always-on-top heads-up for the grace period
+fn show_warning_window(
+    app_handle: &tauri::AppHandle,
+    blocks_at: DateTime<Local>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(window) = app_handle.get_webview_window(WARNING_WINDOW) {
+        window.show()?;
+        return Ok(());
+    }
+
+    let url = format!("/{}?blocksAt={}", WARNING_WINDOW, blocks_at.timestamp_millis());
+    WebviewWindowBuilder::new(app_handle, WARNING_WINDOW, WebviewUrl::App(url.into()))
+        .title("Workout Time")
+        .inner_size(360.0, 170.0)
+        .always_on_top(true)
+        .skip_taskbar(true)
+        .decorations(false)
+        .resizable(false)
+        .focused(false)
+        .build()?;
+
+    tracing::info!("Blocker warning shown, blocking at {}", blocks_at);
+
+    Ok(())
+}
+
+/// Close the blocker or warning window
+fn close_window(app_handle: &tauri::AppHandle, label: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(window) = app_handle.get_webview_window(label) {
         window.close()?;
-        tracing::info!("Blocker window closed");
+        tracing::info!("{} window closed", label);
     }
     Ok(())
 }
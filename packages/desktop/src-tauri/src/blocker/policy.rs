@@ -0,0 +1,296 @@
+/**
+ * Blocking Policy
+ *
+ * When, on a training day without a workout, the blocker comes up
+ * - Per-day window (Monday first): from a start hour, only after some
+ *   screen time today, or both; neither means from midnight
+ * - Grace period: a warning first, the full-screen block `grace_minutes` later
+ * - Optional hard deadline: from that hour the block is immediate, whatever
+ *   the screen time and without a grace period
+ * - Stored as JSON in the `blocker_policy` setting; without one the blocker
+ *   behaves as it always did (blocks from midnight, no warning)
+ * - Today's screen time and when the window opened are kept in settings too
+ *   (keyed by date), so relaunching the app resets neither
+ */
+
+use crate::changes::ChangeOrigin;
+use crate::db::Database;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+
+pub const POLICY_SETTING: &str = "blocker_policy";
+/// `<date> <secs>`: screen time counted so far that day
+pub const SCREEN_TIME_SETTING: &str = "blocker_screen_time";
+/// RFC 3339 local time today's window opened; empty when it hasn't
+pub const DUE_SINCE_SETTING: &str = "blocker_due_since";
+
+/// Screen time is written back at most this often
+const SAVE_EVERY_SECS: u64 = 60;
+
+const MAX_GRACE_MINUTES: u32 = 120;
+
+/// Gaps between checks longer than this are sleep, not screen time
+const MAX_TICK_SECS: u64 = 60;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BlockingWindow {
+    /// Local hour (0-23) blocking may start
+    pub start_hour: Option<u32>,
+    /// Minutes of screen time today before blocking may start
+    pub after_screen_minutes: Option<u32>,
+    /// Local hour (0-23) from which the block is immediate
+    pub deadline_hour: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BlockerPolicy {
+    /// Monday first
+    pub days: [BlockingWindow; 7],
+    /// Warning phase before the full-screen block
+    pub grace_minutes: u32,
+}
+
+/// What the blocker should show right now
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Clear,
+    /// Heads-up; the full-screen block comes at `blocks_at`
+    Warning { blocks_at: DateTime<Local> },
+    Blocked,
+}
+
+impl BlockerPolicy {
+    /// Read from settings; a broken policy falls back to the default
+    pub fn load(db: &Database) -> Result<Self> {
+        let Some(value) = db.get_setting(POLICY_SETTING)? else {
+            return Ok(Self::default());
+        };
+        Ok(serde_json::from_str::<Self>(&value)
+            .map_err(anyhow::Error::from)
+            .and_then(|policy| policy.validate().map(|()| policy))
+            .unwrap_or_else(|e| {
+                tracing::warn!("Ignoring invalid {} setting: {}", POLICY_SETTING, e);
+                Self::default()
+            }))
+    }
+
+    pub fn save(&self, db: &Database) -> Result<()> {
+        self.validate()?;
+        db.set_setting(POLICY_SETTING, &serde_json::to_string(self)?, ChangeOrigin::Desktop)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for window in &self.days {
+            if [window.start_hour, window.deadline_hour].into_iter().flatten().any(|hour| hour > 23) {
+                bail!("Hours must be between 0 and 23");
+            }
+            if let (Some(start), Some(deadline)) = (window.start_hour, window.deadline_hour) {
+                if deadline < start {
+                    bail!("The deadline can't be before the start hour");
+                }
+            }
+        }
+        if self.grace_minutes > MAX_GRACE_MINUTES {
+            bail!("The grace period can be at most {} minutes", MAX_GRACE_MINUTES);
+        }
+        Ok(())
+    }
+
+    fn window(&self, date: NaiveDate) -> &BlockingWindow {
+        &self.days[date.weekday().num_days_from_monday() as usize]
+    }
+
+    /// Whether today's window has opened (the grace period starts then)
+    pub fn is_due(&self, now: DateTime<Local>, screen_secs: u64) -> bool {
+        let window = self.window(now.date_naive());
+        let hour_reached = window.start_hour.is_none_or(|hour| now.hour() >= hour);
+        let screen_reached = window
+            .after_screen_minutes
+            .is_none_or(|minutes| screen_secs >= u64::from(minutes) * 60);
+        (hour_reached && screen_reached) || self.past_deadline(now)
+    }
+
+    fn past_deadline(&self, now: DateTime<Local>) -> bool {
+        self.window(now.date_naive()).deadline_hour.is_some_and(|hour| now.hour() >= hour)
+    }
+
+    /// Phase for a day that needs a workout; `due_since` is when is_due
+    /// first held today
+    pub fn phase(&self, now: DateTime<Local>, due_since: Option<DateTime<Local>>) -> Phase {
+        if self.past_deadline(now) {
+            return Phase::Blocked;
+        }
+        let Some(due_since) = due_since else {
+            return Phase::Clear;
+        };
+
+        let mut blocks_at = due_since + Duration::minutes(i64::from(self.grace_minutes));
+        if let Some(deadline) = self.deadline_today(now) {
+            blocks_at = blocks_at.min(deadline);
+        }
+        if now >= blocks_at {
+            Phase::Blocked
+        } else {
+            Phase::Warning { blocks_at }
+        }
+    }
+
+    fn deadline_today(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        let hour = self.window(now.date_naive()).deadline_hour?;
+        let naive = now.date_naive().and_hms_opt(hour, 0, 0)?;
+        Local.from_local_datetime(&naive).earliest()
+    }
+}
+
+/// Time the app has been running today, sleep excluded
+#[derive(Debug)]
+pub struct ScreenTime {
+    date: NaiveDate,
+    secs: u64,
+    last_tick: std::time::Instant,
+    /// Counted since the last save
+    unsaved_secs: u64,
+}
+
+impl ScreenTime {
+    pub fn new() -> Self {
+        Self::starting_at(Local::now().date_naive(), 0)
+    }
+
+    fn starting_at(date: NaiveDate, secs: u64) -> Self {
+        Self {
+            date,
+            secs,
+            last_tick: std::time::Instant::now(),
+            unsaved_secs: 0,
+        }
+    }
+
+    /// Pick up today's total from before a relaunch
+    pub fn load(db: &Database, today: NaiveDate) -> Result<Self> {
+        let secs = db
+            .get_setting(SCREEN_TIME_SETTING)?
+            .and_then(|value| {
+                let (date, secs) = value.split_once(' ')?;
+                (date.parse::<NaiveDate>().ok()? == today).then(|| secs.parse::<u64>().ok())?
+            })
+            .unwrap_or(0);
+        Ok(Self::starting_at(today, secs))
+    }
+
+    /// Count the time since the last tick; returns today's total
+    pub fn tick(&mut self, today: NaiveDate) -> u64 {
+        let now = std::time::Instant::now();
+        let elapsed = now.duration_since(self.last_tick).as_secs();
+        self.last_tick = now;
+
+        if today != self.date {
+            self.date = today;
+            self.secs = 0;
+            self.unsaved_secs = SAVE_EVERY_SECS;
+        } else if elapsed <= MAX_TICK_SECS {
+            self.secs += elapsed;
+            self.unsaved_secs += elapsed;
+        }
+        self.secs
+    }
+
+    /// Write today's total back once enough has accumulated
+    pub fn save_if_due(&mut self, db: &Database) -> Result<()> {
+        if self.unsaved_secs < SAVE_EVERY_SECS {
+            return Ok(());
+        }
+        db.set_setting(SCREEN_TIME_SETTING, &format!("{} {}", self.date, self.secs), ChangeOrigin::Desktop)?;
+        self.unsaved_secs = 0;
+        Ok(())
+    }
+}
+
+impl Default for ScreenTime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// When today's window opened, as saved before a relaunch
+pub fn load_due_since(db: &Database, today: NaiveDate) -> Result<Option<DateTime<Local>>> {
+    Ok(db
+        .get_setting(DUE_SINCE_SETTING)?
+        .and_then(|value| DateTime::parse_from_rfc3339(&value).ok())
+        .map(|since| since.with_timezone(&Local))
+        .filter(|since| since.date_naive() == today))
+}
+
+pub fn save_due_since(db: &Database, due_since: Option<DateTime<Local>>) -> Result<()> {
+    let value = due_since.map(|since| since.to_rfc3339()).unwrap_or_default();
+    db.set_setting(DUE_SINCE_SETTING, &value, ChangeOrigin::Desktop)
+}
+
+/// Settings the blocker writes for itself (changes to these don't need a re-check)
+pub fn is_own_state(key: &str) -> bool {
+    key == SCREEN_TIME_SETTING || key == DUE_SINCE_SETTING
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Local> {
+        // 2026-03-02 is a Monday
+        let naive = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap().and_hms_opt(hour, minute, 0).unwrap();
+        Local.from_local_datetime(&naive).earliest().unwrap()
+    }
+
+    #[test]
+    fn test_state_survives_relaunch() {
+        let db = Database::new().unwrap();
+        let today = at(9, 0).date_naive();
+
+        let mut screen = ScreenTime::starting_at(today, 90 * 60);
+        screen.unsaved_secs = SAVE_EVERY_SECS;
+        screen.save_if_due(&db).unwrap();
+        assert_eq!(ScreenTime::load(&db, today).unwrap().secs, 90 * 60);
+        assert_eq!(ScreenTime::load(&db, today.succ_opt().unwrap()).unwrap().secs, 0);
+
+        save_due_since(&db, Some(at(8, 30))).unwrap();
+        assert_eq!(load_due_since(&db, today).unwrap(), Some(at(8, 30)));
+        assert_eq!(load_due_since(&db, today.succ_opt().unwrap()).unwrap(), None);
+        save_due_since(&db, None).unwrap();
+        assert_eq!(load_due_since(&db, today).unwrap(), None);
+    }
+
+    #[test]
+    fn test_default_blocks_from_midnight() {
+        let policy = BlockerPolicy::default();
+        assert!(policy.is_due(at(0, 0), 0));
+        assert_eq!(policy.phase(at(0, 0), Some(at(0, 0))), Phase::Blocked);
+    }
+
+    #[test]
+    fn test_window_grace_and_deadline() {
+        let mut policy = BlockerPolicy { grace_minutes: 15, ..Default::default() };
+        policy.days[0] = BlockingWindow { start_hour: Some(17), after_screen_minutes: Some(120), deadline_hour: Some(21) };
+        policy.validate().unwrap();
+
+        // Needs both the hour and two hours of screen time
+        assert!(!policy.is_due(at(9, 0), 8 * 3600));
+        assert!(!policy.is_due(at(17, 30), 3600));
+        assert!(policy.is_due(at(17, 30), 2 * 3600));
+
+        assert_eq!(policy.phase(at(17, 30), None), Phase::Clear);
+        assert_eq!(policy.phase(at(17, 40), Some(at(17, 30))), Phase::Warning { blocks_at: at(17, 45) });
+        assert_eq!(policy.phase(at(17, 45), Some(at(17, 30))), Phase::Blocked);
+
+        // The grace period never runs past the deadline
+        assert_eq!(policy.phase(at(20, 50), Some(at(20, 50))), Phase::Warning { blocks_at: at(21, 0) });
+        // From the deadline it's blocked, screen time or not
+        assert!(policy.is_due(at(21, 0), 0));
+        assert_eq!(policy.phase(at(21, 0), None), Phase::Blocked);
+
+        policy.days[0].deadline_hour = Some(16);
+        assert!(policy.validate().is_err());
+    }
+}
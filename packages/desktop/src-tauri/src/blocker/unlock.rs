@@ -0,0 +1,278 @@
+/**
+ * Emergency Unlock
+ *
+ * The way out of the blocker without a workout, for real emergencies
+ * - Friction: type a random phrase shown on the blocker, or wait out a
+ *   countdown (start_challenge, then unlock)
+ * - A few unlocks per calendar month (`emergency_unlocks_per_month`)
+ * - Each unlock is kept with its reason in the emergency_unlocks table
+ * - An unlock suspends blocking for `emergency_unlock_minutes`; afterwards
+ *   the blocking policy starts over (grace-period warning first)
+ */
+
+use crate::db::{timestamp, Database, EmergencyUnlockRecord};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Datelike, Duration, Local, TimeZone, Utc};
+use rand::seq::SliceRandom;
+use serde::Serialize;
+
+pub const PER_MONTH_SETTING: &str = "emergency_unlocks_per_month";
+pub const MINUTES_SETTING: &str = "emergency_unlock_minutes";
+pub const COUNTDOWN_SETTING: &str = "emergency_unlock_countdown_secs";
+
+const DEFAULT_PER_MONTH: u32 = 3;
+const DEFAULT_MINUTES: u32 = 60;
+const DEFAULT_COUNTDOWN_SECS: u32 = 120;
+
+const MAX_MINUTES: u32 = 8 * 60;
+const MAX_COUNTDOWN_SECS: u32 = 30 * 60;
+
+const MIN_REASON_CHARS: usize = 10;
+
+const PHRASE_WORDS: usize = 6;
+
+// Short, unambiguous words; no autocomplete or muscle memory for the phrase
+const WORDS: &[&str] = &[
+    "anchor", "basket", "candle", "desert", "engine", "falcon", "garden", "harbor",
+    "island", "jacket", "kettle", "ladder", "meadow", "needle", "orange", "pencil",
+    "quartz", "rabbit", "saddle", "timber", "velvet", "walnut", "yellow", "zipper",
+    "bridge", "copper", "dragon", "forest", "glacier", "hammer", "lantern", "marble",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnlockConfig {
+    pub per_month: u32,
+    /// How long an unlock suspends blocking
+    pub minutes: u32,
+    /// Wait before an unlock works without the phrase
+    pub countdown_secs: u32,
+}
+
+impl UnlockConfig {
+    /// Read from settings; missing or unparseable values use the defaults
+    pub fn load(db: &Database) -> Result<Self> {
+        let number = |key: &str, default: u32, max: u32| -> Result<u32> {
+            Ok(db
+                .get_setting(key)?
+                .and_then(|value| value.trim().parse::<u32>().ok())
+                .map_or(default, |value| value.min(max)))
+        };
+        Ok(Self {
+            per_month: number(PER_MONTH_SETTING, DEFAULT_PER_MONTH, 31)?,
+            minutes: number(MINUTES_SETTING, DEFAULT_MINUTES, MAX_MINUTES)?.max(1),
+            countdown_secs: number(COUNTDOWN_SETTING, DEFAULT_COUNTDOWN_SECS, MAX_COUNTDOWN_SECS)?,
+        })
+    }
+}
+
+/// How the friction was passed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnlockMethod {
+    Phrase,
+    Countdown,
+}
+
+impl UnlockMethod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Phrase => "phrase",
+            Self::Countdown => "countdown",
+        }
+    }
+}
+
+/// Phrase and countdown shown on the blocker for one unlock attempt
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Challenge {
+    pub phrase: String,
+    /// From here the unlock works without the phrase
+    pub ready_at: DateTime<Utc>,
+}
+
+impl Challenge {
+    pub fn new(countdown_secs: u32, now: DateTime<Utc>) -> Self {
+        let mut rng = rand::thread_rng();
+        let words: Vec<&str> = (0..PHRASE_WORDS)
+            .filter_map(|_| WORDS.choose(&mut rng).copied())
+            .collect();
+        Self {
+            phrase: words.join(" "),
+            ready_at: now + Duration::seconds(i64::from(countdown_secs)),
+        }
+    }
+
+    /// The typed phrase (spacing and case aside), else the elapsed countdown
+    fn method(&self, typed: Option<&str>, now: DateTime<Utc>) -> Option<UnlockMethod> {
+        let normalize = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+        if typed.is_some_and(|typed| normalize(typed) == self.phrase) {
+            Some(UnlockMethod::Phrase)
+        } else if now >= self.ready_at {
+            Some(UnlockMethod::Countdown)
+        } else {
+            None
+        }
+    }
+}
+
+/// What the blocker shows before an unlock is started
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnlockStatus {
+    #[serde(flatten)]
+    pub config: UnlockConfig,
+    /// Unlocks left this calendar month
+    pub remaining: u32,
+    /// Set while an unlock is in effect
+    pub suspended_until: Option<DateTime<Utc>>,
+}
+
+pub fn status(db: &Database, now: DateTime<Local>) -> Result<UnlockStatus> {
+    let config = UnlockConfig::load(db)?;
+    let used = db.count_emergency_unlocks_since(month_start(now))?;
+    Ok(UnlockStatus {
+        config,
+        remaining: config.per_month.saturating_sub(used),
+        suspended_until: db.emergency_unlock_until(now.with_timezone(&Utc))?,
+    })
+}
+
+/// A new challenge, if any unlocks are left this month
+pub fn start_challenge(db: &Database, now: DateTime<Local>) -> Result<Challenge> {
+    let status = status(db, now)?;
+    if status.remaining == 0 {
+        bail!("No emergency unlocks left this month");
+    }
+    Ok(Challenge::new(status.config.countdown_secs, now.with_timezone(&Utc)))
+}
+
+/// Check the friction and allowance, then record the unlock (the blocker
+/// picks it up from the change bus)
+pub fn unlock(
+    db: &Database,
+    challenge: Option<&Challenge>,
+    reason: &str,
+    typed_phrase: Option<&str>,
+    now: DateTime<Local>,
+) -> Result<EmergencyUnlockRecord> {
+    let reason = reason.trim();
+    if reason.chars().count() < MIN_REASON_CHARS {
+        bail!("Say what the emergency is (at least {} characters)", MIN_REASON_CHARS);
+    }
+    let challenge = challenge.ok_or_else(|| anyhow!("Start an emergency unlock first"))?;
+    let utc_now = now.with_timezone(&Utc);
+    let method = challenge
+        .method(typed_phrase, utc_now)
+        .ok_or_else(|| anyhow!("Type the phrase exactly, or wait for the countdown"))?;
+
+    let status = status(db, now)?;
+    if status.remaining == 0 {
+        bail!("No emergency unlocks left this month");
+    }
+
+    let date_key = now.format("%Y-%m-%d").to_string();
+    let until = utc_now + Duration::minutes(i64::from(status.config.minutes));
+    let id = db.record_emergency_unlock(&date_key, reason, method.as_str(), utc_now, until)?;
+    tracing::warn!("Emergency unlock ({}) until {}: {}", method.as_str(), until, reason);
+
+    Ok(EmergencyUnlockRecord {
+        id,
+        date_key,
+        reason: reason.to_string(),
+        method: method.as_str().to_string(),
+        unlocked_at: timestamp(utc_now),
+        suspended_until: timestamp(until),
+    })
+}
+
+/// Midnight on the first of `now`'s month, local time
+fn month_start(now: DateTime<Local>) -> DateTime<Utc> {
+    now.date_naive()
+        .with_day(1)
+        .and_then(|first| first.and_hms_opt(0, 0, 0))
+        .and_then(|midnight| Local.from_local_datetime(&midnight).earliest())
+        .map_or(now.with_timezone(&Utc), |start| start.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::changes::ChangeOrigin;
+
+    #[test]
+    fn test_phrase_or_countdown() {
+        let now = Utc.with_ymd_and_hms(2026, 3, 4, 9, 0, 0).unwrap();
+        let challenge = Challenge::new(120, now);
+        assert_eq!(challenge.phrase.split(' ').count(), PHRASE_WORDS);
+
+        let sloppy = format!("  {}  ", challenge.phrase.to_uppercase().replace(' ', "   "));
+        assert_eq!(challenge.method(Some(&sloppy), now), Some(UnlockMethod::Phrase));
+        assert_eq!(challenge.method(Some("let me out"), now), None);
+        assert_eq!(challenge.method(None, now + Duration::seconds(119)), None);
+        assert_eq!(challenge.method(None, now + Duration::seconds(120)), Some(UnlockMethod::Countdown));
+    }
+
+    /// A database of its own, so other tests' unlocks don't count against the month
+    fn unlock_db(name: &str) -> Database {
+        let dir = crate::db::data_dir().join("unlock").join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Database::open(&dir.join("traindaily.db")).unwrap()
+    }
+
+    #[test]
+    fn test_fourth_unlock_in_a_month_is_refused() {
+        let db = unlock_db("limit");
+        let now = Local.with_ymd_and_hms(2026, 5, 10, 9, 0, 0).unwrap();
+
+        for day in 0..DEFAULT_PER_MONTH {
+            let at = now + Duration::days(i64::from(day));
+            let challenge = start_challenge(&db, at).unwrap();
+            unlock(&db, Some(&challenge), "Locked out of the car", Some(&challenge.phrase), at).unwrap();
+        }
+        assert_eq!(status(&db, now + Duration::days(5)).unwrap().remaining, 0);
+
+        // Refused both when starting and with a challenge taken earlier
+        let late = now + Duration::days(5);
+        let challenge = Challenge::new(0, late.with_timezone(&Utc));
+        assert!(start_challenge(&db, late).is_err());
+        assert!(unlock(&db, Some(&challenge), "Locked out of the car", Some(&challenge.phrase), late).is_err());
+
+        // The allowance starts over next month
+        let next_month = Local.with_ymd_and_hms(2026, 6, 1, 9, 0, 0).unwrap();
+        assert_eq!(status(&db, next_month).unwrap().remaining, DEFAULT_PER_MONTH);
+        assert!(start_challenge(&db, next_month).is_ok());
+    }
+
+    #[test]
+    fn test_countdown_unlock_suspends_blocking_until_it_expires() {
+        let db = unlock_db("countdown");
+        db.set_setting(COUNTDOWN_SETTING, "60", ChangeOrigin::Desktop).unwrap();
+        db.set_setting(MINUTES_SETTING, "30", ChangeOrigin::Desktop).unwrap();
+        let now = Local.with_ymd_and_hms(2026, 5, 10, 9, 0, 0).unwrap();
+
+        let challenge = start_challenge(&db, now).unwrap();
+        let reason = "Family emergency, need the laptop";
+        assert!(unlock(&db, Some(&challenge), reason, None, now + Duration::seconds(59)).is_err());
+        assert_eq!(db.emergency_unlock_until(now.with_timezone(&Utc)).unwrap(), None);
+
+        let at = now + Duration::seconds(60);
+        let record = unlock(&db, Some(&challenge), reason, None, at).unwrap();
+        assert_eq!(record.method, "countdown");
+
+        let until = at.with_timezone(&Utc) + Duration::minutes(30);
+        let during = (at + Duration::minutes(29)).with_timezone(&Utc);
+        assert_eq!(db.emergency_unlock_until(during).unwrap(), Some(until));
+        assert_eq!(status(&db, at).unwrap().suspended_until, Some(until));
+        assert_eq!(db.emergency_unlock_until(until).unwrap(), None);
+        assert_eq!(status(&db, at + Duration::minutes(31)).unwrap().suspended_until, None);
+    }
+
+    #[test]
+    fn test_month_start() {
+        let now = Local.with_ymd_and_hms(2026, 3, 17, 15, 30, 0).unwrap();
+        let start = month_start(now).with_timezone(&Local);
+        assert_eq!(start.format("%Y-%m-%d %H:%M").to_string(), "2026-03-01 00:00");
+    }
+}
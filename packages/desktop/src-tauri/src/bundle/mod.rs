@@ -0,0 +1,330 @@
+/**
+ * Offline Sync Bundles
+ *
+ * Sync through any file transport (AirDrop, USB stick, a shared folder) when
+ * devices never share a network
+ * - A bundle is one JSON file (`.tdbundle`) holding every session changed
+ *   after a cursor of the writer's change log, in the replication feed format
+ * - Signed: HMAC-SHA256 with the writer's pairing secret, over the header
+ *   and payload (see signing_payload)
+ * - Optionally encrypted: AES-256-GCM with a key derived from the same
+ *   secret (see crypto)
+ * - Ingesting verifies, then writes through the sync server's path: each
+ *   session is validated and saved with conflict detection as a sync
+ *   change, so bundles can arrive late, twice or out of order
+ *
+ * Only paired devices can exchange bundles: a phone shares this desktop's
+ * secret (from the QR), another desktop is verified with its peer secret.
+ */
+
+use crate::changes::ChangeOrigin;
+use crate::crypto;
+use crate::db::{Database, SessionWrite, WriteOutcome};
+use crate::sync::replication::{feed_since, FeedChange};
+use crate::sync::{auth, validate_session};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const FORMAT: &str = "traindaily-bundle";
+pub const VERSION: u32 = 1;
+pub const EXTENSION: &str = "tdbundle";
+
+/// Larger files are refused before parsing
+const MAX_BUNDLE_BYTES: u64 = 32 * 1024 * 1024;
+
+// HKDF purpose for the bundle encryption key
+const KEY_PURPOSE: &str = "traindaily-bundle-v1";
+
+/// The file on disk
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bundle {
+    pub format: String,
+    pub version: u32,
+    /// Device that wrote the bundle
+    pub device_id: String,
+    pub created_at: String,
+    /// Contains the writer's changes after this change id...
+    pub after: i64,
+    /// ...up to this one (pass as `after` for the next bundle)
+    pub cursor: i64,
+    /// AES-GCM nonce (base64) when `payload` is encrypted
+    pub nonce: Option<String>,
+    /// Base64 of the JSON change list, or of its ciphertext
+    pub payload: String,
+    /// Hex HMAC-SHA256 over signing_payload
+    pub signature: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleWritten {
+    pub path: PathBuf,
+    pub changes: usize,
+    pub cursor: i64,
+    pub encrypted: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleIngested {
+    pub device_id: String,
+    pub applied: usize,
+    /// Already identical here
+    pub skipped: usize,
+    /// Dates changed here after the bundle's version: ours are kept
+    pub conflicts: Vec<String>,
+    /// Sessions that failed validation, with the reason
+    pub invalid: Vec<String>,
+    /// The writer's cursor the bundle reaches
+    pub cursor: i64,
+}
+
+impl Bundle {
+    fn header(&self) -> String {
+        format!(
+            "{}-v{}\n{}\n{}\n{}\n{}",
+            self.format, self.version, self.device_id, self.created_at, self.after, self.cursor
+        )
+    }
+
+    /// Canonical string the signature covers
+    fn signing_payload(&self) -> String {
+        format!("{}\n{}\n{}", self.header(), self.nonce.as_deref().unwrap_or_default(), self.payload)
+    }
+}
+
+/// Write every change after `after` to a new bundle file in `folder`
+pub fn write(
+    db: &Database,
+    device_id: &str,
+    secret: &str,
+    folder: &Path,
+    after: i64,
+    encrypt: bool,
+) -> Result<BundleWritten> {
+    let feed = feed_since(db, device_id, after)?;
+    let changes = feed.changes.len();
+    let bundle = seal(device_id, secret, after, feed.cursor, &feed.changes, encrypt)?;
+
+    let name = format!(
+        "traindaily-{}-{}.{}",
+        device_id,
+        chrono::Utc::now().format("%Y%m%d-%H%M%S"),
+        EXTENSION
+    );
+    let path = folder.join(&name);
+    // Write then rename, so a sync tool never picks up half a bundle,
+    // and concurrent exports to one folder each get their own temp file
+    let partial = folder.join(format!(".{}.{:08x}.partial", name, rand::random::<u32>()));
+    std::fs::write(&partial, serde_json::to_vec_pretty(&bundle)?)
+        .with_context(|| format!("Failed to write to {}", folder.display()))?;
+    std::fs::rename(&partial, &path)?;
+
+    tracing::info!("Wrote bundle with {} change(s) to {}", changes, path.display());
+    Ok(BundleWritten { path, changes, cursor: feed.cursor, encrypted: encrypt })
+}
+
+/// Verify a bundle file and apply its sessions
+///
+/// `secret` is this desktop's pairing secret; bundles from a paired desktop
+/// are checked against that peer's secret instead. A session whose date
+/// changed here after the bundle's version was made is a conflict and is
+/// left alone, like a stale write to the sync server.
+pub fn ingest(db: &mut Database, device_id: &str, secret: &str, path: &Path) -> Result<BundleIngested> {
+    let size = std::fs::metadata(path)
+        .with_context(|| format!("Cannot read {}", path.display()))?
+        .len();
+    if size > MAX_BUNDLE_BYTES {
+        bail!("Bundle is larger than {} MiB", MAX_BUNDLE_BYTES / 1024 / 1024);
+    }
+    let bundle: Bundle = serde_json::from_slice(&std::fs::read(path)?).context("Not a TrainDaily bundle")?;
+
+    if bundle.device_id == device_id {
+        bail!("This bundle was written by this desktop");
+    }
+    let peer_secret = db
+        .get_peers()?
+        .into_iter()
+        .find(|peer| peer.device_id == bundle.device_id)
+        .map(|peer| peer.token);
+    let changes = open(&bundle, peer_secret.as_deref().unwrap_or(secret))?;
+
+    let mut summary = BundleIngested {
+        device_id: bundle.device_id.clone(),
+        applied: 0,
+        skipped: 0,
+        conflicts: Vec::new(),
+        invalid: Vec::new(),
+        cursor: bundle.cursor,
+    };
+    let mut writes = Vec::new();
+    for change in changes {
+        if let Err(e) = validate_session(&change.date_key, &change.session) {
+            summary.invalid.push(e);
+            continue;
+        }
+        // A bundle that arrived twice, or our own change coming back
+        if db.get_session(&change.date_key)?.as_ref() == Some(&change.session) {
+            summary.skipped += 1;
+            continue;
+        }
+        let current = db.last_change(&change.date_key)?;
+        if current.as_ref().is_some_and(|current| timestamp(&current.changed_at) > timestamp(&change.changed_at)) {
+            summary.conflicts.push(change.date_key);
+            continue;
+        }
+        // Anything written to the date from now on wins over the bundle
+        writes.push(SessionWrite {
+            date_key: change.date_key,
+            session: change.session,
+            base_change_id: Some(current.map_or(0, |current| current.id)),
+        });
+    }
+
+    let outcomes = db.save_sessions(&writes, ChangeOrigin::Sync)?;
+    for (write, outcome) in writes.into_iter().zip(outcomes) {
+        match outcome {
+            WriteOutcome::Applied { .. } => summary.applied += 1,
+            WriteOutcome::Conflict { .. } => summary.conflicts.push(write.date_key),
+        }
+    }
+
+    tracing::info!(
+        "Ingested bundle from {}: {} applied, {} skipped, {} conflicting, {} invalid",
+        summary.device_id,
+        summary.applied,
+        summary.skipped,
+        summary.conflicts.len(),
+        summary.invalid.len()
+    );
+    Ok(summary)
+}
+
+// Unparseable times sort first: they lose to any change made here
+fn timestamp(value: &str) -> chrono::DateTime<chrono::Utc> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&chrono::Utc))
+        .unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC)
+}
+
+/// Build a signed (and optionally encrypted) bundle
+fn seal(device_id: &str, secret: &str, after: i64, cursor: i64, changes: &[FeedChange], encrypt: bool) -> Result<Bundle> {
+    let mut bundle = Bundle {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        device_id: device_id.to_string(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        after,
+        cursor,
+        nonce: None,
+        payload: String::new(),
+        signature: String::new(),
+    };
+
+    let plaintext = serde_json::to_vec(changes)?;
+    bundle.payload = if encrypt {
+        let key = crypto::derive_key(secret.as_bytes(), KEY_PURPOSE);
+        let (nonce, ciphertext) = crypto::seal(&key, &plaintext, bundle.header().as_bytes())?;
+        bundle.nonce = Some(BASE64.encode(nonce));
+        BASE64.encode(ciphertext)
+    } else {
+        BASE64.encode(plaintext)
+    };
+    bundle.signature = auth::sign(secret, &bundle.signing_payload());
+
+    Ok(bundle)
+}
+
+/// Check format and signature, then decrypt and parse the changes
+fn open(bundle: &Bundle, secret: &str) -> Result<Vec<FeedChange>> {
+    if bundle.format != FORMAT {
+        bail!("Not a TrainDaily bundle");
+    }
+    if bundle.version != VERSION {
+        bail!("Unsupported bundle version {} (this app reads {})", bundle.version, VERSION);
+    }
+    if !auth::tokens_match(&bundle.signature, &auth::sign(secret, &bundle.signing_payload())) {
+        bail!("Bundle signature does not match: it was not written by a paired device, or was modified");
+    }
+
+    let payload = BASE64.decode(&bundle.payload).map_err(|_| anyhow!("Corrupt bundle payload"))?;
+    let plaintext = match &bundle.nonce {
+        Some(nonce) => {
+            let nonce = BASE64.decode(nonce).map_err(|_| anyhow!("Corrupt bundle nonce"))?;
+            let key = crypto::derive_key(secret.as_bytes(), KEY_PURPOSE);
+            crypto::open(&key, &nonce, &payload, bundle.header().as_bytes())?
+        }
+        None => payload,
+    };
+
+    serde_json::from_slice(&plaintext).context("Corrupt bundle contents")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample() -> Vec<FeedChange> {
+        vec![FeedChange {
+            date_key: "2026-03-01".to_string(),
+            session: json!({ "logged_at": "2026-03-01T07:00:00Z" }),
+            changed_at: "2026-03-01T07:00:00Z".to_string(),
+            origin: "iphone".to_string(),
+        }]
+    }
+
+    #[test]
+    fn test_seal_open_round_trip() {
+        for encrypt in [false, true] {
+            let bundle = seal("iphone", "s3cret", 0, 7, &sample(), encrypt).unwrap();
+            assert_eq!(bundle.nonce.is_some(), encrypt);
+
+            let changes = open(&bundle, "s3cret").unwrap();
+            assert_eq!(changes[0].date_key, "2026-03-01");
+            assert!(open(&bundle, "wrong").is_err());
+        }
+    }
+
+    #[test]
+    fn test_open_rejects_modified_bundle() {
+        let mut bundle = seal("iphone", "s3cret", 0, 7, &sample(), false).unwrap();
+        bundle.cursor = 8;
+        assert!(open(&bundle, "s3cret").is_err());
+
+        let mut bundle = seal("iphone", "s3cret", 0, 7, &sample(), false).unwrap();
+        bundle.payload = BASE64.encode(b"[]");
+        assert!(open(&bundle, "s3cret").is_err());
+    }
+
+    #[test]
+    fn test_ingest_keeps_newer_local_changes() {
+        let mut db = Database::new().unwrap();
+        db.save_session("2031-05-02", &json!({ "logged_at": "ours" }), ChangeOrigin::Desktop).unwrap();
+
+        let change = |date_key: &str, changed_at: &str| FeedChange {
+            date_key: date_key.to_string(),
+            session: json!({ "logged_at": changed_at }),
+            changed_at: changed_at.to_string(),
+            origin: "iphone".to_string(),
+        };
+        let changes = [change("2031-05-01", "2020-05-01T07:00:00Z"), change("2031-05-02", "2020-05-02T07:00:00Z")];
+        let bundle = seal("iphone", "s3cret", 0, 2, &changes, true).unwrap();
+        let path = crate::db::data_dir().join("test.tdbundle");
+        std::fs::create_dir_all(crate::db::data_dir()).unwrap();
+        std::fs::write(&path, serde_json::to_vec(&bundle).unwrap()).unwrap();
+
+        let first = ingest(&mut db, "test-mac", "s3cret", &path).unwrap();
+        assert_eq!(first.applied, 1);
+        assert_eq!(first.conflicts, vec!["2031-05-02".to_string()]);
+        assert_eq!(db.get_session("2031-05-02").unwrap(), Some(json!({ "logged_at": "ours" })));
+
+        // Arriving twice changes nothing
+        let again = ingest(&mut db, "test-mac", "s3cret", &path).unwrap();
+        assert_eq!((again.applied, again.skipped), (0, 1));
+    }
+}
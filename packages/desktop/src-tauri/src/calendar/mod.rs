@@ -0,0 +1,325 @@
+/**
+ * iCalendar Feed
+ *
+ * RFC 5545 calendar of the training schedule, for subscribing from any
+ * calendar app (served at /api/calendar.ics) or exporting as a file
+ * - One all-day event per date: upcoming training days with their workout
+ *   type, rest days, and completed sessions with set / rep totals
+ * - UIDs depend only on the date and this device, so a planned day turns
+ *   into its completed session in place once it's logged
+ * - Past days without a session drop out of the feed
+ * - Subscriptions use their own read-only token (`calendar_token` setting),
+ *   never the pairing secret; revoking it only breaks calendar subscriptions
+ */
+
+use crate::activity::{self, Exercise};
+use crate::changes::ChangeOrigin;
+use crate::db::{Database, WorkoutSession};
+use crate::schedule::{self, Schedule, WorkoutType};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+pub const CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+pub const TOKEN_SETTING: &str = "calendar_token";
+pub const EXTENSION: &str = "ics";
+
+/// Schedule days listed ahead of today (today included)
+const UPCOMING_DAYS: i64 = 28;
+
+// Content lines longer than this are folded (RFC 5545 3.1)
+const MAX_LINE_OCTETS: usize = 75;
+
+// Suggested refresh interval for subscribed calendars
+const REFRESH_INTERVAL: &str = "PT1H";
+
+/// Set and rep totals of a logged session
+struct Totals {
+    exercises: Vec<Exercise>,
+    sets: usize,
+    reps: u64,
+}
+
+impl Totals {
+    fn of(session: &WorkoutSession) -> Self {
+        let exercises = activity::exercises(session);
+        Totals {
+            sets: exercises.iter().map(|exercise| exercise.reps.len()).sum(),
+            reps: exercises.iter().flat_map(|exercise| &exercise.reps).sum(),
+            exercises,
+        }
+    }
+}
+
+/// The whole calendar, CRLF-terminated and folded
+pub fn feed(
+    sessions: &HashMap<String, WorkoutSession>,
+    schedule: &Schedule,
+    today: NaiveDate,
+    device_id: &str,
+    now: DateTime<Utc>,
+) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:-//TrainDaily//Desktop {}//EN", env!("CARGO_PKG_VERSION")),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        "X-WR-CALNAME:TrainDaily".to_string(),
+        format!("REFRESH-INTERVAL;VALUE=DURATION:{}", REFRESH_INTERVAL),
+        format!("X-PUBLISHED-TTL:{}", REFRESH_INTERVAL),
+    ];
+
+    let mut completed: Vec<(NaiveDate, &WorkoutSession)> = sessions
+        .iter()
+        .filter_map(|(key, session)| NaiveDate::parse_from_str(key, "%Y-%m-%d").ok().map(|date| (date, session)))
+        .filter(|(date, _)| schedule::is_logged(sessions, *date))
+        .collect();
+    completed.sort_by_key(|(date, _)| *date);
+
+    for (date, session) in completed.iter().filter(|(date, _)| *date < today) {
+        lines.extend(session_event(*date, session, schedule, device_id, now));
+    }
+    for offset in 0..UPCOMING_DAYS {
+        let date = today + Duration::days(offset);
+        match completed.iter().find(|(logged, _)| *logged == date) {
+            Some((_, session)) => lines.extend(session_event(date, session, schedule, device_id, now)),
+            None => lines.extend(schedule_event(date, schedule.workout_type(date), device_id, now)),
+        }
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut ics = String::new();
+    for line in &lines {
+        let _ = write!(ics, "{}\r\n", fold(line));
+    }
+    ics
+}
+
+/// Token for subscribing to the feed, created on first use
+pub fn token(db: &Database) -> Result<String> {
+    match db.get_setting(TOKEN_SETTING)? {
+        Some(token) if !token.is_empty() => {
+            crate::sync::auth::register_secret(&token);
+            Ok(token)
+        }
+        _ => revoke_token(db),
+    }
+}
+
+/// Replace the token, cutting off every existing subscription
+pub fn revoke_token(db: &Database) -> Result<String> {
+    let token = crate::sync::generate_auth_token();
+    crate::sync::auth::register_secret(&token);
+    db.set_setting(TOKEN_SETTING, &token, ChangeOrigin::Desktop)?;
+    Ok(token)
+}
+
+/// Write the feed to `<folder>/traindaily.ics`
+pub fn write(db: &Database, device_id: &str, folder: &Path) -> Result<PathBuf> {
+    let sessions = db.get_all_sessions()?;
+    let schedule = Schedule::load(db)?;
+    let ics = feed(&sessions, &schedule, chrono::Local::now().date_naive(), device_id, Utc::now());
+
+    let path = folder.join(format!("traindaily.{}", EXTENSION));
+    std::fs::write(&path, ics).with_context(|| format!("Failed to write to {}", folder.display()))?;
+
+    tracing::info!("Exported calendar to {}", path.display());
+    Ok(path)
+}
+
+/// A planned training day or a rest day
+fn schedule_event(date: NaiveDate, workout: WorkoutType, device_id: &str, now: DateTime<Utc>) -> Vec<String> {
+    let mut lines = event_start(date, device_id, now);
+
+    if workout == WorkoutType::Rest {
+        lines.push("SUMMARY:Rest day".to_string());
+    } else {
+        lines.push(format!("SUMMARY:{} day", workout.label()));
+        lines.push(format!("CATEGORIES:{}", workout.label()));
+        lines.push("STATUS:TENTATIVE".to_string());
+    }
+    lines.push("SEQUENCE:0".to_string());
+    lines.push("END:VEVENT".to_string());
+    lines
+}
+
+/// A logged session with its totals
+fn session_event(
+    date: NaiveDate,
+    session: &WorkoutSession,
+    schedule: &Schedule,
+    device_id: &str,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    let label = session
+        .get("workout_type")
+        .and_then(JsonValue::as_str)
+        .map(activity::capitalize)
+        .unwrap_or_else(|| schedule.workout_type(date).label().to_string());
+    let totals = Totals::of(session);
+
+    let mut lines = event_start(date, device_id, now);
+    lines.push(format!(
+        "SUMMARY:{}",
+        escape(&format!("{} workout: {} sets, {} reps", label, totals.sets, totals.reps))
+    ));
+
+    let description: Vec<String> = totals
+        .exercises
+        .iter()
+        .map(|exercise| {
+            let sets: Vec<String> = exercise.reps.iter().map(u64::to_string).collect();
+            format!(
+                "{}: {} ({} reps)",
+                activity::exercise_name(&exercise.key),
+                sets.join(" + "),
+                exercise.reps.iter().sum::<u64>()
+            )
+        })
+        .collect();
+    if !description.is_empty() {
+        lines.push(format!("DESCRIPTION:{}", escape(&description.join("\n"))));
+    }
+
+    lines.push(format!("CATEGORIES:{}", escape(&label)));
+    lines.push("STATUS:CONFIRMED".to_string());
+    if let Some(logged_at) = session
+        .get("logged_at")
+        .and_then(JsonValue::as_str)
+        .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+    {
+        lines.push(format!("LAST-MODIFIED:{}", timestamp(logged_at.with_timezone(&Utc))));
+    }
+    // Bumped from the planned day's 0 so clients take the update
+    lines.push("SEQUENCE:1".to_string());
+    lines.push("END:VEVENT".to_string());
+    lines
+}
+
+/// Properties shared by every event (the caller ends it)
+fn event_start(date: NaiveDate, device_id: &str, now: DateTime<Utc>) -> Vec<String> {
+    vec![
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}@{}.traindaily", schedule::date_key(date), device_id),
+        format!("DTSTAMP:{}", timestamp(now)),
+        format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")),
+        format!("DTEND;VALUE=DATE:{}", (date + Duration::days(1)).format("%Y%m%d")),
+        // Workouts don't make the day busy
+        "TRANSP:TRANSPARENT".to_string(),
+    ]
+}
+
+/// UTC DATE-TIME form (`20260302T070000Z`)
+fn timestamp(value: DateTime<Utc>) -> String {
+    value.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// TEXT value escaping (RFC 5545 3.3.11)
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Split a content line into 75-octet pieces, continuations starting with a
+/// space, never inside a UTF-8 sequence
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + line.len() / MAX_LINE_OCTETS * 3);
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn day(key: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(key, "%Y-%m-%d").unwrap()
+    }
+
+    fn events(ics: &str) -> Vec<&str> {
+        ics.split("BEGIN:VEVENT\r\n").skip(1).collect()
+    }
+
+    #[test]
+    fn test_token_is_stable_until_revoked() {
+        let db = Database::new().unwrap();
+        let first = token(&db).unwrap();
+        assert_eq!(token(&db).unwrap(), first);
+
+        let revoked = revoke_token(&db).unwrap();
+        assert_ne!(revoked, first);
+        assert_eq!(token(&db).unwrap(), revoked);
+    }
+
+    #[test]
+    fn test_feed_updates_planned_day_in_place() {
+        let now = Utc::now();
+        // Mon 2026-03-02: a Push day
+        let planned = feed(&HashMap::new(), &Schedule::default(), day("2026-03-02"), "desk", now);
+        let first = events(&planned)[0];
+        assert!(first.contains("UID:2026-03-02@desk.traindaily\r\n"));
+        assert!(first.contains("SUMMARY:Push day\r\n"));
+        assert!(first.contains("DTSTART;VALUE=DATE:20260302\r\nDTEND;VALUE=DATE:20260303\r\n"));
+        assert_eq!(events(&planned).len(), UPCOMING_DAYS as usize);
+        assert!(events(&planned)[6].contains("SUMMARY:Rest day\r\n"));
+
+        let sessions = HashMap::from([(
+            "2026-03-02".to_string(),
+            json!({
+                "trx_pushup": [10, 8, 8],
+                "pike_pushup": [6, null],
+                "logged_at": "2026-03-02T07:15:00Z",
+                "week_number": 1,
+            }),
+        )]);
+        let logged = feed(&sessions, &Schedule::default(), day("2026-03-02"), "desk", now);
+        let first = events(&logged)[0];
+        assert!(first.contains("UID:2026-03-02@desk.traindaily\r\n"));
+        assert!(first.contains("SUMMARY:Push workout: 4 sets\\, 32 reps\r\n"));
+        assert!(first.contains("DESCRIPTION:Pike pushup: 6 (6 reps)\\nTRX pushup: 10 + 8 + 8 (26 reps)\r\n"));
+        assert!(first.contains("LAST-MODIFIED:20260302T071500Z\r\n"));
+        assert!(first.contains("SEQUENCE:1\r\n"));
+        assert_eq!(events(&logged).len(), UPCOMING_DAYS as usize);
+
+        // A day later the session stays, the planned days move on
+        let next_day = feed(&sessions, &Schedule::default(), day("2026-03-03"), "desk", now);
+        assert!(events(&next_day)[0].contains("UID:2026-03-02@desk.traindaily\r\n"));
+        assert!(events(&next_day)[1].contains("SUMMARY:Pull day\r\n"));
+    }
+
+    #[test]
+    fn test_escape_and_fold() {
+        assert_eq!(escape("a,b;c\\d\ne"), "a\\,b\\;c\\\\d\\ne");
+
+        let line = format!("DESCRIPTION:{}", "é".repeat(60));
+        let folded = fold(&line);
+        for piece in folded.split("\r\n") {
+            assert!(piece.len() <= MAX_LINE_OCTETS);
+        }
+        assert_eq!(folded.replace("\r\n ", ""), line);
+    }
+}
@@ -0,0 +1,430 @@
+/**
+ * Local Certificate Authority
+ *
+ * Persistent root CA that issues the sync server's leaf certificate
+ * - Phones trust the CA once (via .mobileconfig or PEM/DER download)
+ *   and keep trusting the desktop across leaf rotations
+ * - Name-constrained to private networks, loopback and .local, so even a
+ *   stolen key can't vouch for anything on the internet
+ * - Kept in the per-user private directory (see db::private_dir), key
+ *   readable by its owner only, not in the shared data directory
+ *
+ * iOS additionally requires enabling full trust for installed roots:
+ * Settings > General > About > Certificate Trust Settings
+ */
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use chrono::{DateTime, Datelike, Duration, Utc};
+use rcgen::{
+    BasicConstraints, CertificateParams, CidrSubnet, DistinguishedName, DnType, GeneralSubtree,
+    IsCa, KeyPair, KeyUsagePurpose, NameConstraints,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+const CA_CERT_FILE: &str = "ca.pem";
+const CA_KEY_FILE: &str = "ca-key.pem";
+const CA_META_FILE: &str = "ca.json";
+
+const CA_COMMON_NAME: &str = "TrainDaily Local CA";
+const CA_VALIDITY_DAYS: i64 = 3650; // 10 years
+
+// What leaf certificates may name: LAN, VPN overlay (CGNAT), link-local and
+// loopback addresses, and mDNS / loopback host names
+const PERMITTED_NETWORKS: &[(&str, u8)] = &[
+    ("10.0.0.0", 8),
+    ("172.16.0.0", 12),
+    ("192.168.0.0", 16),
+    ("100.64.0.0", 10),
+    ("169.254.0.0", 16),
+    ("127.0.0.0", 8),
+    ("::1", 128),
+    ("fc00::", 7),
+    ("fe80::", 10),
+];
+const PERMITTED_DOMAINS: &[&str] = &["local", "localhost"];
+
+// Matches the bundle identifier in tauri.conf.json
+const PROFILE_IDENTIFIER: &str = "com.traindaily.desktop";
+
+pub struct CertificateAuthority {
+    pub cert_pem: String,
+    pub cert_der: Vec<u8>,
+    pub not_after: DateTime<Utc>,
+    key_pair: KeyPair,
+    // Re-derived from the stored key; only used as the issuer when signing
+    issuer: rcgen::Certificate,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CaMeta {
+    not_after: DateTime<Utc>,
+    /// False for CAs from before name constraints, which are replaced
+    #[serde(default)]
+    name_constrained: bool,
+}
+
+impl CertificateAuthority {
+    /// Load the persistent CA, creating it on first run or after expiry
+    pub fn get_or_create() -> Result<Self> {
+        if let Some(ca) = Self::load()? {
+            if Utc::now() < ca.not_after {
+                return Ok(ca);
+            }
+            tracing::warn!("Local CA expired, generating a new one (devices must re-trust it)");
+        }
+
+        remove_unconstrained_ca();
+        Self::generate()
+    }
+
+    /// SHA-256 fingerprint of the CA certificate (hex)
+    pub fn fingerprint(&self) -> String {
+        hex::encode(Sha256::digest(&self.cert_der))
+    }
+
+    /// Sign a leaf certificate for `key_pair` with this CA
+    pub fn issue(&self, params: CertificateParams, key_pair: &KeyPair) -> Result<rcgen::Certificate> {
+        params
+            .signed_by(key_pair, &self.issuer, &self.key_pair)
+            .context("Failed to issue certificate from local CA")
+    }
+
+    /// Apple configuration profile that installs the CA as a trusted root
+    pub fn mobileconfig(&self) -> Vec<u8> {
+        let fingerprint = Sha256::digest(&self.cert_der);
+        let profile_uuid = uuid_from(&fingerprint[0..16]);
+        let payload_uuid = uuid_from(&fingerprint[16..32]);
+        let short_id = &self.fingerprint()[..16];
+        let cert_b64 = base64::engine::general_purpose::STANDARD.encode(&self.cert_der);
+
+        let host = super::local_hostname().unwrap_or_else(|| "this Mac".to_string());
+
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>PayloadContent</key>
+    <array>
+        <dict>
+            <key>PayloadCertificateFileName</key>
+            <string>traindaily-ca.cer</string>
+            <key>PayloadContent</key>
+            <data>{cert_b64}</data>
+            <key>PayloadDescription</key>
+            <string>Lets this device verify the TrainDaily desktop sync server.</string>
+            <key>PayloadDisplayName</key>
+            <string>{CA_COMMON_NAME}</string>
+            <key>PayloadIdentifier</key>
+            <string>{PROFILE_IDENTIFIER}.ca.{short_id}</string>
+            <key>PayloadType</key>
+            <string>com.apple.security.root</string>
+            <key>PayloadUUID</key>
+            <string>{payload_uuid}</string>
+            <key>PayloadVersion</key>
+            <integer>1</integer>
+        </dict>
+    </array>
+    <key>PayloadDisplayName</key>
+    <string>TrainDaily Sync ({host})</string>
+    <key>PayloadIdentifier</key>
+    <string>{PROFILE_IDENTIFIER}.profile.{short_id}</string>
+    <key>PayloadRemovalDisallowed</key>
+    <false/>
+    <key>PayloadType</key>
+    <string>Configuration</string>
+    <key>PayloadUUID</key>
+    <string>{profile_uuid}</string>
+    <key>PayloadVersion</key>
+    <integer>1</integer>
+</dict>
+</plist>
+"#,
+            host = xml_escape(&host),
+        )
+        .into_bytes()
+    }
+
+    fn load() -> Result<Option<Self>> {
+        let private_dir = crate::db::private_dir();
+        let cert_path = private_dir.join(CA_CERT_FILE);
+        let key_path = private_dir.join(CA_KEY_FILE);
+        let meta_path = private_dir.join(CA_META_FILE);
+
+        if !(cert_path.exists() && key_path.exists() && meta_path.exists()) {
+            return Ok(None);
+        }
+
+        let cert_pem = fs::read_to_string(&cert_path)
+            .context("Failed to read CA certificate")?;
+        let key_pem = fs::read_to_string(&key_path)
+            .context("Failed to read CA private key")?;
+        let meta: CaMeta = serde_json::from_str(&fs::read_to_string(&meta_path)?)
+            .context("Failed to parse CA metadata")?;
+        if !meta.name_constrained {
+            tracing::warn!("Local CA has no name constraints, replacing it (devices must re-trust it)");
+            return Ok(None);
+        }
+
+        let key_pair = KeyPair::from_pem(&key_pem)
+            .context("Failed to parse CA private key")?;
+        let cert_der = pem_to_der(&cert_pem)?;
+        let issuer = ca_params(meta.not_after)?.self_signed(&key_pair)?;
+
+        Ok(Some(Self {
+            cert_pem,
+            cert_der,
+            not_after: meta.not_after,
+            key_pair,
+            issuer,
+        }))
+    }
+
+    fn generate() -> Result<Self> {
+        let not_after = Utc::now() + Duration::days(CA_VALIDITY_DAYS);
+
+        let key_pair = KeyPair::generate()
+            .context("Failed to generate CA key pair")?;
+        let issuer = ca_params(not_after)?.self_signed(&key_pair)
+            .context("Failed to generate CA certificate")?;
+
+        let cert_pem = issuer.pem();
+        let cert_der = issuer.der().to_vec();
+
+        let private_dir = crate::db::private_dir();
+        create_private_dir(&private_dir)?;
+        fs::write(private_dir.join(CA_CERT_FILE), &cert_pem)
+            .context("Failed to write CA certificate")?;
+        write_private(&private_dir.join(CA_KEY_FILE), key_pair.serialize_pem().as_bytes())
+            .context("Failed to write CA private key")?;
+        let meta = CaMeta { not_after, name_constrained: true };
+        fs::write(private_dir.join(CA_META_FILE), serde_json::to_vec_pretty(&meta)?)
+            .context("Failed to write CA metadata")?;
+
+        tracing::info!("Generated local certificate authority");
+
+        Ok(Self {
+            cert_pem,
+            cert_der,
+            not_after,
+            key_pair,
+            issuer,
+        })
+    }
+}
+
+/// CA parameters; deterministic apart from the dates, so the issuer can be
+/// re-derived from the stored key without parsing the certificate
+fn ca_params(not_after: DateTime<Utc>) -> Result<CertificateParams> {
+    let mut params = CertificateParams::new(Vec::<String>::new())?;
+
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, CA_COMMON_NAME);
+    name.push(DnType::OrganizationName, "TrainDaily");
+    params.distinguished_name = name;
+
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.name_constraints = Some(NameConstraints {
+        permitted_subtrees: PERMITTED_DOMAINS
+            .iter()
+            .map(|domain| GeneralSubtree::DnsName(domain.to_string()))
+            .chain(PERMITTED_NETWORKS.iter().map(|&(network, prefix)| {
+                GeneralSubtree::IpAddress(CidrSubnet::from_addr_prefix(parse_ip(network), prefix))
+            }))
+            .collect(),
+        excluded_subtrees: Vec::new(),
+    });
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+
+    let not_before = not_after - Duration::days(CA_VALIDITY_DAYS + 1);
+    params.not_before = rcgen::date_time_ymd(not_before.year(), not_before.month() as u8, not_before.day() as u8);
+    params.not_after = rcgen::date_time_ymd(not_after.year(), not_after.month() as u8, not_after.day() as u8);
+
+    Ok(params)
+}
+
+/// Whether a leaf may name `name` under the CA's name constraints
+pub fn permits(name: &str) -> bool {
+    match name.parse::<IpAddr>() {
+        Ok(ip) => PERMITTED_NETWORKS
+            .iter()
+            .any(|&(network, prefix)| in_network(ip.to_canonical(), parse_ip(network), prefix)),
+        Err(_) => {
+            let name = name.trim_end_matches('.').to_lowercase();
+            PERMITTED_DOMAINS
+                .iter()
+                .any(|domain| name == *domain || name.ends_with(&format!(".{}", domain)))
+        }
+    }
+}
+
+fn parse_ip(value: &str) -> IpAddr {
+    value.parse().expect("valid constant address")
+}
+
+fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+pub(super) fn create_private_dir(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).context("Failed to create private data directory")?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+/// Write a file only its owner can read (0600), also when it already exists
+pub(super) fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    use std::io::Write;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        let mut file = options.open(path)?;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        file.write_all(contents)?;
+    }
+    #[cfg(not(unix))]
+    options.open(path)?.write_all(contents)?;
+    Ok(())
+}
+
+/// Delete the CA older versions kept in the shared data directory, key included
+fn remove_unconstrained_ca() {
+    let data_dir = crate::db::data_dir();
+    for file in [CA_KEY_FILE, CA_CERT_FILE, CA_META_FILE] {
+        let path = data_dir.join(file);
+        if path.exists() {
+            if let Err(e) = fs::remove_file(&path) {
+                tracing::warn!("Failed to remove old CA file {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// First certificate in a PEM string, as DER
+fn pem_to_der(pem: &str) -> Result<Vec<u8>> {
+    rustls_pemfile::certs(&mut pem.as_bytes())
+        .next()
+        .ok_or_else(|| anyhow!("No certificate in PEM"))?
+        .map(|der| der.to_vec())
+        .context("Failed to parse PEM certificate")
+}
+
+/// Stable UUID derived from fingerprint bytes (so re-downloads replace the profile)
+fn uuid_from(bytes: &[u8]) -> String {
+    let mut buf = [0u8; 16];
+    buf.copy_from_slice(bytes);
+    uuid::Builder::from_random_bytes(buf).into_uuid().to_string().to_uppercase()
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::client::danger::ServerCertVerifier;
+    use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_leaf_chains_to_ca() {
+        let ca = CertificateAuthority::get_or_create().unwrap();
+
+        let key_pair = KeyPair::generate().unwrap();
+        let params = CertificateParams::new(vec!["192.168.1.42".to_string()]).unwrap();
+        let leaf = ca.issue(params, &key_pair).unwrap();
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(CertificateDer::from(ca.cert_der.clone())).unwrap();
+        let verifier = rustls::client::WebPkiServerVerifier::builder_with_provider(
+            Arc::new(roots),
+            Arc::new(rustls::crypto::aws_lc_rs::default_provider()),
+        )
+        .build()
+        .unwrap();
+
+        let server_name = ServerName::try_from("192.168.1.42").unwrap();
+        let result = verifier.verify_server_cert(
+            leaf.der(),
+            &[],
+            &server_name,
+            &[],
+            UnixTime::now(),
+        );
+        assert!(result.is_ok(), "{:?}", result.err());
+    }
+
+    #[test]
+    fn test_name_constraints() {
+        let ca = CertificateAuthority::get_or_create().unwrap();
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(CertificateDer::from(ca.cert_der.clone())).unwrap();
+        let verifier = rustls::client::WebPkiServerVerifier::builder_with_provider(
+            Arc::new(roots),
+            Arc::new(rustls::crypto::aws_lc_rs::default_provider()),
+        )
+        .build()
+        .unwrap();
+
+        // Nothing on the internet, even if the CA key leaks
+        for name in ["example.com", "8.8.8.8"] {
+            let key_pair = KeyPair::generate().unwrap();
+            let leaf = ca.issue(CertificateParams::new(vec![name.to_string()]).unwrap(), &key_pair).unwrap();
+            let server_name = ServerName::try_from(name).unwrap();
+            assert!(verifier.verify_server_cert(leaf.der(), &[], &server_name, &[], UnixTime::now()).is_err());
+        }
+
+        assert!(permits("studio-mac.local") && permits("localhost") && permits("::1"));
+        assert!(permits("10.1.2.3") && permits("172.20.0.5") && permits("100.101.5.6") && permits("fd12::5"));
+        assert!(!permits("172.32.0.1") && !permits("8.8.8.8") && !permits("2001:db8::1") && !permits("example.com"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let key = fs::metadata(crate::db::private_dir().join(CA_KEY_FILE)).unwrap();
+            assert_eq!(key.permissions().mode() & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_mobileconfig_contains_root_payload() {
+        let ca = CertificateAuthority::get_or_create().unwrap();
+        let profile = String::from_utf8(ca.mobileconfig()).unwrap();
+
+        assert!(profile.contains("com.apple.security.root"));
+        assert!(profile.contains(&base64::engine::general_purpose::STANDARD.encode(&ca.cert_der)));
+        // Stable across calls so reinstalling replaces rather than duplicates
+        assert_eq!(profile, String::from_utf8(ca.mobileconfig()).unwrap());
+    }
+}
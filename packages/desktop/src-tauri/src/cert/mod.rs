@@ -1,63 +1,307 @@
 /**
  * Certificate Module
  *
- * Generates self-signed TLS certificates for HTTPS sync server
- * Certificate is generated once and stored in shared data directory
+ * Issues TLS certificates for HTTPS sync server from a local CA (see ca.rs)
+ * Certificate is stored in shared data directory and regenerated when:
+ * - The set of local addresses / .local hostname changes (SAN mismatch)
+ * - It is close to expiry
+ * - The local CA changed
+ *
+ * The leaf key pair is kept across rotations, so clients can pin its
+ * SHA-256 fingerprint (shared through the pairing QR code). Being this
+ * desktop's identity, it lives in the per-user private directory (see
+ * db::private_dir), readable by its owner only.
+ *
+ * Also the client side for outbound connections to other services
+ * (webhooks, MQTT): see client_tls_config.
  */
 
+pub mod ca;
+
 use anyhow::{Context, Result};
-use rcgen::generate_simple_self_signed;
+use chrono::{DateTime, Datelike, Duration, Utc};
+use ca::CertificateAuthority;
+use rcgen::{
+    CertificateParams, DistinguishedName, DnType, ExtendedKeyUsagePurpose, KeyPair,
+    KeyUsagePurpose,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-const SHARED_DATA_DIR: &str = "/Users/Shared/TrainDaily";
 const CERT_FILE: &str = "cert.pem";
 const KEY_FILE: &str = "key.pem";
+const META_FILE: &str = "cert.json";
+
+// Leaf validity; Apple platforms reject TLS certs valid for more than 825 days
+const VALIDITY_DAYS: i64 = 365;
+// Rotate this long before expiry
+const RENEW_BEFORE_DAYS: i64 = 30;
 
 pub struct Certificate {
+    /// Leaf followed by the CA certificate (full chain)
     pub cert_pem: Vec<u8>,
     pub key_pem: Vec<u8>,
+    pub subject_alt_names: Vec<String>,
+    pub not_after: DateTime<Utc>,
+    pub ca_fingerprint: String,
+    /// SHA-256 of the leaf's SubjectPublicKeyInfo (hex), stable across rotations
+    pub key_fingerprint: String,
+}
+
+// Sidecar metadata so rotation checks don't need to parse the PEM
+#[derive(Serialize, Deserialize)]
+struct CertMeta {
+    subject_alt_names: Vec<String>,
+    not_after: DateTime<Utc>,
+    // Empty for self-signed certificates from older versions
+    #[serde(default)]
+    ca_fingerprint: String,
 }
 
 impl Certificate {
-    /// Get or generate TLS certificate
-    pub fn get_or_create() -> Result<Self> {
-        let data_dir = PathBuf::from(SHARED_DATA_DIR);
+    /// Load the stored certificate, regenerating it if it doesn't cover
+    /// `subject_alt_names` or is due for rotation
+    pub fn get_or_create(subject_alt_names: &[String]) -> Result<Self> {
+        let ca = CertificateAuthority::get_or_create()?;
+
+        if let Some(existing) = Self::load()? {
+            if existing.ca_fingerprint == ca.fingerprint() && !existing.needs_rotation(subject_alt_names) {
+                return Ok(existing);
+            }
+            tracing::info!("TLS certificate out of date, rotating");
+        }
+
+        Self::generate(&ca, subject_alt_names)
+    }
+
+    /// Whether this certificate should be replaced
+    pub fn needs_rotation(&self, subject_alt_names: &[String]) -> bool {
+        let current: BTreeSet<&String> = self.subject_alt_names.iter().collect();
+        let desired: BTreeSet<&String> = subject_alt_names.iter().collect();
+
+        current != desired || Utc::now() + Duration::days(RENEW_BEFORE_DAYS) >= self.not_after
+    }
+
+    /// Load certificate + metadata from disk (None if any piece is missing)
+    fn load() -> Result<Option<Self>> {
+        let data_dir = crate::db::data_dir();
         let cert_path = data_dir.join(CERT_FILE);
-        let key_path = data_dir.join(KEY_FILE);
-
-        if cert_path.exists() && key_path.exists() {
-            // Load existing certificate
-            let cert_pem = fs::read(&cert_path)
-                .context("Failed to read certificate")?;
-            let key_pem = fs::read(&key_path)
-                .context("Failed to read private key")?;
-
-            Ok(Self { cert_pem, key_pem })
-        } else {
-            // Generate new certificate
-            let subject_alt_names = vec![
-                "localhost".to_string(),
-                "127.0.0.1".to_string(),
-                "0.0.0.0".to_string(),
-            ];
-
-            let cert = generate_simple_self_signed(subject_alt_names)
-                .context("Failed to generate certificate")?;
-
-            let cert_pem = cert.cert.pem().into_bytes();
-            let key_pem = cert.key_pair.serialize_pem().into_bytes();
-
-            // Save for future runs
-            fs::write(&cert_path, &cert_pem)
-                .context("Failed to write certificate")?;
-            fs::write(&key_path, &key_pem)
-                .context("Failed to write private key")?;
-
-            tracing::info!("Generated new TLS certificate");
-
-            Ok(Self { cert_pem, key_pem })
+        let key_path = key_path()?;
+        let meta_path = data_dir.join(META_FILE);
+
+        // Certificates from older versions have no metadata: treat as missing
+        if !(cert_path.exists() && key_path.exists() && meta_path.exists()) {
+            return Ok(None);
+        }
+
+        let cert_pem = fs::read(&cert_path)
+            .context("Failed to read certificate")?;
+        let key_pem = fs::read(&key_path)
+            .context("Failed to read private key")?;
+
+        // An unreadable key means a fresh certificate (and key) is needed
+        let key_pair = match KeyPair::from_pem(&String::from_utf8_lossy(&key_pem)) {
+            Ok(key_pair) => key_pair,
+            Err(_) => return Ok(None),
+        };
+
+        let meta: CertMeta = match fs::read_to_string(&meta_path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+        {
+            Some(meta) => meta,
+            None => return Ok(None),
+        };
+
+        Ok(Some(Self {
+            cert_pem,
+            key_pem,
+            subject_alt_names: meta.subject_alt_names,
+            not_after: meta.not_after,
+            ca_fingerprint: meta.ca_fingerprint,
+            key_fingerprint: key_fingerprint(&key_pair),
+        }))
+    }
+
+    /// Issue and persist a new leaf certificate from the local CA
+    fn generate(ca: &CertificateAuthority, subject_alt_names: &[String]) -> Result<Self> {
+        let now = Utc::now();
+        let not_after = now + Duration::days(VALIDITY_DAYS);
+
+        let mut params = CertificateParams::new(subject_alt_names.to_vec())
+            .context("Invalid certificate subject alt names")?;
+        let mut name = DistinguishedName::new();
+        name.push(DnType::CommonName, "TrainDaily Sync");
+        params.distinguished_name = name;
+        // rcgen works in calendar days (UTC midnight); backdate a day to
+        // tolerate clock skew between devices
+        let ymd = |date: DateTime<Utc>| {
+            rcgen::date_time_ymd(date.year(), date.month() as u8, date.day() as u8)
+        };
+        params.not_before = ymd(now - Duration::days(1));
+        params.not_after = ymd(not_after);
+        params.key_usages = vec![
+            KeyUsagePurpose::DigitalSignature,
+            KeyUsagePurpose::KeyEncipherment,
+        ];
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        params.use_authority_key_identifier_extension = true;
+
+        // Reuse the existing key so the pinned fingerprint survives rotation
+        let key_pair = match load_key_pair() {
+            Ok(key_pair) => key_pair,
+            Err(_) => KeyPair::generate()
+                .context("Failed to generate key pair")?,
+        };
+        let cert = ca.issue(params, &key_pair)?;
+
+        // Serve the chain so clients that trust the CA can build the path
+        let cert_pem = format!("{}{}", cert.pem(), ca.cert_pem).into_bytes();
+        let key_pem = key_pair.serialize_pem().into_bytes();
+
+        let meta = CertMeta {
+            subject_alt_names: subject_alt_names.to_vec(),
+            not_after,
+            ca_fingerprint: ca.fingerprint(),
+        };
+
+        // Save for future runs
+        let data_dir = crate::db::data_dir();
+        fs::create_dir_all(&data_dir)
+            .context("Failed to create shared data directory")?;
+        fs::write(data_dir.join(CERT_FILE), &cert_pem)
+            .context("Failed to write certificate")?;
+        ca::write_private(&key_path()?, &key_pem)
+            .context("Failed to write private key")?;
+        fs::write(data_dir.join(META_FILE), serde_json::to_vec_pretty(&meta)?)
+            .context("Failed to write certificate metadata")?;
+
+        tracing::info!(
+            "Generated new TLS certificate for [{}], valid until {}",
+            subject_alt_names.join(", "),
+            not_after.format("%Y-%m-%d")
+        );
+
+        Ok(Self {
+            cert_pem,
+            key_pem,
+            subject_alt_names: meta.subject_alt_names,
+            not_after,
+            ca_fingerprint: meta.ca_fingerprint,
+            key_fingerprint: key_fingerprint(&key_pair),
+        })
+    }
+}
+
+/// Fingerprint clients pin: SHA-256 of the SubjectPublicKeyInfo (hex)
+pub fn key_fingerprint(key_pair: &KeyPair) -> String {
+    hex::encode(Sha256::digest(key_pair.public_key_der()))
+}
+
+/// Fingerprint of the stored server key (the key outlives certificates)
+pub fn pinned_key_fingerprint() -> Result<String> {
+    Ok(key_fingerprint(&load_key_pair()?))
+}
+
+fn load_key_pair() -> Result<KeyPair> {
+    let key_pem = fs::read_to_string(key_path()?)
+        .context("Failed to read private key")?;
+    KeyPair::from_pem(&key_pem).context("Failed to parse private key")
+}
+
+/// Where the leaf key lives, once any key from an older version is moved there
+fn key_path() -> Result<PathBuf> {
+    let private_dir = crate::db::private_dir();
+    ca::create_private_dir(&private_dir)?;
+    migrate_shared_key(&crate::db::data_dir(), &private_dir)?;
+    Ok(private_dir.join(KEY_FILE))
+}
+
+/// Move the key older versions kept in the shared data directory into
+/// `private_dir`, keeping the fingerprint peers and phones have pinned
+fn migrate_shared_key(data_dir: &Path, private_dir: &Path) -> Result<()> {
+    let shared = data_dir.join(KEY_FILE);
+    if !shared.exists() {
+        return Ok(());
+    }
+    let private = private_dir.join(KEY_FILE);
+    if !private.exists() {
+        let key_pem = fs::read(&shared).context("Failed to read private key")?;
+        ca::write_private(&private, &key_pem).context("Failed to write private key")?;
+        tracing::info!("Moved TLS key to {}", private_dir.display());
+    }
+    fs::remove_file(&shared).context("Failed to remove the shared copy of the private key")
+}
+
+/// TLS client configuration for outbound connections: trusts the system's
+/// root certificates, or only the CA certificates in `ca_pem` when given
+/// (e.g. a self-hosted MQTT broker)
+pub fn client_tls_config(ca_pem: Option<&[u8]>) -> Result<rustls::ClientConfig> {
+    let provider = std::sync::Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let config = match ca_pem {
+        Some(mut pem) => {
+            let mut roots = rustls::RootCertStore::empty();
+            for cert in rustls_pemfile::certs(&mut pem) {
+                roots.add(cert.context("Invalid CA certificate")?)?;
+            }
+            if roots.is_empty() {
+                anyhow::bail!("No certificates in CA file");
+            }
+            builder.with_root_certificates(roots).with_no_client_auth()
         }
+        None => builder
+            .dangerous()
+            .with_custom_certificate_verifier(std::sync::Arc::new(
+                rustls_platform_verifier::Verifier::new(provider)?,
+            ))
+            .with_no_client_auth(),
+    };
+    Ok(config)
+}
+
+/// Names the certificate must cover: loopback, the `.local` hostname and
+/// every LAN address phones may connect through
+pub fn subject_alt_names(local_ips: &[String]) -> Vec<String> {
+    let mut names = vec![
+        "localhost".to_string(),
+        "127.0.0.1".to_string(),
+        "::1".to_string(),
+    ];
+
+    if let Some(host) = local_hostname() {
+        names.push(host);
+    }
+
+    // Public addresses are outside the CA's name constraints
+    for ip in local_ips.iter().filter(|ip| ca::permits(ip)) {
+        if !names.contains(ip) {
+            names.push(ip.clone());
+        }
+    }
+
+    names
+}
+
+/// mDNS hostname of this machine (e.g. "studio-mac.local")
+pub fn local_hostname() -> Option<String> {
+    let host = hostname::get().ok()?.into_string().ok()?.to_lowercase();
+    let host = host.trim_end_matches('.');
+    if host.is_empty() {
+        return None;
+    }
+
+    if host.ends_with(".local") {
+        Some(host.to_string())
+    } else {
+        // Keep only the first label of fully-qualified names
+        let label = host.split('.').next().unwrap_or(host);
+        Some(format!("{}.local", label))
     }
 }
 
@@ -67,8 +311,64 @@ mod tests {
 
     #[test]
     fn test_certificate_generation() {
-        let cert = Certificate::get_or_create().unwrap();
+        let names = subject_alt_names(&["192.168.1.42".to_string()]);
+        let cert = Certificate::get_or_create(&names).unwrap();
         assert!(!cert.cert_pem.is_empty());
         assert!(!cert.key_pem.is_empty());
+        assert!(cert.subject_alt_names.contains(&"192.168.1.42".to_string()));
+        assert!(!cert.needs_rotation(&names));
+    }
+
+    #[test]
+    fn test_rotation_on_san_change_or_expiry() {
+        let names = vec!["localhost".to_string(), "192.168.1.42".to_string()];
+        let cert = Certificate {
+            cert_pem: Vec::new(),
+            key_pem: Vec::new(),
+            subject_alt_names: names.clone(),
+            not_after: Utc::now() + Duration::days(VALIDITY_DAYS),
+            ca_fingerprint: String::new(),
+            key_fingerprint: String::new(),
+        };
+
+        assert!(!cert.needs_rotation(&names));
+        assert!(cert.needs_rotation(&["localhost".to_string(), "10.0.0.7".to_string()]));
+
+        let expiring = Certificate {
+            not_after: Utc::now() + Duration::days(RENEW_BEFORE_DAYS - 1),
+            ..cert
+        };
+        assert!(expiring.needs_rotation(&names));
+    }
+
+    #[test]
+    fn test_key_pinned_across_rotation() {
+        let first = Certificate::get_or_create(&subject_alt_names(&["192.168.1.42".to_string()])).unwrap();
+        let rotated = Certificate::get_or_create(&subject_alt_names(&["10.0.0.7".to_string()])).unwrap();
+
+        assert_eq!(first.key_fingerprint.len(), 64);
+        assert_eq!(first.key_fingerprint, rotated.key_fingerprint);
+        assert_eq!(pinned_key_fingerprint().unwrap(), rotated.key_fingerprint);
+    }
+
+    #[test]
+    fn test_shared_key_moves_to_private_dir() {
+        let root = crate::db::data_dir().join("key-migration");
+        let (shared, private) = (root.join("shared"), root.join("private"));
+        fs::create_dir_all(&shared).unwrap();
+        fs::create_dir_all(&private).unwrap();
+        let key_pem = KeyPair::generate().unwrap().serialize_pem();
+        fs::write(shared.join(KEY_FILE), &key_pem).unwrap();
+
+        migrate_shared_key(&shared, &private).unwrap();
+
+        assert!(!shared.join(KEY_FILE).exists());
+        assert_eq!(fs::read_to_string(private.join(KEY_FILE)).unwrap(), key_pem);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let key = fs::metadata(private.join(KEY_FILE)).unwrap();
+            assert_eq!(key.permissions().mode() & 0o777, 0o600);
+        }
     }
 }
@@ -0,0 +1,98 @@
+/**
+ * Change Bus Module
+ *
+ * App-wide broadcast of data changes, owned by AppState
+ * - Database publishes a typed event after every write, whatever its origin
+ *   (Tauri commands, sync server, ...)
+ * - Enforcement events too: blocker and micro-break overlay state, broken
+ *   streaks (published by their modules, never stored)
+ * - Consumers: sync SSE/WebSocket streams, frontend ("data-changed" event),
+ *   blocker, webhooks
+ */
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+// Events buffered per subscriber before it starts lagging
+const BUS_CAPACITY: usize = 256;
+
+/// Tauri event name the frontend listens on
+pub const FRONTEND_EVENT: &str = "data-changed";
+
+/// Who made the change
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeOrigin {
+    /// The desktop app itself (Tauri commands, setup)
+    Desktop,
+    /// A phone through the sync server, or an offline bundle (see bundle module)
+    Sync,
+    /// Another desktop, replicated (see peers module)
+    Peer,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", rename_all_fields = "camelCase")]
+pub enum ChangeKind {
+    /// `change_id` is the change log id (see db::record_change)
+    SessionSaved { change_id: i64, date_key: String },
+    FirstSessionDateSet { date_key: String },
+    /// Values aren't broadcast: settings include secrets
+    SettingChanged { key: String },
+    /// A desktop peer was paired, updated or removed
+    PeersChanged,
+    /// A webhook target was added or removed (see webhooks module)
+    WebhooksChanged,
+    /// A per-date schedule override was set or cleared (template changes
+    /// arrive as SettingChanged)
+    ScheduleChanged { date_key: String },
+    /// The blocker's grace period started; the full-screen block comes at
+    /// `blocks_at` (RFC 3339) unless the workout is logged first
+    BlockerWarning { date_key: String, blocks_at: String },
+    /// The blocker went up on a training day without a workout
+    BlockerShown { date_key: String },
+    /// The blocker came down (the workout was logged)
+    BlockerCleared { date_key: String },
+    /// Blocking suspended until `suspended_until` without a workout (the
+    /// reason stays in the database)
+    EmergencyUnlocked { date_key: String, suspended_until: String },
+    MicroBreakShown,
+    MicroBreakDismissed,
+    /// Postponed because the microphone is in use (on a call)
+    MicroBreakDeferred { minutes: u64 },
+    /// A training day passed without a workout, ending a streak of `length`
+    StreakBroken { length: u32, missed_date: String },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ChangeEvent {
+    pub origin: ChangeOrigin,
+    pub kind: ChangeKind,
+}
+
+#[derive(Clone)]
+pub struct ChangeBus {
+    tx: broadcast::Sender<ChangeEvent>,
+}
+
+impl ChangeBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(BUS_CAPACITY);
+        Self { tx }
+    }
+
+    /// Publish an event (no-op when nobody is listening)
+    pub fn publish(&self, event: ChangeEvent) {
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.tx.subscribe()
+    }
+}
+
+impl Default for ChangeBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
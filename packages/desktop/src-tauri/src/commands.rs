@@ -4,6 +4,7 @@
  * Exposed to frontend via invoke()
  */
 
+use crate::changes::ChangeOrigin;
 use crate::db::WorkoutSession;
 use crate::AppState;
 use std::collections::HashMap;
@@ -22,7 +23,9 @@ pub fn save_session(
     state: State<AppState>,
 ) -> Result<(), String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.save_session(&date_key, &session).map_err(|e| e.to_string())
+    db.save_session(&date_key, &session, ChangeOrigin::Desktop)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -34,7 +37,43 @@ pub fn get_first_session_date(state: State<AppState>) -> Result<Option<String>,
 #[tauri::command]
 pub fn set_first_session_date(date_key: String, state: State<AppState>) -> Result<(), String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.set_first_session_date(&date_key).map_err(|e| e.to_string())
+    db.set_first_session_date(&date_key, ChangeOrigin::Desktop).map_err(|e| e.to_string())
+}
+
+/// Current template, the presets to pick from, and `days` days from `from`
+/// (default: 14 days from today)
+#[tauri::command]
+pub fn get_schedule(
+    from: Option<String>,
+    days: Option<u32>,
+    state: State<AppState>,
+) -> Result<crate::schedule::ScheduleView, String> {
+    let from = match from {
+        Some(key) => crate::schedule::parse_date_key(&key).map_err(|e| e.to_string())?,
+        None => chrono::Local::now().date_naive(),
+    };
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    crate::schedule::view(&db, from, days.unwrap_or(14)).map_err(|e| e.to_string())
+}
+
+/// Replace the schedule template (weekly plan or rotation)
+#[tauri::command]
+pub fn set_schedule_template(template: crate::schedule::Template, state: State<AppState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    crate::schedule::save_template(&db, &template).map_err(|e| e.to_string())
+}
+
+/// Set one date's workout, or return it to the template with `null`
+#[tauri::command]
+pub fn set_schedule_override(
+    date_key: String,
+    workout_type: Option<crate::schedule::WorkoutType>,
+    state: State<AppState>,
+) -> Result<(), String> {
+    crate::schedule::parse_date_key(&date_key).map_err(|e| e.to_string())?;
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_schedule_override(&date_key, workout_type.map(|workout| workout.as_str()))
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -53,6 +92,240 @@ pub fn check_mic_active() -> bool {
     }
 }
 
+/// LAN addresses the phone could use, best first (pin one via the
+/// `sync_preferred_address` setting)
+#[tauri::command]
+pub fn list_network_addresses(state: State<AppState>) -> Result<Vec<crate::network::NetworkAddress>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    crate::network::candidate_addresses_for(&db).map_err(|e| e.to_string())
+}
+
+/// Connected sync clients, per-device activity and recent errors
+/// (pushed as `sync-status-changed` events too)
+#[tauri::command]
+pub fn get_sync_status(state: State<AppState>) -> crate::sync::status::SyncStatus {
+    state.sync_status.snapshot()
+}
+
+/// Switch the sync server on or off (persisted across launches)
+#[tauri::command]
+pub async fn set_sync_server_enabled(enabled: bool, state: State<'_, AppState>) -> Result<(), String> {
+    {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        let value = if enabled { "true" } else { "false" };
+        db.set_setting(crate::sync::handle::ENABLED_SETTING, value, ChangeOrigin::Desktop)
+            .map_err(|e| e.to_string())?;
+    }
+
+    if enabled {
+        state.sync.start().await.map_err(|e| e.to_string())?;
+    } else {
+        state.sync.stop(crate::sync::handle::SHUTDOWN_DEADLINE).await;
+    }
+    Ok(())
+}
+
+/// Restart the sync server, e.g. after changing its port or bind addresses
+#[tauri::command]
+pub async fn restart_sync_server(state: State<'_, AppState>) -> Result<crate::sync::listen::Listening, String> {
+    state.sync.restart().await.map_err(|e| e.to_string())
+}
+
+/// Paired and nearby desktops (see peers module)
+#[tauri::command]
+pub fn list_peers(state: State<AppState>) -> Result<Vec<crate::peers::PeerSummary>, String> {
+    state.peers.list().map_err(|e| e.to_string())
+}
+
+/// Pair with another desktop from its pairing URL; returns its device id
+#[tauri::command]
+pub async fn pair_peer(pairing_url: String, state: State<'_, AppState>) -> Result<String, String> {
+    state.peers.pair(&pairing_url).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn unpair_peer(device_id: String, state: State<AppState>) -> Result<(), String> {
+    state.peers.unpair(&device_id).map_err(|e| e.to_string())
+}
+
+/// Write an offline sync bundle of changes after `after` (default: everything) to `folder`
+#[tauri::command]
+pub fn export_bundle(
+    folder: String,
+    after: Option<i64>,
+    encrypt: Option<bool>,
+    state: State<AppState>,
+) -> Result<crate::bundle::BundleWritten, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    crate::bundle::write(
+        &db,
+        &state.device_id,
+        &state.auth_token,
+        std::path::Path::new(&folder),
+        after.unwrap_or(0),
+        encrypt.unwrap_or(true),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Verify and apply an offline sync bundle from a phone or paired desktop
+#[tauri::command]
+pub fn import_bundle(path: String, state: State<AppState>) -> Result<crate::bundle::BundleIngested, String> {
+    let mut db = state.db.lock().map_err(|e| e.to_string())?;
+    crate::bundle::ingest(&mut db, &state.device_id, &state.auth_token, std::path::Path::new(&path))
+        .map_err(|e| e.to_string())
+}
+
+/// When the blocker comes up on training days (windows, grace period, deadlines)
+#[tauri::command]
+pub fn get_blocker_policy(state: State<AppState>) -> Result<crate::blocker::policy::BlockerPolicy, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    crate::blocker::policy::BlockerPolicy::load(&db).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_blocker_policy(policy: crate::blocker::policy::BlockerPolicy, state: State<AppState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    policy.save(&db).map_err(|e| e.to_string())
+}
+
+/// Emergency unlocks left this month, and the unlock in effect if any
+#[tauri::command]
+pub fn get_emergency_unlock_status(state: State<AppState>) -> Result<crate::blocker::unlock::UnlockStatus, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    crate::blocker::unlock::status(&db, chrono::Local::now()).map_err(|e| e.to_string())
+}
+
+/// Start an emergency unlock: the phrase to type, or when waiting is enough
+#[tauri::command]
+pub fn start_emergency_unlock(state: State<AppState>) -> Result<crate::blocker::unlock::Challenge, String> {
+    // Blocker state before the database, like the blocker task
+    let mut blocker = state.blocker_state.lock().map_err(|e| e.to_string())?;
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let challenge = crate::blocker::unlock::start_challenge(&db, chrono::Local::now()).map_err(|e| e.to_string())?;
+    blocker.unlock_challenge = Some(challenge.clone());
+    Ok(challenge)
+}
+
+/// Finish the started unlock with its reason and the typed phrase (optional
+/// once the countdown is over)
+#[tauri::command]
+pub fn confirm_emergency_unlock(
+    reason: String,
+    phrase: Option<String>,
+    state: State<AppState>,
+) -> Result<crate::db::EmergencyUnlockRecord, String> {
+    let mut blocker = state.blocker_state.lock().map_err(|e| e.to_string())?;
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let record = crate::blocker::unlock::unlock(
+        &db,
+        blocker.unlock_challenge.as_ref(),
+        &reason,
+        phrase.as_deref(),
+        chrono::Local::now(),
+    )
+    .map_err(|e| e.to_string())?;
+    blocker.unlock_challenge = None;
+    Ok(record)
+}
+
+/// Emergency unlock log, newest first
+#[tauri::command]
+pub fn list_emergency_unlocks(
+    limit: Option<usize>,
+    state: State<AppState>,
+) -> Result<Vec<crate::db::EmergencyUnlockRecord>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_emergency_unlocks(limit.unwrap_or(20)).map_err(|e| e.to_string())
+}
+
+/// URL to subscribe to the calendar feed from a calendar app
+#[tauri::command]
+pub fn get_calendar_url(state: State<AppState>) -> Result<String, String> {
+    let listening = state.sync.listening()
+        .ok_or_else(|| "Sync server is not running".to_string())?;
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    crate::sync::calendar_url(&db, &listening).map_err(|e| e.to_string())
+}
+
+/// Revoke the calendar token (existing subscriptions stop updating); returns the new URL
+#[tauri::command]
+pub fn revoke_calendar_token(state: State<AppState>) -> Result<String, String> {
+    let listening = state.sync.listening()
+        .ok_or_else(|| "Sync server is not running".to_string())?;
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    crate::calendar::revoke_token(&db).map_err(|e| e.to_string())?;
+    crate::sync::calendar_url(&db, &listening).map_err(|e| e.to_string())
+}
+
+/// Write the iCalendar feed (schedule and completed sessions) into `folder`
+#[tauri::command]
+pub fn export_calendar(folder: String, state: State<AppState>) -> Result<std::path::PathBuf, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    crate::calendar::write(&db, &state.device_id, std::path::Path::new(&folder)).map_err(|e| e.to_string())
+}
+
+/// Write logged sessions from `from` to `to` (YYYY-MM-DD, inclusive) into
+/// `folder` as activity files (default: FIT and TCX)
+#[tauri::command]
+pub fn export_activities(
+    from: String,
+    to: String,
+    folder: String,
+    formats: Option<Vec<crate::activity::ActivityFormat>>,
+    state: State<AppState>,
+) -> Result<crate::activity::ActivityExport, String> {
+    let parse = |value: &str| {
+        chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| format!("Invalid date '{}'", value))
+    };
+    let formats = formats.unwrap_or_else(|| vec![crate::activity::ActivityFormat::Fit, crate::activity::ActivityFormat::Tcx]);
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    crate::activity::export_range(&db, parse(&from)?, parse(&to)?, std::path::Path::new(&folder), &formats)
+        .map_err(|e| e.to_string())
+}
+
+/// Outbound webhook targets (see webhooks module)
+#[tauri::command]
+pub fn list_webhooks(state: State<AppState>) -> Result<Vec<crate::webhooks::WebhookSummary>, String> {
+    state.webhooks.list().map_err(|e| e.to_string())
+}
+
+/// Add a webhook target for `events` (default: all); the summary includes its signing secret
+#[tauri::command]
+pub fn add_webhook(
+    url: String,
+    events: Option<Vec<String>>,
+    state: State<AppState>,
+) -> Result<crate::webhooks::WebhookSummary, String> {
+    state.webhooks.add(&url, &events.unwrap_or_default()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn remove_webhook(id: i64, state: State<AppState>) -> Result<(), String> {
+    state.webhooks.remove(id).map_err(|e| e.to_string())
+}
+
+/// Send a `ping` event to one webhook target
+#[tauri::command]
+pub fn test_webhook(id: i64, state: State<AppState>) -> Result<(), String> {
+    state.webhooks.send_test(id).map_err(|e| e.to_string())
+}
+
+/// Recent webhook deliveries, newest first
+#[tauri::command]
+pub fn list_webhook_deliveries(
+    limit: Option<usize>,
+    state: State<AppState>,
+) -> Result<Vec<crate::webhooks::DeliverySummary>, String> {
+    state.webhooks.deliveries(limit.unwrap_or(50)).map_err(|e| e.to_string())
+}
+
+/// MQTT broker connection (configured through the mqtt_* settings)
+#[tauri::command]
+pub fn get_mqtt_status(state: State<AppState>) -> crate::mqtt::MqttStatus {
+    state.mqtt.status()
+}
+
 #[tauri::command]
 pub fn get_setting(key: String, state: State<AppState>) -> Result<Option<String>, String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
@@ -62,7 +335,7 @@ pub fn get_setting(key: String, state: State<AppState>) -> Result<Option<String>
 #[tauri::command]
 pub fn set_setting(key: String, value: String, state: State<AppState>) -> Result<(), String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.set_setting(&key, &value).map_err(|e| e.to_string())
+    db.set_setting(&key, &value, ChangeOrigin::Desktop).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -70,7 +343,7 @@ pub fn set_tray_visible(visible: bool, state: State<AppState>) -> Result<(), Str
     // Persist preference
     {
         let db = state.db.lock().map_err(|e| e.to_string())?;
-        db.set_setting("tray_visible", if visible { "true" } else { "false" })
+        db.set_setting("tray_visible", if visible { "true" } else { "false" }, ChangeOrigin::Desktop)
             .map_err(|e| e.to_string())?;
     }
 
@@ -93,7 +366,7 @@ pub fn set_open_at_login(enabled: bool, app: AppHandle, state: State<AppState>)
     // Persist preference
     {
         let db = state.db.lock().map_err(|e| e.to_string())?;
-        db.set_setting("open_at_login", if enabled { "true" } else { "false" })
+        db.set_setting("open_at_login", if enabled { "true" } else { "false" }, ChangeOrigin::Desktop)
             .map_err(|e| e.to_string())?;
     }
 
@@ -110,13 +383,26 @@ pub fn set_open_at_login(enabled: bool, app: AppHandle, state: State<AppState>)
 
 #[tauri::command]
 pub fn get_qr_code_data(state: State<AppState>) -> Result<String, String> {
-    let local_ip = crate::sync::get_local_ip().map_err(|e| e.to_string())?;
+    pairing_payload(&state)
+}
 
-    let qr_data = crate::sync::generate_qr_data(
-        &state.device_id,
-        &state.auth_token,
-        &local_ip,
-    );
+/// Pairing QR code as an image data URL (`format`: svg or png)
+#[tauri::command]
+pub fn get_qr_code_image(
+    format: Option<crate::qr::ImageFormat>,
+    ec_level: Option<crate::qr::ErrorCorrection>,
+    state: State<AppState>,
+) -> Result<String, String> {
+    let payload = pairing_payload(&state)?;
+    crate::qr::data_url(&payload, format.unwrap_or_default(), ec_level.unwrap_or_default())
+        .map_err(|e| e.to_string())
+}
 
-    Ok(qr_data)
+fn pairing_payload(state: &AppState) -> Result<String, String> {
+    let listening = state.sync.listening()
+        .ok_or_else(|| "Sync server is not running".to_string())?;
+
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    crate::sync::pairing_payload(&db, &state.device_id, &state.auth_token, &listening)
+        .map_err(|e| e.to_string())
 }
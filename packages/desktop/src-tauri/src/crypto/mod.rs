@@ -0,0 +1,72 @@
+/**
+ * Payload Encryption
+ *
+ * AES-256-GCM for data that must stay confidential outside TLS
+ * - Keys are derived from a shared secret with HKDF-SHA256, one per
+ *   purpose, so a key for one kind of payload never opens another
+ * - Random 96-bit nonces, prepended by callers or sent alongside
+ * - AES-GCM rather than ChaCha20 so the PWA can use WebCrypto as-is
+ */
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{anyhow, bail, Result};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 12;
+
+pub type Key = [u8; KEY_LEN];
+
+/// Key for `purpose` derived from a shared secret (e.g. the pairing secret)
+pub fn derive_key(secret: &[u8], purpose: &str) -> Key {
+    let mut key = [0u8; KEY_LEN];
+    Hkdf::<Sha256>::new(None, secret)
+        .expand(purpose.as_bytes(), &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+/// A fresh random key
+pub fn generate_key() -> Key {
+    rand::random()
+}
+
+/// Encrypt `plaintext`; `aad` is authenticated but not encrypted.
+/// Returns (nonce, ciphertext with tag).
+pub fn seal(key: &Key, plaintext: &[u8], aad: &[u8]) -> Result<([u8; NONCE_LEN], Vec<u8>)> {
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let ciphertext = Aes256Gcm::new(key.into())
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+        .map_err(|_| anyhow!("Encryption failed"))?;
+    Ok((nonce, ciphertext))
+}
+
+/// Decrypt and authenticate; fails on a wrong key, nonce or `aad`, or tampering
+pub fn open(key: &Key, nonce: &[u8], ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    if nonce.len() != NONCE_LEN {
+        bail!("Invalid nonce length");
+    }
+    Aes256Gcm::new(key.into())
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+        .map_err(|_| anyhow!("Decryption failed (wrong key or tampered data)"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open_round_trip_and_tamper() {
+        let key = derive_key(b"pairing-secret", "test");
+        let (nonce, mut ciphertext) = seal(&key, b"squats", b"2026-03-01").unwrap();
+
+        assert_eq!(open(&key, &nonce, &ciphertext, b"2026-03-01").unwrap(), b"squats");
+        assert!(open(&key, &nonce, &ciphertext, b"2026-03-02").is_err());
+        assert!(open(&derive_key(b"pairing-secret", "other"), &nonce, &ciphertext, b"2026-03-01").is_err());
+
+        ciphertext[0] ^= 1;
+        assert!(open(&key, &nonce, &ciphertext, b"2026-03-01").is_err());
+    }
+}
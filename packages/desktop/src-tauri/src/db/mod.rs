@@ -3,32 +3,177 @@
  *
  * Manages SQLite database at /Users/Shared/TrainDaily/workouts.db
  * Shared across all macOS user accounts (system-wide storage)
+ * TRAINDAILY_DATA_DIR points it elsewhere (e.g. a second instance on one machine);
+ * tests always get a scratch directory
+ * Every write is published on the change bus (see changes module) once attached
  */
 
+use crate::changes::{ChangeBus, ChangeEvent, ChangeKind, ChangeOrigin};
 use anyhow::{Context, Result};
 use rusqlite::{Connection, params};
 use serde_json::Value as JsonValue;
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 // Shared data directory (accessible by all macOS users)
 const SHARED_DATA_DIR: &str = "/Users/Shared/TrainDaily";
 const DB_FILE: &str = "workouts.db";
 const DEVICE_ID_FILE: &str = "device_id.txt";
+const DATA_DIR_ENV: &str = "TRAINDAILY_DATA_DIR";
+
+// Change log rows kept for sync clients resuming with Last-Event-ID
+const CHANGE_LOG_RETENTION: i64 = 5000;
+
+// Webhook delivery log rows kept (pending deliveries are never pruned)
+const DELIVERY_LOG_RETENTION: i64 = 500;
 
 // Session is stored as a JSON blob — schema-agnostic, works with any exercise keys
 pub type WorkoutSession = JsonValue;
 
 pub struct Database {
     conn: Connection,
+    bus: Option<ChangeBus>,
+}
+
+/// A session write in a batch (see Database::save_sessions)
+pub struct SessionWrite {
+    pub date_key: String,
+    pub session: WorkoutSession,
+    /// Last change id the client saw for this date; None writes unconditionally
+    pub base_change_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WriteOutcome {
+    Applied { change_id: i64 },
+    /// The date changed after `base_change_id`; nothing was written
+    Conflict { current_change_id: i64 },
+}
+
+/// One entry of the change log (written for every session save)
+#[derive(Debug, Clone)]
+pub struct ChangeRecord {
+    pub id: i64,
+    pub date_key: String,
+    pub changed_at: String,
+    /// Device that made the change; None = this desktop (or a phone syncing to it)
+    pub origin: Option<String>,
+}
+
+/// A session change from another desktop (see apply_replicated)
+#[derive(Debug, Clone)]
+pub struct ReplicatedChange {
+    pub date_key: String,
+    pub session: WorkoutSession,
+    /// When and where the change was originally made, kept as-is across hops
+    pub changed_at: String,
+    pub origin: String,
+}
+
+/// A paired desktop we replicate from (see peers module)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerRecord {
+    pub device_id: String,
+    /// The peer's pairing secret
+    pub token: String,
+    /// The peer's pinned server key fingerprint
+    pub fingerprint: String,
+    /// Last known addresses, best first
+    pub addresses: Vec<String>,
+    pub port: u16,
+    /// Last change id of the peer's log that has been applied here
+    pub cursor: i64,
+    pub paired_at: String,
+}
+
+/// An outbound webhook target (see webhooks module)
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookRecord {
+    pub id: i64,
+    pub url: String,
+    /// HMAC key for the X-TrainDaily-Signature header
+    pub secret: String,
+    /// Event names it receives; empty = all
+    pub events: Vec<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Queued, or waiting for a retry
+    Pending,
+    Delivered,
+    /// Gave up after the last retry
+    Failed,
+}
+
+impl DeliveryStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Delivered => "delivered",
+            Self::Failed => "failed",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "delivered" => Self::Delivered,
+            "failed" => Self::Failed,
+            _ => Self::Pending,
+        }
+    }
+}
+
+/// A webhook delivery: queue entry while pending, log entry afterwards
+#[derive(Debug, Clone)]
+pub struct DeliveryRecord {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub event: String,
+    /// The JSON body, exactly as signed and sent
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub next_attempt_at: String,
+    /// HTTP status of the last attempt, if it got a response
+    pub response_status: Option<u16>,
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub completed_at: Option<String>,
+}
+
+/// An emergency unlock of the blocker (see blocker::unlock), kept for good
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmergencyUnlockRecord {
+    pub id: i64,
+    /// The training day it was unlocked on
+    pub date_key: String,
+    pub reason: String,
+    /// "phrase" or "countdown"
+    pub method: String,
+    pub unlocked_at: String,
+    /// Blocking resumes at this time
+    pub suspended_until: String,
+}
+
+/// Result of one delivery attempt (see record_delivery_attempt)
+pub struct DeliveryAttempt<'a> {
+    pub status: DeliveryStatus,
+    pub response_status: Option<u16>,
+    pub error: Option<&'a str>,
+    /// When to retry, for a Pending status
+    pub next_attempt_at: &'a str,
 }
 
 impl Database {
     /// Initialize database (creates directory if needed)
     pub fn new() -> Result<Self> {
         // Create shared data directory
-        let data_dir = PathBuf::from(SHARED_DATA_DIR);
+        let data_dir = data_dir();
         if !data_dir.exists() {
             fs::create_dir_all(&data_dir)
                 .context("Failed to create shared data directory")?;
@@ -42,9 +187,12 @@ impl Database {
             }
         }
 
-        // Open database
-        let db_path = data_dir.join(DB_FILE);
-        let conn = Connection::open(&db_path)
+        Self::open(&data_dir.join(DB_FILE))
+    }
+
+    /// Open (or create) the database file at `db_path`
+    pub fn open(db_path: &Path) -> Result<Self> {
+        let conn = Connection::open(db_path)
             .context("Failed to open database")?;
 
         // Check if old schema exists (has 'pushup' column) and migrate
@@ -85,12 +233,107 @@ impl Database {
             [],
         )?;
 
-        Ok(Self { conn })
+        // Monotonic ids (AUTOINCREMENT never reuses them, even after pruning)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS change_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                date_key TEXT NOT NULL,
+                changed_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Writer of replicated changes (older databases lack the column)
+        let has_origin: bool = conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('change_log') WHERE name='origin'",
+            [],
+            |row| row.get::<_, i64>(0),
+        ).map(|c| c > 0).unwrap_or(false);
+        if !has_origin {
+            conn.execute("ALTER TABLE change_log ADD COLUMN origin TEXT", [])?;
+        }
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS peers (
+                device_id TEXT PRIMARY KEY,
+                token TEXT NOT NULL,
+                fingerprint TEXT NOT NULL,
+                addresses TEXT NOT NULL,
+                port INTEGER NOT NULL,
+                cursor INTEGER NOT NULL DEFAULT 0,
+                paired_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS webhooks (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                url TEXT NOT NULL,
+                secret TEXT NOT NULL,
+                events TEXT NOT NULL DEFAULT '',
+                created_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Per-date exceptions to the schedule template (see schedule module)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS schedule_overrides (
+                date_key TEXT PRIMARY KEY,
+                workout_type TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Retry queue and delivery log in one (status tells them apart)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                webhook_id INTEGER NOT NULL,
+                event TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                next_attempt_at TEXT NOT NULL,
+                response_status INTEGER,
+                last_error TEXT,
+                created_at TEXT NOT NULL,
+                completed_at TEXT
+            )",
+            [],
+        )?;
+
+        // Emergency unlock audit trail (timestamps as db::timestamp text)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS emergency_unlocks (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                date_key TEXT NOT NULL,
+                reason TEXT NOT NULL,
+                method TEXT NOT NULL,
+                unlocked_at TEXT NOT NULL,
+                suspended_until TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        Ok(Self { conn, bus: None })
+    }
+
+    /// Publish every subsequent write on the app-wide change bus
+    pub fn attach_bus(&mut self, bus: ChangeBus) {
+        self.bus = Some(bus);
+    }
+
+    fn publish(&self, origin: ChangeOrigin, kind: ChangeKind) {
+        if let Some(bus) = &self.bus {
+            bus.publish(ChangeEvent { origin, kind });
+        }
     }
 
     /// Get or generate device ID (persists forever)
     pub fn get_device_id(&self) -> Result<String> {
-        let device_id_path = PathBuf::from(SHARED_DATA_DIR).join(DEVICE_ID_FILE);
+        let device_id_path = data_dir().join(DEVICE_ID_FILE);
 
         if device_id_path.exists() {
             // Read existing device ID
@@ -141,17 +384,151 @@ impl Database {
         Ok(map)
     }
 
-    /// Save a workout session
-    pub fn save_session(&self, date_key: &str, session: &WorkoutSession) -> Result<()> {
-        let session_data = serde_json::to_string(session)
-            .unwrap_or_else(|_| "{}".to_string());
+    /// Get a single workout session
+    pub fn get_session(&self, date_key: &str) -> Result<Option<WorkoutSession>> {
+        let result: Option<String> = self.conn.query_row(
+            "SELECT session_data FROM sessions WHERE date_key = ?1",
+            params![date_key],
+            |row| row.get(0),
+        ).ok();
 
-        self.conn.execute(
+        Ok(result.and_then(|json_str| serde_json::from_str(&json_str).ok()))
+    }
+
+    /// Save a workout session, returning its change log id
+    ///
+    /// The upsert and its change log entry commit together, so a session is
+    /// never saved without a change for sync and peers to pick up.
+    pub fn save_session(&self, date_key: &str, session: &WorkoutSession, origin: ChangeOrigin) -> Result<i64> {
+        let tx = self.conn.unchecked_transaction()?;
+        let change_id = write_session(&tx, date_key, session)?;
+        tx.commit().context("Failed to commit session save")?;
+
+        self.publish(origin, ChangeKind::SessionSaved {
+            change_id,
+            date_key: date_key.to_string(),
+        });
+
+        Ok(change_id)
+    }
+
+    /// Save several sessions in one transaction
+    ///
+    /// A write whose `base_change_id` is older than the last change to its
+    /// date is skipped as a conflict; the rest are applied together or, on
+    /// error, not at all. Outcomes are in input order.
+    pub fn save_sessions(&mut self, writes: &[SessionWrite], origin: ChangeOrigin) -> Result<Vec<WriteOutcome>> {
+        let tx = self.conn.transaction()?;
+        let mut outcomes = Vec::with_capacity(writes.len());
+
+        for write in writes {
+            if let Some(base) = write.base_change_id {
+                if let Some(current) = last_change_for(&tx, &write.date_key)?.filter(|&id| id > base) {
+                    outcomes.push(WriteOutcome::Conflict { current_change_id: current });
+                    continue;
+                }
+            }
+            let change_id = write_session(&tx, &write.date_key, &write.session)?;
+            outcomes.push(WriteOutcome::Applied { change_id });
+        }
+
+        tx.commit().context("Failed to commit session batch")?;
+
+        // Only announce once everything is durable
+        for (write, outcome) in writes.iter().zip(&outcomes) {
+            if let WriteOutcome::Applied { change_id } = outcome {
+                self.publish(origin, ChangeKind::SessionSaved {
+                    change_id: *change_id,
+                    date_key: write.date_key.clone(),
+                });
+            }
+        }
+
+        Ok(outcomes)
+    }
+
+    /// Id of the most recent change (0 if nothing has changed yet)
+    pub fn latest_change_id(&self) -> Result<i64> {
+        let id: Option<i64> = self.conn.query_row(
+            "SELECT MAX(id) FROM change_log",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(id.unwrap_or(0))
+    }
+
+    /// Id of the oldest change still retained
+    pub fn oldest_change_id(&self) -> Result<Option<i64>> {
+        let id: Option<i64> = self.conn.query_row(
+            "SELECT MIN(id) FROM change_log",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(id)
+    }
+
+    /// Changes with id greater than `after_id`, oldest first
+    pub fn changes_since(&self, after_id: i64, limit: usize) -> Result<Vec<ChangeRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, date_key, changed_at, origin FROM change_log WHERE id > ?1 ORDER BY id LIMIT ?2"
+        )?;
+
+        let rows = stmt.query_map(params![after_id, limit as i64], change_record)?;
+
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Most recent retained change to one date
+    pub fn last_change(&self, date_key: &str) -> Result<Option<ChangeRecord>> {
+        let record = self.conn.query_row(
+            "SELECT id, date_key, changed_at, origin FROM change_log
+             WHERE date_key = ?1 ORDER BY id DESC LIMIT 1",
+            params![date_key],
+            change_record,
+        );
+
+        match record {
+            Ok(record) => Ok(Some(record)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Apply a change made on another device if it is newer than ours (last writer wins)
+    ///
+    /// Changes are ordered by (changed_at, origin device), so every desktop
+    /// settles on the same version and a change echoed back to the desktop
+    /// that made it is a no-op. `via` is how it arrived.
+    /// Returns the new change id, or None if skipped.
+    pub fn apply_replicated(&self, change: &ReplicatedChange, local_device: &str, via: ChangeOrigin) -> Result<Option<i64>> {
+        let remote = change_stamp(&change.changed_at, &change.origin);
+        let local = self.last_change(&change.date_key)?.map(|record| {
+            let origin = record.origin.unwrap_or_else(|| local_device.to_string());
+            (change_stamp(&record.changed_at, &origin).0, origin)
+        });
+
+        if let Some(local) = local {
+            if (remote.0, remote.1) <= (local.0, local.1.as_str()) {
+                return Ok(None);
+            }
+        }
+
+        let session_data = serde_json::to_string(&change.session)
+            .unwrap_or_else(|_| "{}".to_string());
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
             "INSERT OR REPLACE INTO sessions (date_key, session_data) VALUES (?1, ?2)",
-            params![date_key, session_data],
+            params![change.date_key, session_data],
         )?;
+        let change_id = insert_change(&tx, &change.date_key, &change.changed_at, Some(&change.origin))?;
+        tx.commit()?;
 
-        Ok(())
+        self.publish(via, ChangeKind::SessionSaved {
+            change_id,
+            date_key: change.date_key.clone(),
+        });
+
+        Ok(Some(change_id))
     }
 
     /// Get first session date (for week number calculation)
@@ -166,13 +543,16 @@ impl Database {
     }
 
     /// Set first session date (called once)
-    pub fn set_first_session_date(&self, date_key: &str) -> Result<()> {
+    pub fn set_first_session_date(&self, date_key: &str, origin: ChangeOrigin) -> Result<()> {
         // Only set if not already exists
         if self.get_first_session_date()?.is_none() {
             self.conn.execute(
                 "INSERT INTO metadata (key, value) VALUES ('first_session_date', ?1)",
                 params![date_key],
             )?;
+            self.publish(origin, ChangeKind::FirstSessionDateSet {
+                date_key: date_key.to_string(),
+            });
         }
         Ok(())
     }
@@ -188,13 +568,402 @@ impl Database {
     }
 
     /// Set a generic setting in metadata table
-    pub fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+    pub fn set_setting(&self, key: &str, value: &str, origin: ChangeOrigin) -> Result<()> {
         self.conn.execute(
             "INSERT OR REPLACE INTO metadata (key, value) VALUES (?1, ?2)",
             params![key, value],
         )?;
+        self.publish(origin, ChangeKind::SettingChanged { key: key.to_string() });
+        Ok(())
+    }
+
+    /// Schedule overrides: date key -> workout type
+    pub fn get_schedule_overrides(&self) -> Result<HashMap<String, String>> {
+        let mut stmt = self.conn.prepare("SELECT date_key, workout_type FROM schedule_overrides")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<Result<HashMap<_, _>, _>>()?)
+    }
+
+    /// Set the workout for one date, or go back to the template with None
+    pub fn set_schedule_override(&self, date_key: &str, workout_type: Option<&str>) -> Result<()> {
+        match workout_type {
+            Some(workout_type) => self.conn.execute(
+                "INSERT OR REPLACE INTO schedule_overrides (date_key, workout_type) VALUES (?1, ?2)",
+                params![date_key, workout_type],
+            )?,
+            None => self.conn.execute("DELETE FROM schedule_overrides WHERE date_key = ?1", params![date_key])?,
+        };
+        self.publish(ChangeOrigin::Desktop, ChangeKind::ScheduleChanged { date_key: date_key.to_string() });
         Ok(())
     }
+
+    /// All paired desktops
+    pub fn get_peers(&self) -> Result<Vec<PeerRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT device_id, token, fingerprint, addresses, port, cursor, paired_at FROM peers ORDER BY paired_at"
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            let addresses: String = row.get(3)?;
+            Ok(PeerRecord {
+                device_id: row.get(0)?,
+                token: row.get(1)?,
+                fingerprint: row.get(2)?,
+                addresses: addresses.split(',').filter(|a| !a.is_empty()).map(str::to_string).collect(),
+                port: row.get(4)?,
+                cursor: row.get(5)?,
+                paired_at: row.get(6)?,
+            })
+        })?;
+
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Pair with a desktop, or refresh its credentials and addresses
+    ///
+    /// Re-pairing keeps the replication cursor unless the key changed
+    /// (a different key means a reinstalled peer with a new change log).
+    pub fn save_peer(&self, peer: &PeerRecord) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO peers (device_id, token, fingerprint, addresses, port, cursor, paired_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(device_id) DO UPDATE SET
+                 cursor = CASE WHEN fingerprint = excluded.fingerprint THEN cursor ELSE excluded.cursor END,
+                 token = excluded.token,
+                 fingerprint = excluded.fingerprint,
+                 addresses = excluded.addresses,
+                 port = excluded.port",
+            params![
+                peer.device_id,
+                peer.token,
+                peer.fingerprint,
+                peer.addresses.join(","),
+                peer.port,
+                peer.cursor,
+                peer.paired_at
+            ],
+        )?;
+        self.publish(ChangeOrigin::Desktop, ChangeKind::PeersChanged);
+        Ok(())
+    }
+
+    /// Where a paired desktop was last seen (from discovery)
+    pub fn set_peer_endpoint(&self, device_id: &str, addresses: &[String], port: u16) -> Result<()> {
+        self.conn.execute(
+            "UPDATE peers SET addresses = ?2, port = ?3 WHERE device_id = ?1",
+            params![device_id, addresses.join(","), port],
+        )?;
+        Ok(())
+    }
+
+    /// Record replication progress (not published: it changes constantly)
+    pub fn set_peer_cursor(&self, device_id: &str, cursor: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE peers SET cursor = ?2 WHERE device_id = ?1",
+            params![device_id, cursor],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_peer(&self, device_id: &str) -> Result<bool> {
+        let deleted = self.conn.execute("DELETE FROM peers WHERE device_id = ?1", params![device_id])? > 0;
+        if deleted {
+            self.publish(ChangeOrigin::Desktop, ChangeKind::PeersChanged);
+        }
+        Ok(deleted)
+    }
+
+    pub fn get_webhooks(&self) -> Result<Vec<WebhookRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, url, secret, events, created_at FROM webhooks ORDER BY id"
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            let events: String = row.get(3)?;
+            Ok(WebhookRecord {
+                id: row.get(0)?,
+                url: row.get(1)?,
+                secret: row.get(2)?,
+                events: events.split(',').filter(|e| !e.is_empty()).map(str::to_string).collect(),
+                created_at: row.get(4)?,
+            })
+        })?;
+
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Add a webhook target; returns its id
+    pub fn add_webhook(&self, url: &str, secret: &str, events: &[String]) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO webhooks (url, secret, events, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![url, secret, events.join(","), chrono::Utc::now().to_rfc3339()],
+        )?;
+        let id = self.conn.last_insert_rowid();
+        self.publish(ChangeOrigin::Desktop, ChangeKind::WebhooksChanged);
+        Ok(id)
+    }
+
+    /// Remove a webhook target with its queued deliveries and log
+    pub fn delete_webhook(&self, id: i64) -> Result<bool> {
+        let deleted = self.conn.execute("DELETE FROM webhooks WHERE id = ?1", params![id])? > 0;
+        if deleted {
+            self.conn.execute("DELETE FROM webhook_deliveries WHERE webhook_id = ?1", params![id])?;
+            self.publish(ChangeOrigin::Desktop, ChangeKind::WebhooksChanged);
+        }
+        Ok(deleted)
+    }
+
+    /// Queue a delivery, due now; returns its id
+    pub fn enqueue_delivery(&self, webhook_id: i64, event: &str, payload: &str) -> Result<i64> {
+        let now = timestamp(chrono::Utc::now());
+        self.conn.execute(
+            "INSERT INTO webhook_deliveries (webhook_id, event, payload, status, next_attempt_at, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+            params![webhook_id, event, payload, DeliveryStatus::Pending.as_str(), now],
+        )?;
+        let id = self.conn.last_insert_rowid();
+
+        self.conn.execute(
+            "DELETE FROM webhook_deliveries WHERE id <= ?1 AND status != ?2",
+            params![id - DELIVERY_LOG_RETENTION, DeliveryStatus::Pending.as_str()],
+        )?;
+
+        Ok(id)
+    }
+
+    /// Pending deliveries due by `now`, oldest first
+    pub fn due_deliveries(&self, now: chrono::DateTime<chrono::Utc>, limit: usize) -> Result<Vec<DeliveryRecord>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM webhook_deliveries
+             WHERE status = ?1 AND next_attempt_at <= ?2 ORDER BY id LIMIT ?3",
+            DELIVERY_COLUMNS
+        ))?;
+        let rows = stmt.query_map(
+            params![DeliveryStatus::Pending.as_str(), timestamp(now), limit as i64],
+            delivery_record,
+        )?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// When the next pending delivery is due
+    pub fn next_delivery_at(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+        let next: Option<String> = self.conn.query_row(
+            "SELECT MIN(next_attempt_at) FROM webhook_deliveries WHERE status = ?1",
+            params![DeliveryStatus::Pending.as_str()],
+            |row| row.get(0),
+        )?;
+        Ok(next
+            .and_then(|at| chrono::DateTime::parse_from_rfc3339(&at).ok())
+            .map(|at| at.with_timezone(&chrono::Utc)))
+    }
+
+    pub fn record_delivery_attempt(&self, id: i64, attempt: &DeliveryAttempt) -> Result<()> {
+        let completed_at = (attempt.status != DeliveryStatus::Pending)
+            .then(|| timestamp(chrono::Utc::now()));
+        self.conn.execute(
+            "UPDATE webhook_deliveries SET
+                 status = ?2, attempts = attempts + 1, next_attempt_at = ?3,
+                 response_status = ?4, last_error = ?5, completed_at = ?6
+             WHERE id = ?1",
+            params![
+                id,
+                attempt.status.as_str(),
+                attempt.next_attempt_at,
+                attempt.response_status,
+                attempt.error,
+                completed_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Delivery log, newest first
+    pub fn get_deliveries(&self, limit: usize) -> Result<Vec<DeliveryRecord>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM webhook_deliveries ORDER BY id DESC LIMIT ?1",
+            DELIVERY_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![limit as i64], delivery_record)?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Record an emergency unlock; returns its id
+    pub fn record_emergency_unlock(
+        &self,
+        date_key: &str,
+        reason: &str,
+        method: &str,
+        unlocked_at: chrono::DateTime<chrono::Utc>,
+        suspended_until: chrono::DateTime<chrono::Utc>,
+    ) -> Result<i64> {
+        let until = timestamp(suspended_until);
+        self.conn.execute(
+            "INSERT INTO emergency_unlocks (date_key, reason, method, unlocked_at, suspended_until)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![date_key, reason, method, timestamp(unlocked_at), until],
+        )?;
+        let id = self.conn.last_insert_rowid();
+        self.publish(
+            ChangeOrigin::Desktop,
+            ChangeKind::EmergencyUnlocked { date_key: date_key.to_string(), suspended_until: until },
+        );
+        Ok(id)
+    }
+
+    /// Emergency unlocks made at or after `since`
+    pub fn count_emergency_unlocks_since(&self, since: chrono::DateTime<chrono::Utc>) -> Result<u32> {
+        Ok(self.conn.query_row(
+            "SELECT COUNT(*) FROM emergency_unlocks WHERE unlocked_at >= ?1",
+            params![timestamp(since)],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// End of the emergency unlock in effect at `now`, if any
+    pub fn emergency_unlock_until(&self, now: chrono::DateTime<chrono::Utc>) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+        let until: Option<String> = self.conn.query_row(
+            "SELECT MAX(suspended_until) FROM emergency_unlocks WHERE unlocked_at <= ?1 AND suspended_until > ?1",
+            params![timestamp(now)],
+            |row| row.get(0),
+        )?;
+        Ok(until
+            .and_then(|at| chrono::DateTime::parse_from_rfc3339(&at).ok())
+            .map(|at| at.with_timezone(&chrono::Utc)))
+    }
+
+    /// Emergency unlock log, newest first
+    pub fn get_emergency_unlocks(&self, limit: usize) -> Result<Vec<EmergencyUnlockRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, date_key, reason, method, unlocked_at, suspended_until
+             FROM emergency_unlocks ORDER BY id DESC LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(params![limit as i64], |row| {
+            Ok(EmergencyUnlockRecord {
+                id: row.get(0)?,
+                date_key: row.get(1)?,
+                reason: row.get(2)?,
+                method: row.get(3)?,
+                unlocked_at: row.get(4)?,
+                suspended_until: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+}
+
+/// Directory holding the database, device id and certificates
+pub fn data_dir() -> PathBuf {
+    if cfg!(test) {
+        return test_data_dir();
+    }
+    std::env::var_os(DATA_DIR_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(SHARED_DATA_DIR))
+}
+
+/// Per-user directory for secrets that must not sit in the shared,
+/// world-writable data directory (the local CA); inside the data directory
+/// when that is overridden (second instance, tests)
+pub fn private_dir() -> PathBuf {
+    let overridden = cfg!(test) || std::env::var_os(DATA_DIR_ENV).is_some();
+    match std::env::var_os("HOME") {
+        Some(home) if !overridden => PathBuf::from(home).join("Library/Application Support/TrainDaily"),
+        _ => data_dir().join("private"),
+    }
+}
+
+/// Scratch data directory for this test run, whatever TRAINDAILY_DATA_DIR
+/// says, so `cargo test` never touches a real database, CA or pinned key
+fn test_data_dir() -> PathBuf {
+    static DIR: std::sync::OnceLock<PathBuf> = std::sync::OnceLock::new();
+    DIR.get_or_init(|| std::env::temp_dir().join(format!("traindaily-test-{}", std::process::id())))
+        .clone()
+}
+
+/// Upsert a session and log the change (shared by single and batch saves)
+fn write_session(conn: &Connection, date_key: &str, session: &WorkoutSession) -> Result<i64> {
+    let session_data = serde_json::to_string(session)
+        .unwrap_or_else(|_| "{}".to_string());
+
+    conn.execute(
+        "INSERT OR REPLACE INTO sessions (date_key, session_data) VALUES (?1, ?2)",
+        params![date_key, session_data],
+    )?;
+
+    record_change(conn, date_key)
+}
+
+/// Append a local change to the change log
+fn record_change(conn: &Connection, date_key: &str) -> Result<i64> {
+    insert_change(conn, date_key, &chrono::Utc::now().to_rfc3339(), None)
+}
+
+/// Append to the change log and prune entries past retention
+fn insert_change(conn: &Connection, date_key: &str, changed_at: &str, origin: Option<&str>) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO change_log (date_key, changed_at, origin) VALUES (?1, ?2, ?3)",
+        params![date_key, changed_at, origin],
+    )?;
+    let id = conn.last_insert_rowid();
+
+    conn.execute(
+        "DELETE FROM change_log WHERE id <= ?1",
+        params![id - CHANGE_LOG_RETENTION],
+    )?;
+
+    Ok(id)
+}
+
+const DELIVERY_COLUMNS: &str = "id, webhook_id, event, payload, status, attempts, next_attempt_at, \
+    response_status, last_error, created_at, completed_at";
+
+fn delivery_record(row: &rusqlite::Row) -> rusqlite::Result<DeliveryRecord> {
+    let status: String = row.get(4)?;
+    Ok(DeliveryRecord {
+        id: row.get(0)?,
+        webhook_id: row.get(1)?,
+        event: row.get(2)?,
+        payload: row.get(3)?,
+        status: DeliveryStatus::parse(&status),
+        attempts: row.get(5)?,
+        next_attempt_at: row.get(6)?,
+        response_status: row.get(7)?,
+        last_error: row.get(8)?,
+        created_at: row.get(9)?,
+        completed_at: row.get(10)?,
+    })
+}
+
+/// Fixed-width UTC timestamp, so stored times compare correctly as text
+pub fn timestamp(at: chrono::DateTime<chrono::Utc>) -> String {
+    at.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+fn change_record(row: &rusqlite::Row) -> rusqlite::Result<ChangeRecord> {
+    Ok(ChangeRecord {
+        id: row.get(0)?,
+        date_key: row.get(1)?,
+        changed_at: row.get(2)?,
+        origin: row.get(3)?,
+    })
+}
+
+/// Comparable (time, device) for last-writer-wins; unparseable times sort first
+fn change_stamp<'a>(changed_at: &str, origin: &'a str) -> (chrono::DateTime<chrono::Utc>, &'a str) {
+    let time = chrono::DateTime::parse_from_rfc3339(changed_at)
+        .map(|time| time.with_timezone(&chrono::Utc))
+        .unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC);
+    (time, origin)
+}
+
+/// Most recent retained change to one date
+fn last_change_for(conn: &Connection, date_key: &str) -> Result<Option<i64>> {
+    let id: Option<i64> = conn.query_row(
+        "SELECT MAX(id) FROM change_log WHERE date_key = ?1",
+        params![date_key],
+        |row| row.get(0),
+    )?;
+    Ok(id)
 }
 
 #[cfg(test)]
@@ -220,7 +989,7 @@ mod tests {
             "week_number": 1
         });
 
-        db.save_session("2026-02-17", &session).unwrap();
+        db.save_session("2026-02-17", &session, ChangeOrigin::Desktop).unwrap();
 
         let sessions = db.get_all_sessions().unwrap();
         assert!(sessions.len() >= 1);
@@ -229,4 +998,94 @@ mod tests {
         assert_eq!(retrieved["trx_pushup"], json!([10, 8]));
         assert_eq!(retrieved["week_number"], json!(1));
     }
+
+    #[test]
+    fn test_save_records_change() {
+        let db = Database::new().unwrap();
+        let session = json!({ "logged_at": "2026-02-18T10:00:00Z" });
+
+        let first = db.save_session("2026-02-18", &session, ChangeOrigin::Desktop).unwrap();
+        let second = db.save_session("2026-02-18", &session, ChangeOrigin::Desktop).unwrap();
+        assert!(second > first);
+        assert!(db.latest_change_id().unwrap() >= second);
+
+        let changes = db.changes_since(first, 10).unwrap();
+        assert!(changes.iter().any(|c| c.id == second && c.date_key == "2026-02-18"));
+        assert!(changes.iter().all(|c| c.id > first));
+
+        assert_eq!(db.get_session("2026-02-18").unwrap(), Some(session));
+    }
+
+    #[test]
+    fn test_save_session_is_atomic() {
+        let dir = data_dir().join("atomic-save");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = Database::open(&dir.join("traindaily.db")).unwrap();
+
+        // The change log insert fails, so the upsert before it must roll back
+        db.conn.execute("DROP TABLE change_log", []).unwrap();
+        assert!(db.save_session("2026-02-19", &json!({ "v": 1 }), ChangeOrigin::Desktop).is_err());
+        assert_eq!(db.get_session("2026-02-19").unwrap(), None);
+    }
+
+    #[test]
+    fn test_writes_publish_on_bus() {
+        let mut db = Database::new().unwrap();
+        let bus = ChangeBus::new();
+        let mut rx = bus.subscribe();
+        db.attach_bus(bus);
+
+        let change_id = db.save_session("2026-02-20", &json!({}), ChangeOrigin::Sync).unwrap();
+        db.set_setting("tray_visible", "true", ChangeOrigin::Desktop).unwrap();
+
+        assert_eq!(rx.try_recv().unwrap(), ChangeEvent {
+            origin: ChangeOrigin::Sync,
+            kind: ChangeKind::SessionSaved { change_id, date_key: "2026-02-20".to_string() },
+        });
+        assert_eq!(rx.try_recv().unwrap().kind, ChangeKind::SettingChanged { key: "tray_visible".to_string() });
+    }
+
+    #[test]
+    fn test_save_sessions_detects_conflicts() {
+        let mut db = Database::new().unwrap();
+        let seen = db.save_session("2026-02-21", &json!({ "v": 1 }), ChangeOrigin::Sync).unwrap();
+        let newer = db.save_session("2026-02-21", &json!({ "v": 2 }), ChangeOrigin::Desktop).unwrap();
+
+        let writes = vec![
+            SessionWrite { date_key: "2026-02-21".into(), session: json!({ "v": 3 }), base_change_id: Some(seen) },
+            SessionWrite { date_key: "2026-02-22".into(), session: json!({ "v": 1 }), base_change_id: None },
+        ];
+        let outcomes = db.save_sessions(&writes, ChangeOrigin::Sync).unwrap();
+
+        assert_eq!(outcomes[0], WriteOutcome::Conflict { current_change_id: newer });
+        assert!(matches!(outcomes[1], WriteOutcome::Applied { change_id } if change_id > newer));
+        assert_eq!(db.get_session("2026-02-21").unwrap(), Some(json!({ "v": 2 })));
+        assert_eq!(db.get_session("2026-02-22").unwrap(), Some(json!({ "v": 1 })));
+    }
+
+    #[test]
+    fn test_apply_replicated_last_writer_wins() {
+        let db = Database::new().unwrap();
+        db.save_session("2026-02-23", &json!({ "v": "local" }), ChangeOrigin::Desktop).unwrap();
+
+        let mut change = ReplicatedChange {
+            date_key: "2026-02-23".into(),
+            session: json!({ "v": "peer" }),
+            changed_at: "2020-01-01T00:00:00Z".into(),
+            origin: "peer-mac".into(),
+        };
+        assert_eq!(db.apply_replicated(&change, "this-mac", ChangeOrigin::Peer).unwrap(), None);
+        assert_eq!(db.get_session("2026-02-23").unwrap(), Some(json!({ "v": "local" })));
+
+        change.changed_at = (chrono::Utc::now() + chrono::Duration::minutes(1)).to_rfc3339();
+        let applied = db.apply_replicated(&change, "this-mac", ChangeOrigin::Peer).unwrap().unwrap();
+        assert_eq!(db.get_session("2026-02-23").unwrap(), Some(json!({ "v": "peer" })));
+
+        let record = db.last_change("2026-02-23").unwrap().unwrap();
+        assert_eq!((record.id, record.origin.as_deref()), (applied, Some("peer-mac")));
+
+        // The same change coming back (e.g. via a third desktop) is a no-op
+        assert_eq!(db.apply_replicated(&change, "this-mac", ChangeOrigin::Peer).unwrap(), None);
+    }
 }
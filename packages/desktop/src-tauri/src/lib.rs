@@ -2,21 +2,45 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod db;
+mod activity;
+mod bundle;
+mod calendar;
 mod cert;
+mod changes;
 mod commands;
+mod crypto;
 mod mic;
+mod mqtt;
+mod network;
+mod peers;
+mod qr;
+mod schedule;
 mod sync;
+mod webhooks;
 mod blocker;
 mod overlay;
 
-use tauri::Manager;
+use tauri::{Emitter, Manager};
 use std::sync::{Arc, Mutex};
 
+// Coalescing window for sync-status-changed events (every request updates the status)
+const STATUS_EVENT_INTERVAL_MS: u64 = 500;
+
 // Shared application state
 pub struct AppState {
     pub db: Arc<Mutex<db::Database>>,
     pub device_id: String,
     pub auth_token: String,
+    pub changes: changes::ChangeBus,
+    /// Sync server lifecycle (start / stop / restart)
+    pub sync: Arc<sync::handle::SyncServerHandle>,
+    pub sync_status: Arc<sync::status::StatusRegistry>,
+    /// Other desktops we replicate with
+    pub peers: Arc<peers::PeerManager>,
+    /// Outbound webhook targets and delivery queue
+    pub webhooks: Arc<webhooks::Webhooks>,
+    /// Home-automation link (optional, see mqtt_* settings)
+    pub mqtt: Arc<mqtt::MqttBridge>,
     pub blocker_state: Arc<Mutex<blocker::BlockerState>>,
     pub overlay_state: Arc<Mutex<overlay::OverlayState>>,
     #[cfg(desktop)]
@@ -25,11 +49,13 @@ pub struct AppState {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    // Initialize tracing (logging)
-    tracing_subscriber::fmt::init();
+    // Initialize tracing (logging), scrubbing registered secrets from output
+    tracing_subscriber::fmt()
+        .with_writer(sync::auth::RedactingMakeWriter)
+        .init();
 
     // Initialize database
-    let db = db::Database::new().expect("Failed to initialize database");
+    let mut db = db::Database::new().expect("Failed to initialize database");
     let device_id = db.get_device_id().expect("Failed to get device ID");
 
     // Load or generate persistent auth token (used for QR pairing + sync server)
@@ -38,7 +64,7 @@ pub fn run() {
         .flatten()
         .unwrap_or_else(|| {
             let token = sync::generate_auth_token();
-            let _ = db.set_setting("auth_token", &token);
+            let _ = db.set_setting("auth_token", &token, changes::ChangeOrigin::Desktop);
             token
         });
 
@@ -49,9 +75,16 @@ pub fn run() {
         .map(|v| v != "false")
         .unwrap_or(true);
 
+    // `--print-pairing-qr`: show the pairing code in the terminal (no UI needed)
+    let print_pairing_qr = std::env::args().any(|arg| arg == "--print-pairing-qr");
+
     // On first launch, enable open-at-login automatically
     let is_first_run = db.get_setting("open_at_login").ok().flatten().is_none();
 
+    // Every write from here on is published to sync clients, the frontend and the blocker
+    let change_bus = changes::ChangeBus::new();
+    db.attach_bus(change_bus.clone());
+
     // Create shared state
     let blocker_state = Arc::new(Mutex::new(blocker::BlockerState::new()));
     let overlay_state = Arc::new(Mutex::new(overlay::OverlayState::new()));
@@ -60,15 +93,46 @@ pub fn run() {
     let db_arc = Arc::new(Mutex::new(db));
     let db_for_sync = db_arc.clone();
     let db_for_blocker = db_arc.clone();
+    let db_for_streak = db_arc.clone();
+    let db_for_overlay = db_arc.clone();
     let device_id_for_sync = device_id.clone();
     let auth_token_for_sync = auth_token.clone();
     let blocker_state_for_task = blocker_state.clone();
     let overlay_state_for_task = overlay_state.clone();
+    let change_bus_for_tasks = change_bus.clone();
+    let sync_status = sync::status::StatusRegistry::new();
+    let sync_status_for_tasks = sync_status.clone();
+    let sync_server = sync::handle::SyncServerHandle::new(
+        db_arc.clone(),
+        device_id.clone(),
+        auth_token.clone(),
+        change_bus.clone(),
+        sync_status.clone(),
+    );
+    let sync_server_for_tasks = sync_server.clone();
+    let peer_manager = peers::PeerManager::new(
+        db_arc.clone(),
+        device_id.clone(),
+        auth_token.clone(),
+        change_bus.clone(),
+        sync_server.clone(),
+    );
+    let peer_manager_for_tasks = peer_manager.clone();
+    let webhooks = webhooks::Webhooks::new(db_arc.clone(), device_id.clone());
+    let webhooks_for_tasks = webhooks.clone();
+    let mqtt_bridge = mqtt::MqttBridge::new(db_arc.clone(), device_id.clone(), change_bus.clone());
+    let mqtt_bridge_for_tasks = mqtt_bridge.clone();
 
     let state = AppState {
         db: db_arc,
         device_id,
         auth_token,
+        changes: change_bus,
+        sync: sync_server,
+        sync_status,
+        peers: peer_manager,
+        webhooks,
+        mqtt: mqtt_bridge,
         blocker_state: blocker_state.clone(),
         overlay_state: overlay_state.clone(),
         #[cfg(desktop)]
@@ -85,9 +149,38 @@ pub fn run() {
             commands::save_session,
             commands::get_first_session_date,
             commands::set_first_session_date,
+            commands::get_schedule,
+            commands::set_schedule_template,
+            commands::set_schedule_override,
+            commands::get_blocker_policy,
+            commands::set_blocker_policy,
+            commands::get_emergency_unlock_status,
+            commands::start_emergency_unlock,
+            commands::confirm_emergency_unlock,
+            commands::list_emergency_unlocks,
             commands::get_device_id,
             commands::check_mic_active,
             commands::get_qr_code_data,
+            commands::get_qr_code_image,
+            commands::list_network_addresses,
+            commands::get_sync_status,
+            commands::set_sync_server_enabled,
+            commands::restart_sync_server,
+            commands::list_peers,
+            commands::pair_peer,
+            commands::unpair_peer,
+            commands::export_bundle,
+            commands::import_bundle,
+            commands::export_calendar,
+            commands::get_calendar_url,
+            commands::revoke_calendar_token,
+            commands::export_activities,
+            commands::list_webhooks,
+            commands::add_webhook,
+            commands::remove_webhook,
+            commands::test_webhook,
+            commands::list_webhook_deliveries,
+            commands::get_mqtt_status,
             commands::get_setting,
             commands::set_setting,
             commands::set_tray_visible,
@@ -103,36 +196,114 @@ pub fn run() {
                 let _ = app.autolaunch().enable();
                 let state = app.state::<AppState>();
                 if let Ok(db) = state.db.lock() {
-                    let _ = db.set_setting("open_at_login", "true");
+                    let _ = db.set_setting("open_at_login", "true", changes::ChangeOrigin::Desktop);
                 };
             }
 
-            // Start sync server
+            // Start sync server (unless switched off in settings), then follow network changes
             let db_clone = db_for_sync.clone();
             let device_id_clone = device_id_for_sync.clone();
             let auth_token_clone = auth_token_for_sync.clone();
+            let sync_server_clone = sync_server_for_tasks.clone();
 
             tauri::async_runtime::spawn(async move {
-                if let Err(e) = sync::start_server(db_clone, device_id_clone, auth_token_clone).await {
-                    tracing::error!("Failed to start sync server: {}", e);
+                if sync_server_clone.enabled_in_settings() {
+                    match sync_server_clone.start().await {
+                        Ok(listening) => {
+                            if print_pairing_qr {
+                                print_pairing_code(&db_clone, &device_id_clone, &auth_token_clone, &listening);
+                            }
+                        }
+                        Err(e) => tracing::error!("Failed to start sync server: {}", e),
+                    }
+                } else {
+                    tracing::info!("Sync server disabled in settings");
                 }
+
+                sync_server_clone.monitor_network().await;
+            });
+
+            // Advertise on the LAN, find other desktops and replicate with paired ones
+            let peer_manager_clone = peer_manager_for_tasks.clone();
+
+            tauri::async_runtime::spawn(async move {
+                peer_manager_clone.run().await;
             });
 
             // Start app blocker
             let db_clone = db_for_blocker.clone();
             let blocker_state_clone = blocker_state_for_task.clone();
             let app_handle_clone = app_handle.clone();
+            let bus_clone = change_bus_for_tasks.clone();
+
+            tauri::async_runtime::spawn(async move {
+                blocker::start_blocker(app_handle_clone, db_clone, blocker_state_clone, bus_clone).await;
+            });
+
+            // Announce broken streaks on the change bus
+            let db_clone = db_for_streak.clone();
+            let bus_clone = change_bus_for_tasks.clone();
+
+            tauri::async_runtime::spawn(async move {
+                schedule::watch_streak(db_clone, bus_clone).await;
+            });
+
+            // Deliver events to webhook targets (queued in the database, retried)
+            let webhooks_clone = webhooks_for_tasks.clone();
+            let bus_clone = change_bus_for_tasks.clone();
+
+            tauri::async_runtime::spawn(async move {
+                webhooks_clone.run(bus_clone).await;
+            });
+
+            // Publish state to an MQTT broker and take its commands (when enabled)
+            let mqtt_bridge_clone = mqtt_bridge_for_tasks.clone();
+            let app_handle_clone = app_handle.clone();
 
             tauri::async_runtime::spawn(async move {
-                blocker::start_blocker(app_handle_clone, db_clone, blocker_state_clone).await;
+                mqtt_bridge_clone.run(Arc::new(app_handle_clone)).await;
+            });
+
+            // Forward data changes to the frontend (e.g. sessions synced from a phone)
+            let mut change_rx = change_bus_for_tasks.subscribe();
+            let app_handle_clone = app_handle.clone();
+
+            tauri::async_runtime::spawn(async move {
+                use tokio::sync::broadcast::error::RecvError;
+                loop {
+                    match change_rx.recv().await {
+                        Ok(event) => {
+                            let _ = app_handle_clone.emit(changes::FRONTEND_EVENT, &event);
+                        }
+                        Err(RecvError::Lagged(missed)) => {
+                            tracing::warn!("Frontend change forwarder missed {} events", missed);
+                        }
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            // Forward sync status changes to the frontend, at most every STATUS_EVENT_INTERVAL_MS
+            let mut status_rx = sync_status_for_tasks.subscribe();
+            let status_clone = sync_status_for_tasks.clone();
+            let app_handle_clone = app_handle.clone();
+
+            tauri::async_runtime::spawn(async move {
+                while status_rx.changed().await.is_ok() {
+                    let _ = app_handle_clone.emit(sync::status::FRONTEND_EVENT, status_clone.snapshot());
+                    // Changes during the pause coalesce into the next event
+                    tokio::time::sleep(std::time::Duration::from_millis(STATUS_EVENT_INTERVAL_MS)).await;
+                }
             });
 
             // Start micro-break overlay
+            let db_clone = db_for_overlay.clone();
             let overlay_state_clone = overlay_state_for_task.clone();
             let app_handle_clone = app_handle.clone();
+            let bus_clone = change_bus_for_tasks.clone();
 
             tauri::async_runtime::spawn(async move {
-                overlay::start_overlay(app_handle_clone, overlay_state_clone).await;
+                overlay::start_overlay(app_handle_clone, db_clone, overlay_state_clone, bus_clone).await;
             });
 
             // Create system tray icon and store handle in AppState
@@ -203,13 +374,59 @@ pub fn run() {
         })
         .build(tauri::generate_context!())
         .expect("error while running tauri application")
-        .run(|app_handle, event| {
+        .run(|app_handle, event| match event {
             // Cmd+Q hides the app instead of quitting (Raycast-style)
-            if let tauri::RunEvent::ExitRequested { api, .. } = event {
+            tauri::RunEvent::ExitRequested { api, .. } => {
                 api.prevent_exit();
                 if let Some(window) = app_handle.get_webview_window("main") {
                     let _ = window.hide();
                 }
             }
+            // Really quitting (tray menu): let sync clients finish and disconnect cleanly
+            tauri::RunEvent::Exit => {
+                let sync_server = app_handle.state::<AppState>().sync.clone();
+                tauri::async_runtime::block_on(sync_server.stop(sync::handle::SHUTDOWN_DEADLINE));
+            }
+            _ => {}
         });
 }
+
+/// The app side of the MQTT bridge
+impl mqtt::Host for tauri::AppHandle {
+    fn blocker_active(&self) -> bool {
+        self.state::<AppState>().blocker_state.lock().map(|state| state.window_open).unwrap_or(false)
+    }
+
+    fn next_micro_break(&self) -> Option<chrono::DateTime<chrono::Local>> {
+        self.state::<AppState>().overlay_state.lock().ok().map(|state| state.next_break_at())
+    }
+
+    fn snooze_break(&self, minutes: u64) {
+        overlay::snooze_micro_break(self, minutes);
+    }
+
+    fn open_app(&self) {
+        if let Some(window) = self.get_webview_window("main") {
+            let _ = window.show();
+            let _ = window.set_focus();
+        }
+    }
+}
+
+/// Print the pairing QR code to stdout (terminal / headless use)
+fn print_pairing_code(
+    db: &Arc<Mutex<db::Database>>,
+    device_id: &str,
+    auth_token: &str,
+    listening: &sync::listen::Listening,
+) {
+    let payload = match db.lock() {
+        Ok(db) => sync::pairing_payload(&db, device_id, auth_token, listening),
+        Err(e) => Err(anyhow::anyhow!("{}", e)),
+    };
+
+    match payload.and_then(|payload| qr::encode(&payload, qr::ErrorCorrection::default())) {
+        Ok(code) => println!("Scan with the TrainDaily PWA to pair:\n{}", qr::to_ansi(&code)),
+        Err(e) => tracing::error!("Failed to render pairing QR code: {}", e),
+    }
+}
@@ -0,0 +1,617 @@
+/**
+ * MQTT Bridge
+ *
+ * Optional home-automation link (e.g. Home Assistant) through an MQTT broker
+ * - Retained state topics under a prefix (default "traindaily"):
+ *   workout/type (push / pull / legs / rest), workout/logged (ON / OFF),
+ *   blocker/active (ON / OFF), micro_break/next (RFC 3339, empty when
+ *   unknown), streak (training days in a row, as in the app)
+ * - status: "online" while connected, "offline" as last will
+ * - Command topics: command/snooze_break (payload: minutes, default 10)
+ *   and command/open_app
+ * - State is republished on change bus events and every minute (the date
+ *   and next break move on their own); only changed values are sent
+ * - Settings (mqtt_*): enabled, host, port, username, password, tls,
+ *   ca_file (PEM, instead of the system roots), topic_prefix; changing any
+ *   of them reconnects
+ *
+ * The app side (blocker, overlay, main window) is reached through Host, so
+ * the bridge runs against a local broker without a UI.
+ */
+
+use crate::changes::{ChangeBus, ChangeEvent, ChangeKind};
+use crate::db::Database;
+use crate::schedule::{self, Schedule, WorkoutType};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Local};
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Packet, QoS, TlsConfiguration, Transport};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+pub const ENABLED_SETTING: &str = "mqtt_enabled";
+pub const HOST_SETTING: &str = "mqtt_host";
+pub const PORT_SETTING: &str = "mqtt_port";
+pub const USERNAME_SETTING: &str = "mqtt_username";
+pub const PASSWORD_SETTING: &str = "mqtt_password";
+pub const TLS_SETTING: &str = "mqtt_tls";
+pub const CA_FILE_SETTING: &str = "mqtt_ca_file";
+pub const TOPIC_PREFIX_SETTING: &str = "mqtt_topic_prefix";
+
+// Every setting above starts with this
+const SETTING_PREFIX: &str = "mqtt_";
+
+const DEFAULT_HOST: &str = "localhost";
+const DEFAULT_PORT: u16 = 1883;
+const DEFAULT_TLS_PORT: u16 = 8883;
+const DEFAULT_TOPIC_PREFIX: &str = "traindaily";
+
+const DEFAULT_SNOOZE_MINUTES: u64 = 10;
+// Longest snooze accepted from a command topic
+const MAX_SNOOZE_MINUTES: u64 = 240;
+
+const KEEP_ALIVE_SECS: u64 = 30;
+const REFRESH_SECS: u64 = 60;
+const MIN_RETRY_SECS: u64 = 2;
+const MAX_RETRY_SECS: u64 = 60;
+
+// Settings are saved one key at a time; wait for the rest before reconnecting
+const SETTINGS_SETTLE_MS: u64 = 500;
+
+// Requests queued between event loop polls (state + status + subscribe fit easily)
+const REQUEST_CAPACITY: usize = 32;
+
+/// Broker connection settings
+#[derive(Clone, Debug, PartialEq)]
+pub struct MqttConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub tls: bool,
+    /// CA certificates (PEM) to trust instead of the system roots
+    pub ca_file: Option<PathBuf>,
+    pub topic_prefix: String,
+}
+
+impl MqttConfig {
+    pub fn load(db: &Database) -> Result<Self> {
+        let get = |key: &str| -> Result<Option<String>> {
+            Ok(db.get_setting(key)?.map(|value| value.trim().to_string()).filter(|value| !value.is_empty()))
+        };
+
+        let tls = get(TLS_SETTING)?.as_deref() == Some("true");
+        let port = match get(PORT_SETTING)? {
+            Some(port) => port.parse().with_context(|| format!("Invalid MQTT port '{}'", port))?,
+            None if tls => DEFAULT_TLS_PORT,
+            None => DEFAULT_PORT,
+        };
+        let password = get(PASSWORD_SETTING)?;
+        if let Some(password) = &password {
+            crate::sync::auth::register_secret(password);
+        }
+
+        Ok(Self {
+            enabled: get(ENABLED_SETTING)?.as_deref() == Some("true"),
+            host: get(HOST_SETTING)?.unwrap_or_else(|| DEFAULT_HOST.to_string()),
+            port,
+            username: get(USERNAME_SETTING)?,
+            password,
+            tls,
+            ca_file: get(CA_FILE_SETTING)?.map(PathBuf::from),
+            topic_prefix: get(TOPIC_PREFIX_SETTING)?
+                .map(|prefix| prefix.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| DEFAULT_TOPIC_PREFIX.to_string()),
+        })
+    }
+
+    fn options(&self, device_id: &str) -> Result<MqttOptions> {
+        let mut options = MqttOptions::new(format!("traindaily-{}", device_id), &self.host, self.port);
+        options.set_keep_alive(Duration::from_secs(KEEP_ALIVE_SECS));
+        options.set_last_will(LastWill::new(
+            Topics::new(&self.topic_prefix).status,
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+        if let Some(username) = &self.username {
+            options.set_credentials(username, self.password.as_deref().unwrap_or_default());
+        }
+        if self.tls {
+            let ca_pem = match &self.ca_file {
+                Some(path) => Some(
+                    std::fs::read(path).with_context(|| format!("Cannot read CA file {}", path.display()))?,
+                ),
+                None => None,
+            };
+            let tls = crate::cert::client_tls_config(ca_pem.as_deref())?;
+            options.set_transport(Transport::tls_with_config(TlsConfiguration::Rustls(Arc::new(tls))));
+        }
+        Ok(options)
+    }
+
+    fn broker(&self) -> String {
+        format!("{}://{}:{}", if self.tls { "mqtts" } else { "mqtt" }, self.host, self.port)
+    }
+}
+
+/// The app, as seen from the bridge (implemented on the Tauri app handle)
+pub trait Host: Send + Sync {
+    fn blocker_active(&self) -> bool;
+    fn next_micro_break(&self) -> Option<DateTime<Local>>;
+    fn snooze_break(&self, minutes: u64);
+    fn open_app(&self);
+}
+
+/// What the state topics report
+#[derive(Clone, Debug, PartialEq)]
+pub struct HomeState {
+    pub workout_type: WorkoutType,
+    pub logged: bool,
+    pub blocker_active: bool,
+    pub next_micro_break: Option<DateTime<Local>>,
+    pub streak: u32,
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    SnoozeBreak { minutes: u64 },
+    OpenApp,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MqttStatus {
+    pub enabled: bool,
+    pub connected: bool,
+    /// e.g. "mqtt://homeassistant.local:1883"
+    pub broker: Option<String>,
+    pub last_error: Option<String>,
+}
+
+/// Why serve returned without an error
+enum Stop {
+    /// An mqtt_* setting changed
+    Reconfigure,
+    /// The change bus closed (app shutting down)
+    Shutdown,
+}
+
+struct Topics {
+    status: String,
+    workout_type: String,
+    workout_logged: String,
+    blocker_active: String,
+    next_micro_break: String,
+    streak: String,
+    commands: String,
+}
+
+impl Topics {
+    fn new(prefix: &str) -> Self {
+        let topic = |name: &str| format!("{}/{}", prefix, name);
+        Self {
+            status: topic("status"),
+            workout_type: topic("workout/type"),
+            workout_logged: topic("workout/logged"),
+            blocker_active: topic("blocker/active"),
+            next_micro_break: topic("micro_break/next"),
+            streak: topic("streak"),
+            commands: topic("command/"),
+        }
+    }
+
+    /// (topic, payload) for every state topic
+    fn state(&self, state: &HomeState) -> Vec<(&str, String)> {
+        let on_off = |on: bool| if on { "ON" } else { "OFF" }.to_string();
+        vec![
+            (&self.workout_type, serde_json::to_value(state.workout_type)
+                .ok()
+                .and_then(|value| value.as_str().map(str::to_string))
+                .unwrap_or_default()),
+            (&self.workout_logged, on_off(state.logged)),
+            (&self.blocker_active, on_off(state.blocker_active)),
+            (&self.next_micro_break, state.next_micro_break.map(|at| at.to_rfc3339()).unwrap_or_default()),
+            (&self.streak, state.streak.to_string()),
+        ]
+    }
+
+    fn command_filter(&self) -> String {
+        format!("{}#", self.commands)
+    }
+
+    fn parse_command(&self, topic: &str, payload: &[u8]) -> Result<Command> {
+        let name = topic.strip_prefix(&self.commands).ok_or_else(|| anyhow!("not a command topic"))?;
+        let payload = std::str::from_utf8(payload).unwrap_or_default().trim();
+
+        match name {
+            "snooze_break" => {
+                let minutes = if payload.is_empty() {
+                    DEFAULT_SNOOZE_MINUTES
+                } else {
+                    payload.parse().map_err(|_| anyhow!("snooze_break expects minutes, got '{}'", payload))?
+                };
+                if minutes == 0 || minutes > MAX_SNOOZE_MINUTES {
+                    bail!("snooze_break minutes must be 1-{}", MAX_SNOOZE_MINUTES);
+                }
+                Ok(Command::SnoozeBreak { minutes })
+            }
+            "open_app" => Ok(Command::OpenApp),
+            other => bail!("unknown command '{}'", other),
+        }
+    }
+}
+
+pub struct MqttBridge {
+    db: Arc<Mutex<Database>>,
+    device_id: String,
+    bus: ChangeBus,
+    status: Mutex<MqttStatus>,
+}
+
+impl MqttBridge {
+    pub fn new(db: Arc<Mutex<Database>>, device_id: String, bus: ChangeBus) -> Arc<Self> {
+        Arc::new(Self { db, device_id, bus, status: Mutex::new(MqttStatus::default()) })
+    }
+
+    pub fn status(&self) -> MqttStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Stay connected to the configured broker, following settings changes
+    pub async fn run(self: Arc<Self>, host: Arc<dyn Host>) {
+        let mut changes = self.bus.subscribe();
+        let mut retry_secs = MIN_RETRY_SECS;
+
+        loop {
+            let config = match self.db.lock().map_err(|e| anyhow!("{}", e)).and_then(|db| MqttConfig::load(&db)) {
+                Ok(config) => config,
+                Err(e) => {
+                    self.update_status(|status| status.last_error = Some(e.to_string()));
+                    disabled_config()
+                }
+            };
+            self.update_status(|status| {
+                status.enabled = config.enabled;
+                status.connected = false;
+                status.broker = config.enabled.then(|| config.broker());
+            });
+
+            if !config.enabled {
+                match wait_for_settings(&mut changes, None).await {
+                    Some(_) => continue,
+                    None => return,
+                }
+            }
+
+            match self.serve(&config, host.as_ref(), &mut changes).await {
+                Ok(Stop::Reconfigure) => {
+                    retry_secs = MIN_RETRY_SECS;
+                    settle(&mut changes).await;
+                }
+                Ok(Stop::Shutdown) => return,
+                Err(e) => {
+                    let was_connected = self.status().connected;
+                    tracing::warn!("MQTT connection to {} failed: {}", config.broker(), e);
+                    self.update_status(|status| {
+                        status.connected = false;
+                        status.last_error = Some(e.to_string());
+                    });
+                    if was_connected {
+                        retry_secs = MIN_RETRY_SECS;
+                    }
+
+                    match wait_for_settings(&mut changes, Some(Duration::from_secs(retry_secs))).await {
+                        Some(true) => {
+                            retry_secs = MIN_RETRY_SECS;
+                            settle(&mut changes).await;
+                        }
+                        Some(false) => retry_secs = (retry_secs * 2).min(MAX_RETRY_SECS),
+                        None => return,
+                    }
+                }
+            }
+        }
+    }
+
+    /// One connection: publish state, execute commands, until settings change
+    /// or the connection fails
+    async fn serve(
+        &self,
+        config: &MqttConfig,
+        host: &dyn Host,
+        changes: &mut broadcast::Receiver<ChangeEvent>,
+    ) -> Result<Stop> {
+        let topics = Topics::new(&config.topic_prefix);
+        let (client, mut eventloop) = AsyncClient::new(config.options(&self.device_id)?, REQUEST_CAPACITY);
+
+        // Connecting isn't cancel safe: finish it before selecting on anything else
+        while !matches!(eventloop.poll().await?, Event::Incoming(Packet::ConnAck(_))) {}
+        tracing::info!("MQTT connected to {}", config.broker());
+        self.update_status(|status| {
+            status.connected = true;
+            status.last_error = None;
+        });
+
+        // Last payload sent per topic (retained on the broker)
+        let mut published: HashMap<String, String> = HashMap::new();
+        client.subscribe(topics.command_filter(), QoS::AtLeastOnce).await?;
+        client.publish(&topics.status, QoS::AtLeastOnce, true, "online").await?;
+        self.publish_state(&client, &topics, host, &mut published).await?;
+
+        let start = tokio::time::Instant::now() + Duration::from_secs(REFRESH_SECS);
+        let mut refresh = tokio::time::interval_at(start, Duration::from_secs(REFRESH_SECS));
+
+        loop {
+            tokio::select! {
+                event = eventloop.poll() => {
+                    if let Event::Incoming(Packet::Publish(message)) = event? {
+                        match topics.parse_command(&message.topic, &message.payload) {
+                            Ok(command) => {
+                                tracing::info!("MQTT command: {:?}", command);
+                                match command {
+                                    Command::SnoozeBreak { minutes } => host.snooze_break(minutes),
+                                    Command::OpenApp => host.open_app(),
+                                }
+                                self.publish_state(&client, &topics, host, &mut published).await?;
+                            }
+                            Err(e) => tracing::warn!("Ignoring MQTT message on {}: {}", message.topic, e),
+                        }
+                    }
+                }
+                change = changes.recv() => match change {
+                    Ok(ChangeEvent { kind: ChangeKind::SettingChanged { key }, .. }) if key.starts_with(SETTING_PREFIX) => {
+                        disconnect(&client, &mut eventloop, &topics).await;
+                        return Ok(Stop::Reconfigure);
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {
+                        self.publish_state(&client, &topics, host, &mut published).await?;
+                    }
+                    Err(RecvError::Closed) => return Ok(Stop::Shutdown),
+                },
+                _ = refresh.tick() => self.publish_state(&client, &topics, host, &mut published).await?,
+            }
+        }
+    }
+
+    /// Publish the state topics whose value changed (retained)
+    async fn publish_state(
+        &self,
+        client: &AsyncClient,
+        topics: &Topics,
+        host: &dyn Host,
+        published: &mut HashMap<String, String>,
+    ) -> Result<()> {
+        let state = self.home_state(host)?;
+        for (topic, payload) in topics.state(&state) {
+            if published.get(topic) == Some(&payload) {
+                continue;
+            }
+            client.publish(topic, QoS::AtLeastOnce, true, payload.clone()).await?;
+            published.insert(topic.to_string(), payload);
+        }
+        Ok(())
+    }
+
+    fn home_state(&self, host: &dyn Host) -> Result<HomeState> {
+        let today = Local::now().date_naive();
+        let (schedule, sessions) = {
+            let db = self.db.lock().map_err(|e| anyhow!("{}", e))?;
+            (Schedule::load(&db)?, db.get_all_sessions()?)
+        };
+
+        Ok(HomeState {
+            workout_type: schedule.workout_type(today),
+            logged: schedule::is_logged(&sessions, today),
+            blocker_active: host.blocker_active(),
+            next_micro_break: host.next_micro_break(),
+            streak: schedule.training_streak(today, &sessions),
+        })
+    }
+
+    fn update_status(&self, update: impl FnOnce(&mut MqttStatus)) {
+        update(&mut self.status.lock().unwrap());
+    }
+}
+
+fn disabled_config() -> MqttConfig {
+    MqttConfig {
+        enabled: false,
+        host: DEFAULT_HOST.to_string(),
+        port: DEFAULT_PORT,
+        username: None,
+        password: None,
+        tls: false,
+        ca_file: None,
+        topic_prefix: DEFAULT_TOPIC_PREFIX.to_string(),
+    }
+}
+
+/// Leave cleanly: "offline" status (the will only fires on a lost
+/// connection), then DISCONNECT
+async fn disconnect(client: &AsyncClient, eventloop: &mut EventLoop, topics: &Topics) {
+    let _ = client.publish(&topics.status, QoS::AtLeastOnce, true, "offline").await;
+    let _ = client.disconnect().await;
+
+    let drain = async {
+        while let Ok(event) = eventloop.poll().await {
+            if matches!(event, Event::Outgoing(rumqttc::Outgoing::Disconnect)) {
+                break;
+            }
+        }
+    };
+    let _ = tokio::time::timeout(Duration::from_secs(2), drain).await;
+}
+
+/// Wait for an mqtt_* setting to change (Some(true)), or for `timeout`
+/// (Some(false)); None when the bus closed
+async fn wait_for_settings(
+    changes: &mut broadcast::Receiver<ChangeEvent>,
+    timeout: Option<Duration>,
+) -> Option<bool> {
+    let changed = async {
+        loop {
+            match changes.recv().await {
+                Ok(ChangeEvent { kind: ChangeKind::SettingChanged { key }, .. }) if key.starts_with(SETTING_PREFIX) => {
+                    return true;
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return false,
+            }
+        }
+    };
+
+    match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, changed).await {
+            Ok(changed) => changed.then_some(true),
+            Err(_) => Some(false),
+        },
+        None => changed.await.then_some(true),
+    }
+}
+
+/// Let a burst of settings writes finish, then drop the events it queued
+async fn settle(changes: &mut broadcast::Receiver<ChangeEvent>) {
+    tokio::time::sleep(Duration::from_millis(SETTINGS_SETTLE_MS)).await;
+    while !matches!(changes.try_recv(), Err(broadcast::error::TryRecvError::Empty | broadcast::error::TryRecvError::Closed)) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+    use rumqttc::{ConnAck, ConnectReturnCode, PubAck, Publish, SubAck, SubscribeReasonCode};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::mpsc;
+
+    const MAX_PACKET: usize = 1 << 20;
+
+    #[derive(Default)]
+    struct FakeHost {
+        snoozed: Mutex<Vec<u64>>,
+    }
+
+    impl Host for FakeHost {
+        fn blocker_active(&self) -> bool {
+            true
+        }
+        fn next_micro_break(&self) -> Option<DateTime<Local>> {
+            None
+        }
+        fn snooze_break(&self, minutes: u64) {
+            self.snoozed.lock().unwrap().push(minutes);
+        }
+        fn open_app(&self) {}
+    }
+
+    /// Minimal local broker: accepts one client, acknowledges everything,
+    /// reports what it publishes and forwards `to_client` messages to it
+    async fn stand_in_broker() -> (u16, mpsc::UnboundedReceiver<Publish>, mpsc::UnboundedSender<Publish>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (published_tx, published_rx) = mpsc::unbounded_channel();
+        let (to_client_tx, mut to_client_rx) = mpsc::unbounded_channel::<Publish>();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buffer = BytesMut::new();
+            loop {
+                let mut replies = Vec::new();
+                tokio::select! {
+                    read = socket.read_buf(&mut buffer) => {
+                        if read.unwrap_or(0) == 0 {
+                            return;
+                        }
+                        while let Ok(packet) = Packet::read(&mut buffer, MAX_PACKET) {
+                            match packet {
+                                Packet::Connect(_) => {
+                                    replies.push(Packet::ConnAck(ConnAck::new(ConnectReturnCode::Success, false)));
+                                }
+                                Packet::Subscribe(subscribe) => replies.push(Packet::SubAck(SubAck::new(
+                                    subscribe.pkid,
+                                    vec![SubscribeReasonCode::Success(QoS::AtLeastOnce); subscribe.filters.len()],
+                                ))),
+                                Packet::Publish(publish) => {
+                                    if publish.qos == QoS::AtLeastOnce {
+                                        replies.push(Packet::PubAck(PubAck::new(publish.pkid)));
+                                    }
+                                    let _ = published_tx.send(publish);
+                                }
+                                Packet::PingReq => replies.push(Packet::PingResp),
+                                _ => {}
+                            }
+                        }
+                    }
+                    Some(publish) = to_client_rx.recv() => replies.push(Packet::Publish(publish)),
+                }
+
+                let mut out = BytesMut::new();
+                for reply in replies {
+                    reply.write(&mut out, MAX_PACKET).unwrap();
+                }
+                socket.write_all(&out).await.unwrap();
+            }
+        });
+
+        (port, published_rx, to_client_tx)
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let topics = Topics::new("home/traindaily");
+        assert_eq!(topics.command_filter(), "home/traindaily/command/#");
+        assert_eq!(
+            topics.parse_command("home/traindaily/command/snooze_break", b"15").unwrap(),
+            Command::SnoozeBreak { minutes: 15 }
+        );
+        assert_eq!(
+            topics.parse_command("home/traindaily/command/snooze_break", b"").unwrap(),
+            Command::SnoozeBreak { minutes: DEFAULT_SNOOZE_MINUTES }
+        );
+        assert_eq!(topics.parse_command("home/traindaily/command/open_app", b"").unwrap(), Command::OpenApp);
+        assert!(topics.parse_command("home/traindaily/command/snooze_break", b"0").is_err());
+        assert!(topics.parse_command("home/traindaily/command/reboot", b"").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_publishes_retained_state_and_takes_commands() {
+        let (port, mut published, to_client) = stand_in_broker().await;
+        let config = MqttConfig { port, host: "127.0.0.1".into(), topic_prefix: "test/traindaily".into(), ..disabled_config() };
+        let bus = ChangeBus::new();
+        let bridge = MqttBridge::new(Arc::new(Mutex::new(Database::new().unwrap())), "mac".into(), bus.clone());
+        let host = Arc::new(FakeHost::default());
+
+        let serving = {
+            let (bridge, host, mut changes) = (bridge.clone(), host.clone(), bus.subscribe());
+            tokio::spawn(async move { bridge.serve(&config, host.as_ref(), &mut changes).await.map(|_| ()) })
+        };
+
+        let mut retained = HashMap::new();
+        while retained.len() < 6 {
+            let publish = tokio::time::timeout(Duration::from_secs(5), published.recv()).await.unwrap().unwrap();
+            assert!(publish.retain, "{} not retained", publish.topic);
+            retained.insert(publish.topic, String::from_utf8(publish.payload.to_vec()).unwrap());
+        }
+        assert_eq!(retained["test/traindaily/status"], "online");
+        assert_eq!(retained["test/traindaily/blocker/active"], "ON");
+        assert!(["push", "pull", "legs", "rest"].contains(&retained["test/traindaily/workout/type"].as_str()));
+        assert!(bridge.status().connected);
+
+        to_client.send(Publish::new("test/traindaily/command/snooze_break", QoS::AtMostOnce, "15")).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while host.snoozed.lock().unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(*host.snoozed.lock().unwrap(), [15]);
+
+        serving.abort();
+    }
+}
@@ -0,0 +1,158 @@
+/**
+ * Network Module
+ *
+ * LAN addresses the sync server can be reached on, from the interface list
+ * (no default route needed, so offline hotspots work too)
+ * - Skips loopback, link-local and VPN / virtual interfaces
+ * - Ranks what's left: private IPv4 on physical interfaces first, IPv6 last
+ * - The user may pin one address (`sync_preferred_address`), which then
+ *   ranks first while it exists
+ */
+
+use crate::db::Database;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+pub const PREFERRED_ADDRESS_SETTING: &str = "sync_preferred_address";
+
+// Interface name prefixes of tunnels and virtual adapters
+// (utun: macOS VPNs / Tailscale / iCloud Private Relay, awdl/llw: AirDrop)
+const VIRTUAL_INTERFACE_PREFIXES: &[&str] = &[
+    "utun", "tun", "tap", "ppp", "ipsec", "wg", "tailscale", "zt", "gif", "stf",
+    "awdl", "llw", "anpi", "docker", "veth", "virbr", "vmnet", "vboxnet",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkAddress {
+    pub ip: IpAddr,
+    pub interface: String,
+    /// Higher is better (see rank)
+    pub rank: u8,
+    /// Pinned by the user
+    pub preferred: bool,
+}
+
+/// Candidate LAN addresses, best first
+pub fn candidate_addresses(preferred: Option<IpAddr>) -> Result<Vec<NetworkAddress>> {
+    let interfaces = if_addrs::get_if_addrs()
+        .context("Failed to enumerate network interfaces")?;
+
+    let mut addresses: Vec<NetworkAddress> = interfaces
+        .into_iter()
+        .filter(|iface| !iface.is_loopback() && !is_virtual_interface(&iface.name))
+        .filter_map(|iface| {
+            let ip = iface.ip();
+            rank(ip, &iface.name).map(|rank| NetworkAddress {
+                ip,
+                interface: iface.name,
+                rank,
+                preferred: Some(ip) == preferred,
+            })
+        })
+        .collect();
+
+    addresses.sort_by(|a, b| {
+        b.preferred
+            .cmp(&a.preferred)
+            .then(b.rank.cmp(&a.rank))
+            .then(a.interface.cmp(&b.interface))
+    });
+    // Same IP on several interfaces: keep the best-ranked one
+    let mut seen = HashSet::new();
+    addresses.retain(|address| seen.insert(address.ip));
+
+    Ok(addresses)
+}
+
+/// Candidates honouring the user's pinned address
+pub fn candidate_addresses_for(db: &Database) -> Result<Vec<NetworkAddress>> {
+    candidate_addresses(preferred_address(db))
+}
+
+/// The user's pinned address, if set and valid
+pub fn preferred_address(db: &Database) -> Option<IpAddr> {
+    db.get_setting(PREFERRED_ADDRESS_SETTING)
+        .ok()
+        .flatten()
+        .and_then(|value| value.trim().parse().ok())
+}
+
+fn is_virtual_interface(name: &str) -> bool {
+    VIRTUAL_INTERFACE_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
+
+/// How likely a phone on the same network can reach `ip`; None = unusable
+fn rank(ip: IpAddr, interface: &str) -> Option<u8> {
+    // en*/eth*/wl*: Ethernet and Wi-Fi; bridge*: macOS Internet Sharing hotspot
+    let physical = ["en", "eth", "wl", "bridge"].iter().any(|p| interface.starts_with(p));
+    let bonus = if physical { 10 } else { 0 };
+
+    let base = match ip {
+        IpAddr::V4(v4) => {
+            if v4.is_loopback() || v4.is_link_local() || v4.is_unspecified() || v4.is_multicast() {
+                return None;
+            }
+            if v4.is_private() {
+                80
+            } else if is_shared_v4(v4) {
+                // Carrier-grade NAT: usually a VPN overlay (Tailscale) or tethering
+                40
+            } else {
+                30
+            }
+        }
+        IpAddr::V6(v6) => {
+            if v6.is_loopback() || v6.is_unspecified() || v6.is_multicast() || is_link_local_v6(v6) {
+                return None;
+            }
+            if is_unique_local_v6(v6) {
+                20
+            } else {
+                10
+            }
+        }
+    };
+
+    Some(base + bonus)
+}
+
+fn is_shared_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    a == 100 && (64..128).contains(&b)
+}
+
+fn is_link_local_v6(ip: Ipv6Addr) -> bool {
+    (ip.segments()[0] & 0xffc0) == 0xfe80
+}
+
+fn is_unique_local_v6(ip: Ipv6Addr) -> bool {
+    (ip.segments()[0] & 0xfe00) == 0xfc00
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank_prefers_private_ipv4_on_physical_interfaces() {
+        let lan = rank("192.168.1.20".parse().unwrap(), "en0").unwrap();
+        let cgnat = rank("100.101.5.6".parse().unwrap(), "en5").unwrap();
+        let ula = rank("fd12::5".parse().unwrap(), "en0").unwrap();
+        let other = rank("10.0.0.8".parse().unwrap(), "gpd0").unwrap();
+
+        assert!(lan > other && other > cgnat && cgnat > ula);
+        assert_eq!(rank("169.254.3.4".parse().unwrap(), "en0"), None);
+        assert_eq!(rank("fe80::1".parse().unwrap(), "en0"), None);
+    }
+
+    #[test]
+    fn test_virtual_interfaces_are_skipped() {
+        assert!(is_virtual_interface("utun3"));
+        assert!(is_virtual_interface("awdl0"));
+        assert!(!is_virtual_interface("en0"));
+        assert!(!is_virtual_interface("bridge100"));
+    }
+}
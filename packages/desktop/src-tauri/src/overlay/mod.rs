@@ -5,8 +5,9 @@
  * - Triggers every 30 minutes (optimal for desk workers)
  * - 2-3 min active breaks (walking/movement)
  * - Work hours: 8am-midnight (16-hour workday)
- * - Skips rest days (Sunday)
+ * - Skips rest days (per the schedule module)
  * - Defers if microphone is active (on a call)
+ * - Publishes shown / dismissed / deferred on the change bus
  *
  * Evidence: https://www.tandfonline.com/doi/full/10.1080/23311916.2022.2026206
  * Meta-analysis: https://journals.plos.org/plosone/article?id=10.1371/journal.pone.0272460
@@ -14,7 +15,11 @@
 
 use tauri::{Manager, WebviewUrl, WebviewWindowBuilder};
 use tokio::time::{sleep, Duration, Instant};
-use chrono::{Local, Datelike, Timelike};
+use chrono::{Local, Timelike};
+use std::sync::{Arc, Mutex};
+use crate::changes::{ChangeBus, ChangeEvent, ChangeKind, ChangeOrigin};
+use crate::db::Database;
+use crate::schedule::Schedule;
 
 // Evidence-based intervals: 2-3 min breaks every 30 min for sedentary workers
 const MICRO_BREAK_INTERVAL_SECS: u64 = 1800; // 30 minutes (was 60)
@@ -36,16 +41,30 @@ impl OverlayState {
             deferred_until: None,
         }
     }
+
+    /// Wall-clock time the next break is due (ignoring work hours)
+    pub fn next_break_at(&self) -> chrono::DateTime<Local> {
+        let due = self
+            .deferred_until
+            .unwrap_or(self.last_break + Duration::from_secs(MICRO_BREAK_INTERVAL_SECS));
+        let remaining = due.saturating_duration_since(Instant::now());
+        Local::now() + chrono::Duration::from_std(remaining).unwrap_or_default()
+    }
 }
 
 /// Check if current time is within work hours and not a rest day
-fn should_trigger_break() -> bool {
+fn should_trigger_break(db: &Arc<Mutex<Database>>) -> bool {
     let now = Local::now();
     let hour = now.hour() as u8;
-    let weekday = now.weekday();
 
-    // Skip on Sunday (rest day = 0 in chrono, Sun)
-    if weekday == chrono::Weekday::Sun {
+    // Skip rest days
+    let is_training_day = db
+        .lock()
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .and_then(|db| Schedule::load(&db))
+        .map(|schedule| schedule.is_training_day(now.date_naive()))
+        .unwrap_or(true);
+    if !is_training_day {
         return false;
     }
 
@@ -56,13 +75,15 @@ fn should_trigger_break() -> bool {
 /// Start micro-break overlay background task
 pub async fn start_overlay(
     app_handle: tauri::AppHandle,
-    state: std::sync::Arc<std::sync::Mutex<OverlayState>>,
+    db: Arc<Mutex<Database>>,
+    state: Arc<Mutex<OverlayState>>,
+    bus: ChangeBus,
 ) {
     loop {
         sleep(Duration::from_secs(60)).await; // Check every minute
 
         // Skip if outside work hours or rest day
-        if !should_trigger_break() {
+        if !should_trigger_break(&db) {
             continue;
         }
 
@@ -71,24 +92,26 @@ pub async fn start_overlay(
 
         // Check if deferred break should trigger now
         if let Some(deferred_until) = overlay_state.deferred_until {
-            if now >= deferred_until {
-                // Time to show deferred break
-                overlay_state.deferred_until = None;
-
-                if !crate::mic::is_mic_active().unwrap_or(false) {
-                    drop(overlay_state); // Release lock before showing window
-                    if let Err(e) = show_overlay_window(&app_handle) {
-                        tracing::error!("Failed to show overlay: {}", e);
-                    }
-                    let mut state_lock = state.lock().unwrap();
-                    state_lock.last_break = now;
-                } else {
-                    // Still on call, defer again
-                    overlay_state.deferred_until = Some(now + Duration::from_secs(DEFER_DURATION_SECS));
-                    tracing::info!("Micro-break still deferred (mic active)");
-                }
+            // Deferred (or snoozed): wait for it rather than the regular interval
+            if now < deferred_until {
                 continue;
             }
+
+            // Time to show deferred break
+            overlay_state.deferred_until = None;
+
+            if !crate::mic::is_mic_active().unwrap_or(false) {
+                drop(overlay_state); // Release lock before showing window
+                show_break(&app_handle, &bus);
+                let mut state_lock = state.lock().unwrap();
+                state_lock.last_break = now;
+            } else {
+                // Still on call, defer again
+                overlay_state.deferred_until = Some(now + Duration::from_secs(DEFER_DURATION_SECS));
+                tracing::info!("Micro-break still deferred (mic active)");
+                publish_deferred(&bus);
+            }
+            continue;
         }
 
         // Check if regular break should trigger
@@ -99,12 +122,11 @@ pub async fn start_overlay(
                 // Defer break (on a call)
                 overlay_state.deferred_until = Some(now + Duration::from_secs(DEFER_DURATION_SECS));
                 tracing::info!("Micro-break deferred (mic active), will retry in 5 minutes");
+                publish_deferred(&bus);
             } else {
                 // Show break overlay
                 drop(overlay_state); // Release lock before showing window
-                if let Err(e) = show_overlay_window(&app_handle) {
-                    tracing::error!("Failed to show overlay: {}", e);
-                }
+                show_break(&app_handle, &bus);
                 let mut state_lock = state.lock().unwrap();
                 state_lock.last_break = now;
             }
@@ -112,6 +134,21 @@ pub async fn start_overlay(
     }
 }
 
+/// Show the overlay and announce it
+fn show_break(app_handle: &tauri::AppHandle, bus: &ChangeBus) {
+    match show_overlay_window(app_handle) {
+        Ok(()) => bus.publish(ChangeEvent { origin: ChangeOrigin::Desktop, kind: ChangeKind::MicroBreakShown }),
+        Err(e) => tracing::error!("Failed to show overlay: {}", e),
+    }
+}
+
+fn publish_deferred(bus: &ChangeBus) {
+    bus.publish(ChangeEvent {
+        origin: ChangeOrigin::Desktop,
+        kind: ChangeKind::MicroBreakDeferred { minutes: DEFER_DURATION_SECS / 60 },
+    });
+}
+
 /// Show full-screen micro-break overlay
 fn show_overlay_window(app_handle: &tauri::AppHandle) -> Result<(), Box<dyn std::error::Error>> {
     // Check if window already exists
@@ -143,12 +180,34 @@ fn show_overlay_window(app_handle: &tauri::AppHandle) -> Result<(), Box<dyn std:
     Ok(())
 }
 
+/// Postpone the next micro-break by `minutes`, closing the overlay if it is
+/// showing (remote snooze, e.g. from MQTT)
+pub fn snooze_micro_break(app_handle: &tauri::AppHandle, minutes: u64) {
+    let state = app_handle.state::<crate::AppState>();
+    state.overlay_state.lock().unwrap().deferred_until = Some(Instant::now() + Duration::from_secs(minutes * 60));
+
+    if let Some(window) = app_handle.get_webview_window("micro-break") {
+        if let Err(e) = window.close() {
+            tracing::error!("Failed to close overlay: {}", e);
+        }
+    }
+    tracing::info!("Micro-break snoozed for {} minutes", minutes);
+    state.changes.publish(ChangeEvent {
+        origin: ChangeOrigin::Desktop,
+        kind: ChangeKind::MicroBreakDeferred { minutes },
+    });
+}
+
 /// Dismiss micro-break overlay (called from frontend)
 #[tauri::command]
 pub fn dismiss_micro_break(app_handle: tauri::AppHandle) -> Result<(), String> {
     if let Some(window) = app_handle.get_webview_window("micro-break") {
         window.close().map_err(|e| e.to_string())?;
         tracing::info!("Micro-break dismissed");
+        app_handle.state::<crate::AppState>().changes.publish(ChangeEvent {
+            origin: ChangeOrigin::Desktop,
+            kind: ChangeKind::MicroBreakDismissed,
+        });
     }
     Ok(())
 }
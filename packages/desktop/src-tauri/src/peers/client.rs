@@ -0,0 +1,231 @@
+/**
+ * Peer Client
+ *
+ * HTTPS client for another desktop's sync server
+ * - Trust comes from the key fingerprint exchanged when pairing, not from a
+ *   CA: every desktop has its own local CA, and its certificate names rarely
+ *   match the address a peer reaches it on
+ * - Every request carries the peer's pairing secret and our device id
+ * - `verify` checks the signed ping, proving the peer knows the secret
+ */
+
+use crate::sync::replication::{ChangeFeed, PairingConfirmation, PeerAnnouncement};
+use crate::sync::{auth, ping_signing_payload, status::DEVICE_ID_HEADER};
+use anyhow::{anyhow, bail, Context, Result};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, DigitallySignedStruct, SignatureScheme};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long a changes request waits for something new on the peer
+pub const LONG_POLL_SECS: u64 = 25;
+
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+// The peer calls us back to confirm an announcement before answering it
+const ANNOUNCE_TIMEOUT_SECS: u64 = 3 * REQUEST_TIMEOUT_SECS;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PingReply {
+    device_id: String,
+    fingerprint: String,
+    nonce: String,
+    timestamp: i64,
+    signature: String,
+}
+
+pub struct PeerClient {
+    http: reqwest::Client,
+    token: String,
+    fingerprint: String,
+    own_device_id: String,
+}
+
+impl PeerClient {
+    /// Client for a peer with pairing secret `token` and key `fingerprint`
+    pub fn new(fingerprint: &str, token: &str, own_device_id: &str) -> Result<Self> {
+        let fingerprint = fingerprint.to_lowercase();
+        let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+        let tls = rustls::ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedKey {
+                fingerprint: fingerprint.clone(),
+                provider,
+            }))
+            .with_no_client_auth();
+
+        let http = reqwest::Client::builder()
+            .tls_backend_preconfigured(tls)
+            .connect_timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .build()
+            .context("Failed to build peer HTTP client")?;
+
+        Ok(Self {
+            http,
+            token: token.to_string(),
+            fingerprint,
+            own_device_id: own_device_id.to_string(),
+        })
+    }
+
+    /// Signed ping: the peer's device id, once it proves it holds the secret
+    pub async fn verify(&self, base: &str) -> Result<String> {
+        let nonce = hex::encode(rand::random::<[u8; 16]>());
+        let reply: PingReply = self
+            .http
+            .get(format!("{}/api/v1/ping", base))
+            .query(&[("nonce", &nonce)])
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let expected = auth::sign(
+            &self.token,
+            &ping_signing_payload(&reply.device_id, &reply.fingerprint, &reply.nonce, reply.timestamp),
+        );
+        if reply.nonce != nonce || !auth::tokens_match(&reply.signature, &expected) {
+            bail!("Peer at {} did not prove it knows the pairing secret", base);
+        }
+        if reply.fingerprint.to_lowercase() != self.fingerprint {
+            bail!("Peer at {} reports a different key fingerprint", base);
+        }
+
+        Ok(reply.device_id)
+    }
+
+    /// Hand the peer our own credentials so it replicates from us too
+    pub async fn announce(&self, base: &str, announcement: &PeerAnnouncement) -> Result<()> {
+        self.request(reqwest::Method::POST, base, "/api/v1/peers")
+            .json(announcement)
+            .timeout(Duration::from_secs(ANNOUNCE_TIMEOUT_SECS))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Ask the peer whether it issued `nonce` for a pairing in progress
+    pub async fn confirm_pairing(&self, base: &str, nonce: &str) -> Result<()> {
+        self.request(reqwest::Method::POST, base, "/api/v1/peers/confirm")
+            .json(&PairingConfirmation { nonce: nonce.to_string() })
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Changes after `after`, waiting up to LONG_POLL_SECS for new ones
+    pub async fn changes(&self, base: &str, after: i64) -> Result<ChangeFeed> {
+        let feed = self
+            .request(reqwest::Method::GET, base, "/api/v1/changes")
+            .query(&[("after", after), ("wait", LONG_POLL_SECS as i64)])
+            .timeout(Duration::from_secs(LONG_POLL_SECS + REQUEST_TIMEOUT_SECS))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(feed)
+    }
+
+    fn request(&self, method: reqwest::Method, base: &str, path: &str) -> reqwest::RequestBuilder {
+        self.http
+            .request(method, format!("{}{}", base, path))
+            .bearer_auth(&self.token)
+            .header(DEVICE_ID_HEADER, &self.own_device_id)
+    }
+}
+
+/// `https://ip:port` for a peer address (IPv6 in brackets)
+pub fn base_url(ip: IpAddr, port: u16) -> String {
+    match ip {
+        IpAddr::V4(v4) => format!("https://{}:{}", v4, port),
+        IpAddr::V6(v6) => format!("https://[{}]:{}", v6, port),
+    }
+}
+
+/// Accepts exactly the certificate whose public key hashes to the pinned fingerprint
+#[derive(Debug)]
+struct PinnedKey {
+    fingerprint: String,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedKey {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let actual = spki_fingerprint(end_entity)
+            .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::BadEncoding))?;
+        if auth::tokens_match(&actual, &self.fingerprint) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Hex SHA-256 of a certificate's SubjectPublicKeyInfo (same as cert::key_fingerprint)
+fn spki_fingerprint(cert: &CertificateDer<'_>) -> Result<String> {
+    let cert = webpki::EndEntityCert::try_from(cert).map_err(|e| anyhow!("{:?}", e))?;
+    Ok(hex::encode(Sha256::digest(cert.subject_public_key_info().as_ref())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spki_fingerprint_matches_key_fingerprint() {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec!["peer.local".to_string()])
+            .unwrap()
+            .self_signed(&key)
+            .unwrap();
+
+        assert_eq!(spki_fingerprint(cert.der()).unwrap(), crate::cert::key_fingerprint(&key));
+    }
+
+    #[test]
+    fn test_base_url_brackets_ipv6() {
+        assert_eq!(base_url("192.168.1.20".parse().unwrap(), 8765), "https://192.168.1.20:8765");
+        assert_eq!(base_url("fe80::1".parse().unwrap(), 8765), "https://[fe80::1]:8765");
+    }
+}
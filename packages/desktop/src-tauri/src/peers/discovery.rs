@@ -0,0 +1,158 @@
+/**
+ * Peer Discovery (mDNS)
+ *
+ * Advertises this desktop's sync server as `_traindaily._tcp` and browses for
+ * other desktops on the LAN
+ * - Instance name is the device id; TXT records carry the id, the key
+ *   fingerprint and the API version
+ * - Discovery only supplies addresses: a peer is trusted once paired
+ *   (fingerprint pinned, secret verified), never because it was found
+ * - Loopback is included so two instances on one machine find each other
+ */
+
+use anyhow::Result;
+use mdns_sd::{IfKind, ServiceDaemon, ServiceEvent, ServiceInfo};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+pub const SERVICE_TYPE: &str = "_traindaily._tcp.local.";
+
+const TXT_DEVICE_ID: &str = "id";
+const TXT_FINGERPRINT: &str = "fp";
+const TXT_API_VERSION: &str = "api";
+
+/// A desktop seen on the LAN
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscoveredPeer {
+    pub device_id: String,
+    pub fingerprint: Option<String>,
+    pub addresses: Vec<IpAddr>,
+    pub port: u16,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Discovery {
+    Found(DiscoveredPeer),
+    /// Instance name (the device id) of a desktop that left
+    Lost(String),
+}
+
+pub struct Discoverer {
+    daemon: ServiceDaemon,
+    /// Full name of our own advertisement, if any
+    advertised: Option<String>,
+}
+
+impl Discoverer {
+    pub fn new() -> Result<Self> {
+        let daemon = ServiceDaemon::new()?;
+        daemon.enable_interface(IfKind::LoopbackV4)?;
+        Ok(Self { daemon, advertised: None })
+    }
+
+    /// Advertise our sync server (replaces a previous advertisement)
+    pub fn advertise(&mut self, device_id: &str, port: u16, fingerprint: &str) -> Result<()> {
+        self.withdraw();
+
+        let properties = HashMap::from([
+            (TXT_DEVICE_ID.to_string(), device_id.to_string()),
+            (TXT_FINGERPRINT.to_string(), fingerprint.to_string()),
+            (TXT_API_VERSION.to_string(), crate::sync::api::API_VERSION.to_string()),
+        ]);
+        let host = format!("{}.local.", device_id);
+        let info = ServiceInfo::new(SERVICE_TYPE, device_id, &host, (), port, properties)?.enable_addr_auto();
+
+        self.advertised = Some(info.get_fullname().to_string());
+        self.daemon.register(info)?;
+        Ok(())
+    }
+
+    pub fn withdraw(&mut self) {
+        if let Some(fullname) = self.advertised.take() {
+            let _ = self.daemon.unregister(&fullname);
+        }
+    }
+
+    /// Stream of raw mDNS events for SERVICE_TYPE (see parse_event)
+    pub fn browse(&self) -> Result<mdns_sd::Receiver<ServiceEvent>> {
+        Ok(self.daemon.browse(SERVICE_TYPE)?)
+    }
+}
+
+impl Drop for Discoverer {
+    fn drop(&mut self) {
+        self.withdraw();
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// Peers found or lost, ignoring our own advertisement and unresolved records
+pub fn parse_event(event: ServiceEvent, own_device_id: &str) -> Option<Discovery> {
+    match event {
+        ServiceEvent::ServiceResolved(info) => {
+            let device_id = info
+                .get_property_val_str(TXT_DEVICE_ID)
+                .map(str::to_string)
+                .unwrap_or_else(|| instance_name(info.get_fullname()));
+            if device_id == own_device_id || info.get_addresses().is_empty() {
+                return None;
+            }
+
+            let mut addresses: Vec<IpAddr> = info.get_addresses().iter().copied().collect();
+            // IPv4 first, loopback last: the order replication tries them in
+            addresses.sort_by_key(|ip| (ip.is_loopback(), ip.is_ipv6(), *ip));
+
+            Some(Discovery::Found(DiscoveredPeer {
+                device_id,
+                fingerprint: info.get_property_val_str(TXT_FINGERPRINT).map(str::to_lowercase),
+                addresses,
+                port: info.get_port(),
+            }))
+        }
+        ServiceEvent::ServiceRemoved(_, fullname) => {
+            let device_id = instance_name(&fullname);
+            (device_id != own_device_id).then_some(Discovery::Lost(device_id))
+        }
+        _ => None,
+    }
+}
+
+/// `<instance>._traindaily._tcp.local.` -> `<instance>`
+fn instance_name(fullname: &str) -> String {
+    fullname
+        .strip_suffix(SERVICE_TYPE)
+        .map(|name| name.trim_end_matches('.'))
+        .unwrap_or(fullname)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_event_skips_self_and_orders_addresses() {
+        let properties = HashMap::from([
+            (TXT_DEVICE_ID.to_string(), "other-mac".to_string()),
+            (TXT_FINGERPRINT.to_string(), "AB".repeat(32)),
+        ]);
+        let ips: &[IpAddr] = &["127.0.0.1".parse().unwrap(), "192.168.1.20".parse().unwrap()];
+        let info = ServiceInfo::new(SERVICE_TYPE, "other-mac", "other-mac.local.", ips, 8765, properties).unwrap();
+
+        let Some(Discovery::Found(peer)) = parse_event(ServiceEvent::ServiceResolved(info.clone()), "this-mac") else {
+            panic!("expected a found peer");
+        };
+        assert_eq!(peer.device_id, "other-mac");
+        assert_eq!(peer.addresses[0], "192.168.1.20".parse::<IpAddr>().unwrap());
+        assert_eq!(peer.fingerprint, Some("ab".repeat(32)));
+
+        assert_eq!(parse_event(ServiceEvent::ServiceResolved(info), "other-mac"), None);
+        assert_eq!(
+            parse_event(
+                ServiceEvent::ServiceRemoved(SERVICE_TYPE.to_string(), format!("other-mac.{}", SERVICE_TYPE)),
+                "this-mac"
+            ),
+            Some(Discovery::Lost("other-mac".to_string()))
+        );
+    }
+}
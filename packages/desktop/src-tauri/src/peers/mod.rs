@@ -0,0 +1,623 @@
+/**
+ * Desktop Peers
+ *
+ * Replicates sessions between desktops (e.g. a work and a home Mac), each
+ * with its own workouts.db
+ * - Discovery: every desktop advertises its sync server over mDNS as
+ *   `_traindaily._tcp` and browses for the others (see discovery)
+ * - Pairing: paste the other desktop's pairing URL ("Copy Pairing URL"); its
+ *   signed ping is verified, then we announce our own secret back so it
+ *   replicates from us as well (POST /api/v1/peers); it confirms the
+ *   announcement's one-time nonce with us before accepting it
+ * - Replication: one task per paired peer long-polls its GET /api/v1/changes
+ *   and applies each session with last-writer-wins (db::apply_replicated);
+ *   changes keep their original stamp, so echoes back are no-ops
+ * - Addresses: discovered ones first, then the last known ones
+ *
+ * Two instances on one machine: start the second with TRAINDAILY_DATA_DIR
+ * pointing at another directory; it falls back to the next free port and
+ * both find each other over loopback.
+ */
+
+pub mod client;
+pub mod discovery;
+
+use crate::changes::{ChangeBus, ChangeEvent, ChangeKind, ChangeOrigin};
+use crate::db::{Database, PeerRecord, ReplicatedChange};
+use crate::sync::handle::SyncServerHandle;
+use crate::sync::listen::Listening;
+use crate::sync::replication::{ChangeFeed, PeerAnnouncement};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use client::{base_url, PeerClient};
+use discovery::{DiscoveredPeer, Discoverer, Discovery};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+const MIN_RETRY_SECS: u64 = 2;
+const MAX_RETRY_SECS: u64 = 60;
+
+/// A desktop as shown in settings: paired, nearby, or both
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerSummary {
+    pub device_id: String,
+    pub paired: bool,
+    /// Currently advertised on the LAN
+    pub online: bool,
+    pub addresses: Vec<String>,
+    pub port: u16,
+    pub paired_at: Option<String>,
+    /// Base URL replication is running over
+    pub connected_via: Option<String>,
+    pub last_synced_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Clone, Default)]
+struct LinkState {
+    connected_via: Option<String>,
+    last_synced_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+/// A running replication task
+struct Link {
+    /// Credentials it was started with; a re-pair restarts it
+    token: String,
+    fingerprint: String,
+    task: JoinHandle<()>,
+    state: Arc<Mutex<LinkState>>,
+}
+
+/// What a pairing URL tells us about the other desktop
+#[derive(Debug, PartialEq)]
+struct PairingInvite {
+    device_id: String,
+    addresses: Vec<IpAddr>,
+    port: u16,
+    secret: String,
+    fingerprint: String,
+}
+
+pub struct PeerManager {
+    db: Arc<Mutex<Database>>,
+    device_id: String,
+    auth_token: String,
+    bus: ChangeBus,
+    sync: Arc<SyncServerHandle>,
+    discovered: Mutex<HashMap<String, DiscoveredPeer>>,
+    links: Mutex<HashMap<String, Link>>,
+}
+
+impl PeerManager {
+    pub fn new(
+        db: Arc<Mutex<Database>>,
+        device_id: String,
+        auth_token: String,
+        bus: ChangeBus,
+        sync: Arc<SyncServerHandle>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            db,
+            device_id,
+            auth_token,
+            bus,
+            sync,
+            discovered: Mutex::new(HashMap::new()),
+            links: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Paired and nearby desktops
+    pub fn list(&self) -> Result<Vec<PeerSummary>> {
+        let peers = self.db.lock().map_err(|e| anyhow!("{}", e))?.get_peers()?;
+        let discovered = self.discovered.lock().unwrap().clone();
+        let links = self.links.lock().unwrap();
+
+        let mut summaries: Vec<PeerSummary> = peers
+            .into_iter()
+            .map(|peer| {
+                let state = links
+                    .get(&peer.device_id)
+                    .map(|link| link.state.lock().unwrap().clone())
+                    .unwrap_or_default();
+                PeerSummary {
+                    online: discovered.contains_key(&peer.device_id),
+                    device_id: peer.device_id,
+                    paired: true,
+                    addresses: peer.addresses,
+                    port: peer.port,
+                    paired_at: Some(peer.paired_at),
+                    connected_via: state.connected_via,
+                    last_synced_at: state.last_synced_at,
+                    last_error: state.last_error,
+                }
+            })
+            .collect();
+
+        let mut nearby: Vec<PeerSummary> = discovered
+            .into_values()
+            .filter(|found| !summaries.iter().any(|peer| peer.device_id == found.device_id))
+            .map(|found| PeerSummary {
+                device_id: found.device_id,
+                paired: false,
+                online: true,
+                addresses: found.addresses.iter().map(IpAddr::to_string).collect(),
+                port: found.port,
+                paired_at: None,
+                connected_via: None,
+                last_synced_at: None,
+                last_error: None,
+            })
+            .collect();
+        nearby.sort_by(|a, b| a.device_id.cmp(&b.device_id));
+        summaries.append(&mut nearby);
+
+        Ok(summaries)
+    }
+
+    /// Pair with the desktop behind `pairing_url`, in both directions
+    pub async fn pair(&self, pairing_url: &str) -> Result<String> {
+        let invite = PairingInvite::parse(pairing_url)?;
+        if invite.device_id == self.device_id {
+            bail!("That is this desktop's own pairing URL");
+        }
+        let listening = self
+            .sync
+            .listening()
+            .ok_or_else(|| anyhow!("Turn on sync first: the other desktop replicates from this one's sync server"))?;
+
+        // Where mDNS saw it is fresher than the URL
+        let mut addresses = self
+            .discovered
+            .lock()
+            .unwrap()
+            .get(&invite.device_id)
+            .filter(|found| found.port == invite.port)
+            .map(|found| found.addresses.clone())
+            .unwrap_or_default();
+        for ip in &invite.addresses {
+            if !addresses.contains(ip) {
+                addresses.push(*ip);
+            }
+        }
+
+        let client = PeerClient::new(&invite.fingerprint, &invite.secret, &self.device_id)?;
+        let base = reach(&client, &invite.device_id, &addresses, invite.port).await?;
+
+        // The other desktop confirms this nonce with us before saving us as a peer
+        let nonce = self.sync.pairing_nonces().issue();
+        let announcement = self.announcement(&listening, nonce)?;
+        client
+            .announce(&base, &announcement)
+            .await
+            .context("The other desktop did not accept this one")?;
+
+        let db = self.db.lock().map_err(|e| anyhow!("{}", e))?;
+        db.save_peer(&PeerRecord {
+            device_id: invite.device_id.clone(),
+            token: invite.secret,
+            fingerprint: invite.fingerprint,
+            addresses: addresses.iter().map(IpAddr::to_string).collect(),
+            port: invite.port,
+            cursor: 0,
+            paired_at: Utc::now().to_rfc3339(),
+        })?;
+
+        tracing::info!("Paired with desktop {} via {}", invite.device_id, base);
+        Ok(invite.device_id)
+    }
+
+    /// Forget a paired desktop and stop replicating from it
+    pub fn unpair(&self, device_id: &str) -> Result<()> {
+        let deleted = self.db.lock().map_err(|e| anyhow!("{}", e))?.delete_peer(device_id)?;
+        if !deleted {
+            bail!("Not paired with {}", device_id);
+        }
+        Ok(())
+    }
+
+    /// Advertise, discover and keep replication tasks in line with the peers table
+    /// (runs for the app's lifetime)
+    pub async fn run(self: Arc<Self>) {
+        let mut bus = self.bus.subscribe();
+        let mut listening = self.sync.watch_listening();
+        self.reconcile();
+
+        // Without mDNS, paired peers are still reached at their last known addresses
+        let mut discoverer = Discoverer::new()
+            .inspect_err(|e| tracing::warn!("mDNS unavailable, peer discovery off: {}", e))
+            .ok();
+        let mut events = discoverer.as_ref().and_then(|d| {
+            d.browse()
+                .inspect_err(|e| tracing::warn!("Failed to browse for peers: {}", e))
+                .ok()
+        });
+        self.advertise(discoverer.as_mut(), listening.borrow_and_update().as_ref());
+
+        loop {
+            tokio::select! {
+                event = next_event(events.as_ref()) => match event {
+                    Some(event) => {
+                        if let Some(discovery) = discovery::parse_event(event, &self.device_id) {
+                            self.discovered_changed(discovery);
+                        }
+                    }
+                    None => events = None,
+                },
+                event = bus.recv() => match event {
+                    Ok(ChangeEvent { kind: ChangeKind::PeersChanged, .. }) | Err(RecvError::Lagged(_)) => {
+                        self.reconcile();
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Closed) => break,
+                },
+                changed = listening.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    self.advertise(discoverer.as_mut(), listening.borrow_and_update().as_ref());
+                }
+            }
+        }
+    }
+
+    fn advertise(&self, discoverer: Option<&mut Discoverer>, listening: Option<&Listening>) {
+        let Some(discoverer) = discoverer else {
+            return;
+        };
+        let Some(listening) = listening else {
+            discoverer.withdraw();
+            return;
+        };
+
+        let advertised = crate::cert::pinned_key_fingerprint()
+            .and_then(|fingerprint| discoverer.advertise(&self.device_id, listening.port, &fingerprint));
+        match advertised {
+            Ok(()) => tracing::info!("Advertising {} on port {}", discovery::SERVICE_TYPE, listening.port),
+            Err(e) => tracing::warn!("Failed to advertise sync server: {}", e),
+        }
+    }
+
+    fn discovered_changed(&self, discovery: Discovery) {
+        match discovery {
+            Discovery::Found(found) => {
+                tracing::debug!("Found desktop {} at {:?}", found.device_id, found.addresses);
+                // Keep the stored endpoint of a paired peer current (no event: links re-read it)
+                if let Ok(db) = self.db.lock() {
+                    let paired = db.get_peers().unwrap_or_default().into_iter().any(|peer| {
+                        peer.device_id == found.device_id
+                            && found.fingerprint.as_ref().is_none_or(|fp| *fp == peer.fingerprint)
+                    });
+                    if paired {
+                        let addresses: Vec<String> = found.addresses.iter().map(IpAddr::to_string).collect();
+                        let _ = db.set_peer_endpoint(&found.device_id, &addresses, found.port);
+                    }
+                }
+                self.discovered.lock().unwrap().insert(found.device_id.clone(), found);
+            }
+            Discovery::Lost(device_id) => {
+                self.discovered.lock().unwrap().remove(&device_id);
+            }
+        }
+    }
+
+    /// Start tasks for new (or re-paired) peers, stop those for removed ones
+    fn reconcile(self: &Arc<Self>) {
+        let peers = match self.db.lock().map_err(|e| anyhow!("{}", e)).and_then(|db| db.get_peers()) {
+            Ok(peers) => peers,
+            Err(e) => {
+                tracing::error!("Failed to load peers: {}", e);
+                return;
+            }
+        };
+
+        let mut links = self.links.lock().unwrap();
+        links.retain(|device_id, link| {
+            let current = peers.iter().any(|peer| {
+                peer.device_id == *device_id && peer.token == link.token && peer.fingerprint == link.fingerprint
+            });
+            if !current {
+                link.task.abort();
+            }
+            current
+        });
+
+        for peer in peers {
+            if links.contains_key(&peer.device_id) {
+                continue;
+            }
+            let state = Arc::new(Mutex::new(LinkState::default()));
+            let link = Link {
+                token: peer.token.clone(),
+                fingerprint: peer.fingerprint.clone(),
+                task: tokio::spawn(self.clone().replicate(peer.device_id.clone(), state.clone())),
+                state,
+            };
+            links.insert(peer.device_id, link);
+        }
+    }
+
+    /// Pull changes from one peer until the task is aborted
+    async fn replicate(self: Arc<Self>, device_id: String, state: Arc<Mutex<LinkState>>) {
+        let mut retry = MIN_RETRY_SECS;
+
+        loop {
+            let result = self.follow(&device_id, &state).await;
+            let was_connected = {
+                let mut state = state.lock().unwrap();
+                if let Err(e) = &result {
+                    state.last_error = Some(e.to_string());
+                }
+                state.connected_via.take().is_some()
+            };
+            if let Err(e) = result {
+                tracing::warn!("Replication from {} interrupted: {}", device_id, e);
+            }
+
+            // A connection that worked for a while starts the backoff over
+            if was_connected {
+                retry = MIN_RETRY_SECS;
+            }
+            tokio::time::sleep(Duration::from_secs(retry)).await;
+            retry = (retry * 2).min(MAX_RETRY_SECS);
+        }
+    }
+
+    /// Connect to the peer and apply its feed until a request fails
+    async fn follow(&self, device_id: &str, state: &Mutex<LinkState>) -> Result<()> {
+        let peer = self
+            .db
+            .lock()
+            .map_err(|e| anyhow!("{}", e))?
+            .get_peers()?
+            .into_iter()
+            .find(|peer| peer.device_id == device_id)
+            .ok_or_else(|| anyhow!("no longer paired"))?;
+
+        let (addresses, port) = self.endpoint(&peer);
+        let client = PeerClient::new(&peer.fingerprint, &peer.token, &self.device_id)?;
+        let base = reach(&client, device_id, &addresses, port).await?;
+        tracing::info!("Replicating from {} via {}", device_id, base);
+        state.lock().unwrap().connected_via = Some(base.clone());
+
+        let mut cursor = peer.cursor;
+        loop {
+            let feed = client.changes(&base, cursor).await?;
+            cursor = self.apply_feed(device_id, feed)?;
+
+            let mut state = state.lock().unwrap();
+            state.last_synced_at = Some(Utc::now());
+            state.last_error = None;
+        }
+    }
+
+    /// Apply a page of the peer's feed; returns the new cursor
+    fn apply_feed(&self, device_id: &str, feed: ChangeFeed) -> Result<i64> {
+        let db = self.db.lock().map_err(|e| anyhow!("{}", e))?;
+        let mut applied = 0;
+        for change in feed.changes {
+            let change = ReplicatedChange {
+                date_key: change.date_key,
+                session: change.session,
+                changed_at: change.changed_at,
+                origin: change.origin,
+            };
+            if db.apply_replicated(&change, &self.device_id, ChangeOrigin::Peer)?.is_some() {
+                applied += 1;
+            }
+        }
+        db.set_peer_cursor(device_id, feed.cursor)?;
+
+        if applied > 0 {
+            tracing::info!("Applied {} session(s) from {}", applied, device_id);
+        }
+        Ok(feed.cursor)
+    }
+
+    /// Addresses to try for a peer, discovered ones first
+    fn endpoint(&self, peer: &PeerRecord) -> (Vec<IpAddr>, u16) {
+        let stored = peer.addresses.iter().filter_map(|address| address.parse().ok());
+        match self.discovered.lock().unwrap().get(&peer.device_id) {
+            Some(found) => {
+                let mut addresses = found.addresses.clone();
+                if found.port == peer.port {
+                    addresses.extend(stored.filter(|ip| !found.addresses.contains(ip)));
+                }
+                (addresses, found.port)
+            }
+            None => (stored.collect(), peer.port),
+        }
+    }
+
+    /// What the other side needs to replicate from us
+    fn announcement(&self, listening: &Listening, nonce: String) -> Result<PeerAnnouncement> {
+        let db = self.db.lock().map_err(|e| anyhow!("{}", e))?;
+        let candidates = crate::network::candidate_addresses_for(&db)?;
+
+        Ok(PeerAnnouncement {
+            device_id: self.device_id.clone(),
+            secret: self.auth_token.clone(),
+            fingerprint: crate::cert::pinned_key_fingerprint()?,
+            port: listening.port,
+            addresses: crate::sync::pairing_addresses(listening, &candidates),
+            nonce,
+        })
+    }
+}
+
+impl PairingInvite {
+    /// Parse the pairing URL from the QR code / "Copy Pairing URL"
+    fn parse(pairing_url: &str) -> Result<Self> {
+        let url = reqwest::Url::parse(pairing_url.trim()).context("Not a pairing URL")?;
+        // Secrets ride in the fragment (never sent to a server); older
+        // desktops put everything in the query
+        let mut secrets = url.clone();
+        secrets.set_query(url.fragment());
+        let params: HashMap<String, String> = url
+            .query_pairs()
+            .into_owned()
+            .chain(secrets.query_pairs().into_owned())
+            .collect();
+        let param = |key: &str| {
+            params
+                .get(key)
+                .filter(|value| !value.is_empty())
+                .cloned()
+                .ok_or_else(|| anyhow!("Pairing URL is missing `{}`", key))
+        };
+
+        let mut addresses: Vec<IpAddr> = Vec::new();
+        let listed = params.get("ips").map(String::as_str).unwrap_or_default().split(',');
+        for address in params.get("ip").map(String::as_str).into_iter().chain(listed) {
+            if let Ok(ip) = address.trim().parse() {
+                if !addresses.contains(&ip) {
+                    addresses.push(ip);
+                }
+            }
+        }
+        if addresses.is_empty() {
+            bail!("Pairing URL has no addresses");
+        }
+
+        Ok(Self {
+            device_id: param("deviceId")?,
+            addresses,
+            port: param("port")?.parse().context("Invalid port in pairing URL")?,
+            secret: param("secret")?,
+            fingerprint: param("fp")?.to_lowercase(),
+        })
+    }
+}
+
+/// First address where the expected desktop answers a verified ping
+pub(crate) async fn reach(client: &PeerClient, device_id: &str, addresses: &[IpAddr], port: u16) -> Result<String> {
+    let mut last_error = anyhow!("no known address");
+    for ip in addresses {
+        let base = base_url(*ip, port);
+        match client.verify(&base).await {
+            Ok(id) if id == device_id => return Ok(base),
+            Ok(id) => last_error = anyhow!("{} is {}, not {}", base, id, device_id),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error.context(format!("Could not reach desktop {}", device_id)))
+}
+
+async fn next_event(events: Option<&mdns_sd::Receiver<mdns_sd::ServiceEvent>>) -> Option<mdns_sd::ServiceEvent> {
+    match events {
+        Some(events) => events.recv_async().await.ok(),
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pairing_url() {
+        let fingerprint = "AB".repeat(32);
+        let url = crate::sync::generate_qr_data(
+            "home-mac",
+            "s3cret",
+            &["192.168.1.20".to_string(), "fd00::20".to_string()],
+            8766,
+            &fingerprint,
+            "k",
+        );
+
+        let invite = PairingInvite::parse(&url).unwrap();
+        assert_eq!(invite.device_id, "home-mac");
+        assert_eq!(invite.addresses, vec!["192.168.1.20".parse::<IpAddr>().unwrap(), "fd00::20".parse().unwrap()]);
+        assert_eq!(invite.port, 8766);
+        assert_eq!(invite.secret, "s3cret");
+        assert_eq!(invite.fingerprint, "ab".repeat(32));
+
+        assert!(PairingInvite::parse("https://traindaily.vercel.app/pair?deviceId=x").is_err());
+    }
+
+    /// A desktop for the loopback test: its own database, key and server
+    struct Desktop {
+        state: crate::sync::SyncServerState,
+        fingerprint: String,
+        port: u16,
+    }
+
+    async fn desktop(device_id: &str, secret: &str) -> Desktop {
+        let dir = crate::db::data_dir().join("loopback").join(device_id);
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = Database::open(&dir.join("traindaily.db")).unwrap();
+
+        let key_pair = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec!["127.0.0.1".to_string()])
+            .unwrap()
+            .self_signed(&key_pair)
+            .unwrap();
+        let fingerprint = crate::cert::key_fingerprint(&key_pair);
+
+        let state = crate::sync::tests::test_state_with(db, device_id, secret, &fingerprint);
+        let port = crate::sync::tests::serve_tls(state.clone(), cert.pem(), key_pair.serialize_pem()).await;
+        Desktop { state, fingerprint, port }
+    }
+
+    #[tokio::test]
+    async fn test_two_desktops_pair_and_replicate_on_loopback() {
+        let work = desktop("work-mac", "work-secret").await;
+        let home = desktop("home-mac", "home-secret").await;
+        let loopback = IpAddr::from([127, 0, 0, 1]);
+
+        // Home pairs with work's invite, as pair() does: work confirms the nonce with home
+        let client = PeerClient::new(&work.fingerprint, "work-secret", "home-mac").unwrap();
+        let base = reach(&client, "work-mac", &[loopback], work.port).await.unwrap();
+        let announcement = |fingerprint: &str, nonce: String| PeerAnnouncement {
+            device_id: "home-mac".to_string(),
+            secret: "home-secret".to_string(),
+            fingerprint: fingerprint.to_string(),
+            port: home.port,
+            addresses: vec![loopback.to_string()],
+            nonce,
+        };
+        client.announce(&base, &announcement(&home.fingerprint, home.state.pairing.issue())).await.unwrap();
+
+        // Someone else with work's secret can't take home's place
+        let hijack = client.announce(&base, &announcement(&"ab".repeat(32), "forged".to_string())).await.unwrap_err();
+        let status = hijack.downcast_ref::<reqwest::Error>().and_then(reqwest::Error::status);
+        assert_eq!(status, Some(reqwest::StatusCode::CONFLICT));
+
+        let session = serde_json::json!({ "logged_at": "2026-03-02T07:00:00Z" });
+        home.state.db.lock().unwrap().save_session("2026-03-02", &session, ChangeOrigin::Desktop).unwrap();
+
+        // Work replicates from home with the credentials home announced
+        let sync = SyncServerHandle::new(
+            work.state.db.clone(),
+            "work-mac".to_string(),
+            "work-secret".to_string(),
+            ChangeBus::new(),
+            crate::sync::status::StatusRegistry::new(),
+        );
+        let peers = PeerManager::new(work.state.db.clone(), "work-mac".to_string(), "work-secret".to_string(), ChangeBus::new(), sync);
+        let link = Mutex::new(LinkState::default());
+        let replicated = async {
+            loop {
+                let found = work.state.db.lock().unwrap().get_session("2026-03-02").unwrap();
+                match found {
+                    Some(found) => return found,
+                    None => tokio::time::sleep(Duration::from_millis(20)).await,
+                }
+            }
+        };
+
+        tokio::select! {
+            found = replicated => assert_eq!(found, session),
+            result = peers.follow("home-mac", &link) => panic!("replication stopped: {:?}", result),
+            _ = tokio::time::sleep(Duration::from_secs(10)) => panic!("session did not replicate"),
+        }
+    }
+}
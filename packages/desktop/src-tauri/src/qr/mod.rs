@@ -0,0 +1,179 @@
+/**
+ * QR Code Module
+ *
+ * Renders pairing QR codes natively, so no frontend QR library is needed
+ * - SVG (settings window, /pair.svg on localhost)
+ * - PNG (image data URLs)
+ * - Unicode half blocks and ANSI colours (terminal / --print-pairing-qr)
+ */
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use qrcode::render::{svg, unicode};
+use qrcode::{Color, EcLevel, QrCode};
+use serde::Deserialize;
+
+// Standard quiet zone around the code, in modules
+const QUIET_ZONE: usize = 4;
+// Default pixels per module for PNG output
+pub const DEFAULT_PNG_SCALE: u32 = 8;
+const SVG_MIN_SIZE: u32 = 256;
+
+/// Error correction level: higher survives more damage but makes denser codes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorCorrection {
+    #[serde(alias = "L")]
+    Low,
+    /// Default: the pairing URL stays scannable from a laptop screen
+    #[default]
+    #[serde(alias = "M")]
+    Medium,
+    #[serde(alias = "Q")]
+    Quartile,
+    #[serde(alias = "H")]
+    High,
+}
+
+impl From<ErrorCorrection> for EcLevel {
+    fn from(level: ErrorCorrection) -> Self {
+        match level {
+            ErrorCorrection::Low => EcLevel::L,
+            ErrorCorrection::Medium => EcLevel::M,
+            ErrorCorrection::Quartile => EcLevel::Q,
+            ErrorCorrection::High => EcLevel::H,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+pub fn encode(data: &str, level: ErrorCorrection) -> Result<QrCode> {
+    QrCode::with_error_correction_level(data, level.into())
+        .context("Data too long for a QR code")
+}
+
+pub fn to_svg(code: &QrCode) -> String {
+    code.render::<svg::Color>()
+        .min_dimensions(SVG_MIN_SIZE, SVG_MIN_SIZE)
+        .quiet_zone(true)
+        .build()
+}
+
+/// 8-bit grayscale PNG, `scale` pixels per module
+pub fn to_png(code: &QrCode, scale: u32) -> Result<Vec<u8>> {
+    let scale = scale.max(1) as usize;
+    let modules = code.width() + 2 * QUIET_ZONE;
+    let size = modules * scale;
+    let colors = code.to_colors();
+
+    let mut pixels = vec![0xffu8; size * size];
+    for y in 0..code.width() {
+        for x in 0..code.width() {
+            if colors[y * code.width() + x] != Color::Dark {
+                continue;
+            }
+            for dy in 0..scale {
+                let row = (y + QUIET_ZONE) * scale + dy;
+                let start = row * size + (x + QUIET_ZONE) * scale;
+                pixels[start..start + scale].fill(0);
+            }
+        }
+    }
+
+    let mut png_bytes = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png_bytes, size as u32, size as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().context("Failed to write PNG header")?;
+        writer.write_image_data(&pixels).context("Failed to encode PNG")?;
+    }
+
+    Ok(png_bytes)
+}
+
+/// Two modules per character cell with half blocks (for dark-on-light terminals)
+pub fn to_unicode(code: &QrCode) -> String {
+    code.render::<unicode::Dense1x2>()
+        .quiet_zone(true)
+        .build()
+}
+
+/// Coloured cells that scan on any terminal theme (two spaces per module)
+pub fn to_ansi(code: &QrCode) -> String {
+    const DARK: &str = "\x1b[40m  ";
+    const LIGHT: &str = "\x1b[47m  ";
+    const RESET: &str = "\x1b[0m";
+
+    let width = code.width();
+    let colors = code.to_colors();
+    let total = width + 2 * QUIET_ZONE;
+    let mut out = String::new();
+
+    for y in 0..total {
+        for x in 0..total {
+            let inside = (QUIET_ZONE..QUIET_ZONE + width).contains(&x)
+                && (QUIET_ZONE..QUIET_ZONE + width).contains(&y);
+            let dark = inside && colors[(y - QUIET_ZONE) * width + (x - QUIET_ZONE)] == Color::Dark;
+            out.push_str(if dark { DARK } else { LIGHT });
+        }
+        out.push_str(RESET);
+        out.push('\n');
+    }
+
+    out
+}
+
+/// `data:` URL for an <img> tag
+pub fn data_url(data: &str, format: ImageFormat, level: ErrorCorrection) -> Result<String> {
+    let code = encode(data, level)?;
+    let (mime, bytes) = match format {
+        ImageFormat::Svg => ("image/svg+xml", to_svg(&code).into_bytes()),
+        ImageFormat::Png => ("image/png", to_png(&code, DEFAULT_PNG_SCALE)?),
+    };
+
+    Ok(format!(
+        "data:{};base64,{}",
+        mime,
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAYLOAD: &str = "https://traindaily.vercel.app/pair?deviceId=mac&ip=192.168.1.20&port=8841";
+
+    #[test]
+    fn test_png_dimensions_include_quiet_zone() {
+        let code = encode(PAYLOAD, ErrorCorrection::Medium).unwrap();
+        let png_bytes = to_png(&code, 4).unwrap();
+
+        let decoder = png::Decoder::new(std::io::Cursor::new(png_bytes));
+        let reader = decoder.read_info().unwrap();
+        let expected = ((code.width() + 2 * QUIET_ZONE) * 4) as u32;
+        assert_eq!(reader.info().width, expected);
+        assert_eq!(reader.info().height, expected);
+    }
+
+    #[test]
+    fn test_renderings() {
+        let low = encode(PAYLOAD, ErrorCorrection::Low).unwrap();
+        let high = encode(PAYLOAD, ErrorCorrection::High).unwrap();
+        assert!(high.width() > low.width());
+
+        assert!(to_svg(&low).starts_with("<?xml"));
+        assert_eq!(to_ansi(&low).lines().count(), low.width() + 2 * QUIET_ZONE);
+        assert!(data_url(PAYLOAD, ImageFormat::Png, ErrorCorrection::Medium)
+            .unwrap()
+            .starts_with("data:image/png;base64,"));
+    }
+}
@@ -0,0 +1,383 @@
+/**
+ * Training Schedule Module
+ *
+ * The one answer to "what is today?" for the blocker, the micro-break
+ * overlay, MQTT, the calendar feed and the UI (get_schedule)
+ * - Template (`schedule_template` setting, JSON): a weekly plan (Monday
+ *   first) or a rotation of any length, day one on first_session_date
+ * - Presets: PPL (the default, same as getWorkoutType in
+ *   packages/core/lib/schedule.ts), upper/lower, full-body 3x
+ * - Per-date overrides (schedule_overrides table) win over the template
+ * - Streak: consecutive training days logged, counting back from yesterday;
+ *   rest days don't break it (same rules as getTrainingStreak)
+ * - watch_streak publishes StreakBroken on the change bus when a missed
+ *   training day ends a streak
+ */
+
+use crate::changes::{ChangeBus, ChangeEvent, ChangeKind, ChangeOrigin};
+use crate::db::{Database, WorkoutSession};
+use anyhow::{bail, Context, Result};
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+pub const TEMPLATE_SETTING: &str = "schedule_template";
+
+const STREAK_CHECK_INTERVAL_SECS: u64 = 60;
+
+// getTrainingStreak looks back at most a year
+const STREAK_LOOKBACK_DAYS: usize = 365;
+
+/// Longest rotation accepted (four weeks)
+const MAX_CYCLE_DAYS: usize = 28;
+
+/// Longest range get_schedule returns
+const MAX_VIEW_DAYS: u32 = 366;
+
+/// Day one of rotations before the first session is logged (a Monday)
+const DEFAULT_ANCHOR: (i32, u32, u32) = (2024, 1, 1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkoutType {
+    Push,
+    Pull,
+    Legs,
+    Upper,
+    Lower,
+    FullBody,
+    Rest,
+}
+
+impl WorkoutType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WorkoutType::Push => "push",
+            WorkoutType::Pull => "pull",
+            WorkoutType::Legs => "legs",
+            WorkoutType::Upper => "upper",
+            WorkoutType::Lower => "lower",
+            WorkoutType::FullBody => "full_body",
+            WorkoutType::Rest => "rest",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "push" => Some(WorkoutType::Push),
+            "pull" => Some(WorkoutType::Pull),
+            "legs" => Some(WorkoutType::Legs),
+            "upper" => Some(WorkoutType::Upper),
+            "lower" => Some(WorkoutType::Lower),
+            "full_body" => Some(WorkoutType::FullBody),
+            "rest" => Some(WorkoutType::Rest),
+            _ => None,
+        }
+    }
+
+    /// Display name ("Push", "Full body"...)
+    pub fn label(self) -> &'static str {
+        match self {
+            WorkoutType::Push => "Push",
+            WorkoutType::Pull => "Pull",
+            WorkoutType::Legs => "Legs",
+            WorkoutType::Upper => "Upper",
+            WorkoutType::Lower => "Lower",
+            WorkoutType::FullBody => "Full body",
+            WorkoutType::Rest => "Rest",
+        }
+    }
+}
+
+/// Which workout falls on which day
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Template {
+    /// Same plan every week, Monday first
+    Weekly { days: [WorkoutType; 7] },
+    /// Repeating cycle, day one on first_session_date
+    Rotation { cycle: Vec<WorkoutType> },
+}
+
+impl Template {
+    /// Built-in templates offered in settings, by name
+    pub fn presets() -> Vec<(&'static str, Template)> {
+        use WorkoutType::*;
+
+        vec![
+            ("ppl", Template::Weekly { days: [Push, Pull, Legs, Push, Pull, Legs, Rest] }),
+            ("upper_lower", Template::Weekly { days: [Upper, Lower, Rest, Upper, Lower, Rest, Rest] }),
+            ("full_body_3x", Template::Weekly { days: [FullBody, Rest, FullBody, Rest, FullBody, Rest, Rest] }),
+        ]
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Template::Rotation { cycle } = self {
+            if cycle.is_empty() || cycle.len() > MAX_CYCLE_DAYS {
+                bail!("A rotation needs between 1 and {} days", MAX_CYCLE_DAYS);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Template::presets().swap_remove(0).1
+    }
+}
+
+/// One day as the UI sees it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledDay {
+    pub date_key: String,
+    pub workout_type: WorkoutType,
+    pub is_training: bool,
+    /// Set by a per-date override rather than the template
+    pub overridden: bool,
+}
+
+/// Everything the settings screen and today screen need (get_schedule)
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleView {
+    pub template: Template,
+    pub presets: Vec<SchedulePreset>,
+    pub days: Vec<ScheduledDay>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SchedulePreset {
+    pub name: &'static str,
+    pub template: Template,
+}
+
+/// Template, rotation anchor and overrides, loaded together
+#[derive(Clone, Debug, Default)]
+pub struct Schedule {
+    pub template: Template,
+    anchor: Option<NaiveDate>,
+    overrides: HashMap<NaiveDate, WorkoutType>,
+}
+
+impl Schedule {
+    pub fn new(template: Template, anchor: Option<NaiveDate>, overrides: HashMap<NaiveDate, WorkoutType>) -> Self {
+        Self { template, anchor, overrides }
+    }
+
+    /// Read from settings; a broken template falls back to the default
+    pub fn load(db: &Database) -> Result<Self> {
+        let template = match db.get_setting(TEMPLATE_SETTING)? {
+            Some(value) => serde_json::from_str::<Template>(&value)
+                .map_err(anyhow::Error::from)
+                .and_then(|template| template.validate().map(|()| template))
+                .unwrap_or_else(|e| {
+                    tracing::warn!("Ignoring invalid {} setting: {}", TEMPLATE_SETTING, e);
+                    Template::default()
+                }),
+            None => Template::default(),
+        };
+        let anchor = db.get_first_session_date()?.and_then(|key| parse_date_key(&key).ok());
+        let overrides = db
+            .get_schedule_overrides()?
+            .into_iter()
+            .filter_map(|(key, value)| Some((parse_date_key(&key).ok()?, WorkoutType::parse(&value)?)))
+            .collect();
+
+        Ok(Self::new(template, anchor, overrides))
+    }
+
+    /// What the template says, ignoring overrides
+    fn planned(&self, date: NaiveDate) -> WorkoutType {
+        match &self.template {
+            Template::Weekly { days } => days[date.weekday().num_days_from_monday() as usize],
+            Template::Rotation { cycle } => {
+                let anchor = self.anchor.unwrap_or_else(default_anchor);
+                let index = (date - anchor).num_days().rem_euclid(cycle.len() as i64);
+                cycle[index as usize]
+            }
+        }
+    }
+
+    pub fn workout_type(&self, date: NaiveDate) -> WorkoutType {
+        self.overrides.get(&date).copied().unwrap_or_else(|| self.planned(date))
+    }
+
+    pub fn is_training_day(&self, date: NaiveDate) -> bool {
+        self.workout_type(date) != WorkoutType::Rest
+    }
+
+    pub fn day(&self, date: NaiveDate) -> ScheduledDay {
+        let workout_type = self.workout_type(date);
+        ScheduledDay {
+            date_key: date_key(date),
+            workout_type,
+            is_training: workout_type != WorkoutType::Rest,
+            overridden: self.overrides.contains_key(&date),
+        }
+    }
+
+    pub fn days(&self, from: NaiveDate, count: u32) -> Vec<ScheduledDay> {
+        from.iter_days().take(count as usize).map(|date| self.day(date)).collect()
+    }
+
+    /// Consecutive training days logged before `today`
+    pub fn training_streak(&self, today: NaiveDate, sessions: &HashMap<String, WorkoutSession>) -> u32 {
+        let mut streak = 0;
+        let mut date = today - Duration::days(1);
+
+        for _ in 0..STREAK_LOOKBACK_DAYS {
+            if self.is_training_day(date) {
+                if !is_logged(sessions, date) {
+                    break;
+                }
+                streak += 1;
+            }
+            date -= Duration::days(1);
+        }
+
+        streak
+    }
+
+    /// Most recent training day before `today` (None for an all-rest schedule)
+    fn previous_training_day(&self, today: NaiveDate) -> Option<NaiveDate> {
+        (1..=STREAK_LOOKBACK_DAYS as i64)
+            .map(|days| today - Duration::days(days))
+            .find(|date| self.is_training_day(*date))
+    }
+}
+
+fn default_anchor() -> NaiveDate {
+    let (year, month, day) = DEFAULT_ANCHOR;
+    NaiveDate::from_ymd_opt(year, month, day).expect("valid anchor date")
+}
+
+pub fn date_key(date: NaiveDate) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+pub fn parse_date_key(key: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(key, "%Y-%m-%d").with_context(|| format!("Invalid date '{}' (expected YYYY-MM-DD)", key))
+}
+
+/// Whether a workout was logged on `date`
+pub fn is_logged(sessions: &HashMap<String, WorkoutSession>, date: NaiveDate) -> bool {
+    sessions
+        .get(&date_key(date))
+        .and_then(|session| session.get("logged_at"))
+        .is_some_and(|logged_at| !logged_at.is_null())
+}
+
+pub fn view(db: &Database, from: NaiveDate, days: u32) -> Result<ScheduleView> {
+    let schedule = Schedule::load(db)?;
+    Ok(ScheduleView {
+        days: schedule.days(from, days.min(MAX_VIEW_DAYS)),
+        presets: Template::presets()
+            .into_iter()
+            .map(|(name, template)| SchedulePreset { name, template })
+            .collect(),
+        template: schedule.template,
+    })
+}
+
+/// Store a new template (published as a SettingChanged)
+pub fn save_template(db: &Database, template: &Template) -> Result<()> {
+    template.validate()?;
+    db.set_setting(TEMPLATE_SETTING, &serde_json::to_string(template)?, ChangeOrigin::Desktop)
+}
+
+/// Publish StreakBroken whenever the streak drops to zero (checked every minute,
+/// so it fires shortly after midnight following a missed training day)
+pub async fn watch_streak(db: Arc<Mutex<Database>>, bus: ChangeBus) {
+    use tokio::time::{sleep, Duration};
+
+    let mut last_streak: Option<u32> = None;
+
+    loop {
+        let today = chrono::Local::now().date_naive();
+        let (schedule, sessions) = match db.lock() {
+            Ok(db) => (Schedule::load(&db).unwrap_or_default(), db.get_all_sessions().unwrap_or_default()),
+            Err(_) => break,
+        };
+        let streak = schedule.training_streak(today, &sessions);
+
+        if let Some(length) = last_streak.filter(|&length| length > 0 && streak == 0) {
+            if let Some(missed) = schedule.previous_training_day(today) {
+                let missed_date = date_key(missed);
+                tracing::info!("Streak of {} broken: no workout on {}", length, missed_date);
+                bus.publish(ChangeEvent {
+                    origin: ChangeOrigin::Desktop,
+                    kind: ChangeKind::StreakBroken { length, missed_date },
+                });
+            }
+        }
+        last_streak = Some(streak);
+
+        sleep(Duration::from_secs(STREAK_CHECK_INTERVAL_SECS)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn day(key: &str) -> NaiveDate {
+        parse_date_key(key).unwrap()
+    }
+
+    #[test]
+    fn test_ppl_cycle() {
+        // 2026-03-02 is a Monday
+        let schedule = Schedule::default();
+        let week: Vec<_> = (0..7).map(|i| schedule.workout_type(day("2026-03-02") + Duration::days(i))).collect();
+        assert_eq!(week, [
+            WorkoutType::Push, WorkoutType::Pull, WorkoutType::Legs,
+            WorkoutType::Push, WorkoutType::Pull, WorkoutType::Legs,
+            WorkoutType::Rest,
+        ]);
+    }
+
+    #[test]
+    fn test_streak_skips_rest_days_and_stops_at_a_miss() {
+        let schedule = Schedule::default();
+        let logged = json!({ "logged_at": "2026-03-01T07:00:00Z" });
+        let sessions: HashMap<_, _> = ["2026-02-25", "2026-02-27", "2026-02-28", "2026-03-02"]
+            .into_iter()
+            .map(|key| (key.to_string(), logged.clone()))
+            .collect();
+
+        // Mon 03-02 logged, Sun 03-01 rest, Sat + Fri logged, Thu 02-26 missed
+        assert_eq!(schedule.training_streak(day("2026-03-03"), &sessions), 3);
+        // Tue 03-03 missed
+        assert_eq!(schedule.training_streak(day("2026-03-04"), &sessions), 0);
+        assert_eq!(schedule.previous_training_day(day("2026-03-02")), Some(day("2026-02-28")));
+
+        // Moving Thursday to rest lets the streak reach Wednesday
+        let overrides = HashMap::from([(day("2026-02-26"), WorkoutType::Rest)]);
+        let schedule = Schedule::new(Template::default(), None, overrides);
+        assert_eq!(schedule.training_streak(day("2026-03-03"), &sessions), 4);
+        assert!(schedule.day(day("2026-02-26")).overridden);
+    }
+
+    #[test]
+    fn test_rotation_anchored_on_first_session() {
+        use WorkoutType::*;
+
+        let template = Template::Rotation { cycle: vec![Upper, Lower, Rest] };
+        let schedule = Schedule::new(template, Some(day("2026-03-04")), HashMap::new());
+        let types: Vec<_> = schedule.days(day("2026-03-02"), 6).into_iter().map(|d| d.workout_type).collect();
+        assert_eq!(types, [Lower, Rest, Upper, Lower, Rest, Upper]);
+
+        assert!(Template::Rotation { cycle: Vec::new() }.validate().is_err());
+
+        let json = serde_json::to_string(&Template::presets()[2].1).unwrap();
+        assert!(json.starts_with(r#"{"kind":"weekly","days":["full_body","rest""#));
+
+        // Never finds a training day, and says so instead of looping
+        let all_rest = Schedule::new(Template::Rotation { cycle: vec![Rest] }, None, HashMap::new());
+        assert_eq!(all_rest.previous_training_day(day("2026-03-02")), None);
+    }
+}
@@ -0,0 +1,233 @@
+/**
+ * Sync API Contract
+ *
+ * Versioning, error format and OpenAPI description for the sync server
+ * - Routes live under /api/v1; the original /api/ping and /api/sync/... paths
+ *   are deprecated aliases answering with `Deprecation` and a `Link` to
+ *   their successor
+ * - Every error is JSON: { code, message }, with a stable machine-readable code
+ * - GET /api/openapi.json is generated from the handler and payload types
+ */
+
+use super::{batch, e2e, replication, sse, ws};
+use axum::async_trait;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{FromRequest, Request};
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use utoipa::{OpenApi, ToSchema};
+
+/// Major API version (the /api/v1 prefix)
+pub const API_VERSION: &str = "1";
+
+/// Optional features this server supports, advertised in /api/v1/ping
+pub const CAPABILITIES: &[&str] = &["batch_upload", "sse", "websocket", "signed_ping", "ca_download", "replication", "e2e", "calendar"];
+
+// When the pre-v1 aliases were deprecated (2026-10-18T00:00:00Z), for the
+// RFC 9745 `Deprecation: @<epoch seconds>` header
+const DEPRECATED_SINCE: i64 = 1_792_281_600;
+
+// Deprecated path -> its /api/v1 successor
+const LEGACY_ALIASES: &[(&str, &str)] = &[
+    ("/api/ping", "/api/v1/ping"),
+    ("/api/sync/sessions", "/api/v1/sessions"),
+    ("/api/sync/session", "/api/v1/session"),
+    ("/api/sync/stream", "/api/v1/stream"),
+    ("/api/sync/ws", "/api/v1/ws"),
+];
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "TrainDaily Desktop Sync API", version = "1"),
+    paths(
+        super::handle_ping,
+        super::handle_get_sessions,
+        super::handle_post_session,
+        super::handle_get_status,
+        super::handle_calendar,
+        replication::handle_changes,
+        replication::handle_announce_peer,
+        replication::handle_confirm_pairing,
+        batch::handle_batch_upload,
+        sse::handle_sse_stream,
+        ws::handle_ws,
+    ),
+    components(schemas(ErrorBody, e2e::Envelope)),
+    modifiers(&BearerAuth),
+)]
+struct ApiDoc;
+
+/// Registers the bearer token scheme referenced by `security(...)`
+struct BearerAuth;
+
+impl utoipa::Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// GET /api/openapi.json - Machine-readable API description (no auth required)
+pub async fn handle_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Body of every error response
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Stable identifier, e.g. `unauthorized`, `invalid_request`
+    code: &'static str,
+    /// Human-readable detail
+    message: String,
+}
+
+/// Error returned by sync handlers, rendered as a JSON ErrorBody
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self { status, code, message: message.into() }
+    }
+
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_request", message)
+    }
+
+    /// Log the cause server-side; clients only get a generic message
+    pub fn internal(error: impl std::fmt::Display) -> Self {
+        tracing::error!("Sync request failed: {}", error);
+        Self::from(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        let (code, message) = match status {
+            StatusCode::UNAUTHORIZED => ("unauthorized", "invalid or missing token"),
+            StatusCode::TOO_MANY_REQUESTS => ("rate_limited", "too many failed attempts, try again later"),
+            StatusCode::BAD_REQUEST => ("invalid_request", "invalid request"),
+            StatusCode::NOT_FOUND => ("not_found", "not found"),
+            StatusCode::PAYLOAD_TOO_LARGE => ("payload_too_large", "request body too large"),
+            _ => ("internal", "internal server error"),
+        };
+        Self::new(status, code, message)
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        let status = rejection.status();
+        let code = match status {
+            StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+            _ => "invalid_request",
+        };
+        Self::new(status, code, rejection.body_text())
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}): {}", self.code, self.status.as_u16(), self.message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody { code: self.code, message: self.message };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+/// `Json` extractor whose rejections use the API error format
+pub struct ApiJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(request, state).await?;
+        Ok(Self(value))
+    }
+}
+
+/// Middleware for the pre-v1 aliases: mark responses as deprecated (RFC 9745)
+pub async fn deprecated(request: Request, next: Next) -> Response {
+    let successor = LEGACY_ALIASES
+        .iter()
+        .find(|(legacy, _)| *legacy == request.uri().path())
+        .map(|(_, successor)| *successor);
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    if let Ok(since) = HeaderValue::from_str(&format!("@{}", DEPRECATED_SINCE)) {
+        headers.insert("Deprecation", since);
+    }
+    if let Some(successor) = successor {
+        if let Ok(link) = HeaderValue::from_str(&format!("<{}>; rel=\"successor-version\"", successor)) {
+            headers.insert(header::LINK, link);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi_lists_v1_routes() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let paths = doc["paths"].as_object().unwrap();
+
+        for (_, successor) in LEGACY_ALIASES {
+            assert!(paths.contains_key(*successor), "missing {}", successor);
+        }
+        // New in v1, no legacy alias
+        assert!(paths.contains_key("/api/v1/status"));
+        assert!(doc["components"]["schemas"]["ErrorBody"].is_object());
+        assert!(doc["components"]["securitySchemes"]["bearer"].is_object());
+    }
+
+    #[tokio::test]
+    async fn test_legacy_alias_headers() {
+        use axum::body::Body;
+        use tower::ServiceExt;
+
+        let app = crate::sync::router(crate::sync::tests::test_state(), &[]);
+        let request = axum::http::Request::get("/api/ping").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.headers()["Deprecation"], "@1792281600");
+        assert_eq!(response.headers()[header::LINK], "</api/v1/ping>; rel=\"successor-version\"");
+    }
+
+    #[tokio::test]
+    async fn test_error_body_shape() {
+        let response = ApiError::from(StatusCode::UNAUTHORIZED).into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body, serde_json::json!({ "code": "unauthorized", "message": "invalid or missing token" }));
+    }
+}
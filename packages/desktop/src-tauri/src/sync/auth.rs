@@ -0,0 +1,289 @@
+/**
+ * Sync Authentication
+ *
+ * Bearer token verification for the sync server
+ * - Constant-time token comparison
+ * - Credentials from the Authorization header only
+ *   (query tokens are accepted solely on routes that opt in, e.g. SSE)
+ * - Per-IP failed-attempt throttling with temporary lockout
+ * - Redaction of registered secrets in all tracing output
+ * - HMAC signatures proving possession of the pairing secret
+ */
+
+use axum::http::{HeaderMap, StatusCode};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use subtle::ConstantTimeEq;
+
+// Failed attempts allowed per IP inside one window before lockout
+const MAX_FAILED_ATTEMPTS: u32 = 5;
+const FAILURE_WINDOW: Duration = Duration::from_secs(60);
+const LOCKOUT_DURATION: Duration = Duration::from_secs(300); // 5 minutes
+
+// Secrets scrubbed from every log line (see RedactingMakeWriter)
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Where a route is willing to read the token from
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TokenSource {
+    /// `Authorization: Bearer <token>` only
+    Header,
+    /// Header, or `?token=` for clients that cannot set headers (EventSource)
+    HeaderOrQuery,
+}
+
+struct Attempts {
+    failures: u32,
+    window_start: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Per-IP failed-attempt tracker
+#[derive(Default)]
+pub struct AuthLimiter {
+    attempts: Mutex<HashMap<IpAddr, Attempts>>,
+}
+
+impl AuthLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether this IP is currently locked out
+    pub fn is_locked(&self, ip: IpAddr) -> bool {
+        let attempts = self.attempts.lock().unwrap();
+        attempts
+            .get(&ip)
+            .and_then(|a| a.locked_until)
+            .map(|until| Instant::now() < until)
+            .unwrap_or(false)
+    }
+
+    /// Record a failed attempt, locking the IP out once the limit is reached
+    pub fn record_failure(&self, ip: IpAddr) {
+        let now = Instant::now();
+        let mut attempts = self.attempts.lock().unwrap();
+
+        // Drop stale entries so the map can't grow without bound
+        attempts.retain(|_, a| {
+            a.locked_until.map(|until| now < until).unwrap_or(false)
+                || now.duration_since(a.window_start) < FAILURE_WINDOW
+        });
+
+        let entry = attempts.entry(ip).or_insert(Attempts {
+            failures: 0,
+            window_start: now,
+            locked_until: None,
+        });
+
+        if now.duration_since(entry.window_start) >= FAILURE_WINDOW {
+            entry.failures = 0;
+            entry.window_start = now;
+        }
+
+        entry.failures += 1;
+
+        if entry.failures >= MAX_FAILED_ATTEMPTS {
+            entry.locked_until = Some(now + LOCKOUT_DURATION);
+            entry.failures = 0;
+            entry.window_start = now;
+            tracing::warn!("Sync auth: {} locked out after {} failed attempts", ip, MAX_FAILED_ATTEMPTS);
+        }
+    }
+
+    /// Forget failures after a successful authentication
+    pub fn record_success(&self, ip: IpAddr) {
+        self.attempts.lock().unwrap().remove(&ip);
+    }
+}
+
+/// Verify a request's credentials, applying lockout and throttling
+///
+/// Returns 429 while the IP is locked out, 401 on a bad or missing token.
+pub fn authorize(
+    limiter: &AuthLimiter,
+    ip: IpAddr,
+    headers: &HeaderMap,
+    query_token: Option<&str>,
+    source: TokenSource,
+    expected: &str,
+) -> Result<(), StatusCode> {
+    if limiter.is_locked(ip) {
+        return Err(StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    let provided = bearer_token(headers).or(match source {
+        TokenSource::HeaderOrQuery => query_token,
+        TokenSource::Header => None,
+    });
+
+    match provided {
+        Some(token) if tokens_match(token, expected) => {
+            limiter.record_success(ip);
+            Ok(())
+        }
+        _ => {
+            limiter.record_failure(ip);
+            Err(StatusCode::UNAUTHORIZED)
+        }
+    }
+}
+
+/// Extract the token from `Authorization: Bearer <token>`
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// Compare tokens without leaking the mismatch position through timing
+pub fn tokens_match(provided: &str, expected: &str) -> bool {
+    provided.as_bytes().ct_eq(expected.as_bytes()).into()
+}
+
+/// HMAC-SHA256 of `message` keyed with the pairing secret (hex)
+pub fn sign(secret: &str, message: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(message.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Register a secret to be scrubbed from all tracing output
+pub fn register_secret(secret: &str) {
+    if secret.is_empty() {
+        return;
+    }
+    let mut secrets = SECRETS.write().unwrap();
+    if !secrets.iter().any(|s| s == secret) {
+        secrets.push(secret.to_string());
+    }
+}
+
+/// Short, non-reversible form of a secret for log messages
+pub fn redact(secret: &str) -> String {
+    let prefix: String = secret.chars().take(4).collect();
+    format!("{}…[redacted]", prefix)
+}
+
+/// Replace every registered secret in `text` with its redacted form
+pub fn scrub(text: &str) -> Cow<'_, str> {
+    let secrets = SECRETS.read().unwrap();
+    let mut output = Cow::Borrowed(text);
+    for secret in secrets.iter() {
+        if output.contains(secret.as_str()) {
+            output = Cow::Owned(output.replace(secret.as_str(), &redact(secret)));
+        }
+    }
+    output
+}
+
+/// Writer that scrubs registered secrets before forwarding to stdout
+pub struct RedactingWriter {
+    inner: io::Stdout,
+}
+
+impl io::Write for RedactingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The fmt layer writes each event as one formatted buffer
+        match std::str::from_utf8(buf) {
+            Ok(text) => self.inner.write_all(scrub(text).as_bytes())?,
+            Err(_) => self.inner.write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// `MakeWriter` for tracing-subscriber that redacts registered secrets
+pub struct RedactingMakeWriter;
+
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for RedactingMakeWriter {
+    type Writer = RedactingWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter { inner: io::stdout() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const TOKEN: &str = "0123456789abcdef0123456789abcdef";
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_header_token_accepted() {
+        let limiter = AuthLimiter::new();
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+        let result = authorize(&limiter, ip, &bearer(TOKEN), None, TokenSource::Header, TOKEN);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_query_token_only_where_allowed() {
+        let limiter = AuthLimiter::new();
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 21));
+        let headers = HeaderMap::new();
+
+        let rejected = authorize(&limiter, ip, &headers, Some(TOKEN), TokenSource::Header, TOKEN);
+        assert_eq!(rejected, Err(StatusCode::UNAUTHORIZED));
+
+        let accepted = authorize(&limiter, ip, &headers, Some(TOKEN), TokenSource::HeaderOrQuery, TOKEN);
+        assert!(accepted.is_ok());
+    }
+
+    #[test]
+    fn test_lockout_after_repeated_failures() {
+        let limiter = AuthLimiter::new();
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 22));
+
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            let result = authorize(&limiter, ip, &bearer("wrong"), None, TokenSource::Header, TOKEN);
+            assert_eq!(result, Err(StatusCode::UNAUTHORIZED));
+        }
+
+        // Even the correct token is refused while locked out
+        let result = authorize(&limiter, ip, &bearer(TOKEN), None, TokenSource::Header, TOKEN);
+        assert_eq!(result, Err(StatusCode::TOO_MANY_REQUESTS));
+
+        // Other IPs are unaffected
+        let other = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 23));
+        assert!(authorize(&limiter, other, &bearer(TOKEN), None, TokenSource::Header, TOKEN).is_ok());
+    }
+
+    #[test]
+    fn test_signature_depends_on_secret_and_message() {
+        let signature = sign(TOKEN, "nonce-1");
+        assert_eq!(signature.len(), 64);
+        assert_eq!(signature, sign(TOKEN, "nonce-1"));
+        assert_ne!(signature, sign(TOKEN, "nonce-2"));
+        assert_ne!(signature, sign("another-secret", "nonce-1"));
+    }
+
+    #[test]
+    fn test_scrub_registered_secret() {
+        register_secret(TOKEN);
+        let line = format!("GET /api/sync/stream?token={} 200", TOKEN);
+        let scrubbed = scrub(&line);
+        assert!(!scrubbed.contains(TOKEN));
+        assert!(scrubbed.contains("0123…[redacted]"));
+    }
+}
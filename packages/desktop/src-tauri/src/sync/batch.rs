@@ -0,0 +1,223 @@
+/**
+ * Sync Batch Upload
+ *
+ * POST /api/v1/sessions - apply many writes in one request
+ * - Body: { sessions: [{ dateKey, session, baseChangeId? }] }
+ *   (`session` may be an e2e envelope)
+ *   (object rather than a bare array so deletions and settings can be
+ *   added as sibling lists without breaking clients)
+ * - All valid items go through one SQLite transaction: a database error
+ *   applies nothing, item-level problems never fail the whole batch
+ * - Per-item result, in request order:
+ *   applied   { changeId }
+ *   conflict  { currentChangeId }  the date changed after baseChangeId
+ *   invalid   { error }            rejected before touching the database
+ * - Request size bounded by MAX_BODY_BYTES and MAX_ITEMS; the body is only
+ *   read and parsed once the token checks out
+ */
+
+use super::api::{ApiError, ErrorBody};
+use super::e2e::{self, E2e};
+use super::{auth::TokenSource, validate_session, SyncServerState};
+use crate::changes::ChangeOrigin;
+use crate::db::{Database, SessionWrite, WriteOutcome};
+use anyhow::Result;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::net::SocketAddr;
+use utoipa::ToSchema;
+
+/// Request body limit (a few years of sessions)
+pub const MAX_BODY_BYTES: usize = 4 * 1024 * 1024;
+const MAX_ITEMS: usize = 1000;
+
+#[derive(Deserialize, ToSchema)]
+pub struct BatchUpload {
+    #[serde(default)]
+    sessions: Vec<BatchSession>,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct BatchSession {
+    #[serde(alias = "date_key")]
+    date_key: String,
+    /// The session, or an e2e envelope
+    #[schema(value_type = Object)]
+    session: JsonValue,
+    /// Last change id the client saw for this date (omit to overwrite)
+    #[serde(default)]
+    base_change_id: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchResponse {
+    results: Vec<ItemResult>,
+    /// Resume point for SSE / WebSocket after applying the batch
+    latest_change_id: i64,
+}
+
+#[derive(Debug, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct ItemResult {
+    index: usize,
+    date_key: String,
+    #[serde(flatten)]
+    status: ItemStatus,
+}
+
+#[derive(Debug, PartialEq, Serialize, ToSchema)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum ItemStatus {
+    #[serde(rename_all = "camelCase")]
+    Applied { change_id: i64 },
+    #[serde(rename_all = "camelCase")]
+    Conflict { current_change_id: i64 },
+    Invalid { error: String },
+}
+
+/// POST /api/v1/sessions - Upload many sessions at once (auth required)
+#[utoipa::path(
+    post,
+    path = "/api/v1/sessions",
+    security(("bearer" = [])),
+    request_body = BatchUpload,
+    responses(
+        (status = 200, description = "Per-item results in request order", body = BatchResponse),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 413, description = "Body over 4 MiB or too many items", body = ErrorBody),
+        (status = 429, body = ErrorBody),
+    ),
+)]
+pub async fn handle_batch_upload(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    State(state): State<SyncServerState>,
+    body: Body,
+) -> Result<Json<BatchResponse>, ApiError> {
+    // Verify auth token before buffering up to 4 MiB from the client
+    let device = state.authorize(addr, &headers, None, TokenSource::Header)?;
+
+    let bytes = axum::body::to_bytes(body, MAX_BODY_BYTES)
+        .await
+        .map_err(|_| state.failed(&device, ApiError::from(StatusCode::PAYLOAD_TOO_LARGE)))?;
+    let upload: BatchUpload = serde_json::from_slice(&bytes)
+        .map_err(|e| state.failed(&device, ApiError::invalid_request(format!("invalid JSON body: {}", e))))?;
+
+    if upload.sessions.len() > MAX_ITEMS {
+        return Err(state.failed(&device, ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "payload_too_large",
+            format!("at most {} items per batch", MAX_ITEMS),
+        )));
+    }
+
+    let encrypted = upload.sessions.iter().any(|item| e2e::is_envelope(&item.session));
+    let mut db = state.db.lock().map_err(ApiError::internal)?;
+    let response = apply_batch(&mut db, upload, &state.e2e)
+        .map_err(|e| state.failed(&device, ApiError::internal(e)))?;
+    state.status.record_push(&device);
+    state.status.record_e2e(&device, encrypted);
+
+    Ok(Json(response))
+}
+
+/// Open and validate every item, then write the valid ones in one transaction
+fn apply_batch(db: &mut Database, upload: BatchUpload, e2e: &E2e) -> Result<BatchResponse> {
+    let mut results: Vec<Option<ItemResult>> = Vec::with_capacity(upload.sessions.len());
+    let mut writes = Vec::new();
+    let mut write_indices = Vec::new();
+
+    for (index, item) in upload.sessions.into_iter().enumerate() {
+        let opened = e2e
+            .open_upload(&item.date_key, item.session)
+            .and_then(|(session, _)| validate_session(&item.date_key, &session).map(|()| session));
+
+        match opened {
+            Ok(session) => {
+                results.push(None);
+                write_indices.push(index);
+                writes.push(SessionWrite {
+                    date_key: item.date_key,
+                    session,
+                    base_change_id: item.base_change_id,
+                });
+            }
+            Err(error) => results.push(Some(ItemResult {
+                index,
+                date_key: item.date_key,
+                status: ItemStatus::Invalid { error },
+            })),
+        }
+    }
+
+    let outcomes = db.save_sessions(&writes, ChangeOrigin::Sync)?;
+
+    for ((index, write), outcome) in write_indices.into_iter().zip(writes).zip(outcomes) {
+        let status = match outcome {
+            WriteOutcome::Applied { change_id } => ItemStatus::Applied { change_id },
+            WriteOutcome::Conflict { current_change_id } => ItemStatus::Conflict { current_change_id },
+        };
+        results[index] = Some(ItemResult { index, date_key: write.date_key, status });
+    }
+
+    Ok(BatchResponse {
+        results: results.into_iter().flatten().collect(),
+        latest_change_id: db.latest_change_id()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_unauthorized_body_is_never_parsed() {
+        let app = crate::sync::router(crate::sync::tests::test_state(), &[]);
+        let mut request = Request::post("/api/v1/sessions")
+            .header("Authorization", "Bearer wrong")
+            .body(Body::from("not json"))
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([192, 168, 1, 30], 50000))));
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_apply_batch_reports_each_item() {
+        let mut db = Database::new().unwrap();
+        let upload: BatchUpload = serde_json::from_value(json!({
+            "sessions": [
+                { "dateKey": "2026-03-01", "session": { "logged_at": "x" } },
+                { "dateKey": "yesterday", "session": {} },
+                { "date_key": "2026-03-02", "session": { "logged_at": "y" } },
+            ]
+        }))
+        .unwrap();
+
+        let response = apply_batch(&mut db, upload, &E2e::new(crate::crypto::generate_key(), false)).unwrap();
+
+        assert_eq!(response.results.len(), 3);
+        assert!(matches!(response.results[0].status, ItemStatus::Applied { .. }));
+        assert!(matches!(response.results[1].status, ItemStatus::Invalid { .. }));
+        assert!(matches!(response.results[2].status, ItemStatus::Applied { .. }));
+        assert_eq!(db.get_session("2026-03-02").unwrap(), Some(json!({ "logged_at": "y" })));
+
+        let body = serde_json::to_value(&response.results[0]).unwrap();
+        assert_eq!(body["status"], "applied");
+        assert_eq!(body["dateKey"], "2026-03-01");
+        assert!(body["changeId"].is_i64());
+    }
+}
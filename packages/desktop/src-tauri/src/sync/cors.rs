@@ -0,0 +1,157 @@
+/**
+ * Sync CORS
+ *
+ * Cross-origin access for the PWA calling the desktop from a browser
+ * - Origin allowlist (setting: sync_allowed_origins, comma-separated)
+ * - Preflight handling with credential headers (and X-E2E, see e2e.rs)
+ * - Chrome Private Network Access (Access-Control-Allow-Private-Network)
+ */
+
+use crate::db::Database;
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, AllowPrivateNetwork, CorsLayer};
+
+pub const ALLOWED_ORIGINS_SETTING: &str = "sync_allowed_origins";
+
+// Production PWA origin
+const DEFAULT_ORIGINS: &[&str] = &["https://traindaily.vercel.app"];
+
+// Next.js dev server, only trusted in debug builds
+const DEV_ORIGINS: &[&str] = &["http://localhost:3000"];
+
+// Asks for sealed downloads (e2e::E2E_HEADER, lowercase for HeaderName)
+const E2E_HEADER: &str = "x-e2e";
+
+// How long browsers may cache a preflight result
+const PREFLIGHT_MAX_AGE_SECS: u64 = 600;
+
+/// Read the configured origin allowlist, falling back to the defaults
+pub fn configured_origins(db: &Database) -> Vec<String> {
+    let mut origins: Vec<String> = db
+        .get_setting(ALLOWED_ORIGINS_SETTING)
+        .ok()
+        .flatten()
+        .map(|value| parse_origins(&value))
+        .filter(|list| !list.is_empty())
+        .unwrap_or_else(|| DEFAULT_ORIGINS.iter().map(|o| o.to_string()).collect());
+
+    if cfg!(debug_assertions) {
+        origins.extend(DEV_ORIGINS.iter().map(|o| o.to_string()));
+    }
+
+    origins
+}
+
+/// Parse a comma-separated origin list, dropping blanks and wildcards
+fn parse_origins(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|origin| origin.trim().trim_end_matches('/'))
+        .filter(|origin| !origin.is_empty() && *origin != "*")
+        .map(|origin| origin.to_string())
+        .collect()
+}
+
+/// Build the CORS layer for the sync router
+pub fn layer(origins: &[String]) -> CorsLayer {
+    let allowed: Vec<HeaderValue> = origins
+        .iter()
+        .filter_map(|origin| match HeaderValue::from_str(origin) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                tracing::warn!("Ignoring invalid CORS origin: {}", origin);
+                None
+            }
+        })
+        .collect();
+
+    // Only grant private network access to origins that are allowed at all
+    let private_network_origins = allowed.clone();
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(allowed))
+        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE, HeaderName::from_static(E2E_HEADER)])
+        .allow_credentials(true)
+        .allow_private_network(AllowPrivateNetwork::predicate(move |origin, _| {
+            private_network_origins.contains(origin)
+        }))
+        .max_age(Duration::from_secs(PREFLIGHT_MAX_AGE_SECS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::Router;
+    use tower::ServiceExt;
+
+    const PWA_ORIGIN: &str = "https://traindaily.vercel.app";
+
+    /// The sync server's own router, with the configured allowlist
+    fn app() -> Router {
+        let state = crate::sync::tests::test_state();
+        let origins = configured_origins(&state.db.lock().unwrap());
+        crate::sync::router(state, &origins)
+    }
+
+    fn preflight(origin: &str) -> Request<Body> {
+        Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/api/v1/ping")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization,x-e2e")
+            .header("Access-Control-Request-Private-Network", "true")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_preflight_allowed_origin() {
+        let response = app().oneshot(preflight(PWA_ORIGIN)).await.unwrap();
+        let headers = response.headers();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], PWA_ORIGIN);
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers["access-control-allow-private-network"], "true");
+        assert!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS]
+            .to_str()
+            .unwrap()
+            .contains("authorization"));
+        assert!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS]
+            .to_str()
+            .unwrap()
+            .contains("x-e2e"));
+    }
+
+    #[tokio::test]
+    async fn test_preflight_rejected_origin() {
+        let response = app().oneshot(preflight("https://evil.example")).await.unwrap();
+        let headers = response.headers();
+
+        assert!(headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+        assert!(headers.get("access-control-allow-private-network").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_simple_request_gets_origin_header() {
+        let request = Request::builder()
+            .uri("/api/v1/ping")
+            .header(header::ORIGIN, PWA_ORIGIN)
+            .body(Body::empty())
+            .unwrap();
+        let response = app().oneshot(request).await.unwrap();
+
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], PWA_ORIGIN);
+    }
+
+    #[test]
+    fn test_parse_origins() {
+        let origins = parse_origins(" https://a.example/ , *, ,http://b.example");
+        assert_eq!(origins, vec!["https://a.example", "http://b.example"]);
+    }
+}
@@ -0,0 +1,209 @@
+/**
+ * End-to-End Encrypted Payloads
+ *
+ * Application-layer envelope so session contents stay confidential even if
+ * TLS is intercepted (browsers can't verify the self-signed certificate, so
+ * a hostile LAN can sit in the middle)
+ * - Key: 256 bits, generated once per desktop (`e2e_key` setting) and only
+ *   ever handed out in the pairing QR (`key=` in the URL fragment, base64url)
+ * - Envelope, in place of a session object:
+ *   { e2e: 1, alg: "A256GCM", kid, nonce, ciphertext }
+ *   AES-256-GCM, AAD "traindaily-e2e-v1\n<dateKey>" so an envelope can't be
+ *   replayed under another date; kid identifies the key (stale after re-pair)
+ * - Uploads (POST session / sessions, WebSocket push_session) may carry an
+ *   envelope; it is opened, then validated and saved like any session
+ * - Downloads are sealed for clients that ask: `X-E2E: 1` header, `?e2e=1`
+ *   for EventSource, `e2e: true` in the WebSocket hello
+ * - `e2e_required` setting: plaintext uploads are refused and every
+ *   download is sealed
+ * - The status registry records per device whether it syncs encrypted
+ *
+ * Desktop peers don't use envelopes: their client pins the server key, so
+ * their TLS can't be intercepted (see peers::client).
+ */
+
+use crate::crypto;
+use crate::db::Database;
+use anyhow::{anyhow, bail, Result};
+use axum::http::HeaderMap;
+use base64::engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD as BASE64_URL};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+pub const KEY_SETTING: &str = "e2e_key";
+pub const REQUIRED_SETTING: &str = "e2e_required";
+
+/// Request header asking for sealed downloads
+pub const E2E_HEADER: &str = "X-E2E";
+
+pub const ENVELOPE_VERSION: u32 = 1;
+pub const ALGORITHM: &str = "A256GCM";
+
+const AAD_PREFIX: &str = "traindaily-e2e-v1";
+
+/// An encrypted session
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Envelope {
+    /// Envelope version (1)
+    pub e2e: u32,
+    pub alg: String,
+    /// Key id: first 8 hex digits of SHA-256(key)
+    pub kid: String,
+    /// Base64 96-bit nonce
+    pub nonce: String,
+    /// Base64 ciphertext with the GCM tag appended
+    pub ciphertext: String,
+}
+
+/// The desktop's end-to-end key and policy, fixed for a server run
+pub struct E2e {
+    key: crypto::Key,
+    pub kid: String,
+    pub required: bool,
+}
+
+impl E2e {
+    pub fn new(key: crypto::Key, required: bool) -> Self {
+        Self { kid: key_id(&key), key, required }
+    }
+
+    /// Load the key from settings (creating it on first use) and the policy
+    pub fn load(db: &Database) -> Result<Self> {
+        let key = load_or_create_key(db)?;
+        let required = db.get_setting(REQUIRED_SETTING)?.as_deref() == Some("true");
+        Ok(Self::new(key, required))
+    }
+
+    /// Envelope for `session` on `date_key`
+    pub fn seal(&self, date_key: &str, session: &JsonValue) -> Result<JsonValue> {
+        let plaintext = serde_json::to_vec(session)?;
+        let (nonce, ciphertext) = crypto::seal(&self.key, &plaintext, aad(date_key).as_bytes())?;
+
+        Ok(serde_json::to_value(Envelope {
+            e2e: ENVELOPE_VERSION,
+            alg: ALGORITHM.to_string(),
+            kid: self.kid.clone(),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        })?)
+    }
+
+    /// An uploaded session in the clear: opens envelopes, passes plaintext
+    /// through unless encryption is required. Returns (session, was_encrypted).
+    pub fn open_upload(&self, date_key: &str, session: JsonValue) -> Result<(JsonValue, bool), String> {
+        if !is_envelope(&session) {
+            if self.required {
+                return Err("end-to-end encryption is required: send an envelope".to_string());
+            }
+            return Ok((session, false));
+        }
+
+        let envelope: Envelope = serde_json::from_value(session).map_err(|e| format!("invalid envelope: {}", e))?;
+        self.open(date_key, &envelope).map(|session| (session, true)).map_err(|e| e.to_string())
+    }
+
+    /// A session for a client, sealed if `sealed` (see seals_for)
+    pub fn download(&self, date_key: &str, session: JsonValue, sealed: bool) -> JsonValue {
+        if !sealed {
+            return session;
+        }
+        self.seal(date_key, &session).unwrap_or_else(|e| {
+            tracing::error!("Failed to seal session {}: {}", date_key, e);
+            JsonValue::Null
+        })
+    }
+
+    /// Whether downloads go out sealed to a client that `asked` (or not)
+    pub fn seals_for(&self, asked: bool) -> bool {
+        asked || self.required
+    }
+
+    fn open(&self, date_key: &str, envelope: &Envelope) -> Result<JsonValue> {
+        if envelope.e2e != ENVELOPE_VERSION || envelope.alg != ALGORITHM {
+            bail!("unsupported envelope (version {}, {})", envelope.e2e, envelope.alg);
+        }
+        if envelope.kid != self.kid {
+            bail!("sealed with another key (kid {}); pair again", envelope.kid);
+        }
+
+        let nonce = BASE64.decode(&envelope.nonce).map_err(|_| anyhow!("invalid envelope nonce"))?;
+        let ciphertext = BASE64.decode(&envelope.ciphertext).map_err(|_| anyhow!("invalid envelope ciphertext"))?;
+        let plaintext = crypto::open(&self.key, &nonce, &ciphertext, aad(date_key).as_bytes())?;
+
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+/// The key as it appears in the pairing QR (base64url, no padding)
+pub fn pairing_key(db: &Database) -> Result<String> {
+    Ok(BASE64_URL.encode(load_or_create_key(db)?))
+}
+
+/// Whether a client asked for sealed downloads with the X-E2E header
+pub fn requested(headers: &HeaderMap) -> bool {
+    headers
+        .get(E2E_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim() == "1")
+}
+
+pub fn is_envelope(value: &JsonValue) -> bool {
+    value.get("e2e").is_some() && value.get("ciphertext").is_some()
+}
+
+fn load_or_create_key(db: &Database) -> Result<crypto::Key> {
+    if let Some(encoded) = db.get_setting(KEY_SETTING)? {
+        let key = BASE64_URL
+            .decode(&encoded)
+            .ok()
+            .and_then(|bytes| crypto::Key::try_from(bytes).ok())
+            .ok_or_else(|| anyhow!("Corrupt {} setting", KEY_SETTING))?;
+        super::auth::register_secret(&encoded);
+        return Ok(key);
+    }
+
+    let key = crypto::generate_key();
+    let encoded = BASE64_URL.encode(key);
+    super::auth::register_secret(&encoded);
+    db.set_setting(KEY_SETTING, &encoded, crate::changes::ChangeOrigin::Desktop)?;
+    Ok(key)
+}
+
+fn key_id(key: &crypto::Key) -> String {
+    hex::encode(&Sha256::digest(key)[..4])
+}
+
+fn aad(date_key: &str) -> String {
+    format!("{}\n{}", AAD_PREFIX, date_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_envelope_round_trip_bound_to_date() {
+        let e2e = E2e::new(crypto::generate_key(), false);
+        let session = json!({ "logged_at": "2026-03-01T07:00:00Z" });
+        let sealed = e2e.download("2026-03-01", session.clone(), true);
+
+        assert!(is_envelope(&sealed));
+        assert_eq!(e2e.open_upload("2026-03-01", sealed.clone()).unwrap(), (session.clone(), true));
+        assert!(e2e.open_upload("2026-03-02", sealed).is_err());
+        assert_eq!(e2e.download("2026-03-01", session.clone(), false), session);
+    }
+
+    #[test]
+    fn test_required_refuses_plaintext_and_seals_downloads() {
+        let e2e = E2e::new(crypto::generate_key(), true);
+        let session = json!({ "logged_at": "x" });
+
+        assert!(e2e.open_upload("2026-03-01", session.clone()).is_err());
+        assert!(e2e.seals_for(false));
+        assert!(is_envelope(&e2e.download("2026-03-01", session, e2e.seals_for(false))));
+    }
+}
@@ -0,0 +1,259 @@
+/**
+ * Sync Server Lifecycle
+ *
+ * SyncServerHandle owns the running server so it can be stopped and restarted
+ * - start / stop / restart, serialized so they never interleave
+ * - Graceful stop: no new connections, SSE and WebSocket streams are told to
+ *   close (WebSocket code 1001), in-flight requests get a deadline, then
+ *   anything left is dropped
+ * - Restart re-reads settings (port, bind addresses, CORS) and the certificate;
+ *   auth lockouts carry over (the limiter lives here, not in the server)
+ * - A network monitor restarts the server when the LAN addresses change
+ *   (Wi-Fi switch, dock unplugged); clients reconnect and resume from their
+ *   last change id
+ * - `sync_enabled` setting: the server stays off across launches when "false"
+ */
+
+use super::auth::AuthLimiter;
+use super::listen::Listening;
+use super::replication::PairingNonces;
+use super::status::StatusRegistry;
+use crate::changes::ChangeBus;
+use crate::db::Database;
+use anyhow::Result;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+pub const ENABLED_SETTING: &str = "sync_enabled";
+
+/// Grace period for in-flight requests when stopping
+pub const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(5);
+
+const NETWORK_CHECK_INTERVAL_SECS: u64 = 15;
+
+/// A started server: everything needed to take it down again
+pub(super) struct RunningServer {
+    pub listening: Listening,
+    /// One per bound socket
+    pub handles: Vec<axum_server::Handle>,
+    pub tasks: Vec<JoinHandle<()>>,
+    pub cert_watcher: JoinHandle<()>,
+    /// Flipped to true to end SSE and WebSocket streams
+    pub shutdown: watch::Sender<bool>,
+}
+
+impl RunningServer {
+    async fn stop(self, deadline: Duration) {
+        let _ = self.shutdown.send(true);
+        for handle in &self.handles {
+            handle.graceful_shutdown(Some(deadline));
+        }
+        self.cert_watcher.abort();
+
+        // Serve tasks end once connections drain or the deadline closes them
+        let serving = futures::future::join_all(self.tasks);
+        if tokio::time::timeout(deadline + Duration::from_secs(1), serving).await.is_err() {
+            tracing::warn!("Sync server did not stop within {:?}", deadline);
+        }
+    }
+}
+
+pub struct SyncServerHandle {
+    db: Arc<Mutex<Database>>,
+    device_id: String,
+    auth_token: String,
+    bus: ChangeBus,
+    status: Arc<StatusRegistry>,
+    /// Failed-auth lockouts, shared by every run so a restart can't reset them
+    auth_limiter: Arc<AuthLimiter>,
+    /// Nonces of pairings this desktop started, confirmed through the server
+    pairing: Arc<PairingNonces>,
+    /// Whether the server should be running (the network monitor honours it)
+    enabled: AtomicBool,
+    server: tokio::sync::Mutex<Option<RunningServer>>,
+    /// Copy of the running server's address, readable without awaiting
+    listening: watch::Sender<Option<Listening>>,
+}
+
+impl SyncServerHandle {
+    pub fn new(
+        db: Arc<Mutex<Database>>,
+        device_id: String,
+        auth_token: String,
+        bus: ChangeBus,
+        status: Arc<StatusRegistry>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            db,
+            device_id,
+            auth_token,
+            bus,
+            status,
+            auth_limiter: Arc::new(AuthLimiter::new()),
+            pairing: Arc::new(PairingNonces::new()),
+            enabled: AtomicBool::new(false),
+            server: tokio::sync::Mutex::new(None),
+            listening: watch::channel(None).0,
+        })
+    }
+
+    /// Where the server listens, None while stopped
+    pub fn listening(&self) -> Option<Listening> {
+        self.listening.borrow().clone()
+    }
+
+    /// Follow start / stop / restart (e.g. to re-advertise on the new port)
+    pub fn watch_listening(&self) -> watch::Receiver<Option<Listening>> {
+        self.listening.subscribe()
+    }
+
+    /// Where peers::PeerManager::pair registers its pairing attempts
+    pub fn pairing_nonces(&self) -> Arc<PairingNonces> {
+        self.pairing.clone()
+    }
+
+    /// Whether the user has the server switched on (default: yes)
+    pub fn enabled_in_settings(&self) -> bool {
+        self.db
+            .lock()
+            .ok()
+            .and_then(|db| db.get_setting(ENABLED_SETTING).ok().flatten())
+            .map(|value| value != "false")
+            .unwrap_or(true)
+    }
+
+    /// Start the server (no-op if already running)
+    pub async fn start(&self) -> Result<Listening> {
+        self.enabled.store(true, Ordering::SeqCst);
+        let mut server = self.server.lock().await;
+        self.start_locked(&mut server).await
+    }
+
+    /// Stop the server, giving in-flight requests `deadline` to finish
+    pub async fn stop(&self, deadline: Duration) {
+        self.enabled.store(false, Ordering::SeqCst);
+        let mut server = self.server.lock().await;
+        self.stop_locked(&mut server, deadline).await;
+    }
+
+    /// Stop and start again, picking up changed settings and addresses
+    pub async fn restart(&self) -> Result<Listening> {
+        self.enabled.store(true, Ordering::SeqCst);
+        let mut server = self.server.lock().await;
+        self.stop_locked(&mut server, SHUTDOWN_DEADLINE).await;
+        self.start_locked(&mut server).await
+    }
+
+    async fn start_locked(&self, server: &mut Option<RunningServer>) -> Result<Listening> {
+        if let Some(running) = server.as_ref() {
+            return Ok(running.listening.clone());
+        }
+
+        let running = super::start_server(
+            self.db.clone(),
+            self.device_id.clone(),
+            self.auth_token.clone(),
+            self.bus.clone(),
+            self.status.clone(),
+            self.auth_limiter.clone(),
+            self.pairing.clone(),
+        )
+        .await
+        .inspect_err(|e| {
+            self.status.record_error(None, format!("Failed to start sync server: {}", e));
+        })?;
+
+        let listening = running.listening.clone();
+        self.listening.send_replace(Some(listening.clone()));
+        self.status.set_running(Some(listening.port));
+        *server = Some(running);
+
+        Ok(listening)
+    }
+
+    async fn stop_locked(&self, server: &mut Option<RunningServer>, deadline: Duration) {
+        let Some(running) = server.take() else {
+            return;
+        };
+
+        tracing::info!("Stopping sync server");
+        self.listening.send_replace(None);
+        running.stop(deadline).await;
+        self.status.set_running(None);
+        tracing::info!("Sync server stopped");
+    }
+
+    /// Restart whenever the LAN addresses change (runs for the app's lifetime)
+    pub async fn monitor_network(self: Arc<Self>) {
+        let mut known = network_fingerprint();
+        let mut interval = tokio::time::interval(Duration::from_secs(NETWORK_CHECK_INTERVAL_SECS));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            let current = network_fingerprint();
+            if current == known {
+                continue;
+            }
+            known = current;
+
+            if !self.enabled.load(Ordering::SeqCst) {
+                continue;
+            }
+            tracing::info!("Network addresses changed, restarting sync server");
+            if let Err(e) = self.restart().await {
+                // Retried on the next change (e.g. a bind address coming back)
+                tracing::error!("Failed to restart sync server: {}", e);
+            }
+        }
+    }
+}
+
+/// Current candidate LAN addresses, in a comparable form
+fn network_fingerprint() -> Vec<IpAddr> {
+    let mut ips: Vec<IpAddr> = crate::network::candidate_addresses(None)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|address| address.ip)
+        .collect();
+    ips.sort();
+    ips
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stop_releases_port_and_ends_streams() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = axum_server::Handle::new();
+        let app = axum::Router::new().route("/", axum::routing::get(|| async { "ok" }));
+        let server = axum_server::from_tcp(listener).handle(handle.clone());
+        let task = tokio::spawn(async move {
+            let _ = server.serve(app.into_make_service()).await;
+        });
+
+        let (shutdown, streams) = watch::channel(false);
+        let running = RunningServer {
+            listening: Listening { port: addr.port(), addresses: vec![addr] },
+            handles: vec![handle],
+            tasks: vec![task],
+            cert_watcher: tokio::spawn(std::future::pending()),
+            shutdown,
+        };
+
+        running.stop(Duration::from_millis(100)).await;
+
+        assert!(*streams.borrow());
+        std::net::TcpListener::bind(addr).unwrap();
+    }
+}
@@ -0,0 +1,217 @@
+/**
+ * Sync Listener
+ *
+ * Binds the sync server's TCP sockets from user settings (read at startup)
+ * - `sync_port`: preferred port (default 8841)
+ * - `sync_bind_addresses`: comma-separated IPs to listen on
+ *   (default `::`, dual-stack: IPv6 plus IPv4-mapped connections)
+ * - Port taken (e.g. another macOS user's instance): try the next
+ *   PORT_FALLBACK_ATTEMPTS ports, then let the OS pick one
+ * - IPv6 unavailable: the dual-stack wildcard falls back to 0.0.0.0
+ *
+ * The ports actually bound are what the QR payload and /api/v1/ping advertise.
+ */
+
+use crate::db::Database;
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener};
+
+pub const PORT_SETTING: &str = "sync_port";
+pub const BIND_SETTING: &str = "sync_bind_addresses";
+
+pub const DEFAULT_PORT: u16 = 8841;
+const PORT_FALLBACK_ATTEMPTS: u16 = 10;
+const LISTEN_BACKLOG: i32 = 128;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListenConfig {
+    pub port: u16,
+    pub bind: Vec<IpAddr>,
+}
+
+/// Where the server actually ended up listening
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Listening {
+    pub port: u16,
+    pub addresses: Vec<SocketAddr>,
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        Self {
+            port: DEFAULT_PORT,
+            bind: vec![IpAddr::V6(Ipv6Addr::UNSPECIFIED)],
+        }
+    }
+}
+
+impl ListenConfig {
+    /// Read port and bind addresses from settings, ignoring invalid values
+    pub fn from_settings(db: &Database) -> Self {
+        let mut config = Self::default();
+
+        if let Some(value) = db.get_setting(PORT_SETTING).ok().flatten() {
+            match value.trim().parse::<u16>() {
+                Ok(port) => config.port = port,
+                Err(_) => tracing::warn!("Ignoring invalid {}: {:?}", PORT_SETTING, value),
+            }
+        }
+
+        if let Some(value) = db.get_setting(BIND_SETTING).ok().flatten() {
+            match parse_bind_addresses(&value) {
+                Ok(bind) if !bind.is_empty() => config.bind = bind,
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Ignoring invalid {}: {}", BIND_SETTING, e),
+            }
+        }
+
+        config
+    }
+}
+
+impl Listening {
+    /// A specific, non-loopback address the server is bound to, if any
+    ///
+    /// None when listening on a wildcard: any LAN address works then.
+    pub fn advertised_ip(&self) -> Option<IpAddr> {
+        if self.addresses.iter().any(|addr| addr.ip().is_unspecified()) {
+            return None;
+        }
+        self.addresses
+            .iter()
+            .map(|addr| addr.ip())
+            .find(|ip| !ip.is_loopback())
+    }
+
+    /// Whether a client could reach the server on `ip`
+    pub fn accepts(&self, ip: IpAddr) -> bool {
+        self.addresses.iter().any(|addr| match (addr.ip(), ip) {
+            // The IPv6 wildcard is bound dual-stack (see bind_one)
+            (IpAddr::V6(bound), _) if bound.is_unspecified() => true,
+            (IpAddr::V4(bound), IpAddr::V4(_)) if bound.is_unspecified() => true,
+            (bound, ip) => bound == ip,
+        })
+    }
+}
+
+/// Comma-separated IP list (brackets around IPv6 are tolerated)
+pub fn parse_bind_addresses(value: &str) -> Result<Vec<IpAddr>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            part.trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<IpAddr>()
+                .map_err(|_| anyhow!("not an IP address: {}", part))
+        })
+        .collect()
+}
+
+/// Bind every configured address on one shared port, falling back to
+/// nearby ports and finally an OS-assigned one
+pub fn bind(config: &ListenConfig) -> Result<(Vec<TcpListener>, Listening)> {
+    let fallbacks = (1..=PORT_FALLBACK_ATTEMPTS).filter_map(|offset| config.port.checked_add(offset));
+    let candidates = std::iter::once(config.port).chain(fallbacks).chain(std::iter::once(0));
+
+    let mut last_error = None;
+    for port in candidates {
+        match bind_all(&config.bind, port) {
+            Ok(listeners) => {
+                let addresses = listeners
+                    .iter()
+                    .map(|listener| listener.local_addr())
+                    .collect::<io::Result<Vec<_>>>()?;
+                let port = addresses.first().map(|addr| addr.port()).unwrap_or(port);
+
+                if port != config.port {
+                    tracing::warn!("Sync port {} unavailable, using {}", config.port, port);
+                }
+                return Ok((listeners, Listening { port, addresses }));
+            }
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => last_error = Some(e),
+            Err(e) => return Err(e).context("Failed to bind sync server"),
+        }
+    }
+
+    Err(anyhow!("No free port for the sync server: {:?}", last_error))
+}
+
+/// All addresses on `port`, or none (0 = OS-assigned, then reused for the rest)
+fn bind_all(addresses: &[IpAddr], port: u16) -> io::Result<Vec<TcpListener>> {
+    let mut listeners: Vec<TcpListener> = Vec::with_capacity(addresses.len());
+    let mut port = port;
+
+    for ip in addresses {
+        let listener = match bind_one(*ip, port) {
+            Ok(listener) => listener,
+            // No IPv6 stack: the dual-stack wildcard degrades to IPv4 only
+            Err(e) if *ip == IpAddr::V6(Ipv6Addr::UNSPECIFIED) && e.kind() != io::ErrorKind::AddrInUse => {
+                tracing::warn!("IPv6 unavailable ({}), listening on IPv4 only", e);
+                bind_one(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port)?
+            }
+            Err(e) => return Err(e),
+        };
+
+        if port == 0 {
+            port = listener.local_addr()?.port();
+        }
+        listeners.push(listener);
+    }
+
+    Ok(listeners)
+}
+
+fn bind_one(ip: IpAddr, port: u16) -> io::Result<TcpListener> {
+    let addr = SocketAddr::new(ip, port);
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+
+    if ip == IpAddr::V6(Ipv6Addr::UNSPECIFIED) {
+        // Accept IPv4 too, whatever the OS default is
+        socket.set_only_v6(false)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+    // Required by tokio when the listener is handed to axum-server
+    socket.set_nonblocking(true)?;
+
+    Ok(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bind_addresses() {
+        let parsed = parse_bind_addresses("0.0.0.0, [::1] ,fe80::1").unwrap();
+        assert_eq!(parsed, vec![
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpAddr::V6(Ipv6Addr::LOCALHOST),
+            "fe80::1".parse::<IpAddr>().unwrap(),
+        ]);
+        assert!(parse_bind_addresses("192.168.1.300").is_err());
+    }
+
+    #[test]
+    fn test_bind_falls_back_when_port_taken() {
+        let config = ListenConfig {
+            port: 0,
+            bind: vec![IpAddr::V4(Ipv4Addr::LOCALHOST)],
+        };
+        let (_held, taken) = bind(&config).unwrap();
+
+        let config = ListenConfig { port: taken.port, ..config };
+        let (_listeners, listening) = bind(&config).unwrap();
+
+        assert_ne!(listening.port, taken.port);
+        assert_eq!(listening.addresses[0].port(), listening.port);
+        assert_eq!(listening.advertised_ip(), None);
+    }
+}
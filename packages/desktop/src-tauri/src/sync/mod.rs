@@ -2,200 +2,737 @@
  * Sync Server Module
  *
  * HTTPS server for syncing workout data with mobile PWA
- * - REST API endpoints for workout data
- * - Token-based authentication
- * - SSE stream for real-time updates
- * - Self-signed TLS certificate
+ * - Versioned REST API under /api/v1 with JSON errors and an OpenAPI
+ *   description; pre-v1 paths kept as deprecated aliases (see api.rs)
+ * - Token-based authentication (see auth.rs)
+ * - Configurable port and bind addresses, dual-stack IPv6, port fallback (see listen.rs)
+ * - Transactional batch upload for catching up after being offline (see batch.rs)
+ * - CORS + Private Network Access for the PWA origin (see cors.rs)
+ * - Resumable SSE stream for real-time updates (see sse.rs), fed by the
+ *   app-wide change bus so desktop edits reach phones too
+ * - WebSocket channel for bidirectional sync (see ws.rs)
+ * - Replication feed and peer registration for other desktops (see replication.rs)
+ * - Status registry: connected clients, per-device activity, recent errors
+ *   (see status.rs), also served at /api/v1/status
+ * - Start / stop / restart with graceful shutdown, restarted on network
+ *   changes (see handle.rs)
+ * - TLS certificate from a local CA, rotated and hot-reloaded when the LAN address changes
+ * - Unauthenticated CA download (/ca) so phones can trust the desktop once
+ * - Pairing QR as SVG (/pair.svg), served to loopback clients only
+ * - iCalendar feed of the schedule and sessions (/api/calendar.ics, see calendar)
+ * - Key fingerprint pinning: the QR carries the server key's SHA-256 and
+ *   /api/ping returns an HMAC signature proving knowledge of the pairing secret
+ * - End-to-end encrypted session payloads on request, with the key from the
+ *   QR (see e2e)
  */
 
+pub mod api;
+pub mod auth;
+mod batch;
+mod cors;
+pub mod e2e;
+pub mod handle;
+pub mod listen;
+pub mod replication;
+mod sse;
+pub mod status;
+mod ws;
+
+use crate::cert::{ca::CertificateAuthority, Certificate};
+use crate::changes::{ChangeBus, ChangeEvent, ChangeKind, ChangeOrigin};
 use crate::db::Database;
+use crate::network::NetworkAddress;
 use anyhow::{Context, Result};
+use api::{ApiError, ApiJson};
+use auth::{AuthLimiter, TokenSource};
+use listen::{ListenConfig, Listening};
+use handle::RunningServer;
+use status::StatusRegistry;
 use axum::{
-    extract::{Query, State},
-    http::{HeaderMap, StatusCode},
-    response::Sse,
+    extract::{ConnectInfo, DefaultBodyLimit, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
 use axum_server::tls_rustls::RustlsConfig;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
-use tokio::sync::broadcast;
+use tokio::sync::watch;
+use utoipa::{IntoParams, ToSchema};
 
-const SYNC_PORT: u16 = 8841;
+const CERT_CHECK_INTERVAL_SECS: u64 = 300; // 5 minutes
+const MAX_PING_NONCE_LEN: usize = 128;
+// Addresses put in the pairing QR (kept short so the code stays scannable)
+const MAX_PAIRING_ADDRESSES: usize = 4;
+// Change log rows read per query when replaying to a reconnecting client
+const REPLAY_BATCH: usize = 500;
 
 #[derive(Clone)]
 pub struct SyncServerState {
     pub db: Arc<Mutex<Database>>,
     pub auth_token: String,
     pub device_id: String,
-    pub update_tx: broadcast::Sender<String>,
+    pub bus: ChangeBus,
+    pub auth_limiter: Arc<AuthLimiter>,
+    /// Pairings this desktop started (see replication::handle_confirm_pairing)
+    pub pairing: Arc<replication::PairingNonces>,
+    /// Pinned server key fingerprint (see cert::key_fingerprint)
+    pub key_fingerprint: String,
+    /// Where the server actually listens (port may differ from the configured one)
+    pub listening: Listening,
+    pub status: Arc<StatusRegistry>,
+    /// End-to-end key and policy (see e2e)
+    pub e2e: Arc<e2e::E2e>,
+    /// Becomes true when the server is stopping; long-lived streams end on it
+    pub shutdown: watch::Receiver<bool>,
+}
+
+/// A session write seen on the change bus, whatever its origin
+#[derive(Clone, Debug)]
+pub struct ChangeNotice {
+    /// Change log id (doubles as the SSE event id)
+    pub id: i64,
+    pub date_key: String,
+}
+
+impl ChangeNotice {
+    /// The session write carried by a bus event (other kinds aren't streamed)
+    fn from_event(event: ChangeEvent) -> Option<Self> {
+        match event.kind {
+            ChangeKind::SessionSaved { change_id, date_key } => Some(Self { id: change_id, date_key }),
+            _ => None,
+        }
+    }
+}
+
+/// A change replayed from the log, with the session's current payload
+struct SessionChange {
+    id: i64,
+    date_key: String,
+    session: Option<JsonValue>,
+}
+
+enum Replay {
+    Changes(Vec<SessionChange>),
+    /// The client's position is no longer in the log; it must re-pull everything
+    Gap { latest: i64 },
+}
+
+#[derive(Deserialize, IntoParams)]
+struct PingQuery {
+    /// Random client challenge echoed back and covered by the signature
+    nonce: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct PingResponse {
+    device_id: String,
+    status: &'static str,
+    /// Major API version (see api::API_VERSION)
+    api_version: &'static str,
+    #[schema(value_type = Vec<String>)]
+    capabilities: &'static [&'static str],
+    /// Port the server is listening on (after any fallback)
+    port: u16,
+    /// SHA-256 of the server's public key, as pinned in the QR code
+    fingerprint: String,
+    nonce: String,
+    timestamp: i64,
+    /// HMAC-SHA256 over ping_signing_payload, keyed with the pairing secret
+    signature: String,
+    /// Id of the end-to-end key; a mismatch means the client must pair again
+    e2e_key_id: String,
+    /// Plaintext session uploads are refused
+    e2e_required: bool,
 }
 
 #[derive(Deserialize)]
-struct AuthQuery {
+struct CaQuery {
+    format: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+struct CalendarQuery {
+    /// Calendar token (see calendar::token), for calendar apps which can't send headers
     token: Option<String>,
 }
 
-/// Start HTTPS sync server
-pub async fn start_server(
+/// Bind and start the HTTPS sync server (see handle::SyncServerHandle)
+async fn start_server(
     db: Arc<Mutex<Database>>,
     device_id: String,
     auth_token: String,
-) -> Result<()> {
+    bus: ChangeBus,
+    status: Arc<StatusRegistry>,
+    auth_limiter: Arc<AuthLimiter>,
+    pairing: Arc<replication::PairingNonces>,
+) -> Result<RunningServer> {
+    // Never let the token reach the logs, even via request URLs or errors
+    auth::register_secret(&auth_token);
 
-    tracing::info!("Sync server auth token: {}", auth_token);
     tracing::info!("Device ID: {}", device_id);
 
-    // Broadcast channel for real-time updates
-    let (update_tx, _) = broadcast::channel::<String>(100);
+    let (allowed_origins, listen_config, e2e) = {
+        let db = db.lock().map_err(|e| anyhow::anyhow!("{}", e))?;
+        (cors::configured_origins(&db), ListenConfig::from_settings(&db), e2e::E2e::load(&db)?)
+    };
+    tracing::info!("Sync CORS origins: {}", allowed_origins.join(", "));
+
+    // Bind first so a port clash is resolved before anything advertises the port
+    let (listeners, listening) = listen::bind(&listen_config)?;
+
+    // Load TLS certificate covering the current LAN addresses
+    let cert = Certificate::get_or_create(&certificate_names())?;
+    let config = RustlsConfig::from_pem(cert.cert_pem.clone(), cert.key_pem.clone())
+        .await
+        .context("Failed to load TLS config")?;
+    let key_fingerprint = cert.key_fingerprint.clone();
+
+    // Keep the certificate in step with network changes and expiry
+    let cert_watcher = tokio::spawn(watch_certificate(config.clone(), cert));
+    let (shutdown, shutdown_rx) = watch::channel(false);
 
     let state = SyncServerState {
         db,
         auth_token,
         device_id: device_id.clone(),
-        update_tx,
+        bus,
+        auth_limiter,
+        pairing,
+        key_fingerprint,
+        listening: listening.clone(),
+        status,
+        e2e: Arc::new(e2e),
+        shutdown: shutdown_rx,
     };
 
-    // Build router
-    let app = Router::new()
+    let app = router(state, &allowed_origins);
+
+    // Start one server per bound socket (non-blocking)
+    let mut handles = Vec::new();
+    let mut tasks = Vec::new();
+    for listener in listeners {
+        let addr = listener.local_addr()?;
+        let handle = axum_server::Handle::new();
+        let server = axum_server::from_tcp_rustls(listener, config.clone()).handle(handle.clone());
+        let app = app.clone();
+
+        tracing::info!("Starting HTTPS sync server on {}", addr);
+
+        handles.push(handle);
+        tasks.push(tokio::spawn(async move {
+            if let Err(e) = server
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+            {
+                tracing::error!("Sync server error on {}: {}", addr, e);
+            }
+        }));
+    }
+
+    Ok(RunningServer {
+        listening,
+        handles,
+        tasks,
+        cert_watcher,
+        shutdown,
+    })
+}
+
+/// Every route of the sync server, behind the CORS layer
+fn router(state: SyncServerState, allowed_origins: &[String]) -> Router {
+    let v1 = Router::new()
+        .route("/ping", get(handle_ping))
+        .route(
+            "/sessions",
+            get(handle_get_sessions)
+                .post(batch::handle_batch_upload)
+                .layer(DefaultBodyLimit::max(batch::MAX_BODY_BYTES)),
+        )
+        .route("/session", post(handle_post_session))
+        .route("/status", get(handle_get_status))
+        .route("/changes", get(replication::handle_changes))
+        .route("/peers", post(replication::handle_announce_peer))
+        .route("/peers/confirm", post(replication::handle_confirm_pairing))
+        .route("/stream", get(sse::handle_sse_stream))
+        .route("/ws", get(ws::handle_ws));
+
+    // Pre-v1 paths, kept for clients paired before versioning
+    let legacy = Router::new()
         .route("/api/ping", get(handle_ping))
-        .route("/api/sync/sessions", get(handle_get_sessions))
+        .route(
+            "/api/sync/sessions",
+            get(handle_get_sessions)
+                .post(batch::handle_batch_upload)
+                .layer(DefaultBodyLimit::max(batch::MAX_BODY_BYTES)),
+        )
         .route("/api/sync/session", post(handle_post_session))
-        .route("/api/sync/stream", get(handle_sse_stream))
-        .with_state(state);
+        .route("/api/sync/stream", get(sse::handle_sse_stream))
+        .route("/api/sync/ws", get(ws::handle_ws))
+        .layer(middleware::from_fn(api::deprecated));
+
+    Router::new()
+        .nest("/api/v1", v1)
+        .merge(legacy)
+        .route("/api/openapi.json", get(api::handle_openapi))
+        .route("/ca", get(handle_ca))
+        .route("/pair.svg", get(handle_pair_svg))
+        .route("/api/calendar.ics", get(handle_calendar))
+        .layer(cors::layer(allowed_origins))
+        .with_state(state)
+}
 
-    // Load TLS certificate
-    let cert = crate::cert::Certificate::get_or_create()?;
-    let config = RustlsConfig::from_pem(cert.cert_pem, cert.key_pem)
-        .await
-        .context("Failed to load TLS config")?;
+/// Names the TLS certificate must cover right now (every candidate LAN address)
+fn certificate_names() -> Vec<String> {
+    let local_ips: Vec<String> = crate::network::candidate_addresses(None)
+        .unwrap_or_else(|e| {
+            tracing::warn!("{}", e);
+            Vec::new()
+        })
+        .into_iter()
+        .map(|address| address.ip.to_string())
+        .collect();
+    crate::cert::subject_alt_names(&local_ips)
+}
 
-    // Bind to all interfaces
-    let addr = SocketAddr::from(([0, 0, 0, 0], SYNC_PORT));
+/// Rotate the certificate when local addresses change or expiry nears,
+/// hot-swapping it into the running server without a restart
+async fn watch_certificate(config: RustlsConfig, mut current: Certificate) {
+    use tokio::time::{sleep, Duration};
 
-    tracing::info!("Starting HTTPS sync server on {}", addr);
+    loop {
+        sleep(Duration::from_secs(CERT_CHECK_INTERVAL_SECS)).await;
 
-    // Start server (non-blocking)
-    tokio::spawn(async move {
-        if let Err(e) = axum_server::bind_rustls(addr, config)
-            .serve(app.into_make_service())
-            .await
-        {
-            tracing::error!("Sync server error: {}", e);
+        let names = certificate_names();
+        if !current.needs_rotation(&names) {
+            continue;
         }
-    });
 
-    Ok(())
+        let cert = match Certificate::get_or_create(&names) {
+            Ok(cert) => cert,
+            Err(e) => {
+                tracing::error!("Failed to rotate TLS certificate: {}", e);
+                continue;
+            }
+        };
+
+        match config.reload_from_pem(cert.cert_pem.clone(), cert.key_pem.clone()).await {
+            Ok(()) => {
+                tracing::info!("Reloaded TLS certificate");
+                current = cert;
+            }
+            Err(e) => tracing::error!("Failed to reload TLS config: {}", e),
+        }
+    }
 }
 
-/// GET /api/ping - Device discovery (no auth required)
+/// GET /api/v1/ping - Device discovery (no auth required)
+///
+/// Clients pass a random `?nonce=` and verify `signature` with the secret
+/// from the QR code, and `fingerprint` against the pinned key.
+#[utoipa::path(
+    get,
+    path = "/api/v1/ping",
+    params(PingQuery),
+    responses(
+        (status = 200, body = PingResponse),
+        (status = 400, description = "Nonce too long", body = api::ErrorBody),
+    ),
+)]
 async fn handle_ping(
+    Query(query): Query<PingQuery>,
+    State(state): State<SyncServerState>,
+) -> Result<Json<PingResponse>, ApiError> {
+    let nonce = query.nonce.unwrap_or_default();
+    if nonce.len() > MAX_PING_NONCE_LEN {
+        return Err(ApiError::invalid_request(format!(
+            "nonce longer than {} characters",
+            MAX_PING_NONCE_LEN
+        )));
+    }
+
+    let timestamp = chrono::Utc::now().timestamp();
+    let signature = auth::sign(
+        &state.auth_token,
+        &ping_signing_payload(&state.device_id, &state.key_fingerprint, &nonce, timestamp),
+    );
+
+    Ok(Json(PingResponse {
+        device_id: state.device_id.clone(),
+        status: "ok",
+        api_version: api::API_VERSION,
+        capabilities: api::CAPABILITIES,
+        port: state.listening.port,
+        fingerprint: state.key_fingerprint.clone(),
+        nonce,
+        timestamp,
+        signature,
+        e2e_key_id: state.e2e.kid.clone(),
+        e2e_required: state.e2e.required,
+    }))
+}
+
+/// Canonical string signed in ping responses (verified by peers::client too)
+pub fn ping_signing_payload(device_id: &str, fingerprint: &str, nonce: &str, timestamp: i64) -> String {
+    format!("traindaily-ping-v1\n{}\n{}\n{}\n{}", device_id, fingerprint, nonce, timestamp)
+}
+
+/// GET /ca - Local CA certificate download (no auth required)
+///
+/// `?format=mobileconfig` (default, iOS profile), `pem` or `der`
+async fn handle_ca(Query(query): Query<CaQuery>) -> Result<Response, ApiError> {
+    let ca = CertificateAuthority::get_or_create().map_err(ApiError::internal)?;
+
+    let (content_type, filename, body) = match query.format.as_deref().unwrap_or("mobileconfig") {
+        "mobileconfig" => ("application/x-apple-aspen-config", "traindaily-ca.mobileconfig", ca.mobileconfig()),
+        "pem" => ("application/x-pem-file", "traindaily-ca.pem", ca.cert_pem.into_bytes()),
+        "der" => ("application/x-x509-ca-cert", "traindaily-ca.cer", ca.cert_der),
+        other => return Err(ApiError::invalid_request(format!("unknown format: {}", other))),
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// GET /pair.svg - Pairing QR code (loopback clients only)
+///
+/// Contains the pairing secret, so anything off this machine gets a 404.
+async fn handle_pair_svg(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     State(state): State<SyncServerState>,
-) -> Json<HashMap<String, String>> {
-    let mut response = HashMap::new();
-    response.insert("deviceId".to_string(), state.device_id.clone());
-    response.insert("status".to_string(), "ok".to_string());
-    Json(response)
+) -> Result<Response, ApiError> {
+    if !addr.ip().to_canonical().is_loopback() {
+        return Err(ApiError::from(StatusCode::NOT_FOUND));
+    }
+
+    let payload = {
+        let db = state.db.lock().map_err(ApiError::internal)?;
+        pairing_payload(&db, &state.device_id, &state.auth_token, &state.listening)
+            .map_err(ApiError::internal)?
+    };
+    let code = crate::qr::encode(&payload, crate::qr::ErrorCorrection::default())
+        .map_err(ApiError::internal)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        crate::qr::to_svg(&code),
+    )
+        .into_response())
 }
 
-/// GET /api/sync/sessions - Get all sessions (auth required)
+/// GET /api/calendar.ics - Schedule and completed sessions (auth required)
+///
+/// Subscribe with `?token=` set to the calendar token, which is read-only
+/// and revocable on its own; the pairing secret is not accepted here. The
+/// feed is generated on every request.
+#[utoipa::path(
+    get,
+    path = "/api/calendar.ics",
+    security(("bearer" = [])),
+    params(CalendarQuery),
+    responses(
+        (status = 200, content_type = "text/calendar", description = "RFC 5545 calendar", body = String),
+        (status = 401, body = api::ErrorBody),
+        (status = 429, body = api::ErrorBody),
+    ),
+)]
+async fn handle_calendar(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<CalendarQuery>,
+    headers: HeaderMap,
+    State(state): State<SyncServerState>,
+) -> Result<Response, ApiError> {
+    let expected = {
+        let db = state.db.lock().map_err(ApiError::internal)?;
+        crate::calendar::token(&db).map_err(ApiError::internal)?
+    };
+    let device = state.authorize_with(addr, &headers, query.token.as_deref(), TokenSource::HeaderOrQuery, &expected)?;
+
+    let (sessions, schedule) = {
+        let db = state.db.lock().map_err(ApiError::internal)?;
+        let sessions = db
+            .get_all_sessions()
+            .map_err(|e| state.failed(&device, ApiError::internal(e)))?;
+        let schedule = crate::schedule::Schedule::load(&db).map_err(ApiError::internal)?;
+        (sessions, schedule)
+    };
+    let today = chrono::Local::now().date_naive();
+    let ics = crate::calendar::feed(&sessions, &schedule, today, &state.device_id, chrono::Utc::now());
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, crate::calendar::CONTENT_TYPE),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        ics,
+    )
+        .into_response())
+}
+
+/// GET /api/v1/sessions - Get all sessions (auth required)
+///
+/// Sessions come back as e2e envelopes when the client sends `X-E2E: 1`.
+#[utoipa::path(
+    get,
+    path = "/api/v1/sessions",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Sessions (or envelopes) keyed by date (YYYY-MM-DD)", body = HashMap<String, Object>),
+        (status = 401, body = api::ErrorBody),
+        (status = 429, body = api::ErrorBody),
+    ),
+)]
 async fn handle_get_sessions(
-    Query(auth): Query<AuthQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
     State(state): State<SyncServerState>,
-) -> Result<Json<HashMap<String, JsonValue>>, StatusCode> {
+) -> Result<Json<HashMap<String, JsonValue>>, ApiError> {
     // Verify auth token
-    if !verify_auth(&auth, &headers, &state.auth_token) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+    let device = state.authorize(addr, &headers, None, TokenSource::Header)?;
 
     // Get sessions from database
-    let db = state.db.lock().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let sessions = db.get_all_sessions().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let db = state.db.lock().map_err(ApiError::internal)?;
+    let sessions = db
+        .get_all_sessions()
+        .map_err(|e| state.failed(&device, ApiError::internal(e)))?;
+    state.status.record_pull(&device);
+
+    let sealed = state.e2e.seals_for(e2e::requested(&headers));
+    state.status.record_e2e(&device, sealed);
+    let sessions = sessions
+        .into_iter()
+        .map(|(date_key, session)| {
+            let session = state.e2e.download(&date_key, session, sealed);
+            (date_key, session)
+        })
+        .collect();
 
     Ok(Json(sessions))
 }
 
-/// POST /api/sync/session - Upload session (auth required)
+/// POST /api/v1/session - Upload session (auth required)
+#[utoipa::path(
+    post,
+    path = "/api/v1/session",
+    security(("bearer" = [])),
+    request_body = SessionUpload,
+    responses(
+        (status = 200, body = SessionSaved),
+        (status = 400, body = api::ErrorBody),
+        (status = 401, body = api::ErrorBody),
+        (status = 429, body = api::ErrorBody),
+    ),
+)]
 async fn handle_post_session(
-    Query(auth): Query<AuthQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
     State(state): State<SyncServerState>,
-    Json(payload): Json<SessionUpload>,
-) -> Result<StatusCode, StatusCode> {
+    ApiJson(payload): ApiJson<SessionUpload>,
+) -> Result<Json<SessionSaved>, ApiError> {
     // Verify auth token
-    if !verify_auth(&auth, &headers, &state.auth_token) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+    let device = state.authorize(addr, &headers, None, TokenSource::Header)?;
 
-    // Save session to database
-    let db = state.db.lock().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    db.save_session(&payload.date_key, &payload.session)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let session = state
+        .open_upload(&device, &payload.date_key, payload.session)
+        .and_then(|session| validate_session(&payload.date_key, &session).map(|()| session))
+        .map_err(|e| state.failed(&device, ApiError::invalid_request(e)))?;
 
-    // Broadcast update to SSE clients
-    let _ = state.update_tx.send(payload.date_key);
+    // Save session to database
+    let db = state.db.lock().map_err(ApiError::internal)?;
+    // Stream subscribers are notified through the change bus
+    let change_id = db.save_session(&payload.date_key, &session, ChangeOrigin::Sync)
+        .map_err(|e| state.failed(&device, ApiError::internal(e)))?;
+    state.status.record_push(&device);
 
-    Ok(StatusCode::OK)
+    Ok(Json(SessionSaved { change_id }))
 }
 
-/// GET /api/sync/stream - SSE stream for real-time updates (auth required)
-async fn handle_sse_stream(
-    Query(auth): Query<AuthQuery>,
+/// GET /api/v1/status - Connected clients, device activity, recent errors (auth required)
+#[utoipa::path(
+    get,
+    path = "/api/v1/status",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, body = status::SyncStatus),
+        (status = 401, body = api::ErrorBody),
+        (status = 429, body = api::ErrorBody),
+    ),
+)]
+async fn handle_get_status(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
     State(state): State<SyncServerState>,
-) -> Result<Sse<impl futures::Stream<Item = Result<axum::response::sse::Event, std::convert::Infallible>>>, StatusCode> {
-    // Verify auth token
-    if !verify_auth(&auth, &headers, &state.auth_token) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-
-    let mut rx = state.update_tx.subscribe();
-
-    let stream = async_stream::stream! {
-        loop {
-            match rx.recv().await {
-                Ok(date_key) => {
-                    let event = axum::response::sse::Event::default()
-                        .event("session_updated")
-                        .data(date_key);
-                    yield Ok(event);
-                }
-                Err(_) => break,
+) -> Result<Json<status::SyncStatus>, ApiError> {
+    state.authorize(addr, &headers, None, TokenSource::Header)?;
+
+    Ok(Json(state.status.snapshot()))
+}
+
+/// Catch a stream client (SSE or WebSocket) up on everything after
+/// `last_sent`, advancing it: one `change` per changed date, or a single
+/// `resync` when the log no longer reaches back that far
+fn catch_up<T>(
+    state: &SyncServerState,
+    last_sent: &mut i64,
+    change: impl FnMut(SessionChange) -> T,
+    resync: impl FnOnce(i64) -> T,
+) -> Vec<T> {
+    match replay_changes(&state.db, *last_sent) {
+        Ok(Replay::Changes(changes)) => {
+            if let Some(last) = changes.last() {
+                *last_sent = last.id;
             }
+            changes.into_iter().map(change).collect()
         }
-    };
+        Ok(Replay::Gap { latest }) => {
+            *last_sent = latest;
+            vec![resync(latest)]
+        }
+        Err(e) => {
+            tracing::error!("Failed to replay change log: {}", e);
+            Vec::new()
+        }
+    }
+}
 
-    Ok(Sse::new(stream))
+/// Changes after `after_id`, each date once with its latest change id and
+/// current payload (like replication's feed), or a gap marker when the log
+/// no longer reaches back that far. The database is locked per page and per
+/// session, not for the whole replay.
+fn replay_changes(db: &Mutex<Database>, after_id: i64) -> Result<Replay> {
+    let lock = || db.lock().map_err(|e| anyhow::anyhow!("{}", e));
+
+    {
+        let db = lock()?;
+        let latest = db.latest_change_id()?;
+        let oldest = db.oldest_change_id()?.unwrap_or(latest + 1);
+        if after_id > latest || after_id < oldest - 1 {
+            return Ok(Replay::Gap { latest });
+        }
+    }
+
+    let mut latest_by_date: HashMap<String, i64> = HashMap::new();
+    let mut cursor = after_id;
+    loop {
+        let batch = lock()?.changes_since(cursor, REPLAY_BATCH)?;
+        let done = batch.len() < REPLAY_BATCH;
+        for record in batch {
+            cursor = record.id;
+            latest_by_date.insert(record.date_key, record.id);
+        }
+        if done {
+            break;
+        }
+    }
+
+    let mut dates: Vec<(i64, String)> = latest_by_date.into_iter().map(|(date_key, id)| (id, date_key)).collect();
+    dates.sort_unstable();
+
+    let mut changes = Vec::with_capacity(dates.len());
+    for (id, date_key) in dates {
+        let session = lock()?.get_session(&date_key)?;
+        changes.push(SessionChange { id, date_key, session });
+    }
+
+    Ok(Replay::Changes(changes))
 }
 
-#[derive(Deserialize)]
+/// Basic shape checks for an uploaded session (also applied to bundles)
+pub fn validate_session(date_key: &str, session: &JsonValue) -> Result<(), String> {
+    if chrono::NaiveDate::parse_from_str(date_key, "%Y-%m-%d").is_err() {
+        return Err(format!("invalid date key: {}", date_key));
+    }
+    if !session.is_object() {
+        return Err("session must be a JSON object".to_string());
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
 struct SessionUpload {
+    /// YYYY-MM-DD (`date_key` accepted from older clients)
+    #[serde(alias = "date_key")]
     date_key: String,
+    /// The session, or an e2e envelope
+    #[schema(value_type = Object)]
     session: JsonValue,
 }
 
-/// Verify auth token from query or header
-fn verify_auth(query: &AuthQuery, headers: &HeaderMap, expected: &str) -> bool {
-    // Check query parameter first
-    if let Some(token) = &query.token {
-        return token == expected;
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct SessionSaved {
+    /// Change log id of the write (see sse.rs)
+    change_id: i64,
+}
+
+impl SyncServerState {
+    /// Verify the caller's token, with per-IP lockout
+    ///
+    /// Returns the caller's device label (see status::device_label) and
+    /// records the attempt in the status registry.
+    fn authorize(
+        &self,
+        addr: SocketAddr,
+        headers: &HeaderMap,
+        query_token: Option<&str>,
+        source: TokenSource,
+    ) -> Result<String, ApiError> {
+        self.authorize_with(addr, headers, query_token, source, &self.auth_token)
     }
 
-    // Check Authorization header
-    if let Some(auth_header) = headers.get("Authorization") {
-        if let Ok(auth_str) = auth_header.to_str() {
-            if let Some(token) = auth_str.strip_prefix("Bearer ") {
-                return token == expected;
+    /// Like authorize, for routes with their own token (the calendar feed)
+    fn authorize_with(
+        &self,
+        addr: SocketAddr,
+        headers: &HeaderMap,
+        query_token: Option<&str>,
+        source: TokenSource,
+        expected: &str,
+    ) -> Result<String, ApiError> {
+        // IPv4 clients of the dual-stack socket arrive as ::ffff:a.b.c.d
+        let ip = addr.ip().to_canonical();
+        let device = status::device_label(
+            headers.get(status::DEVICE_ID_HEADER).and_then(|value| value.to_str().ok()),
+            ip,
+        );
+
+        match auth::authorize(&self.auth_limiter, ip, headers, query_token, source, expected) {
+            Ok(()) => {
+                self.status.record_request(&device);
+                Ok(device)
             }
+            Err(code) => Err(self.failed(&device, ApiError::from(code))),
         }
     }
 
-    false
+    /// An uploaded session in the clear (see e2e::E2e::open_upload)
+    fn open_upload(&self, device: &str, date_key: &str, session: JsonValue) -> Result<JsonValue, String> {
+        let (session, encrypted) = self.e2e.open_upload(date_key, session)?;
+        self.status.record_e2e(device, encrypted);
+        Ok(session)
+    }
+
+    /// Record a failed request from `device` in the status registry
+    fn failed(&self, device: &str, error: ApiError) -> ApiError {
+        self.status.record_error(Some(device), error.to_string());
+        error
+    }
 }
 
 /// Generate random auth token (32 hex characters)
@@ -207,28 +744,198 @@ pub fn generate_auth_token() -> String {
 }
 
 /// Generate QR code data for pairing
-pub fn generate_qr_data(device_id: &str, auth_token: &str, local_ip: &str) -> String {
+///
+/// `fingerprint` is the pinned server key fingerprint (see
+/// cert::key_fingerprint), sent as `fp`; the PWA checks it and the ping
+/// signature before trusting the desktop.
+/// `port` is the port actually bound (see listen::bind). `ip` carries the
+/// best address for older clients, `ips` every candidate in order. IPv6
+/// addresses go in unbracketed, clients bracket them when building URLs.
+/// The pairing secret and `key`, the end-to-end payload key (see
+/// e2e::pairing_key), go in the fragment so opening the URL never sends
+/// them to the web server.
+pub fn generate_qr_data(
+    device_id: &str,
+    auth_token: &str,
+    addresses: &[String],
+    port: u16,
+    fingerprint: &str,
+    e2e_key: &str,
+) -> String {
     format!(
-        "https://traindaily.vercel.app/pair?deviceId={}&ip={}&port={}&secret={}",
-        device_id, local_ip, SYNC_PORT, auth_token
+        "https://traindaily.vercel.app/pair?deviceId={}&ip={}&ips={}&port={}&fp={}#secret={}&key={}",
+        device_id,
+        addresses.first().map(String::as_str).unwrap_or_default(),
+        addresses.join(","),
+        port,
+        fingerprint,
+        auth_token,
+        e2e_key
     )
 }
 
-/// Get local IP address (best guess)
-pub fn get_local_ip() -> Result<String> {
-    use std::net::IpAddr;
+/// Pairing URL for the current network (the QR payload)
+pub fn pairing_payload(db: &Database, device_id: &str, auth_token: &str, listening: &Listening) -> Result<String> {
+    let candidates = crate::network::candidate_addresses_for(db)?;
+    let addresses = pairing_addresses(listening, &candidates);
+    if addresses.is_empty() {
+        anyhow::bail!("No network address to pair over (connect to Wi-Fi or Ethernet)");
+    }
+    let fingerprint = crate::cert::pinned_key_fingerprint()?;
+    let e2e_key = e2e::pairing_key(db)?;
 
-    // Try to get local IP by connecting to an external address
-    // (doesn't actually send data, just determines which interface would be used)
-    let socket = std::net::UdpSocket::bind("0.0.0.0:0")?;
-    socket.connect("8.8.8.8:80")?;
-    let local_addr = socket.local_addr()?;
+    Ok(generate_qr_data(device_id, auth_token, &addresses, listening.port, &fingerprint, &e2e_key))
+}
+
+/// Subscription URL of the calendar feed for the current network
+pub fn calendar_url(db: &Database, listening: &Listening) -> Result<String> {
+    let candidates = crate::network::candidate_addresses_for(db)?;
+    let address = pairing_addresses(listening, &candidates)
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("No network address to subscribe over (connect to Wi-Fi or Ethernet)"))?;
+    let host = if address.contains(':') { format!("[{}]", address) } else { address };
+
+    Ok(format!(
+        "https://{}:{}/api/calendar.ics?token={}",
+        host,
+        listening.port,
+        crate::calendar::token(db)?
+    ))
+}
+
+/// Addresses to offer in the pairing payload, best first
+///
+/// A specific bind address is the only choice; otherwise the ranked
+/// candidates the listening sockets actually accept.
+pub fn pairing_addresses(listening: &Listening, candidates: &[NetworkAddress]) -> Vec<String> {
+    if let Some(ip) = listening.advertised_ip() {
+        return vec![ip.to_string()];
+    }
+
+    // Public addresses aren't in the certificate (see cert::ca::permits)
+    candidates
+        .iter()
+        .filter(|candidate| listening.accepts(candidate.ip))
+        .filter(|candidate| crate::cert::ca::permits(&candidate.ip.to_string()))
+        .take(MAX_PAIRING_ADDRESSES)
+        .map(|candidate| candidate.ip.to_string())
+        .collect()
+}
 
-    match local_addr.ip() {
-        IpAddr::V4(ip) => Ok(ip.to_string()),
-        IpAddr::V6(_) => {
-            // Fallback to 127.0.0.1 if IPv6
-            Ok("127.0.0.1".to_string())
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Server state over the test database (see db::data_dir), for
+    /// exercising the real router
+    pub(super) fn test_state() -> SyncServerState {
+        test_state_with(Database::new().unwrap(), "test-mac", "test-token", "ab")
+    }
+
+    /// Server state for one of several desktops in a test
+    pub(crate) fn test_state_with(db: Database, device_id: &str, auth_token: &str, key_fingerprint: &str) -> SyncServerState {
+        let e2e = e2e::E2e::load(&db).unwrap();
+        SyncServerState {
+            db: Arc::new(Mutex::new(db)),
+            auth_token: auth_token.to_string(),
+            device_id: device_id.to_string(),
+            bus: ChangeBus::new(),
+            auth_limiter: Arc::new(AuthLimiter::new()),
+            pairing: Arc::new(replication::PairingNonces::new()),
+            key_fingerprint: key_fingerprint.to_string(),
+            listening: Listening { port: 8841, addresses: vec!["0.0.0.0:8841".parse().unwrap()] },
+            status: StatusRegistry::new(),
+            e2e: Arc::new(e2e),
+            shutdown: watch::channel(false).1,
+        }
+    }
+
+    /// Serve `state` over HTTPS on a loopback port, like start_server does;
+    /// returns the port
+    pub(crate) async fn serve_tls(state: SyncServerState, cert_pem: String, key_pem: String) -> u16 {
+        let config = RustlsConfig::from_pem(cert_pem.into_bytes(), key_pem.into_bytes()).await.unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let app = router(state, &[]);
+        tokio::spawn(async move {
+            axum_server::from_tcp_rustls(listener, config)
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+                .unwrap()
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_calendar_takes_only_its_own_token() {
+        use axum::body::Body;
+        use axum::extract::ConnectInfo;
+        use tower::ServiceExt;
+
+        let state = test_state();
+        let calendar_token = crate::calendar::token(&state.db.lock().unwrap()).unwrap();
+        let app = router(state, &[]);
+        let fetch = |token: &str| {
+            let mut request = axum::http::Request::get(format!("/api/calendar.ics?token={}", token))
+                .body(Body::empty())
+                .unwrap();
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from(([192, 168, 1, 30], 50000))));
+            app.clone().oneshot(request)
+        };
+
+        assert_eq!(fetch("test-token").await.unwrap().status(), axum::http::StatusCode::UNAUTHORIZED);
+        assert_eq!(fetch(&calendar_token).await.unwrap().status(), axum::http::StatusCode::OK);
+    }
+
+    #[test]
+    fn test_replay_changes_and_gap() {
+        let db = Database::new().unwrap();
+        let first = json!({ "logged_at": "2026-02-19T10:00:00Z" });
+        let session = json!({ "logged_at": "2026-02-19T11:00:00Z" });
+        let start = db.save_session("2026-02-19", &first, ChangeOrigin::Sync).unwrap() - 1;
+        db.save_session("2026-02-20", &first, ChangeOrigin::Sync).unwrap();
+        let id = db.save_session("2026-02-19", &session, ChangeOrigin::Sync).unwrap();
+        let db = Mutex::new(db);
+
+        match replay_changes(&db, start).unwrap() {
+            Replay::Changes(changes) => {
+                // Each date once, at its latest change, in change order
+                let dates: Vec<&str> = changes.iter().map(|c| c.date_key.as_str()).collect();
+                assert_eq!(dates, vec!["2026-02-20", "2026-02-19"]);
+                assert_eq!(changes[1].id, id);
+                assert_eq!(changes[1].session, Some(session));
+            }
+            Replay::Gap { .. } => panic!("expected replayable changes"),
         }
+
+        // A client ahead of the server (e.g. after a reset) must resync
+        assert!(matches!(replay_changes(&db, i64::MAX).unwrap(), Replay::Gap { .. }));
+    }
+
+    #[test]
+    fn test_pairing_addresses_follow_bound_family() {
+        let candidate = |ip: &str, rank| NetworkAddress {
+            ip: ip.parse().unwrap(),
+            interface: "en0".to_string(),
+            rank,
+            preferred: false,
+        };
+        let candidates = vec![candidate("192.168.1.20", 90), candidate("fd00::20", 30)];
+
+        let ipv4_only = Listening { port: 8841, addresses: vec!["0.0.0.0:8841".parse().unwrap()] };
+        assert_eq!(pairing_addresses(&ipv4_only, &candidates), vec!["192.168.1.20"]);
+
+        let dual_stack = Listening { port: 8841, addresses: vec!["[::]:8841".parse().unwrap()] };
+        assert_eq!(pairing_addresses(&dual_stack, &candidates), vec!["192.168.1.20", "fd00::20"]);
+
+        let qr = generate_qr_data("mac", "secret", &pairing_addresses(&dual_stack, &candidates), 8841, "ab", "k");
+        assert!(qr.contains("&ip=192.168.1.20&ips=192.168.1.20,fd00::20&port=8841"));
+        let (query, fragment) = qr.split_once('#').unwrap();
+        assert!(!query.contains("secret") && !query.contains("key="));
+        assert_eq!(fragment, "secret=secret&key=k");
     }
 }
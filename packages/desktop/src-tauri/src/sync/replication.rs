@@ -0,0 +1,437 @@
+/**
+ * Sync Replication Feed
+ *
+ * Endpoints other desktops replicate through (see peers module)
+ * - GET /api/v1/changes?after=&wait= - sessions changed after a change id,
+ *   each stamped with when and on which device it was last changed (for
+ *   last-writer-wins, see db::apply_replicated); long-polls up to `wait`
+ *   seconds when there is nothing new
+ * - `resync`: the log no longer reaches back to `after` (pruned, or a reset
+ *   server), so the feed carries every session instead
+ * - POST /api/v1/peers - a desktop that paired with this one announces its
+ *   own credentials, so replication runs both ways. The announcement carries
+ *   a one-time nonce from that desktop's pairing attempt; it is confirmed
+ *   with the announcer (POST /api/v1/peers/confirm, over its pinned key)
+ *   before anything is saved. A paired desktop's key is never replaced
+ *   this way (409): re-pairing under a new key starts with unpairing here,
+ *   so holding the pairing secret isn't enough to take a peer's place
+ */
+
+use super::api::{ApiError, ApiJson, ErrorBody};
+use super::{auth::TokenSource, ChangeNotice, SyncServerState, REPLAY_BATCH};
+use crate::db::{Database, PeerRecord};
+use crate::peers::client::PeerClient;
+use anyhow::Result;
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use utoipa::{IntoParams, ToSchema};
+
+const MAX_WAIT_SECS: u64 = 30;
+// How long a pairing attempt's nonce can be confirmed
+const PAIRING_NONCE_SECS: u64 = 120;
+// Stamp for sessions whose changes were pruned from the log: any real change wins
+const UNKNOWN_CHANGED_AT: &str = "1970-01-01T00:00:00Z";
+
+#[derive(Deserialize, IntoParams)]
+pub struct ChangesQuery {
+    /// Return changes after this change id (0 = from the start)
+    #[serde(default)]
+    after: i64,
+    /// Seconds to wait for a change when there is none yet (max 30)
+    wait: Option<u64>,
+}
+
+/// A session as of its latest change
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FeedChange {
+    pub date_key: String,
+    #[schema(value_type = Object)]
+    pub session: JsonValue,
+    /// RFC 3339 time of the change, on the device that made it
+    pub changed_at: String,
+    /// Device id that made the change
+    pub origin: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeFeed {
+    pub changes: Vec<FeedChange>,
+    /// Pass as `after` next time
+    pub cursor: i64,
+    /// More changes are waiting: ask again right away
+    pub more: bool,
+    /// `changes` is a full snapshot; the client's position was lost
+    pub resync: bool,
+}
+
+/// Credentials a paired desktop hands over so we can replicate from it
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerAnnouncement {
+    pub device_id: String,
+    /// Its pairing secret
+    pub secret: String,
+    /// Its pinned server key fingerprint
+    pub fingerprint: String,
+    pub port: u16,
+    pub addresses: Vec<String>,
+    /// One-time nonce of its pairing attempt (see PairingNonces)
+    #[serde(default)]
+    pub nonce: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PairingConfirmation {
+    pub nonce: String,
+}
+
+/// Nonces of this desktop's own pairing attempts, confirmed at most once
+/// by the desktop it announced itself to
+#[derive(Default)]
+pub struct PairingNonces {
+    issued: Mutex<HashMap<String, Instant>>,
+}
+
+impl PairingNonces {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A fresh nonce for one pairing attempt
+    pub fn issue(&self) -> String {
+        let nonce = hex::encode(rand::random::<[u8; 16]>());
+        let mut issued = self.issued.lock().unwrap();
+        issued.retain(|_, at| at.elapsed() < Duration::from_secs(PAIRING_NONCE_SECS));
+        issued.insert(nonce.clone(), Instant::now());
+        nonce
+    }
+
+    /// Whether `nonce` was issued here and is still fresh; it can't be redeemed again
+    pub fn redeem(&self, nonce: &str) -> bool {
+        self.issued
+            .lock()
+            .unwrap()
+            .remove(nonce)
+            .is_some_and(|at| at.elapsed() < Duration::from_secs(PAIRING_NONCE_SECS))
+    }
+}
+
+/// GET /api/v1/changes - Replication feed for other desktops (auth required)
+#[utoipa::path(
+    get,
+    path = "/api/v1/changes",
+    params(ChangesQuery),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, body = ChangeFeed),
+        (status = 401, body = ErrorBody),
+        (status = 429, body = ErrorBody),
+    ),
+)]
+pub async fn handle_changes(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<ChangesQuery>,
+    headers: HeaderMap,
+    State(state): State<SyncServerState>,
+) -> Result<Json<ChangeFeed>, ApiError> {
+    let device = state.authorize(addr, &headers, None, TokenSource::Header)?;
+    let wait = Duration::from_secs(query.wait.unwrap_or(0).min(MAX_WAIT_SECS));
+
+    // Subscribe before reading so a change in between still wakes us
+    let mut rx = state.bus.subscribe();
+    let mut feed = read_feed(&state, query.after)?;
+
+    if feed.changes.is_empty() && !feed.resync && !wait.is_zero() {
+        let mut shutdown = state.shutdown.clone();
+        // Any session write (or a lag, which may have hidden one) ends the wait
+        let session_written = async {
+            while let Ok(event) = rx.recv().await {
+                if ChangeNotice::from_event(event).is_some() {
+                    break;
+                }
+            }
+        };
+
+        tokio::select! {
+            _ = session_written => {}
+            _ = tokio::time::sleep(wait) => {}
+            _ = shutdown.changed() => {}
+        }
+        feed = read_feed(&state, query.after)?;
+    }
+
+    state.status.record_pull(&device);
+    Ok(Json(feed))
+}
+
+/// POST /api/v1/peers - Register the calling desktop as a peer (auth required)
+#[utoipa::path(
+    post,
+    path = "/api/v1/peers",
+    security(("bearer" = [])),
+    request_body = PeerAnnouncement,
+    responses(
+        (status = 204, description = "Peer saved; replication from it starts"),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, description = "The announcer did not confirm the nonce", body = ErrorBody),
+        (status = 409, description = "Paired with this device id under another key", body = ErrorBody),
+        (status = 429, body = ErrorBody),
+    ),
+)]
+pub async fn handle_announce_peer(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    State(state): State<SyncServerState>,
+    ApiJson(announcement): ApiJson<PeerAnnouncement>,
+) -> Result<StatusCode, ApiError> {
+    let device = state.authorize(addr, &headers, None, TokenSource::Header)?;
+
+    validate_announcement(&announcement, &state.device_id)
+        .map_err(|e| state.failed(&device, ApiError::invalid_request(e)))?;
+    check_known_key(&*state.db.lock().map_err(ApiError::internal)?, &announcement)
+        .map_err(|e| state.failed(&device, e))?;
+
+    // The address it called from is known to work; try it first
+    let mut addresses = vec![addr.ip().to_canonical().to_string()];
+    for address in &announcement.addresses {
+        if !addresses.contains(address) {
+            addresses.push(address.clone());
+        }
+    }
+
+    // Only a desktop that is pairing right now can vouch for its nonce
+    if let Err(e) = confirm_announcement(&state, &announcement, &addresses).await {
+        tracing::warn!("Refused peer announcement from {}: {:#}", announcement.device_id, e);
+        return Err(state.failed(&device, ApiError::new(
+            StatusCode::FORBIDDEN,
+            "pairing_unconfirmed",
+            "the announcing desktop did not confirm this pairing",
+        )));
+    }
+
+    let db = state.db.lock().map_err(ApiError::internal)?;
+    // Again: it may have been paired while we waited for the confirmation
+    check_known_key(&db, &announcement).map_err(|e| state.failed(&device, e))?;
+    db.save_peer(&PeerRecord {
+        device_id: announcement.device_id,
+        token: announcement.secret,
+        fingerprint: announcement.fingerprint.to_lowercase(),
+        addresses,
+        port: announcement.port,
+        cursor: 0,
+        paired_at: chrono::Utc::now().to_rfc3339(),
+    })
+    .map_err(ApiError::internal)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/v1/peers/confirm - Confirm a pairing this desktop started (auth required)
+///
+/// Called back by the desktop we announced ourselves to, with the nonce
+/// from our announcement; each nonce confirms once.
+#[utoipa::path(
+    post,
+    path = "/api/v1/peers/confirm",
+    security(("bearer" = [])),
+    request_body = PairingConfirmation,
+    responses(
+        (status = 204, description = "This desktop issued the nonce"),
+        (status = 401, body = ErrorBody),
+        (status = 404, description = "Unknown, used or expired nonce", body = ErrorBody),
+        (status = 429, body = ErrorBody),
+    ),
+)]
+pub async fn handle_confirm_pairing(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    State(state): State<SyncServerState>,
+    ApiJson(confirmation): ApiJson<PairingConfirmation>,
+) -> Result<StatusCode, ApiError> {
+    let device = state.authorize(addr, &headers, None, TokenSource::Header)?;
+
+    if !state.pairing.redeem(&confirmation.nonce) {
+        return Err(state.failed(&device, ApiError::new(
+            StatusCode::NOT_FOUND,
+            "unknown_nonce",
+            "no pairing in progress with that nonce",
+        )));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Reach the announcing desktop over its pinned key and have it confirm
+/// the nonce of its pairing attempt
+/// Refuse to replace the key of a desktop that is already paired
+fn check_known_key(db: &Database, announcement: &PeerAnnouncement) -> Result<(), ApiError> {
+    let peers = db.get_peers().map_err(ApiError::internal)?;
+    match peers.iter().find(|peer| peer.device_id == announcement.device_id) {
+        Some(peer) if !peer.fingerprint.eq_ignore_ascii_case(&announcement.fingerprint) => Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "peer_key_changed",
+            "already paired with this device under another key; unpair it on this desktop first",
+        )),
+        _ => Ok(()),
+    }
+}
+
+async fn confirm_announcement(
+    state: &SyncServerState,
+    announcement: &PeerAnnouncement,
+    addresses: &[String],
+) -> Result<()> {
+    let client = PeerClient::new(&announcement.fingerprint, &announcement.secret, &state.device_id)?;
+    let addresses: Vec<IpAddr> = addresses.iter().filter_map(|address| address.parse().ok()).collect();
+    let base = crate::peers::reach(&client, &announcement.device_id, &addresses, announcement.port).await?;
+    client.confirm_pairing(&base, &announcement.nonce).await
+}
+
+fn read_feed(state: &SyncServerState, after: i64) -> Result<ChangeFeed, ApiError> {
+    let db = state.db.lock().map_err(ApiError::internal)?;
+    build_feed(&db, &state.device_id, after).map_err(ApiError::internal)
+}
+
+/// One page of changes after `after`, or a snapshot when that position is gone
+fn build_feed(db: &Database, device_id: &str, after: i64) -> Result<ChangeFeed> {
+    let latest = db.latest_change_id()?;
+    let oldest = db.oldest_change_id()?.unwrap_or(latest + 1);
+
+    if after > latest || after < oldest - 1 {
+        return snapshot(db, device_id, latest);
+    }
+
+    let records = db.changes_since(after, REPLAY_BATCH)?;
+    let more = records.len() == REPLAY_BATCH;
+    let cursor = records.last().map(|record| record.id).unwrap_or(after);
+
+    // Each date once, stamped with its latest change so the stamp matches the payload
+    let mut seen = HashSet::new();
+    let mut changes = Vec::new();
+    for record in records.iter().rev() {
+        if seen.insert(record.date_key.as_str()) {
+            if let Some(change) = feed_change(db, device_id, &record.date_key)? {
+                changes.push(change);
+            }
+        }
+    }
+    changes.reverse();
+
+    Ok(ChangeFeed { changes, cursor, more, resync: false })
+}
+
+/// Every change after `after`, across pages (offline bundles, see bundle module)
+pub fn feed_since(db: &Database, device_id: &str, after: i64) -> Result<ChangeFeed> {
+    let mut feed = build_feed(db, device_id, after)?;
+    while feed.more {
+        let next = build_feed(db, device_id, feed.cursor)?;
+        feed.changes.extend(next.changes);
+        feed.cursor = next.cursor;
+        feed.more = next.more;
+    }
+    Ok(feed)
+}
+
+fn snapshot(db: &Database, device_id: &str, latest: i64) -> Result<ChangeFeed> {
+    let mut date_keys: Vec<String> = db.get_all_sessions()?.into_keys().collect();
+    date_keys.sort();
+
+    let mut changes = Vec::with_capacity(date_keys.len());
+    for date_key in &date_keys {
+        if let Some(change) = feed_change(db, device_id, date_key)? {
+            changes.push(change);
+        }
+    }
+
+    Ok(ChangeFeed { changes, cursor: latest, more: false, resync: true })
+}
+
+fn feed_change(db: &Database, device_id: &str, date_key: &str) -> Result<Option<FeedChange>> {
+    let Some(session) = db.get_session(date_key)? else {
+        return Ok(None);
+    };
+    let (changed_at, origin) = match db.last_change(date_key)? {
+        Some(record) => (record.changed_at, record.origin.unwrap_or_else(|| device_id.to_string())),
+        None => (UNKNOWN_CHANGED_AT.to_string(), device_id.to_string()),
+    };
+
+    Ok(Some(FeedChange { date_key: date_key.to_string(), session, changed_at, origin }))
+}
+
+fn validate_announcement(announcement: &PeerAnnouncement, own_device_id: &str) -> Result<(), String> {
+    if announcement.device_id.trim().is_empty() || announcement.device_id == own_device_id {
+        return Err("invalid deviceId".to_string());
+    }
+    if announcement.secret.is_empty() {
+        return Err("missing secret".to_string());
+    }
+    if announcement.fingerprint.len() != 64 || !announcement.fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("fingerprint must be a hex SHA-256".to_string());
+    }
+    if announcement.port == 0 {
+        return Err("invalid port".to_string());
+    }
+    if announcement.nonce.is_empty() {
+        return Err("missing nonce (pair again from an up-to-date desktop)".to_string());
+    }
+    if let Some(bad) = announcement.addresses.iter().find(|a| a.parse::<IpAddr>().is_err()) {
+        return Err(format!("not an IP address: {}", bad));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::changes::ChangeOrigin;
+    use serde_json::json;
+
+    #[test]
+    fn test_feed_sends_each_date_once_with_latest_stamp() {
+        let db = Database::new().unwrap();
+        let start = db.latest_change_id().unwrap();
+        db.save_session("2026-03-01", &json!({ "v": 1 }), ChangeOrigin::Desktop).unwrap();
+        db.save_session("2026-03-01", &json!({ "v": 2 }), ChangeOrigin::Desktop).unwrap();
+
+        let feed = build_feed(&db, "this-mac", start).unwrap();
+        let changes: Vec<_> = feed.changes.iter().filter(|c| c.date_key == "2026-03-01").collect();
+
+        assert!(!feed.resync);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].session, json!({ "v": 2 }));
+        assert_eq!(changes[0].origin, "this-mac");
+
+        // A client from the future (reset server) gets everything
+        assert!(build_feed(&db, "this-mac", i64::MAX).unwrap().resync);
+    }
+
+    #[test]
+    fn test_announcements_need_a_one_time_nonce() {
+        let nonces = PairingNonces::new();
+        let nonce = nonces.issue();
+        assert!(!nonces.redeem("made-up"));
+        assert!(nonces.redeem(&nonce));
+        assert!(!nonces.redeem(&nonce));
+
+        let mut announcement = PeerAnnouncement {
+            device_id: "home-mac".to_string(),
+            secret: "s3cret".to_string(),
+            fingerprint: "ab".repeat(32),
+            port: 8841,
+            addresses: vec!["192.168.1.20".to_string()],
+            nonce,
+        };
+        assert!(validate_announcement(&announcement, "this-mac").is_ok());
+        announcement.nonce.clear();
+        assert!(validate_announcement(&announcement, "this-mac").is_err());
+    }
+}
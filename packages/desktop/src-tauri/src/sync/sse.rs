@@ -0,0 +1,169 @@
+/**
+ * Sync SSE Stream
+ *
+ * GET /api/v1/stream - resumable Server-Sent Events feed of session changes
+ * - Event ids are change log ids (see db::record_change)
+ * - `session_updated` events carry { dateKey, session }; `session` is an e2e
+ *   envelope for clients that ask (`?e2e=1` or the X-E2E header)
+ * - Reconnecting clients send Last-Event-ID (or ?lastEventId=) and get
+ *   everything they missed replayed from the change log
+ * - `resync` event when the gap can't be replayed (log pruned / server reset):
+ *   the client should re-pull all sessions
+ * - Periodic heartbeat comments keep proxies and Wi-Fi NAT from dropping the stream
+ * - Broadcast lag is recovered from the change log instead of ending the stream
+ * - Subscribers are listed in the status registry while connected
+ * - The stream ends when the server stops; EventSource reconnects and resumes
+ */
+
+use super::api::{ApiError, ErrorBody};
+use super::e2e;
+use super::status::StreamKind;
+use super::{auth::TokenSource, catch_up, ChangeNotice, SessionChange, SyncServerState};
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use utoipa::IntoParams;
+
+const HEARTBEAT_SECS: u64 = 15;
+
+#[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamQuery {
+    /// Pairing secret, for EventSource which can't send headers
+    token: Option<String>,
+    /// Resume after this change id (the Last-Event-ID header takes precedence)
+    last_event_id: Option<i64>,
+    /// Seal sessions in e2e envelopes (for EventSource, which can't send X-E2E)
+    #[serde(default)]
+    e2e: bool,
+}
+
+/// GET /api/v1/stream - SSE stream for real-time updates (auth required)
+///
+/// EventSource can't set headers, so this is the one route that also
+/// accepts `?token=`.
+#[utoipa::path(
+    get,
+    path = "/api/v1/stream",
+    params(StreamQuery),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, content_type = "text/event-stream",
+         description = "`session_updated` events ({ dateKey, session }) with change ids, `resync` when the gap can't be replayed"),
+        (status = 401, body = ErrorBody),
+        (status = 429, body = ErrorBody),
+    ),
+)]
+pub async fn handle_sse_stream(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<StreamQuery>,
+    headers: HeaderMap,
+    State(state): State<SyncServerState>,
+) -> Result<Sse<impl futures::Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    // Verify auth token
+    let device = state.authorize(addr, &headers, query.token.as_deref(), TokenSource::HeaderOrQuery)?;
+
+    // EventSource sends Last-Event-ID itself when it reconnects
+    let resume_from = headers
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<i64>().ok())
+        .or(query.last_event_id);
+
+    // Subscribe before reading the log so no change falls in between
+    let mut rx = state.bus.subscribe();
+
+    let latest = {
+        let db = state.db.lock().map_err(ApiError::internal)?;
+        db.latest_change_id().map_err(ApiError::internal)?
+    };
+
+    // Listed as connected until the stream is dropped
+    let client = state.status.connect(&device, StreamKind::Sse, addr.ip().to_canonical());
+    let sealed = state.e2e.seals_for(query.e2e || e2e::requested(&headers));
+    state.status.record_e2e(&device, sealed);
+
+    let mut shutdown = state.shutdown.clone();
+
+    let stream = async_stream::stream! {
+        let _client = client;
+
+        // New clients start from "now"; resuming clients from what they saw
+        let mut last_sent = resume_from.unwrap_or(latest);
+
+        if resume_from.is_some() {
+            for event in replay(&state, &mut last_sent, sealed) {
+                yield Ok(event);
+            }
+        }
+
+        loop {
+            let update = tokio::select! {
+                update = rx.recv() => update,
+                _ = shutdown.changed() => break,
+            };
+
+            match update {
+                Ok(event) => {
+                    let Some(notice) = ChangeNotice::from_event(event) else {
+                        continue;
+                    };
+                    // Already delivered by a replay
+                    if notice.id <= last_sent {
+                        continue;
+                    }
+                    let session = state.db.lock().ok().and_then(|db| db.get_session(&notice.date_key).ok().flatten());
+                    last_sent = notice.id;
+                    yield Ok(session_event(&state, SessionChange {
+                        id: notice.id,
+                        date_key: notice.date_key,
+                        session,
+                    }, sealed));
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    tracing::warn!("SSE client lagged by {} updates, replaying from change log", missed);
+                    for event in replay(&state, &mut last_sent, sealed) {
+                        yield Ok(event);
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    };
+
+    Ok(Sse::new(stream).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(HEARTBEAT_SECS))
+            .text("heartbeat"),
+    ))
+}
+
+/// Events for everything after `last_sent`, advancing it as we go
+fn replay(state: &SyncServerState, last_sent: &mut i64, sealed: bool) -> Vec<Event> {
+    catch_up(
+        state,
+        last_sent,
+        |change| session_event(state, change, sealed),
+        |latest| Event::default().event("resync").id(latest.to_string()).data("{}"),
+    )
+}
+
+fn session_event(state: &SyncServerState, change: SessionChange, sealed: bool) -> Event {
+    let session = change
+        .session
+        .map(|session| state.e2e.download(&change.date_key, session, sealed));
+    let data = serde_json::json!({
+        "dateKey": change.date_key,
+        "session": session,
+    });
+
+    Event::default()
+        .event("session_updated")
+        .id(change.id.to_string())
+        .data(data.to_string())
+}
@@ -0,0 +1,283 @@
+/**
+ * Sync Status Registry
+ *
+ * What the sync server is doing right now, for the settings UI and clients
+ * - Live stream subscribers (SSE and WebSocket), removed when they drop
+ * - Per-device activity: last request, last push (upload), last pull (download)
+ * - Whether each device syncs end-to-end encrypted
+ * - Recent errors (bounded)
+ *
+ * Devices are identified by the `X-Device-Id` header (or the WebSocket hello)
+ * and fall back to the client IP. Every change bumps a watch channel that the
+ * app forwards to the frontend as a Tauri event.
+ */
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use utoipa::ToSchema;
+
+/// Tauri event carrying a fresh SyncStatus after every change
+pub const FRONTEND_EVENT: &str = "sync-status-changed";
+
+/// Header clients may send to identify themselves
+pub const DEVICE_ID_HEADER: &str = "X-Device-Id";
+
+const MAX_RECENT_ERRORS: usize = 20;
+const MAX_DEVICE_ID_LEN: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamKind {
+    Sse,
+    WebSocket,
+}
+
+/// A connected stream subscriber
+#[derive(Clone, Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamClient {
+    pub id: u64,
+    pub device: String,
+    pub kind: StreamKind,
+    #[schema(value_type = String)]
+    pub ip: IpAddr,
+    pub connected_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceActivity {
+    pub device: String,
+    pub last_request_at: DateTime<Utc>,
+    pub last_push_at: Option<DateTime<Utc>>,
+    pub last_pull_at: Option<DateTime<Utc>>,
+    /// Whether its latest session payloads were end-to-end encrypted (see e2e)
+    pub encrypted: Option<bool>,
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncError {
+    pub at: DateTime<Utc>,
+    pub device: Option<String>,
+    pub message: String,
+}
+
+/// Point-in-time view of the registry
+#[derive(Clone, Debug, Default, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStatus {
+    pub running: bool,
+    pub port: Option<u16>,
+    pub clients: Vec<StreamClient>,
+    pub devices: Vec<DeviceActivity>,
+    pub last_push_at: Option<DateTime<Utc>>,
+    pub last_pull_at: Option<DateTime<Utc>>,
+    pub recent_errors: Vec<SyncError>,
+}
+
+#[derive(Default)]
+struct Inner {
+    running: bool,
+    port: Option<u16>,
+    clients: HashMap<u64, StreamClient>,
+    devices: HashMap<String, DeviceActivity>,
+    errors: VecDeque<SyncError>,
+}
+
+pub struct StatusRegistry {
+    inner: Mutex<Inner>,
+    next_client_id: AtomicU64,
+    changed: watch::Sender<()>,
+}
+
+/// Keeps a stream subscriber registered; unregisters on drop
+pub struct ClientGuard {
+    registry: Arc<StatusRegistry>,
+    id: u64,
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.registry.update(|inner| {
+            inner.clients.remove(&self.id);
+        });
+    }
+}
+
+impl StatusRegistry {
+    pub fn new() -> Arc<Self> {
+        let (changed, _) = watch::channel(());
+        Arc::new(Self {
+            inner: Mutex::new(Inner::default()),
+            next_client_id: AtomicU64::new(1),
+            changed,
+        })
+    }
+
+    /// Resolves whenever the status changes (coalesced)
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.changed.subscribe()
+    }
+
+    pub fn snapshot(&self) -> SyncStatus {
+        let inner = self.inner.lock().unwrap();
+
+        let mut clients: Vec<_> = inner.clients.values().cloned().collect();
+        clients.sort_by_key(|client| client.id);
+        let mut devices: Vec<_> = inner.devices.values().cloned().collect();
+        devices.sort_by_key(|device| std::cmp::Reverse(device.last_request_at));
+
+        SyncStatus {
+            running: inner.running,
+            port: inner.port,
+            last_push_at: devices.iter().filter_map(|d| d.last_push_at).max(),
+            last_pull_at: devices.iter().filter_map(|d| d.last_pull_at).max(),
+            clients,
+            devices,
+            recent_errors: inner.errors.iter().cloned().collect(),
+        }
+    }
+
+    pub fn set_running(&self, port: Option<u16>) {
+        self.update(|inner| {
+            inner.running = port.is_some();
+            inner.port = port;
+            if port.is_none() {
+                inner.clients.clear();
+            }
+        });
+    }
+
+    /// Authenticated request from `device`
+    pub fn record_request(&self, device: &str) {
+        self.update(|inner| {
+            touch(inner, device);
+        });
+    }
+
+    /// Sessions uploaded by `device`
+    pub fn record_push(&self, device: &str) {
+        self.update(|inner| {
+            touch(inner, device).last_push_at = Some(Utc::now());
+        });
+    }
+
+    /// Sessions downloaded by `device`
+    pub fn record_pull(&self, device: &str) {
+        self.update(|inner| {
+            touch(inner, device).last_pull_at = Some(Utc::now());
+        });
+    }
+
+    /// Whether `device` just sent or received sessions end-to-end encrypted
+    pub fn record_e2e(&self, device: &str, encrypted: bool) {
+        self.update(|inner| {
+            touch(inner, device).encrypted = Some(encrypted);
+        });
+    }
+
+    pub fn record_error(&self, device: Option<&str>, message: impl Into<String>) {
+        let error = SyncError {
+            at: Utc::now(),
+            device: device.map(str::to_string),
+            message: message.into(),
+        };
+        self.update(|inner| {
+            if inner.errors.len() == MAX_RECENT_ERRORS {
+                inner.errors.pop_front();
+            }
+            inner.errors.push_back(error);
+        });
+    }
+
+    /// Register a stream subscriber for as long as the guard lives
+    pub fn connect(self: &Arc<Self>, device: &str, kind: StreamKind, ip: IpAddr) -> ClientGuard {
+        let id = self.next_client_id.fetch_add(1, Ordering::Relaxed);
+        let client = StreamClient {
+            id,
+            device: device.to_string(),
+            kind,
+            ip,
+            connected_at: Utc::now(),
+        };
+        self.update(|inner| {
+            touch(inner, device);
+            inner.clients.insert(id, client);
+        });
+
+        ClientGuard { registry: self.clone(), id }
+    }
+
+    fn update(&self, apply: impl FnOnce(&mut Inner)) {
+        apply(&mut self.inner.lock().unwrap());
+        self.changed.send_replace(());
+    }
+}
+
+fn touch<'a>(inner: &'a mut Inner, device: &str) -> &'a mut DeviceActivity {
+    let now = Utc::now();
+    let activity = inner
+        .devices
+        .entry(device.to_string())
+        .or_insert_with(|| DeviceActivity {
+            device: device.to_string(),
+            last_request_at: now,
+            last_push_at: None,
+            last_pull_at: None,
+            encrypted: None,
+        });
+    activity.last_request_at = now;
+    activity
+}
+
+/// Device label for a request: the client's self-reported id, else its IP
+pub fn device_label(self_reported: Option<&str>, ip: IpAddr) -> String {
+    self_reported
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| id.chars().take(MAX_DEVICE_ID_LEN).collect())
+        .unwrap_or_else(|| ip.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_clients_tracked_until_guard_dropped() {
+        let registry = StatusRegistry::new();
+        let changes = registry.subscribe();
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 30));
+
+        let guard = registry.connect("iphone", StreamKind::Sse, ip);
+        registry.record_push("iphone");
+
+        let status = registry.snapshot();
+        assert_eq!(status.clients.len(), 1);
+        assert_eq!(status.devices[0].device, "iphone");
+        assert!(status.last_push_at.is_some());
+        assert!(changes.has_changed().unwrap());
+
+        drop(guard);
+        assert!(registry.snapshot().clients.is_empty());
+    }
+
+    #[test]
+    fn test_recent_errors_are_bounded() {
+        let registry = StatusRegistry::new();
+        for i in 0..MAX_RECENT_ERRORS + 5 {
+            registry.record_error(None, format!("error {}", i));
+        }
+
+        let errors = registry.snapshot().recent_errors;
+        assert_eq!(errors.len(), MAX_RECENT_ERRORS);
+        assert_eq!(errors[0].message, "error 5");
+    }
+}
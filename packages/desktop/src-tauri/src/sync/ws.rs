@@ -0,0 +1,480 @@
+/**
+ * Sync WebSocket Channel
+ *
+ * GET /api/v1/ws - one long-lived, bidirectional sync connection
+ *
+ * Protocol (JSON text frames, `type` field selects the message):
+ *
+ * Client -> server
+ * - hello         { token?, deviceId?, protocol?, lastEventId?, e2e? }
+ *                 must be the first message (token may instead come from the
+ *                 Authorization header); lastEventId resumes after a reconnect;
+ *                 e2e: true seals `change` sessions in e2e envelopes
+ * - push_session  { id, dateKey, session }          save a session (or envelope); answered by ack
+ * - ping          { id? }                           answered by pong
+ *
+ * Server -> client
+ * - welcome       { deviceId, protocol, heartbeatSecs }
+ * - ack           { id, ok, error? }
+ * - change        { id, dateKey, session }          a session changed (any origin);
+ *                 id is the change log id to pass back as lastEventId
+ * - resync        { id }                            missed changes can't be replayed:
+ *                 re-pull everything, then resume from id
+ * - pong          { id? }
+ * - error         { code, message }                 followed by close
+ *
+ * Backpressure: outbound frames go through a bounded queue. A client that
+ * can't keep up is disconnected with close code 4008; it should reconnect
+ * (exponential backoff, 1s..60s) and send hello with its last change id.
+ * The server sends WebSocket pings every HEARTBEAT_SECS and drops clients
+ * silent for IDLE_TIMEOUT_SECS. When the server stops, clients get close code
+ * 1001 (going away) and should reconnect the same way. Connections are
+ * listed in the status registry, labelled with the hello's deviceId.
+ */
+
+use super::api::{ApiError, ErrorBody};
+use super::e2e;
+use super::status::{self, StreamKind};
+use super::{auth, catch_up, validate_session, ChangeNotice, SyncServerState};
+use crate::changes::ChangeOrigin;
+use axum::extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::{interval, timeout, Instant};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+const HELLO_TIMEOUT_SECS: u64 = 10;
+const HEARTBEAT_SECS: u64 = 30;
+const IDLE_TIMEOUT_SECS: u64 = 90;
+const OUTBOUND_QUEUE: usize = 64;
+const MAX_MESSAGE_BYTES: usize = 256 * 1024;
+
+// Application close codes (4000-4999 are reserved for applications)
+const CLOSE_GOING_AWAY: u16 = 1001;
+const CLOSE_UNAUTHORIZED: u16 = 4001;
+const CLOSE_PROTOCOL_ERROR: u16 = 4002;
+const CLOSE_SLOW_CONSUMER: u16 = 4008;
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", rename_all_fields = "camelCase")]
+enum ClientMessage {
+    Hello {
+        token: Option<String>,
+        device_id: Option<String>,
+        protocol: Option<u32>,
+        last_event_id: Option<i64>,
+        #[serde(default)]
+        e2e: bool,
+    },
+    PushSession {
+        id: String,
+        date_key: String,
+        session: JsonValue,
+    },
+    Ping {
+        id: Option<String>,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", rename_all_fields = "camelCase")]
+enum ServerMessage {
+    Welcome {
+        device_id: String,
+        protocol: u32,
+        heartbeat_secs: u64,
+    },
+    Ack {
+        id: String,
+        ok: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    Change {
+        id: i64,
+        date_key: String,
+        session: Option<JsonValue>,
+    },
+    Resync {
+        id: i64,
+    },
+    Pong {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    Error {
+        code: &'static str,
+        message: String,
+    },
+}
+
+impl ServerMessage {
+    fn into_frame(self) -> Message {
+        Message::Text(serde_json::to_string(&self).unwrap_or_default())
+    }
+}
+
+/// GET /api/v1/ws - Upgrade to the sync WebSocket (auth in hello or header)
+#[utoipa::path(
+    get,
+    path = "/api/v1/ws",
+    responses(
+        (status = 101, description = "WebSocket upgrade; JSON message protocol described in ws.rs"),
+        (status = 429, body = ErrorBody),
+    ),
+)]
+pub async fn handle_ws(
+    ws: WebSocketUpgrade,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    State(state): State<SyncServerState>,
+) -> Response {
+    // IPv4 clients of the dual-stack socket arrive as ::ffff:a.b.c.d
+    let ip = addr.ip().to_canonical();
+    if state.auth_limiter.is_locked(ip) {
+        return ApiError::from(StatusCode::TOO_MANY_REQUESTS).into_response();
+    }
+
+    // Native clients may authenticate the upgrade itself; browsers can't set headers
+    let header_authorized = headers.contains_key("Authorization")
+        && state.authorize(addr, &headers, None, auth::TokenSource::Header).is_ok();
+    let header_e2e = e2e::requested(&headers);
+
+    ws.max_message_size(MAX_MESSAGE_BYTES)
+        .on_upgrade(move |socket| {
+            run_connection(socket, state, ip, header_authorized, header_e2e)
+        })
+}
+
+async fn run_connection(
+    socket: WebSocket,
+    state: SyncServerState,
+    ip: IpAddr,
+    header_authorized: bool,
+    header_e2e: bool,
+) {
+    let (mut sink, mut stream) = socket.split();
+
+    // Handshake: first frame must be a valid hello
+    let hello = match timeout(Duration::from_secs(HELLO_TIMEOUT_SECS), stream.next()).await {
+        Ok(Some(Ok(Message::Text(text)))) => serde_json::from_str::<ClientMessage>(&text).ok(),
+        _ => None,
+    };
+
+    let (peer_device, resume_from, asked_e2e) = match hello {
+        Some(ClientMessage::Hello { token, device_id, protocol, last_event_id, e2e }) => {
+            if protocol.unwrap_or(PROTOCOL_VERSION) > PROTOCOL_VERSION {
+                let _ = close(&mut sink, CLOSE_PROTOCOL_ERROR, "unsupported protocol version").await;
+                return;
+            }
+
+            // Sockets opened before a lockout don't each get another guess
+            if !header_authorized && state.auth_limiter.is_locked(ip) {
+                let _ = sink
+                    .send(error_message("rate_limited", "too many failed attempts, try again later").into_frame())
+                    .await;
+                let _ = close(&mut sink, CLOSE_UNAUTHORIZED, "rate limited").await;
+                return;
+            }
+
+            let token_ok = token
+                .as_deref()
+                .map(|t| auth::tokens_match(t, &state.auth_token))
+                .unwrap_or(false);
+
+            if !(header_authorized || token_ok) {
+                state.auth_limiter.record_failure(ip);
+                state.status.record_error(device_id.as_deref(), "WebSocket hello: invalid or missing token");
+                let _ = sink
+                    .send(error_message("unauthorized", "invalid or missing token").into_frame())
+                    .await;
+                let _ = close(&mut sink, CLOSE_UNAUTHORIZED, "unauthorized").await;
+                return;
+            }
+
+            state.auth_limiter.record_success(ip);
+            (status::device_label(device_id.as_deref(), ip), last_event_id, e2e)
+        }
+        _ => {
+            let _ = close(&mut sink, CLOSE_PROTOCOL_ERROR, "expected hello").await;
+            return;
+        }
+    };
+
+    tracing::info!("Sync WebSocket connected: {}", peer_device);
+    let _client = state.status.connect(&peer_device, StreamKind::WebSocket, ip);
+    let sealed = state.e2e.seals_for(asked_e2e || header_e2e);
+    state.status.record_e2e(&peer_device, sealed);
+
+    // Outbound queue: everything we send goes through here (bounded = backpressure)
+    let (out_tx, mut out_rx) = mpsc::channel::<Message>(OUTBOUND_QUEUE);
+    let writer = tokio::spawn(async move {
+        while let Some(frame) = out_rx.recv().await {
+            let is_close = matches!(frame, Message::Close(_));
+            if sink.send(frame).await.is_err() || is_close {
+                break;
+            }
+        }
+    });
+
+    let welcome = ServerMessage::Welcome {
+        device_id: state.device_id.clone(),
+        protocol: PROTOCOL_VERSION,
+        heartbeat_secs: HEARTBEAT_SECS,
+    };
+    // Subscribe before replaying so nothing falls in between
+    let mut updates = state.bus.subscribe();
+    let mut last_sent = match state.db.lock() {
+        Ok(db) => db.latest_change_id().unwrap_or(0),
+        Err(_) => 0,
+    };
+
+    let _ = out_tx.send(welcome.into_frame()).await;
+
+    if let Some(after) = resume_from {
+        last_sent = after;
+        for message in replay(&state, &mut last_sent, sealed) {
+            if out_tx.send(message.into_frame()).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    let mut shutdown = state.shutdown.clone();
+    let mut heartbeat = interval(Duration::from_secs(HEARTBEAT_SECS));
+    let mut last_seen = Instant::now();
+
+    loop {
+        let outbound: Vec<ServerMessage> = tokio::select! {
+            incoming = stream.next() => {
+                last_seen = Instant::now();
+                match incoming {
+                    Some(Ok(Message::Text(text))) => handle_client_message(&state, &peer_device, &text).into_iter().collect(),
+                    Some(Ok(Message::Binary(_))) => {
+                        vec![error_message("unsupported", "binary frames are not supported")]
+                    }
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                    // Ping/pong frames only refresh last_seen
+                    Some(Ok(_)) => Vec::new(),
+                }
+            }
+            update = updates.recv() => match update.map(ChangeNotice::from_event) {
+                // Not a session write, or already delivered by a replay
+                Ok(None) => Vec::new(),
+                Ok(Some(notice)) if notice.id <= last_sent => Vec::new(),
+                Ok(Some(notice)) => {
+                    last_sent = notice.id;
+                    vec![change_message(&state, notice, sealed)]
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    tracing::warn!("Sync WebSocket {} missed {} updates, replaying", peer_device, missed);
+                    replay(&state, &mut last_sent, sealed)
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = shutdown.changed() => {
+                let _ = out_tx.try_send(close_frame(CLOSE_GOING_AWAY, "server shutting down"));
+                break;
+            }
+            _ = heartbeat.tick() => {
+                if last_seen.elapsed() > Duration::from_secs(IDLE_TIMEOUT_SECS) {
+                    tracing::info!("Sync WebSocket {} timed out", peer_device);
+                    break;
+                }
+                if out_tx.try_send(Message::Ping(Vec::new())).is_err() {
+                    break;
+                }
+                Vec::new()
+            }
+        };
+
+        if !enqueue(&out_tx, outbound, &peer_device).await {
+            break;
+        }
+    }
+
+    drop(out_tx);
+    let _ = writer.await;
+    tracing::info!("Sync WebSocket disconnected: {}", peer_device);
+}
+
+/// Queue outbound messages; false once the connection should end
+async fn enqueue(out_tx: &mpsc::Sender<Message>, messages: Vec<ServerMessage>, peer_device: &str) -> bool {
+    for message in messages {
+        match out_tx.try_send(message.into_frame()) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                tracing::warn!("Sync WebSocket {} too slow, disconnecting", peer_device);
+                // Queue is full, so the close frame has to wait its turn
+                let _ = timeout(
+                    Duration::from_secs(1),
+                    out_tx.send(close_frame(CLOSE_SLOW_CONSUMER, "too slow; reconnect and resume")),
+                )
+                .await;
+                return false;
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => return false,
+        }
+    }
+    true
+}
+
+/// Messages for everything after `last_sent`, advancing it as we go
+fn replay(state: &SyncServerState, last_sent: &mut i64, sealed: bool) -> Vec<ServerMessage> {
+    catch_up(
+        state,
+        last_sent,
+        |change| ServerMessage::Change {
+            id: change.id,
+            session: change
+                .session
+                .map(|session| state.e2e.download(&change.date_key, session, sealed)),
+            date_key: change.date_key,
+        },
+        |latest| ServerMessage::Resync { id: latest },
+    )
+}
+
+/// Handle one client frame, returning the reply (if any)
+fn handle_client_message(state: &SyncServerState, peer_device: &str, text: &str) -> Option<ServerMessage> {
+    let message = match serde_json::from_str::<ClientMessage>(text) {
+        Ok(message) => message,
+        Err(e) => return Some(error_message("bad_message", &e.to_string())),
+    };
+
+    match message {
+        ClientMessage::Hello { .. } => Some(error_message("bad_message", "already authenticated")),
+        ClientMessage::Ping { id } => Some(ServerMessage::Pong { id }),
+        ClientMessage::PushSession { id, date_key, session } => {
+            let result = state.open_upload(peer_device, &date_key, session).and_then(|session| {
+                validate_session(&date_key, &session)?;
+                let db = state.db.lock().map_err(|e| e.to_string())?;
+                db.save_session(&date_key, &session, ChangeOrigin::Sync).map_err(|e| e.to_string())
+            });
+
+            // The change itself reaches every connection (this one too) via the bus
+            match result {
+                Ok(_) => {
+                    state.status.record_push(peer_device);
+                    Some(ServerMessage::Ack { id, ok: true, error: None })
+                }
+                Err(error) => {
+                    state.status.record_error(Some(peer_device), format!("push_session {}: {}", date_key, error));
+                    Some(ServerMessage::Ack { id, ok: false, error: Some(error) })
+                }
+            }
+        }
+    }
+}
+
+/// Build a change notification carrying the current session payload
+fn change_message(state: &SyncServerState, notice: ChangeNotice, sealed: bool) -> ServerMessage {
+    let session = state
+        .db
+        .lock()
+        .ok()
+        .and_then(|db| db.get_session(&notice.date_key).ok().flatten())
+        .map(|session| state.e2e.download(&notice.date_key, session, sealed));
+
+    ServerMessage::Change {
+        id: notice.id,
+        date_key: notice.date_key,
+        session,
+    }
+}
+
+fn error_message(code: &'static str, message: &str) -> ServerMessage {
+    ServerMessage::Error {
+        code,
+        message: message.to_string(),
+    }
+}
+
+fn close_frame(code: u16, reason: &'static str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    }))
+}
+
+async fn close<S>(sink: &mut S, code: u16, reason: &'static str) -> Result<(), axum::Error>
+where
+    S: futures::Sink<Message, Error = axum::Error> + Unpin,
+{
+    sink.send(close_frame(code, reason)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_message_parsing() {
+        let hello: ClientMessage =
+            serde_json::from_str(r#"{"type":"hello","token":"abc","deviceId":"phone","protocol":1}"#).unwrap();
+        assert!(matches!(hello, ClientMessage::Hello { token: Some(t), .. } if t == "abc"));
+
+        let push: ClientMessage = serde_json::from_str(
+            r#"{"type":"push_session","id":"1","dateKey":"2026-02-17","session":{"logged_at":"x"}}"#,
+        )
+        .unwrap();
+        assert!(matches!(push, ClientMessage::PushSession { date_key, .. } if date_key == "2026-02-17"));
+    }
+
+    #[test]
+    fn test_server_message_shape() {
+        let ack = serde_json::to_value(ServerMessage::Ack { id: "7".into(), ok: true, error: None }).unwrap();
+        assert_eq!(ack, serde_json::json!({"type": "ack", "id": "7", "ok": true}));
+
+        let welcome = serde_json::to_value(ServerMessage::Welcome {
+            device_id: "mac".into(),
+            protocol: PROTOCOL_VERSION,
+            heartbeat_secs: HEARTBEAT_SECS,
+        })
+        .unwrap();
+        assert_eq!(welcome["heartbeatSecs"], HEARTBEAT_SECS);
+        assert_eq!(welcome["type"], "welcome");
+    }
+
+    #[tokio::test]
+    async fn test_bad_hellos_lock_out_mapped_ipv4_clients() {
+        use axum::extract::Request;
+        use axum::middleware::{self, Next};
+        use tokio_tungstenite::tungstenite;
+
+        // How an IPv4 client of the dual-stack socket arrives
+        let mapped: SocketAddr = "[::ffff:192.0.2.7]:50000".parse().unwrap();
+        let app = super::super::router(super::super::tests::test_state(), &[]).layer(middleware::from_fn(
+            move |mut request: Request, next: Next| async move {
+                request.extensions_mut().insert(ConnectInfo(mapped));
+                next.run(request).await
+            },
+        ));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/api/v1/ws", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        for attempt in 1..=6 {
+            match tokio_tungstenite::connect_async(&url).await {
+                Ok((mut socket, _)) => {
+                    assert!(attempt <= 5, "upgrade accepted after {} bad hellos", attempt - 1);
+                    let hello = r#"{"type":"hello","token":"wrong","deviceId":"phone"}"#;
+                    socket.send(tungstenite::Message::Text(hello.into())).await.unwrap();
+                    while let Some(Ok(_)) = socket.next().await {}
+                }
+                Err(tungstenite::Error::Http(response)) => {
+                    assert_eq!((attempt, response.status().as_u16()), (6, 429));
+                }
+                Err(e) => panic!("WebSocket connect failed: {}", e),
+            }
+        }
+    }
+}
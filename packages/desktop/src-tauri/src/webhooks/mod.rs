@@ -0,0 +1,608 @@
+/**
+ * Outbound Webhooks
+ *
+ * POSTs workout and enforcement events to user-configured URLs (Zapier,
+ * Home Assistant, a Slack relay, ...)
+ * - Events (from the change bus): session.saved, blocker.warning,
+ *   blocker.shown, blocker.cleared, blocker.emergency_unlocked,
+ *   micro_break.shown, micro_break.dismissed, micro_break.deferred,
+ *   streak.broken; `ping` from "Send test"
+ * - Each target has its own secret and an optional event filter
+ * - Body: { id, event, occurredAt, deviceId, origin, data }; `id` stays the
+ *   same across retries, so receivers can drop duplicates
+ * - Signed: X-TrainDaily-Signature = "sha256=" + hex HMAC-SHA256 of
+ *   "<X-TrainDaily-Timestamp>.<body>" with the target's secret
+ * - Deliveries are queued in the database before sending, so they survive
+ *   restarts; failures retry with backoff (network errors, 5xx, 408, 429),
+ *   other 4xx fail at once
+ * - Queueing runs apart from sending, so a slow target never holds up the
+ *   bus; if the queue still falls behind, missed session.saved events are
+ *   rebuilt from the change log (with a null origin)
+ * - The same table is the delivery log shown in settings
+ */
+
+use crate::changes::{ChangeBus, ChangeEvent, ChangeKind};
+use crate::db::{timestamp, Database, DeliveryAttempt, DeliveryRecord, DeliveryStatus, WebhookRecord};
+use crate::sync::auth;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::{json, Value as JsonValue};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Notify;
+
+pub const SIGNATURE_HEADER: &str = "X-TrainDaily-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-TrainDaily-Timestamp";
+pub const EVENT_HEADER: &str = "X-TrainDaily-Event";
+
+/// Events a target can subscribe to
+pub const EVENTS: &[&str] = &[
+    "session.saved",
+    "blocker.warning",
+    "blocker.shown",
+    "blocker.cleared",
+    "blocker.emergency_unlocked",
+    "micro_break.shown",
+    "micro_break.dismissed",
+    "micro_break.deferred",
+    "streak.broken",
+];
+
+/// Test event, sent to one target whatever its filter
+pub const PING_EVENT: &str = "ping";
+
+/// Wait before retry n (after attempt n failed); then the delivery fails
+const RETRY_DELAYS_SECS: &[u64] = &[30, 120, 600, 1800, 7200, 21600];
+
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+
+// Deliveries read from the queue at a time
+const DELIVERY_BATCH: usize = 20;
+
+// Change log entries read at a time when rebuilding missed session.saved
+const RECOVERY_PAGE: usize = 200;
+
+// Longest sleep between queue checks
+const IDLE_CHECK_SECS: u64 = 300;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookSummary {
+    pub id: i64,
+    pub url: String,
+    pub secret: String,
+    /// Empty = all events
+    pub events: Vec<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliverySummary {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub event: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// Next retry, while pending
+    pub next_attempt_at: Option<String>,
+    pub response_status: Option<u16>,
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub completed_at: Option<String>,
+}
+
+/// Why an attempt failed
+#[derive(Debug)]
+struct SendError {
+    response_status: Option<u16>,
+    message: String,
+    /// Worth trying again later (vs. a request the target will never accept)
+    transient: bool,
+}
+
+impl SendError {
+    fn network(message: String) -> Self {
+        Self { response_status: None, message, transient: true }
+    }
+
+    fn response(status: reqwest::StatusCode) -> Self {
+        let code = status.as_u16();
+        Self {
+            response_status: Some(code),
+            message: format!("HTTP {}", status),
+            transient: status.is_server_error() || code == 408 || code == 429,
+        }
+    }
+}
+
+pub struct Webhooks {
+    db: Arc<Mutex<Database>>,
+    device_id: String,
+    /// Wakes the delivery loop when something is queued outside it
+    wake: Notify,
+}
+
+impl Webhooks {
+    pub fn new(db: Arc<Mutex<Database>>, device_id: String) -> Arc<Self> {
+        Arc::new(Self { db, device_id, wake: Notify::new() })
+    }
+
+    pub fn list(&self) -> Result<Vec<WebhookSummary>> {
+        Ok(self.webhooks()?.into_iter().map(summary).collect())
+    }
+
+    /// Add a target for `events` (empty = all), with a fresh secret
+    pub fn add(&self, url: &str, events: &[String]) -> Result<WebhookSummary> {
+        let url = url.trim();
+        let parsed = reqwest::Url::parse(url).context("Invalid webhook URL")?;
+        if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+            bail!("Webhook URL must be http(s)://host/...");
+        }
+        if let Some(unknown) = events.iter().find(|event| !EVENTS.contains(&event.as_str())) {
+            bail!("Unknown webhook event '{}' (expected one of {})", unknown, EVENTS.join(", "));
+        }
+
+        let secret = crate::sync::generate_auth_token();
+        auth::register_secret(&secret);
+        let id = self.db()?.add_webhook(url, &secret, events)?;
+        tracing::info!("Added webhook {} -> {}", id, parsed.host_str().unwrap_or_default());
+
+        self.webhooks()?
+            .into_iter()
+            .find(|webhook| webhook.id == id)
+            .map(summary)
+            .ok_or_else(|| anyhow!("Webhook {} vanished", id))
+    }
+
+    pub fn remove(&self, id: i64) -> Result<()> {
+        if !self.db()?.delete_webhook(id)? {
+            bail!("No webhook {}", id);
+        }
+        Ok(())
+    }
+
+    /// Delivery log, newest first
+    pub fn deliveries(&self, limit: usize) -> Result<Vec<DeliverySummary>> {
+        Ok(self.db()?.get_deliveries(limit)?.into_iter().map(delivery_summary).collect())
+    }
+
+    /// Queue a `ping` to one target and send it right away
+    pub fn send_test(&self, id: i64) -> Result<()> {
+        if !self.webhooks()?.iter().any(|webhook| webhook.id == id) {
+            bail!("No webhook {}", id);
+        }
+        let payload = payload(PING_EVENT, &self.device_id, None, json!({}));
+        self.db()?.enqueue_delivery(id, PING_EVENT, &payload)?;
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    /// Queue events from the bus and work through the delivery queue
+    pub async fn run(self: Arc<Self>, bus: ChangeBus) {
+        let http = match http_client() {
+            Ok(http) => http,
+            Err(e) => {
+                tracing::error!("Webhooks disabled: {}", e);
+                return;
+            }
+        };
+        if let Ok(webhooks) = self.webhooks() {
+            for webhook in webhooks {
+                auth::register_secret(&webhook.secret);
+            }
+        }
+
+        tokio::spawn(self.clone().subscribe(bus));
+
+        loop {
+            self.deliver_due(&http).await;
+
+            let wait = self.until_next_delivery();
+            tokio::select! {
+                _ = self.wake.notified() => {}
+                _ = tokio::time::sleep(wait) => {}
+            }
+        }
+    }
+
+    /// Queue deliveries for bus events; never waits on a target
+    async fn subscribe(self: Arc<Self>, bus: ChangeBus) {
+        let mut last_change_id = self.db().and_then(|db| db.latest_change_id()).unwrap_or_else(|e| {
+            tracing::error!("Failed to read the change log: {}", e);
+            0
+        });
+        let mut events = bus.subscribe();
+        // Saves between reading the id and subscribing
+        last_change_id = self.recover_sessions(last_change_id);
+
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if let ChangeKind::SessionSaved { change_id, .. } = event.kind {
+                        // Already rebuilt from the change log
+                        if change_id <= last_change_id {
+                            continue;
+                        }
+                        last_change_id = change_id;
+                    }
+                    self.enqueue(&event);
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!("Webhooks missed {} events, rebuilding session.saved from the change log", missed);
+                    last_change_id = self.recover_sessions(last_change_id);
+                }
+                Err(RecvError::Closed) => break,
+            }
+            self.wake.notify_one();
+        }
+    }
+
+    /// Queue a delivery of `event` for every target that wants it
+    fn enqueue(&self, event: &ChangeEvent) {
+        self.queue(&event.kind, serde_json::to_value(event.origin).ok());
+    }
+
+    /// Queue session.saved for every change after `after`, returning the
+    /// last change id covered. Other events can't be rebuilt.
+    fn recover_sessions(&self, after: i64) -> i64 {
+        let mut last = after;
+        loop {
+            let changes = match self.db().and_then(|db| db.changes_since(last, RECOVERY_PAGE)) {
+                Ok(changes) => changes,
+                Err(e) => {
+                    tracing::error!("Failed to read the change log: {}", e);
+                    return last;
+                }
+            };
+            if changes.is_empty() {
+                return last;
+            }
+            for change in changes {
+                last = change.id;
+                self.queue(&ChangeKind::SessionSaved { change_id: change.id, date_key: change.date_key }, None);
+            }
+        }
+    }
+
+    fn queue(&self, kind: &ChangeKind, origin: Option<JsonValue>) {
+        let Some(name) = event_name(kind) else {
+            return;
+        };
+        let result = (|| -> Result<usize> {
+            let db = self.db()?;
+            let targets: Vec<_> = db
+                .get_webhooks()?
+                .into_iter()
+                .filter(|webhook| webhook.events.is_empty() || webhook.events.iter().any(|e| e == name))
+                .collect();
+            if targets.is_empty() {
+                return Ok(0);
+            }
+
+            let mut data = event_data(kind);
+            if let ChangeKind::SessionSaved { date_key, .. } = kind {
+                data["session"] = db.get_session(date_key)?.unwrap_or(JsonValue::Null);
+            }
+            let payload = payload(name, &self.device_id, origin, data);
+
+            for webhook in &targets {
+                db.enqueue_delivery(webhook.id, name, &payload)?;
+            }
+            Ok(targets.len())
+        })();
+
+        match result {
+            Ok(0) => {}
+            Ok(queued) => tracing::debug!("Queued {} for {} webhook(s)", name, queued),
+            Err(e) => tracing::error!("Failed to queue webhook {}: {}", name, e),
+        }
+    }
+
+    /// Send every delivery that is due
+    async fn deliver_due(&self, http: &reqwest::Client) {
+        loop {
+            let due = match self.db().and_then(|db| db.due_deliveries(Utc::now(), DELIVERY_BATCH)) {
+                Ok(due) => due,
+                Err(e) => {
+                    tracing::error!("Failed to read webhook queue: {}", e);
+                    return;
+                }
+            };
+            if due.is_empty() {
+                return;
+            }
+            let webhooks = self.webhooks().unwrap_or_default();
+
+            for delivery in due {
+                let result = match webhooks.iter().find(|webhook| webhook.id == delivery.webhook_id) {
+                    Some(webhook) => post(http, webhook, &delivery.event, &delivery.payload).await,
+                    None => Err(SendError { response_status: None, message: "webhook was removed".to_string(), transient: false }),
+                };
+                if let Err(e) = self.record(&delivery, result) {
+                    tracing::error!("Failed to record webhook delivery {}: {}", delivery.id, e);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn record(&self, delivery: &DeliveryRecord, result: Result<u16, SendError>) -> Result<()> {
+        let attempts = delivery.attempts + 1;
+        let db = self.db()?;
+
+        match result {
+            Ok(status) => db.record_delivery_attempt(delivery.id, &DeliveryAttempt {
+                status: DeliveryStatus::Delivered,
+                response_status: Some(status),
+                error: None,
+                next_attempt_at: &delivery.next_attempt_at,
+            }),
+            Err(e) => {
+                let retry = retry_delay(attempts).filter(|_| e.transient);
+                let next_attempt_at = match retry {
+                    Some(delay) => timestamp(Utc::now() + delay),
+                    None => delivery.next_attempt_at.clone(),
+                };
+                match retry {
+                    Some(delay) => tracing::warn!(
+                        "Webhook delivery {} ({}) failed, retrying in {}s: {}",
+                        delivery.id, delivery.event, delay.num_seconds(), e.message
+                    ),
+                    None => tracing::error!(
+                        "Webhook delivery {} ({}) failed after {} attempt(s): {}",
+                        delivery.id, delivery.event, attempts, e.message
+                    ),
+                }
+                db.record_delivery_attempt(delivery.id, &DeliveryAttempt {
+                    status: if retry.is_some() { DeliveryStatus::Pending } else { DeliveryStatus::Failed },
+                    response_status: e.response_status,
+                    error: Some(&e.message),
+                    next_attempt_at: &next_attempt_at,
+                })
+            }
+        }
+    }
+
+    /// Time until the next queued delivery is due (capped at IDLE_CHECK_SECS)
+    fn until_next_delivery(&self) -> Duration {
+        let idle = Duration::from_secs(IDLE_CHECK_SECS);
+        match self.db().and_then(|db| db.next_delivery_at()) {
+            Ok(Some(at)) => (at - Utc::now()).to_std().unwrap_or(Duration::ZERO).min(idle),
+            _ => idle,
+        }
+    }
+
+    fn webhooks(&self) -> Result<Vec<WebhookRecord>> {
+        self.db()?.get_webhooks()
+    }
+
+    fn db(&self) -> Result<std::sync::MutexGuard<'_, Database>> {
+        self.db.lock().map_err(|e| anyhow!("{}", e))
+    }
+}
+
+/// Webhook event name for a bus event (None = not sent)
+fn event_name(kind: &ChangeKind) -> Option<&'static str> {
+    match kind {
+        ChangeKind::SessionSaved { .. } => Some("session.saved"),
+        ChangeKind::BlockerWarning { .. } => Some("blocker.warning"),
+        ChangeKind::BlockerShown { .. } => Some("blocker.shown"),
+        ChangeKind::BlockerCleared { .. } => Some("blocker.cleared"),
+        ChangeKind::EmergencyUnlocked { .. } => Some("blocker.emergency_unlocked"),
+        ChangeKind::MicroBreakShown => Some("micro_break.shown"),
+        ChangeKind::MicroBreakDismissed => Some("micro_break.dismissed"),
+        ChangeKind::MicroBreakDeferred { .. } => Some("micro_break.deferred"),
+        ChangeKind::StreakBroken { .. } => Some("streak.broken"),
+        ChangeKind::FirstSessionDateSet { .. }
+        | ChangeKind::SettingChanged { .. }
+        | ChangeKind::PeersChanged
+        | ChangeKind::WebhooksChanged
+        | ChangeKind::ScheduleChanged { .. } => None,
+    }
+}
+
+/// The event's fields (camelCase, without the bus `type` tag)
+fn event_data(kind: &ChangeKind) -> JsonValue {
+    let mut data = serde_json::to_value(kind).unwrap_or_else(|_| json!({}));
+    if let Some(fields) = data.as_object_mut() {
+        fields.remove("type");
+    }
+    data
+}
+
+fn payload(event: &str, device_id: &str, origin: Option<JsonValue>, data: JsonValue) -> String {
+    json!({
+        "id": uuid::Uuid::new_v4().to_string(),
+        "event": event,
+        "occurredAt": Utc::now().to_rfc3339(),
+        "deviceId": device_id,
+        "origin": origin,
+        "data": data,
+    })
+    .to_string()
+}
+
+/// Value of the signature header for a body sent at `timestamp` (Unix seconds)
+pub fn signature(secret: &str, timestamp: i64, body: &str) -> String {
+    format!("sha256={}", auth::sign(secret, &format!("{}.{}", timestamp, body)))
+}
+
+/// POST one delivery; Ok(status) on a 2xx response
+async fn post(http: &reqwest::Client, webhook: &WebhookRecord, event: &str, body: &str) -> Result<u16, SendError> {
+    let timestamp = Utc::now().timestamp();
+    let response = http
+        .post(&webhook.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, event)
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(SIGNATURE_HEADER, signature(&webhook.secret, timestamp, body))
+        .body(body.to_string())
+        .send()
+        .await
+        .map_err(|e| SendError::network(e.without_url().to_string()))?;
+
+    let status = response.status();
+    if status.is_success() {
+        Ok(status.as_u16())
+    } else {
+        Err(SendError::response(status))
+    }
+}
+
+/// Backoff before the next attempt, after `attempts` failed ones
+fn retry_delay(attempts: u32) -> Option<chrono::Duration> {
+    RETRY_DELAYS_SECS
+        .get(attempts.saturating_sub(1) as usize)
+        .map(|&secs| chrono::Duration::seconds(secs as i64))
+}
+
+/// HTTPS client trusting the system's root certificates
+fn http_client() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .tls_backend_preconfigured(crate::cert::client_tls_config(None)?)
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+        .user_agent(concat!("TrainDaily/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .context("Failed to build webhook HTTP client")
+}
+
+fn summary(webhook: WebhookRecord) -> WebhookSummary {
+    WebhookSummary {
+        id: webhook.id,
+        url: webhook.url,
+        secret: webhook.secret,
+        events: webhook.events,
+        created_at: webhook.created_at,
+    }
+}
+
+fn delivery_summary(delivery: DeliveryRecord) -> DeliverySummary {
+    DeliverySummary {
+        id: delivery.id,
+        webhook_id: delivery.webhook_id,
+        event: delivery.event,
+        next_attempt_at: (delivery.status == DeliveryStatus::Pending).then_some(delivery.next_attempt_at),
+        status: delivery.status,
+        attempts: delivery.attempts,
+        response_status: delivery.response_status,
+        last_error: delivery.last_error,
+        created_at: delivery.created_at,
+        completed_at: delivery.completed_at,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::changes::ChangeOrigin;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post as post_route;
+
+    type Received = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+    /// Local stand-in for a webhook receiver, answering every POST with `status`
+    async fn stand_in(status: StatusCode) -> (String, Received) {
+        let received: Received = Arc::default();
+        let log = received.clone();
+        let app = axum::Router::new().route(
+            "/hook",
+            post_route(move |headers: HeaderMap, body: String| async move {
+                log.lock().unwrap().push((headers, body));
+                status
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (url, received)
+    }
+
+    fn webhook(url: String) -> WebhookRecord {
+        WebhookRecord { id: 1, url, secret: "s3cret".into(), events: vec![], created_at: String::new() }
+    }
+
+    #[tokio::test]
+    async fn test_post_signs_body() {
+        let (url, received) = stand_in(StatusCode::NO_CONTENT).await;
+        let body = payload("session.saved", "mac", None, json!({ "dateKey": "2026-03-02" }));
+
+        assert_eq!(post(&http_client().unwrap(), &webhook(url), "session.saved", &body).await.unwrap(), 204);
+
+        let (headers, received_body) = received.lock().unwrap().pop().unwrap();
+        let header = |name: &str| headers.get(name).unwrap().to_str().unwrap().to_string();
+        let timestamp: i64 = header(TIMESTAMP_HEADER).parse().unwrap();
+        assert_eq!(received_body, body);
+        assert_eq!(header(EVENT_HEADER), "session.saved");
+        assert_eq!(header(SIGNATURE_HEADER), signature("s3cret", timestamp, &body));
+    }
+
+    #[tokio::test]
+    async fn test_failures_retry_only_when_transient() {
+        let (url, _) = stand_in(StatusCode::SERVICE_UNAVAILABLE).await;
+        let error = post(&http_client().unwrap(), &webhook(url), PING_EVENT, "{}").await.unwrap_err();
+        assert_eq!(error.response_status, Some(503));
+        assert!(error.transient);
+
+        let (url, _) = stand_in(StatusCode::GONE).await;
+        assert!(!post(&http_client().unwrap(), &webhook(url), PING_EVENT, "{}").await.unwrap_err().transient);
+
+        assert_eq!(retry_delay(1), Some(chrono::Duration::seconds(30)));
+        assert_eq!(retry_delay(RETRY_DELAYS_SECS.len() as u32 + 1), None);
+    }
+
+    #[test]
+    fn test_event_data_drops_bus_tag() {
+        let kind = ChangeKind::StreakBroken { length: 4, missed_date: "2026-03-03".into() };
+        assert_eq!(event_name(&kind), Some("streak.broken"));
+        assert_eq!(event_data(&kind), json!({ "length": 4, "missedDate": "2026-03-03" }));
+        assert_eq!(event_name(&ChangeKind::PeersChanged), None);
+    }
+
+    #[tokio::test]
+    async fn test_lagged_saves_are_rebuilt_from_change_log() {
+        let bus = ChangeBus::new();
+        let mut db = Database::new().unwrap();
+        db.attach_bus(bus.clone());
+        let db = Arc::new(Mutex::new(db));
+        let webhooks = Webhooks::new(db.clone(), "mac".into());
+        let target = webhooks.add("https://hooks.example.com/lag", &["session.saved".to_string()]).unwrap();
+        let subscriber = tokio::spawn(webhooks.clone().subscribe(bus));
+        tokio::task::yield_now().await;
+
+        // More saves than the bus holds, without yielding to the subscriber
+        let saves = 300;
+        for n in 0..saves {
+            db.lock().unwrap().save_session(&format!("lag-{}", n), &json!({}), ChangeOrigin::Desktop).unwrap();
+        }
+
+        let mut queued = Vec::new();
+        for _ in 0..200 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            queued = db
+                .lock()
+                .unwrap()
+                .get_deliveries(100_000)
+                .unwrap()
+                .into_iter()
+                .filter(|delivery| delivery.webhook_id == target.id)
+                .filter_map(|delivery| {
+                    let body: JsonValue = serde_json::from_str(&delivery.payload).unwrap();
+                    body["data"]["dateKey"].as_str().filter(|key| key.starts_with("lag-")).map(String::from)
+                })
+                .collect();
+            if queued.len() >= saves {
+                break;
+            }
+        }
+        subscriber.abort();
+
+        // Each save once: none lost, none queued from both the bus and the log
+        let total = queued.len();
+        queued.sort();
+        queued.dedup();
+        assert_eq!((total, queued.len()), (saves, saves));
+    }
+}
@@ -2,10 +2,11 @@ import { useState, useEffect } from 'react';
 import { TodayScreen } from './components/TodayScreen';
 import { PairingScreen } from './components/PairingScreen';
 import { BlockerScreen } from './components/BlockerScreen';
+import { BlockerWarningScreen } from './components/BlockerWarningScreen';
 import { MicroBreakScreen } from './components/MicroBreakScreen';
 import './App.css';
 
-type Route = 'main' | 'pairing' | 'blocker' | 'micro-break';
+type Route = 'main' | 'pairing' | 'blocker' | 'workout-warning' | 'micro-break';
 
 function App() {
   const [route, setRoute] = useState<Route>('main');
@@ -15,6 +16,8 @@ function App() {
     const path = window.location.pathname;
     if (path.includes('/pairing')) {
       setRoute('pairing');
+    } else if (path.includes('/workout-warning')) {
+      setRoute('workout-warning');
     } else if (path.includes('/blocker')) {
       setRoute('blocker');
     } else if (path.includes('/micro-break')) {
@@ -38,6 +41,8 @@ function App() {
       return <PairingScreen onClose={() => setRoute('main')} />;
     case 'blocker':
       return <BlockerScreen />;
+    case 'workout-warning':
+      return <BlockerWarningScreen />;
     case 'micro-break':
       return <MicroBreakScreen />;
     case 'main':
@@ -2,9 +2,13 @@
 
 import { Dumbbell, Calendar, Lock } from 'lucide-react';
 import { formatDisplayDate } from '../lib/workout-utils';
+import { useScheduleView } from '../hooks/useSchedule';
+import { EmergencyUnlock } from './EmergencyUnlock';
 
 export function BlockerScreen() {
   const today = new Date();
+  const { view } = useScheduleView(today, 7);
+  const workoutType = view?.days[0]?.workoutType;
   const dayName = today.toLocaleDateString('en-US', { weekday: 'long' });
 
   // Time until end of training day (midnight)
@@ -47,7 +51,12 @@ export function BlockerScreen() {
 
         <div className="bg-secondary/50 rounded-lg p-4 space-y-2 text-sm text-muted-foreground">
           <p className="font-semibold text-foreground">Training Schedule</p>
-          <p>Monday • Wednesday • Friday</p>
+          {workoutType && <p>Today: {workoutType.replace('_', ' ').toUpperCase()}</p>}
+          {view && (
+            <p className="font-mono text-xs">
+              {view.days.map((day) => day.workoutType.replace('_', ' ').toUpperCase()).join(' · ')}
+            </p>
+          )}
           <p className="text-xs text-muted-foreground/60 mt-2">
             No excuses. Your future self will thank you.
           </p>
@@ -58,6 +67,8 @@ export function BlockerScreen() {
         <p>Open TrainDaily from the menu bar to log your workout</p>
         <p className="text-muted-foreground/60 font-mono">{unlockMessage}</p>
       </div>
+
+      <EmergencyUnlock />
     </div>
   );
 }
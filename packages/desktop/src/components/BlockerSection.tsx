@@ -0,0 +1,185 @@
+'use client';
+
+import { useState, useEffect } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Button } from '@/components/ui/button';
+import { Input } from '@/components/ui/input';
+
+// get_blocker_policy (src-tauri/src/blocker/policy.rs)
+interface BlockingWindow {
+  startHour: number | null;
+  afterScreenMinutes: number | null;
+  deadlineHour: number | null;
+}
+
+interface BlockerPolicy {
+  days: BlockingWindow[];
+  graceMinutes: number;
+}
+
+// list_emergency_unlocks (src-tauri/src/db)
+interface EmergencyUnlockRecord {
+  id: number;
+  dateKey: string;
+  reason: string;
+  method: 'phrase' | 'countdown';
+  unlockedAt: string;
+  suspendedUntil: string;
+}
+
+// Monday first, like the Rust policy
+const DAY_NAMES = ['Mon', 'Tue', 'Wed', 'Thu', 'Fri', 'Sat', 'Sun'];
+
+type Row = Record<'start' | 'screenHours' | 'deadline', string>;
+
+function toRow(window: BlockingWindow): Row {
+  return {
+    start: window.startHour?.toString() ?? '',
+    screenHours: window.afterScreenMinutes != null ? String(window.afterScreenMinutes / 60) : '',
+    deadline: window.deadlineHour?.toString() ?? '',
+  };
+}
+
+function parseNumber(value: string): number | null {
+  const trimmed = value.trim();
+  if (!trimmed) return null;
+  const number = Number(trimmed);
+  return Number.isFinite(number) ? number : null;
+}
+
+function toWindow(row: Row): BlockingWindow {
+  const screenHours = parseNumber(row.screenHours);
+  return {
+    startHour: parseNumber(row.start),
+    afterScreenMinutes: screenHours != null ? Math.round(screenHours * 60) : null,
+    deadlineHour: parseNumber(row.deadline),
+  };
+}
+
+/** When the blocker comes up on training days: windows, warning, deadline */
+export function BlockerSection() {
+  const [rows, setRows] = useState<Row[]>([]);
+  const [grace, setGrace] = useState('0');
+  const [unlocksPerMonth, setUnlocksPerMonth] = useState('');
+  const [unlockMinutes, setUnlockMinutes] = useState('');
+  const [unlocks, setUnlocks] = useState<EmergencyUnlockRecord[]>([]);
+  const [error, setError] = useState<string | null>(null);
+  const [saving, setSaving] = useState(false);
+
+  useEffect(() => {
+    invoke<BlockerPolicy>('get_blocker_policy')
+      .then((policy) => {
+        setRows(policy.days.map(toRow));
+        setGrace(String(policy.graceMinutes));
+      })
+      .catch(console.error);
+
+    Promise.all([
+      invoke<string | null>('get_setting', { key: 'emergency_unlocks_per_month' }),
+      invoke<string | null>('get_setting', { key: 'emergency_unlock_minutes' }),
+      invoke<EmergencyUnlockRecord[]>('list_emergency_unlocks', { limit: 5 }),
+    ])
+      .then(([perMonth, minutes, log]) => {
+        setUnlocksPerMonth(perMonth ?? '');
+        setUnlockMinutes(minutes ?? '');
+        setUnlocks(log);
+      })
+      .catch(console.error);
+  }, []);
+
+  const updateRow = (index: number, field: keyof Row, value: string) => {
+    setRows((current) => current.map((row, i) => (i === index ? { ...row, [field]: value } : row)));
+  };
+
+  const handleSave = async () => {
+    setSaving(true);
+    setError(null);
+    try {
+      await invoke('set_blocker_policy', {
+        policy: { days: rows.map(toWindow), graceMinutes: parseNumber(grace) ?? 0 },
+      });
+      await invoke('set_setting', { key: 'emergency_unlocks_per_month', value: unlocksPerMonth.trim() });
+      await invoke('set_setting', { key: 'emergency_unlock_minutes', value: unlockMinutes.trim() });
+    } catch (e) {
+      setError(String(e));
+    } finally {
+      setSaving(false);
+    }
+  };
+
+  return (
+    <div className="flex flex-col gap-3 py-4">
+      <div className="flex flex-col gap-1">
+        <span className="text-sm font-medium leading-none">Blocking hours</span>
+        <span className="text-xs text-muted-foreground">
+          On training days, block from an hour and/or after hours of screen time. Empty blocks
+          from midnight; from the deadline hour the block is immediate.
+        </span>
+      </div>
+
+      <div className="grid grid-cols-[2.5rem_1fr_1fr_1fr] items-center gap-1 text-xs text-muted-foreground">
+        <span />
+        <span>From hour</span>
+        <span>Screen hours</span>
+        <span>Deadline</span>
+        {rows.map((row, index) => (
+          <div key={DAY_NAMES[index]} className="contents">
+            <span>{DAY_NAMES[index]}</span>
+            <Input value={row.start} onChange={(e) => updateRow(index, 'start', e.target.value)} placeholder="0" />
+            <Input
+              value={row.screenHours}
+              onChange={(e) => updateRow(index, 'screenHours', e.target.value)}
+              placeholder="-"
+            />
+            <Input
+              value={row.deadline}
+              onChange={(e) => updateRow(index, 'deadline', e.target.value)}
+              placeholder="-"
+            />
+          </div>
+        ))}
+      </div>
+
+      <div className="flex items-center gap-2 text-xs text-muted-foreground">
+        Emergency unlocks:
+        <Input
+          className="w-14"
+          value={unlocksPerMonth}
+          onChange={(e) => setUnlocksPerMonth(e.target.value)}
+          placeholder="3"
+        />
+        a month, for
+        <Input
+          className="w-14"
+          value={unlockMinutes}
+          onChange={(e) => setUnlockMinutes(e.target.value)}
+          placeholder="60"
+        />
+        min
+      </div>
+
+      {unlocks.length > 0 && (
+        <ul className="flex flex-col gap-1 text-xs text-muted-foreground">
+          {unlocks.map((unlock) => (
+            <li key={unlock.id} className="truncate">
+              <span className="font-mono">{unlock.dateKey}</span> ({unlock.method}) {unlock.reason}
+            </li>
+          ))}
+        </ul>
+      )}
+
+      <div className="flex items-center justify-between gap-2">
+        <label className="flex items-center gap-2 text-xs text-muted-foreground">
+          Warn
+          <Input className="w-16" value={grace} onChange={(e) => setGrace(e.target.value)} />
+          minutes before
+        </label>
+        <Button size="sm" onClick={handleSave} disabled={saving || rows.length === 0}>
+          {saving ? 'Saving...' : 'Save'}
+        </Button>
+      </div>
+
+      {error && <span className="text-xs text-destructive">{error}</span>}
+    </div>
+  );
+}
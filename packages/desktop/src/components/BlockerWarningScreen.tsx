@@ -0,0 +1,45 @@
+'use client';
+
+import { useState, useEffect } from 'react';
+import { Dumbbell } from 'lucide-react';
+
+// The blocker passes the block time in the window URL (ms since epoch)
+function blocksAtFromUrl(): number | null {
+  const value = Number(new URLSearchParams(window.location.search).get('blocksAt'));
+  return Number.isFinite(value) && value > 0 ? value : null;
+}
+
+/** Grace-period heads-up before the full-screen blocker */
+export function BlockerWarningScreen() {
+  const blocksAt = blocksAtFromUrl();
+  const [now, setNow] = useState(Date.now());
+
+  useEffect(() => {
+    const interval = setInterval(() => setNow(Date.now()), 1000);
+    return () => clearInterval(interval);
+  }, []);
+
+  const secondsLeft = blocksAt ? Math.max(0, Math.round((blocksAt - now) / 1000)) : null;
+  const countdown =
+    secondsLeft == null
+      ? 'soon'
+      : `in ${Math.floor(secondsLeft / 60)}:${String(secondsLeft % 60).padStart(2, '0')}`;
+
+  return (
+    <div className="flex items-center gap-4 min-h-screen bg-background p-4">
+      <Dumbbell className="w-10 h-10 shrink-0 text-muted-foreground animate-pulse" />
+      <div className="flex flex-col gap-1">
+        <span className="text-sm font-semibold">Workout time</span>
+        <span className="text-xs text-muted-foreground">
+          Your screen locks {countdown} unless today&apos;s workout is logged. Wrap up what you&apos;re
+          doing.
+        </span>
+        {blocksAt && (
+          <span className="text-xs font-mono text-muted-foreground/60">
+            Blocking at {new Date(blocksAt).toLocaleTimeString([], { hour: '2-digit', minute: '2-digit' })}
+          </span>
+        )}
+      </div>
+    </div>
+  );
+}
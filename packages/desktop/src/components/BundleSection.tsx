@@ -0,0 +1,102 @@
+'use client';
+
+import { useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Button } from '@/components/ui/button';
+import { Input } from '@/components/ui/input';
+
+interface BundleWritten {
+  path: string;
+  changes: number;
+  cursor: number;
+  encrypted: boolean;
+}
+
+interface BundleIngested {
+  deviceId: string;
+  applied: number;
+  skipped: number;
+  conflicts: string[];
+  invalid: string[];
+  cursor: number;
+}
+
+function ingestSummary(result: BundleIngested): string {
+  const parts = [`${result.applied} applied`, `${result.skipped} already here`];
+  if (result.conflicts.length > 0) parts.push(`${result.conflicts.length} newer here, kept`);
+  if (result.invalid.length > 0) parts.push(`${result.invalid.length} invalid`);
+  return `From ${result.deviceId}: ${parts.join(', ')}`;
+}
+
+/** Offline sync through files (AirDrop, a USB stick, a shared folder) */
+export function BundleSection() {
+  const [folder, setFolder] = useState('');
+  const [bundlePath, setBundlePath] = useState('');
+  const [busy, setBusy] = useState(false);
+  const [message, setMessage] = useState<string | null>(null);
+  const [error, setError] = useState<string | null>(null);
+
+  const run = async (action: () => Promise<string>) => {
+    setBusy(true);
+    setMessage(null);
+    setError(null);
+    try {
+      setMessage(await action());
+    } catch (e) {
+      setError(String(e));
+    } finally {
+      setBusy(false);
+    }
+  };
+
+  const handleExport = () =>
+    run(async () => {
+      const written = await invoke<BundleWritten>('export_bundle', { folder: folder.trim() });
+      return `Wrote ${written.changes} change(s) to ${written.path}`;
+    });
+
+  const handleImport = () =>
+    run(async () => {
+      const ingested = await invoke<BundleIngested>('import_bundle', { path: bundlePath.trim() });
+      setBundlePath('');
+      return ingestSummary(ingested);
+    });
+
+  return (
+    <div className="flex flex-col gap-3 py-4">
+      <div className="flex flex-col gap-1">
+        <span className="text-sm font-medium leading-none">Sync through files</span>
+        <span className="text-xs text-muted-foreground">
+          When the phone can't reach this Mac, exchange a .tdbundle file instead (AirDrop, USB stick).
+        </span>
+      </div>
+
+      <div className="flex gap-2">
+        <Input
+          value={folder}
+          onChange={(e) => setFolder(e.target.value)}
+          placeholder="Folder to write to, e.g. /Users/you/Desktop"
+          disabled={busy}
+        />
+        <Button size="sm" onClick={handleExport} disabled={busy || !folder.trim()}>
+          Export
+        </Button>
+      </div>
+
+      <div className="flex gap-2">
+        <Input
+          value={bundlePath}
+          onChange={(e) => setBundlePath(e.target.value)}
+          placeholder="Path of a received .tdbundle file"
+          disabled={busy}
+        />
+        <Button size="sm" onClick={handleImport} disabled={busy || !bundlePath.trim()}>
+          Import
+        </Button>
+      </div>
+
+      {message && <span className="text-xs text-muted-foreground">{message}</span>}
+      {error && <span className="text-xs text-destructive">{error}</span>}
+    </div>
+  );
+}
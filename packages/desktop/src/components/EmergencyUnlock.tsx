@@ -0,0 +1,105 @@
+'use client';
+
+import { useState, useEffect } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Button } from '@/components/ui/button';
+import { Input } from '@/components/ui/input';
+
+// src-tauri/src/blocker/unlock.rs
+interface UnlockStatus {
+  perMonth: number;
+  minutes: number;
+  countdownSecs: number;
+  remaining: number;
+  suspendedUntil: string | null;
+}
+
+interface Challenge {
+  phrase: string;
+  readyAt: string;
+}
+
+/** Way out of the blocker without a workout: reason + phrase or countdown */
+export function EmergencyUnlock() {
+  const [status, setStatus] = useState<UnlockStatus | null>(null);
+  const [challenge, setChallenge] = useState<Challenge | null>(null);
+  const [reason, setReason] = useState('');
+  const [phrase, setPhrase] = useState('');
+  const [error, setError] = useState<string | null>(null);
+  const [now, setNow] = useState(Date.now());
+
+  useEffect(() => {
+    invoke<UnlockStatus>('get_emergency_unlock_status').then(setStatus).catch(console.error);
+  }, []);
+
+  useEffect(() => {
+    if (!challenge) return;
+    const interval = setInterval(() => setNow(Date.now()), 1000);
+    return () => clearInterval(interval);
+  }, [challenge]);
+
+  const handleStart = async () => {
+    setError(null);
+    try {
+      setChallenge(await invoke<Challenge>('start_emergency_unlock'));
+      setNow(Date.now());
+    } catch (e) {
+      setError(String(e));
+    }
+  };
+
+  const handleConfirm = async () => {
+    setError(null);
+    try {
+      // The blocker closes itself once the unlock is recorded
+      await invoke('confirm_emergency_unlock', { reason, phrase: phrase || null });
+    } catch (e) {
+      setError(String(e));
+    }
+  };
+
+  if (!status) return null;
+
+  if (!challenge) {
+    return (
+      <div className="flex flex-col items-center gap-1">
+        <Button
+          size="sm"
+          variant="ghost"
+          className="text-muted-foreground/60"
+          onClick={handleStart}
+          disabled={status.remaining === 0}
+        >
+          Emergency unlock
+        </Button>
+        <span className="text-xs text-muted-foreground/40">
+          {status.remaining} of {status.perMonth} left this month · {status.minutes} min each
+        </span>
+        {error && <span className="text-xs text-destructive">{error}</span>}
+      </div>
+    );
+  }
+
+  const secondsLeft = Math.max(0, Math.ceil((new Date(challenge.readyAt).getTime() - now) / 1000));
+
+  return (
+    <div className="w-full max-w-md flex flex-col gap-3 rounded-lg border border-border p-4 text-sm">
+      <p className="text-muted-foreground">
+        Unlocks for {status.minutes} minutes and is logged with your reason. Type the phrase, or
+        wait {secondsLeft > 0 ? `${Math.floor(secondsLeft / 60)}:${String(secondsLeft % 60).padStart(2, '0')}` : 'no longer'}.
+      </p>
+      <p className="font-mono text-xs select-none text-foreground">{challenge.phrase}</p>
+      <Input value={phrase} onChange={(e) => setPhrase(e.target.value)} placeholder="Type the phrase" />
+      <Input value={reason} onChange={(e) => setReason(e.target.value)} placeholder="What's the emergency?" />
+      <div className="flex items-center justify-end gap-2">
+        <Button size="sm" variant="ghost" onClick={() => setChallenge(null)}>
+          Cancel
+        </Button>
+        <Button size="sm" variant="destructive" onClick={handleConfirm}>
+          Unlock
+        </Button>
+      </div>
+      {error && <span className="text-xs text-destructive">{error}</span>}
+    </div>
+  );
+}
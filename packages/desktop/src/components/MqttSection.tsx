@@ -0,0 +1,118 @@
+'use client';
+
+import { useState, useEffect, useCallback } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Button } from '@/components/ui/button';
+import { Input } from '@/components/ui/input';
+import { Switch } from '@/components/ui/switch';
+
+interface MqttStatus {
+  enabled: boolean;
+  connected: boolean;
+  broker: string | null;
+  lastError: string | null;
+}
+
+// Settings read by the Rust bridge (src-tauri/src/mqtt)
+const FIELDS = [
+  { key: 'mqtt_host', placeholder: 'Broker host (homeassistant.local)' },
+  { key: 'mqtt_port', placeholder: 'Port (1883, or 8883 with TLS)' },
+  { key: 'mqtt_username', placeholder: 'Username (optional)' },
+  { key: 'mqtt_password', placeholder: 'Password (optional)', type: 'password' },
+  { key: 'mqtt_topic_prefix', placeholder: 'Topic prefix (traindaily)' },
+  { key: 'mqtt_ca_file', placeholder: 'CA certificate file (optional, PEM)' },
+] as const;
+
+type FieldKey = (typeof FIELDS)[number]['key'];
+
+function statusText(status: MqttStatus | null): string {
+  if (!status?.enabled) return 'Off';
+  if (status.connected) return `Connected to ${status.broker}`;
+  return status.lastError ? `Not connected: ${status.lastError}` : 'Connecting...';
+}
+
+/** Home Assistant (or any MQTT broker): state topics and snooze / open commands */
+export function MqttSection() {
+  const [status, setStatus] = useState<MqttStatus | null>(null);
+  const [enabled, setEnabled] = useState(false);
+  const [tls, setTls] = useState(false);
+  const [values, setValues] = useState<Record<FieldKey, string>>(
+    () => Object.fromEntries(FIELDS.map((f) => [f.key, ''])) as Record<FieldKey, string>
+  );
+  const [saving, setSaving] = useState(false);
+
+  const refreshStatus = useCallback(() => {
+    invoke<MqttStatus>('get_mqtt_status').then(setStatus).catch(console.error);
+  }, []);
+
+  useEffect(() => {
+    Promise.all([
+      invoke<string | null>('get_setting', { key: 'mqtt_enabled' }),
+      invoke<string | null>('get_setting', { key: 'mqtt_tls' }),
+      ...FIELDS.map((f) => invoke<string | null>('get_setting', { key: f.key })),
+    ])
+      .then(([enabledValue, tlsValue, ...fieldValues]) => {
+        setEnabled(enabledValue === 'true');
+        setTls(tlsValue === 'true');
+        setValues(
+          Object.fromEntries(FIELDS.map((f, i) => [f.key, fieldValues[i] ?? ''])) as Record<FieldKey, string>
+        );
+      })
+      .catch(console.error);
+  }, []);
+
+  // Connection state changes without an event; poll while settings are open
+  useEffect(() => {
+    refreshStatus();
+    const interval = setInterval(refreshStatus, 3000);
+    return () => clearInterval(interval);
+  }, [refreshStatus]);
+
+  const handleSave = async () => {
+    setSaving(true);
+    try {
+      for (const field of FIELDS) {
+        await invoke('set_setting', { key: field.key, value: values[field.key].trim() });
+      }
+      await invoke('set_setting', { key: 'mqtt_tls', value: String(tls) });
+      await invoke('set_setting', { key: 'mqtt_enabled', value: String(enabled) });
+      refreshStatus();
+    } catch (e) {
+      console.error('Failed to save MQTT settings:', e);
+    } finally {
+      setSaving(false);
+    }
+  };
+
+  return (
+    <div className="flex flex-col gap-3 py-4">
+      <div className="flex items-start justify-between gap-4">
+        <div className="flex flex-col gap-1">
+          <span className="text-sm font-medium leading-none">MQTT</span>
+          <span className="text-xs text-muted-foreground">{statusText(status)}</span>
+        </div>
+        <Switch checked={enabled} onCheckedChange={setEnabled} />
+      </div>
+
+      {FIELDS.map((field) => (
+        <Input
+          key={field.key}
+          type={'type' in field ? field.type : 'text'}
+          value={values[field.key]}
+          onChange={(e) => setValues((v) => ({ ...v, [field.key]: e.target.value }))}
+          placeholder={field.placeholder}
+        />
+      ))}
+
+      <div className="flex items-center justify-between gap-2">
+        <label className="flex items-center gap-2 text-xs text-muted-foreground">
+          <Switch checked={tls} onCheckedChange={setTls} />
+          TLS
+        </label>
+        <Button size="sm" onClick={handleSave} disabled={saving}>
+          {saving ? 'Saving...' : 'Save'}
+        </Button>
+      </div>
+    </div>
+  );
+}
@@ -3,7 +3,6 @@
 import { useState, useEffect, useRef } from 'react';
 import { Smartphone, CheckCircle, Wifi, Copy } from 'lucide-react';
 import { Button } from './ui/button';
-import QRCode from 'qrcode';
 import { invoke } from '@tauri-apps/api/core';
 
 export function PairingScreen({ onClose }: { onClose?: () => void }) {
@@ -20,11 +19,8 @@ export function PairingScreen({ onClose }: { onClose?: () => void }) {
         const data = await invoke<string>('get_qr_code_data');
         setQrData(data);
 
-        const url = await QRCode.toDataURL(data, {
-          width: 300,
-          margin: 2,
-          color: { dark: '#000000', light: '#FFFFFF' },
-        });
+        // Rendered natively by the Rust qr module
+        const url = await invoke<string>('get_qr_code_image', { format: 'svg' });
         setQrDataUrl(url);
         setLoading(false);
       } catch (err) {
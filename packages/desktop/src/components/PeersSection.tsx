@@ -0,0 +1,109 @@
+'use client';
+
+import { useState, useEffect, useCallback } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Button } from '@/components/ui/button';
+import { Input } from '@/components/ui/input';
+
+interface PeerSummary {
+  deviceId: string;
+  paired: boolean;
+  online: boolean;
+  addresses: string[];
+  port: number;
+  pairedAt: string | null;
+  connectedVia: string | null;
+  lastSyncedAt: string | null;
+  lastError: string | null;
+}
+
+function peerStatus(peer: PeerSummary): string {
+  if (!peer.paired) return 'Nearby, not paired';
+  if (peer.connectedVia) {
+    return peer.lastSyncedAt
+      ? `Synced ${new Date(peer.lastSyncedAt).toLocaleTimeString()}`
+      : 'Connected';
+  }
+  return peer.lastError ?? (peer.online ? 'Connecting...' : 'Offline');
+}
+
+/** Other desktops (work / home Mac) that replicate workouts with this one */
+export function PeersSection() {
+  const [peers, setPeers] = useState<PeerSummary[]>([]);
+  const [pairingUrl, setPairingUrl] = useState('');
+  const [pairing, setPairing] = useState(false);
+  const [error, setError] = useState<string | null>(null);
+
+  const refresh = useCallback(() => {
+    invoke<PeerSummary[]>('list_peers').then(setPeers).catch(console.error);
+  }, []);
+
+  // Link status changes without an event; poll while settings are open
+  useEffect(() => {
+    refresh();
+    const interval = setInterval(refresh, 3000);
+    return () => clearInterval(interval);
+  }, [refresh]);
+
+  const handlePair = async () => {
+    setPairing(true);
+    setError(null);
+    try {
+      await invoke<string>('pair_peer', { pairingUrl });
+      setPairingUrl('');
+      refresh();
+    } catch (e) {
+      setError(String(e));
+    } finally {
+      setPairing(false);
+    }
+  };
+
+  const handleUnpair = async (deviceId: string) => {
+    try {
+      await invoke('unpair_peer', { deviceId });
+      refresh();
+    } catch (e) {
+      console.error('Failed to unpair desktop:', e);
+    }
+  };
+
+  return (
+    <div className="flex flex-col gap-3 py-4">
+      <div className="flex flex-col gap-1">
+        <span className="text-sm font-medium leading-none">Other desktops</span>
+        <span className="text-xs text-muted-foreground">
+          Paste the pairing URL from another Mac to keep workouts in sync between them.
+        </span>
+      </div>
+
+      {peers.map((peer) => (
+        <div key={peer.deviceId} className="flex items-center justify-between gap-2">
+          <div className="flex flex-col gap-0.5 min-w-0">
+            <span className="text-xs font-mono truncate">{peer.deviceId}</span>
+            <span className="text-xs text-muted-foreground truncate">{peerStatus(peer)}</span>
+          </div>
+          {peer.paired && (
+            <Button size="sm" variant="ghost" onClick={() => handleUnpair(peer.deviceId)}>
+              Unpair
+            </Button>
+          )}
+        </div>
+      ))}
+
+      <div className="flex gap-2">
+        <Input
+          value={pairingUrl}
+          onChange={(e) => setPairingUrl(e.target.value)}
+          placeholder="https://traindaily.vercel.app/pair?..."
+          disabled={pairing}
+        />
+        <Button size="sm" onClick={handlePair} disabled={pairing || !pairingUrl.trim()}>
+          {pairing ? 'Pairing...' : 'Pair'}
+        </Button>
+      </div>
+
+      {error && <span className="text-xs text-destructive">{error}</span>}
+    </div>
+  );
+}
@@ -0,0 +1,86 @@
+'use client';
+
+import { useMemo } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Button } from '@/components/ui/button';
+import { useScheduleView, ScheduleTemplate } from '@/hooks/useSchedule';
+
+const PRESET_LABELS: Record<string, string> = {
+  ppl: 'Push / Pull / Legs',
+  upper_lower: 'Upper / Lower',
+  full_body_3x: 'Full body 3x',
+};
+
+function sameTemplate(a: ScheduleTemplate, b: ScheduleTemplate): boolean {
+  return JSON.stringify(a) === JSON.stringify(b);
+}
+
+/** Weekly template and today's override, read by the blocker and micro-breaks too */
+export function ScheduleSection() {
+  const today = useMemo(() => new Date(), []);
+  const { view, refresh } = useScheduleView(today, 7);
+  const todayPlan = view?.days[0];
+
+  const handlePreset = async (template: ScheduleTemplate) => {
+    try {
+      await invoke('set_schedule_template', { template });
+      refresh();
+    } catch (e) {
+      console.error('Failed to set schedule:', e);
+    }
+  };
+
+  const handleToggleRest = async () => {
+    if (!todayPlan) return;
+    try {
+      await invoke('set_schedule_override', {
+        dateKey: todayPlan.dateKey,
+        workoutType: todayPlan.overridden ? null : 'rest',
+      });
+      refresh();
+    } catch (e) {
+      console.error('Failed to override today:', e);
+    }
+  };
+
+  return (
+    <div className="flex flex-col gap-3 py-4">
+      <div className="flex flex-col gap-1">
+        <span className="text-sm font-medium leading-none">Training schedule</span>
+        <span className="text-xs text-muted-foreground">
+          Which days are training days for the blocker, micro-breaks and today screen.
+        </span>
+      </div>
+
+      <div className="flex flex-wrap gap-1">
+        {view?.presets.map((preset) => (
+          <Button
+            key={preset.name}
+            size="sm"
+            variant={sameTemplate(preset.template, view.template) ? 'default' : 'ghost'}
+            onClick={() => handlePreset(preset.template)}
+          >
+            {PRESET_LABELS[preset.name] ?? preset.name}
+          </Button>
+        ))}
+      </div>
+
+      {view && (
+        <span className="text-xs font-mono text-muted-foreground">
+          {view.days.map((day) => day.workoutType.replace('_', ' ').toUpperCase()).join(' · ')}
+        </span>
+      )}
+
+      {todayPlan && (todayPlan.isTraining || todayPlan.overridden) && (
+        <div className="flex items-center justify-between gap-2">
+          <span className="text-xs text-muted-foreground">
+            {todayPlan.overridden ? 'Today is moved to a rest day' : 'Need a rest day today?'}
+          </span>
+          <Button size="sm" variant="ghost" onClick={handleToggleRest}>
+            {todayPlan.overridden ? 'Back to plan' : 'Rest today'}
+          </Button>
+        </div>
+      )}
+    </div>
+  );
+}
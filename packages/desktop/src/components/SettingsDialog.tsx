@@ -12,10 +12,17 @@ import {
 import { Switch } from '@/components/ui/switch';
 import { Button } from '@/components/ui/button';
 import { Settings } from 'lucide-react';
+import { PeersSection } from './PeersSection';
+import { BundleSection } from './BundleSection';
+import { WebhooksSection } from './WebhooksSection';
+import { MqttSection } from './MqttSection';
+import { ScheduleSection } from './ScheduleSection';
+import { BlockerSection } from './BlockerSection';
 
 interface SettingsState {
   trayVisible: boolean;
   openAtLogin: boolean;
+  syncEnabled: boolean;
 }
 
 export function SettingsDialog() {
@@ -24,6 +31,7 @@ export function SettingsDialog() {
   const [settings, setSettings] = useState<SettingsState>({
     trayVisible: true,
     openAtLogin: false,
+    syncEnabled: true,
   });
 
   // Load settings when dialog opens
@@ -35,11 +43,13 @@ export function SettingsDialog() {
     Promise.all([
       invoke<string | null>('get_setting', { key: 'tray_visible' }),
       invoke<string | null>('get_setting', { key: 'open_at_login' }),
+      invoke<string | null>('get_setting', { key: 'sync_enabled' }),
     ])
-      .then(([trayValue, loginValue]) => {
+      .then(([trayValue, loginValue, syncValue]) => {
         setSettings({
           trayVisible: trayValue !== 'false',
           openAtLogin: loginValue === 'true',
+          syncEnabled: syncValue !== 'false',
         });
       })
       .catch(console.error)
@@ -67,6 +77,16 @@ export function SettingsDialog() {
     }
   };
 
+  const handleSyncChange = async (enabled: boolean) => {
+    setSettings((s) => ({ ...s, syncEnabled: enabled }));
+    try {
+      await invoke('set_sync_server_enabled', { enabled });
+    } catch (e) {
+      console.error('Failed to toggle sync server:', e);
+      setSettings((s) => ({ ...s, syncEnabled: !enabled }));
+    }
+  };
+
   return (
     <Dialog open={open} onOpenChange={setOpen}>
       <DialogTrigger asChild>
@@ -125,6 +145,53 @@ export function SettingsDialog() {
 
           <div className="h-px bg-border" />
 
+          {/* Phone sync server */}
+          <div className="flex items-start justify-between gap-4 py-4">
+            <div className="flex flex-col gap-1">
+              <span className="text-sm font-medium leading-none">Phone sync</span>
+              <span className="text-xs text-muted-foreground">
+                Let paired phones sync workouts over your local network.
+              </span>
+            </div>
+            <Switch
+              checked={settings.syncEnabled}
+              onCheckedChange={handleSyncChange}
+              disabled={loading}
+            />
+          </div>
+
+          <div className="h-px bg-border" />
+
+          {/* Training schedule (blocker, micro-breaks and today screen) */}
+          <ScheduleSection />
+
+          <div className="h-px bg-border" />
+
+          {/* When the blocker comes up on training days */}
+          <BlockerSection />
+
+          <div className="h-px bg-border" />
+
+          {/* Desktop-to-desktop replication */}
+          <PeersSection />
+
+          <div className="h-px bg-border" />
+
+          {/* Offline sync bundles */}
+          <BundleSection />
+
+          <div className="h-px bg-border" />
+
+          {/* Outbound webhooks */}
+          <WebhooksSection />
+
+          <div className="h-px bg-border" />
+
+          {/* Home automation */}
+          <MqttSection />
+
+          <div className="h-px bg-border" />
+
           {/* Version */}
           <div className="pt-4 pb-1">
             <span className="text-xs text-muted-foreground/50">v1.6.0</span>
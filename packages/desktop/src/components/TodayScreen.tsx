@@ -11,8 +11,7 @@ import { useFirstSessionDate } from '@/hooks/useFirstSessionDate';
 import { useMobility } from '@/hooks/useMobility';
 import { useDevTools } from '@/lib/devtools';
 import { formatDisplayDate, getWeekNumber } from '@/lib/workout-utils';
-import { PUSH_EXERCISES, PULL_EXERCISES, LEGS_EXERCISES } from '@/lib/constants';
-import { getWorkoutType } from '@traindaily/core';
+import { getExercisesForWorkout } from '@/lib/constants';
 
 const ONBOARDING_KEY = 'traindaily_onboarding_completed';
 
@@ -59,11 +58,11 @@ export function TodayScreen() {
 function TodayContent({ date }: { date: Date }) {
   const router = useRouter();
   const schedule = useSchedule(date);
-  const workout = useWorkout(date);
+  const workoutType = schedule.workoutType;
+  const workout = useWorkout(date, workoutType);
   const mobility = useMobility();
   const firstSession = useFirstSessionDate();
   const weekNumber = getWeekNumber(firstSession, date);
-  const workoutType = getWorkoutType(date);
 
   const content = (() => {
     // Rest day
@@ -82,16 +81,12 @@ function TodayContent({ date }: { date: Date }) {
     if (schedule.isDone && workout.state === 'idle') {
       const session = workout.data[schedule.dateKey];
       const sessionWorkoutType = session?.workout_type || workoutType;
-      const completedExercises = sessionWorkoutType === 'push'
-        ? PUSH_EXERCISES
-        : sessionWorkoutType === 'pull'
-        ? PULL_EXERCISES
-        : LEGS_EXERCISES;
+      const completedExercises = getExercisesForWorkout(sessionWorkoutType);
 
       return (
         <div className="flex flex-col items-center justify-center min-h-screen bg-background p-6 gap-6">
           <CheckCircle className="w-16 h-16 text-green-500" />
-          <h1 className="text-xl font-bold tracking-tight uppercase">{sessionWorkoutType} DONE</h1>
+          <h1 className="text-xl font-bold tracking-tight uppercase">{sessionWorkoutType.replace('_', ' ')} DONE</h1>
           <p className="text-sm text-muted-foreground">{formatDisplayDate(date)}</p>
 
           {/* Quick summary */}
@@ -190,7 +185,7 @@ function TodayContent({ date }: { date: Date }) {
             style={{ animation: 'bounce-in 600ms ease-out backwards' }}
           />
           <h1 className="text-2xl font-bold tracking-tight uppercase">
-            {workoutType === 'push' ? 'PUSH' : workoutType === 'pull' ? 'PULL' : workoutType === 'legs' ? 'LEGS' : 'TRAINING'}
+            {workoutType === 'rest' ? 'TRAINING' : workoutType.replace('_', ' ').toUpperCase()}
           </h1>
           <p className="text-sm text-muted-foreground">{formatDisplayDate(date)}</p>
         </div>
@@ -0,0 +1,139 @@
+'use client';
+
+import { useState, useEffect, useCallback } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Button } from '@/components/ui/button';
+import { Input } from '@/components/ui/input';
+
+interface WebhookSummary {
+  id: number;
+  url: string;
+  secret: string;
+  events: string[];
+  createdAt: string;
+}
+
+interface DeliverySummary {
+  id: number;
+  webhookId: number;
+  event: string;
+  status: 'pending' | 'delivered' | 'failed';
+  attempts: number;
+  nextAttemptAt: string | null;
+  responseStatus: number | null;
+  lastError: string | null;
+  createdAt: string;
+  completedAt: string | null;
+}
+
+function lastDeliveryStatus(delivery: DeliverySummary | undefined): string {
+  if (!delivery) return 'No deliveries yet';
+  switch (delivery.status) {
+    case 'delivered':
+      return `${delivery.event} delivered ${new Date(delivery.completedAt ?? delivery.createdAt).toLocaleTimeString()}`;
+    case 'pending':
+      return delivery.attempts > 0
+        ? `${delivery.event} retrying (${delivery.lastError ?? 'failed'})`
+        : `${delivery.event} sending...`;
+    case 'failed':
+      return `${delivery.event} failed: ${delivery.lastError ?? 'unknown error'}`;
+  }
+}
+
+/** URLs that receive signed workout and blocker events */
+export function WebhooksSection() {
+  const [webhooks, setWebhooks] = useState<WebhookSummary[]>([]);
+  const [deliveries, setDeliveries] = useState<DeliverySummary[]>([]);
+  const [url, setUrl] = useState('');
+  const [error, setError] = useState<string | null>(null);
+
+  const refresh = useCallback(() => {
+    invoke<WebhookSummary[]>('list_webhooks').then(setWebhooks).catch(console.error);
+    invoke<DeliverySummary[]>('list_webhook_deliveries', { limit: 50 })
+      .then(setDeliveries)
+      .catch(console.error);
+  }, []);
+
+  // Deliveries progress in the background; poll while settings are open
+  useEffect(() => {
+    refresh();
+    const interval = setInterval(refresh, 3000);
+    return () => clearInterval(interval);
+  }, [refresh]);
+
+  const handleAdd = async () => {
+    setError(null);
+    try {
+      await invoke<WebhookSummary>('add_webhook', { url });
+      setUrl('');
+      refresh();
+    } catch (e) {
+      setError(String(e));
+    }
+  };
+
+  const handleTest = async (id: number) => {
+    try {
+      await invoke('test_webhook', { id });
+      refresh();
+    } catch (e) {
+      setError(String(e));
+    }
+  };
+
+  const handleRemove = async (id: number) => {
+    try {
+      await invoke('remove_webhook', { id });
+      refresh();
+    } catch (e) {
+      console.error('Failed to remove webhook:', e);
+    }
+  };
+
+  return (
+    <div className="flex flex-col gap-3 py-4">
+      <div className="flex flex-col gap-1">
+        <span className="text-sm font-medium leading-none">Webhooks</span>
+        <span className="text-xs text-muted-foreground">
+          POST workouts, blocker and micro-break events to other services. Verify the
+          X-TrainDaily-Signature header with the secret shown below.
+        </span>
+      </div>
+
+      {webhooks.map((webhook) => (
+        <div key={webhook.id} className="flex items-center justify-between gap-2">
+          <div className="flex flex-col gap-0.5 min-w-0">
+            <span className="text-xs font-mono truncate">{webhook.url}</span>
+            <span className="text-xs font-mono text-muted-foreground truncate">
+              Secret: {webhook.secret}
+            </span>
+            <span className="text-xs text-muted-foreground truncate">
+              {lastDeliveryStatus(deliveries.find((d) => d.webhookId === webhook.id))}
+            </span>
+          </div>
+          <div className="flex gap-1">
+            <Button size="sm" variant="ghost" onClick={() => handleTest(webhook.id)}>
+              Test
+            </Button>
+            <Button size="sm" variant="ghost" onClick={() => handleRemove(webhook.id)}>
+              Remove
+            </Button>
+          </div>
+        </div>
+      ))}
+
+      <div className="flex gap-2">
+        <Input
+          value={url}
+          onChange={(e) => setUrl(e.target.value)}
+          placeholder="https://hooks.example.com/traindaily"
+        />
+        <Button size="sm" onClick={handleAdd} disabled={!url.trim()}>
+          Add
+        </Button>
+      </div>
+
+      {error && <span className="text-xs text-destructive">{error}</span>}
+    </div>
+  );
+}
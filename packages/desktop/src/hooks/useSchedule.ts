@@ -1,14 +1,103 @@
-import { useState, useEffect } from 'react';
-import { useSchedule as useCoreSchedule } from '@traindaily/core';
+import { useState, useEffect, useCallback, useMemo } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { addDays, startOfWeek } from 'date-fns';
+import { useSchedule as useCoreSchedule, getWorkoutType } from '@traindaily/core';
 import { loadWorkoutData } from '../lib/storage';
-import { WorkoutData } from '../lib/types';
+import { WorkoutData, WorkoutType, formatDateKey } from '../lib/types';
+import { formatDisplayDate } from '../lib/workout-utils';
 
+export type ScheduleTemplate =
+  | { kind: 'weekly'; days: WorkoutType[] }
+  | { kind: 'rotation'; cycle: WorkoutType[] };
+
+export interface ScheduledDay {
+  dateKey: string;
+  workoutType: WorkoutType;
+  isTraining: boolean;
+  overridden: boolean;
+}
+
+// get_schedule (src-tauri/src/schedule)
+export interface ScheduleView {
+  template: ScheduleTemplate;
+  presets: { name: string; template: ScheduleTemplate }[];
+  days: ScheduledDay[];
+}
+
+// Rust change-bus events that move the schedule
+interface DataChangedEvent {
+  kind: { type: string; key?: string };
+}
+
+function affectsSchedule(kind: DataChangedEvent['kind']): boolean {
+  return (
+    kind.type === 'schedule_changed' ||
+    kind.type === 'first_session_date_set' ||
+    (kind.type === 'setting_changed' && kind.key === 'schedule_template')
+  );
+}
+
+/** The desktop schedule (template + overrides) for `days` days from `from` */
+export function useScheduleView(from: Date, days: number) {
+  const [view, setView] = useState<ScheduleView | null>(null);
+  const fromKey = formatDateKey(from);
+
+  const refresh = useCallback(() => {
+    invoke<ScheduleView>('get_schedule', { from: fromKey, days }).then(setView).catch(console.error);
+  }, [fromKey, days]);
+
+  useEffect(() => {
+    refresh();
+    const unlisten = listen<DataChangedEvent>('data-changed', (event) => {
+      if (affectsSchedule(event.payload.kind)) refresh();
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [refresh]);
+
+  return { view, refresh };
+}
+
+/** Today's plan from the Rust schedule; the core PPL rules until it loads */
 export function useSchedule(date: Date) {
   const [data, setData] = useState<WorkoutData>({});
+  const weekStart = startOfWeek(date, { weekStartsOn: 1 });
+  const { view } = useScheduleView(weekStart, 14);
 
   useEffect(() => {
     loadWorkoutData().then(setData);
   }, []);
 
-  return useCoreSchedule({ date, data });
+  const fallback = useCoreSchedule({ date, data });
+
+  return useMemo(() => {
+    if (!view) {
+      return { ...fallback, workoutType: getWorkoutType(date) };
+    }
+
+    const dateKey = formatDateKey(date);
+    const today = view.days.find((day) => day.dateKey === dateKey);
+    const week = view.days.slice(0, 7);
+    const next = view.days.find((day) => day.dateKey > dateKey && day.isTraining);
+    const isTraining = today?.isTraining ?? fallback.isTraining;
+
+    return {
+      isTraining,
+      isDone: !!data[dateKey]?.logged_at,
+      weekProgress: {
+        completed: week.filter((day) => data[day.dateKey]?.logged_at).length,
+        total: week.filter((day) => day.isTraining).length,
+      },
+      nextTraining:
+        !isTraining && next
+          ? `${formatDisplayDate(addDays(weekStart, view.days.indexOf(next)))} - ${next.workoutType.replace('_', ' ').toUpperCase()}`
+          : null,
+      dateKey,
+      workoutType: today?.workoutType ?? getWorkoutType(date),
+    };
+    // weekStart is derived from date
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+  }, [view, data, date, fallback]);
 }
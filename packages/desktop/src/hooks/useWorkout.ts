@@ -1,4 +1,6 @@
-import { useWorkout as useCoreWorkout } from '@traindaily/core';
+import { useEffect } from 'react';
+import { listen } from '@tauri-apps/api/event';
+import { useWorkout as useCoreWorkout, WorkoutType } from '@traindaily/core';
 import { tauriStorage } from '../lib/storage-tauri';
 import {
   unlockAudio,
@@ -11,9 +13,20 @@ import {
   playSessionComplete,
 } from '../lib/audio';
 
-export function useWorkout(date: Date) {
-  return useCoreWorkout({
+// Emitted by the Rust change bus (src-tauri/src/changes) after every write
+interface DataChangedEvent {
+  origin: 'desktop' | 'sync';
+  kind: { type: 'session_saved'; changeId: number; dateKey: string }
+    | { type: 'first_session_date_set'; dateKey: string }
+    | { type: 'setting_changed'; key: string }
+    | { type: 'schedule_changed'; dateKey: string };
+}
+
+/** `workoutType` comes from the desktop schedule (see useSchedule) */
+export function useWorkout(date: Date, workoutType?: WorkoutType) {
+  const workout = useCoreWorkout({
     date,
+    workoutType,
     storageAdapter: tauriStorage,
     audioCallbacks: {
       unlockAudio,
@@ -26,4 +39,20 @@ export function useWorkout(date: Date) {
       playSessionComplete,
     },
   });
+
+  const { refreshData } = workout;
+
+  // Reload when a session arrives from a synced phone
+  useEffect(() => {
+    const unlisten = listen<DataChangedEvent>('data-changed', (event) => {
+      if (event.payload.origin !== 'desktop' && event.payload.kind.type === 'session_saved') {
+        refreshData();
+      }
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [refreshData]);
+
+  return workout;
 }
@@ -10,4 +10,5 @@ export {
   DEFAULT_TARGETS_REPS,
   MOBILITY_EXERCISES,
   MICRO_BREAK_EXERCISES,
+  getExercisesForWorkout,
 } from '@traindaily/core';
@@ -5,7 +5,7 @@ import { ArrowLeft, Trophy } from 'lucide-react';
 import { BarChart, Bar, XAxis, Tooltip, ResponsiveContainer, Cell } from 'recharts';
 import { format, startOfWeek, addDays, subWeeks } from 'date-fns';
 import { cn } from '../lib/utils';
-import { WorkoutData, WorkoutType, PUSH_EXERCISES, PULL_EXERCISES, LEGS_EXERCISES, EXERCISES, formatDateKey } from '@traindaily/core';
+import { WorkoutData, WorkoutType, EXERCISES, LEGS_EXERCISES, formatDateKey, getExercisesForWorkout } from '@traindaily/core';
 
 interface HistoryScreenProps {
   data: WorkoutData;
@@ -17,12 +17,18 @@ const TYPE_COLOR: Record<Exclude<WorkoutType, 'rest'>, string> = {
   push: 'text-orange-400',
   pull: 'text-blue-400',
   legs: 'text-green-400',
+  upper: 'text-orange-400',
+  lower: 'text-green-400',
+  full_body: 'text-purple-400',
 };
 
 const TYPE_BG: Record<Exclude<WorkoutType, 'rest'>, string> = {
   push: 'bg-orange-400/10',
   pull: 'bg-blue-400/10',
   legs: 'bg-green-400/10',
+  upper: 'bg-orange-400/10',
+  lower: 'bg-green-400/10',
+  full_body: 'bg-purple-400/10',
 };
 
 export function HistoryScreen({ data, currentDate, onBack }: HistoryScreenProps) {
@@ -197,12 +203,8 @@ export function HistoryScreen({ data, currentDate, onBack }: HistoryScreenProps)
               <div className="space-y-2">
                 {recentSessions.map(([dateKey, session]) => {
                   const wt = session.workout_type;
-                  const exercises =
-                    wt === 'push'
-                      ? PUSH_EXERCISES
-                      : wt === 'pull'
-                        ? PULL_EXERCISES
-                        : LEGS_EXERCISES;
+                  // Legacy sessions have no workout_type (and older clients may send one we don't know)
+                  const exercises = (wt && getExercisesForWorkout(wt)) ?? LEGS_EXERCISES;
                   const totalReps = exercises.reduce((sum, ex) => {
                     const reps = session[ex.key];
                     return sum + (reps ? reps.reduce((s, r) => s + r, 0) : 0);
@@ -14,6 +14,9 @@ const WORKOUT_TYPE_COLORS: Record<WorkoutType, string> = {
   push: 'text-orange-400',
   pull: 'text-blue-400',
   legs: 'text-green-400',
+  upper: 'text-orange-400',
+  lower: 'text-green-400',
+  full_body: 'text-purple-400',
   rest: 'text-muted-foreground/40',
 };
 
@@ -21,6 +24,9 @@ const WORKOUT_TYPE_LABELS: Record<WorkoutType, string> = {
   push: 'PUSH',
   pull: 'PULL',
   legs: 'LEGS',
+  upper: 'UPPER',
+  lower: 'LOWER',
+  full_body: 'FULL BODY',
   rest: 'REST',
 };
 